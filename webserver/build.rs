@@ -0,0 +1,4 @@
+// sqlx::migrate! 在编译时嵌入 migrations/postgres 和 migrations/sqlite，新增迁移文件时需要重新编译
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
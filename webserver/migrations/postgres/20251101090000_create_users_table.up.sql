@@ -0,0 +1,8 @@
+-- 管理员账号，密码以 Argon2id PHC 字符串存储
+create table users(
+    user_id uuid not null,
+    username text not null unique,
+    password_hash text not null,
+    created_at timestamptz not null default now(),
+    primary key (user_id)
+);
@@ -0,0 +1,2 @@
+-- 已有的订阅都视为有效订阅
+alter table subscriptions add column status text not null default 'confirmed';
@@ -0,0 +1,2 @@
+drop table subscription_events;
+alter table subscriptions drop constraint subscriptions_status_check;
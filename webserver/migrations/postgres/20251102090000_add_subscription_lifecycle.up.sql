@@ -0,0 +1,21 @@
+-- 状态取值与 domain::SubscriptionStatus 一致
+alter table subscriptions add constraint subscriptions_status_check
+    check (status in ('pending_confirmation', 'confirmed', 'unsubscribed', 'bounced', 'complained'));
+
+-- 每次状态变化一行，from_status 为空表示创建订阅
+create table subscription_events(
+    id uuid not null,
+    subscriber_id uuid not null references subscriptions (id) on delete cascade,
+    from_status text check (from_status in ('pending_confirmation', 'confirmed', 'unsubscribed', 'bounced', 'complained')),
+    to_status text not null check (to_status in ('pending_confirmation', 'confirmed', 'unsubscribed', 'bounced', 'complained')),
+    reason text not null,
+    occurred_at timestamptz not null default now(),
+    primary key (id)
+);
+
+create index idx_subscription_events_subscriber on subscription_events (subscriber_id, occurred_at);
+
+-- 已有订阅者补一条创建事件
+insert into subscription_events (id, subscriber_id, from_status, to_status, reason, occurred_at)
+select md5(random()::text || id::text)::uuid, id, null, status, 'existing subscription', subscribed_at
+from subscriptions;
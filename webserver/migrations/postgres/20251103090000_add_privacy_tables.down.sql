@@ -0,0 +1,2 @@
+drop table erasure_tombstones;
+drop table privacy_tokens;
@@ -0,0 +1,23 @@
+-- 订阅者自助查看/删除数据的魔法链接，只保存令牌的 SHA-256，数据库泄露也无法直接使用
+create table privacy_tokens(
+    token_hash text not null,
+    email text not null,
+    created_at timestamptz not null default now(),
+    expires_at timestamptz not null,
+    primary key (token_hash)
+);
+
+create index idx_privacy_tokens_email on privacy_tokens (email);
+
+-- 每次彻底删除留下一条记录，用于审计；不保存邮箱，也不保存它的哈希
+create table erasure_tombstones(
+    id uuid not null,
+    erased_at timestamptz not null default now(),
+    requested_by text not null,
+    reason text not null,
+    -- 各表删除的行数，JSON 对象
+    erased_rows text not null,
+    primary key (id)
+);
+
+create index idx_erasure_tombstones_erased_at on erasure_tombstones (erased_at);
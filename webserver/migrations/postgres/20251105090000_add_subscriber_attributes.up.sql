@@ -0,0 +1,2 @@
+-- 订阅者的自定义属性，JSON 对象（键和值都是字符串），通讯的合并标签从这里取值
+alter table subscriptions add column attributes text not null default '{}';
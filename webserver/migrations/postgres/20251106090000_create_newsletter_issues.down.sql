@@ -0,0 +1 @@
+drop table newsletter_issues;
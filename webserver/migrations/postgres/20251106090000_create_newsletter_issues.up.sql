@@ -0,0 +1,22 @@
+-- 通讯期刊：到了 scheduled_at，服务器中的调度任务把它发给所有 confirmed 的订阅者
+create table newsletter_issues(
+    id uuid not null,
+    title text not null,
+    -- Markdown 正文，可以包含合并标签
+    content text not null,
+    -- scheduled：等待发送，可以改期或取消；sending：调度任务已领取；sent：发送完毕；cancelled：已取消
+    status text not null check (status in ('scheduled', 'sending', 'sent', 'cancelled')),
+    scheduled_at timestamptz not null,
+    created_at timestamptz not null default now(),
+    -- 创建这一期的管理员
+    created_by text not null,
+    started_at timestamptz,
+    completed_at timestamptz,
+    -- 发送成功和失败的收件人数
+    sent_count bigint not null default 0,
+    failed_count bigint not null default 0,
+    primary key (id)
+);
+
+-- 调度任务只查找到期的 scheduled 通讯
+create index idx_newsletter_issues_due on newsletter_issues (scheduled_at) where status = 'scheduled';
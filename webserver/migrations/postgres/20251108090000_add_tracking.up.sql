@@ -0,0 +1,20 @@
+-- 订阅者可以关闭打开和点击跟踪，关闭后发给他的邮件不加跟踪像素、链接不改写，已发出的链接也不再记录
+alter table subscriptions add column tracking boolean not null default true;
+
+-- 每期通讯可以关闭跟踪，创建时决定
+alter table newsletter_issues add column tracking boolean not null default true;
+
+-- 打开（跟踪像素被加载）和点击（经由 /t/c/ 跳转）事件
+create table tracking_events(
+    id uuid not null,
+    issue_id uuid not null references newsletter_issues (id) on delete cascade,
+    subscriber_id uuid not null references subscriptions (id) on delete cascade,
+    kind text not null check (kind in ('open', 'click')),
+    -- 点击的目标地址，打开事件为空
+    url text,
+    occurred_at timestamptz not null default now(),
+    primary key (id)
+);
+
+create index idx_tracking_events_issue on tracking_events (issue_id, kind);
+create index idx_tracking_events_subscriber on tracking_events (subscriber_id);
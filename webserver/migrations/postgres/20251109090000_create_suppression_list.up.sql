@@ -0,0 +1,14 @@
+-- 不再发送任何邮件的地址：服务商报告硬退信或垃圾邮件投诉时加入，管理员可以解除
+-- 按邮箱保存，删除订阅者后名单仍然有效；只有删除个人数据（erase）时一并删除
+create table suppression_list(
+    email text not null,
+    reason text not null check (reason in ('bounce', 'complaint')),
+    -- 服务商给出的说明，例如退信原因
+    detail text,
+    -- 服务商的邮件 id，便于对照服务商后台
+    message_id text,
+    suppressed_at timestamptz not null default now(),
+    primary key (email)
+);
+
+create index idx_suppression_list_suppressed_at on suppression_list (suppressed_at);
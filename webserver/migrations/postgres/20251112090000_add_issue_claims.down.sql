@@ -0,0 +1 @@
+alter table newsletter_issues drop column claimed_at;
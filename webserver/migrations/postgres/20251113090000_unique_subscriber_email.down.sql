@@ -0,0 +1,2 @@
+drop index idx_subscriptions_email_lower;
+create index idx_subscriptions_email on subscriptions (email);
@@ -0,0 +1,21 @@
+-- 同一个邮箱（不区分大小写）只保留最早的一行；重复行的状态事件、同意记录和打开/点击记录并入保留的行，
+-- 投递记录的主键含 subscriber_id，无法合并，随重复行一起删除
+create temporary table duplicate_subscriptions as
+select id, kept_id from (
+    select id, first_value(id) over (partition by lower(email) order by subscribed_at, id) as kept_id
+    from subscriptions
+) as ranked
+where id <> kept_id;
+
+update subscription_events set subscriber_id = (select kept_id from duplicate_subscriptions where id = subscriber_id)
+where subscriber_id in (select id from duplicate_subscriptions);
+update consent_records set subscriber_id = (select kept_id from duplicate_subscriptions where id = subscriber_id)
+where subscriber_id in (select id from duplicate_subscriptions);
+update tracking_events set subscriber_id = (select kept_id from duplicate_subscriptions where id = subscriber_id)
+where subscriber_id in (select id from duplicate_subscriptions);
+delete from subscriptions where id in (select id from duplicate_subscriptions);
+drop table duplicate_subscriptions;
+
+-- 重新订阅沿用已有的行，按状态机改变状态
+drop index idx_subscriptions_email;
+create unique index idx_subscriptions_email_lower on subscriptions (lower(email));
@@ -0,0 +1,5 @@
+update newsletter_issues set status = 'cancelled' where status = 'failed';
+alter table newsletter_issues drop column failure_reason;
+alter table newsletter_issues drop constraint newsletter_issues_status_check;
+alter table newsletter_issues add constraint newsletter_issues_status_check
+    check (status in ('scheduled', 'sending', 'sent', 'cancelled'));
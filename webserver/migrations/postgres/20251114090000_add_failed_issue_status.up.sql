@@ -0,0 +1,5 @@
+-- failed：调度任务无法渲染这一期（例如模板目录中的文件有问题），没有发给任何人；failure_reason 是渲染错误
+alter table newsletter_issues drop constraint newsletter_issues_status_check;
+alter table newsletter_issues add constraint newsletter_issues_status_check
+    check (status in ('scheduled', 'sending', 'sent', 'cancelled', 'failed'));
+alter table newsletter_issues add column failure_reason text;
@@ -0,0 +1 @@
+drop index idx_subscriptions_status_subscribed;
@@ -0,0 +1,2 @@
+-- 调度任务按 subscribed_at、id 分页读取 confirmed 的订阅者，索引与查询的条件和顺序一致，每一页只扫描这一页的行
+create index idx_subscriptions_status_subscribed on subscriptions (status, subscribed_at, id);
@@ -0,0 +1,4 @@
+-- 按时间分段的统计只读 kind 和 occurred_at；打开/点击人数和每个链接的点击数还要读 subscriber_id 和 url，
+-- 放进 INCLUDE 之后这些报告也不需要回表
+drop index idx_tracking_events_issue_time;
+create index idx_tracking_events_issue_time on tracking_events (issue_id, kind, occurred_at) include (subscriber_id, url);
@@ -0,0 +1,8 @@
+-- 只有哈希的记录无法恢复成邮箱，回滚时删除
+delete from suppression_list where email is null;
+alter table suppression_list drop constraint suppression_list_email_check;
+alter table suppression_list drop constraint suppression_list_email_hash_key;
+alter table suppression_list drop constraint suppression_list_email_key;
+alter table suppression_list drop column email_hash;
+alter table suppression_list alter column email set not null;
+alter table suppression_list add primary key (email);
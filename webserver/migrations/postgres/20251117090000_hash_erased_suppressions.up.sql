@@ -0,0 +1,7 @@
+-- 删除个人数据（erase）时保留停发：清空邮箱、说明和服务商 id，只留下小写邮箱的 SHA-256，发送之前仍然能查到
+alter table suppression_list drop constraint suppression_list_pkey;
+alter table suppression_list alter column email drop not null;
+alter table suppression_list add column email_hash text;
+alter table suppression_list add constraint suppression_list_email_key unique (email);
+alter table suppression_list add constraint suppression_list_email_hash_key unique (email_hash);
+alter table suppression_list add constraint suppression_list_email_check check (email is not null or email_hash is not null);
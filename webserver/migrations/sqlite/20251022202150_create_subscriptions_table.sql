@@ -0,0 +1,10 @@
+-- SQLite 版本：uuid 存为 BLOB，时间存为 ISO 8601 文本
+create table subscriptions(
+    id blob not null,
+    email text not null,
+    name text not null,
+    subscribed_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    primary key (id)
+);
+
+create index idx_subscriptions_email on subscriptions (email);
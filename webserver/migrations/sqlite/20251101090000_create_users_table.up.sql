@@ -0,0 +1,8 @@
+-- 管理员账号，密码以 Argon2id PHC 字符串存储
+create table users(
+    user_id blob not null,
+    username text not null unique,
+    password_hash text not null,
+    created_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    primary key (user_id)
+);
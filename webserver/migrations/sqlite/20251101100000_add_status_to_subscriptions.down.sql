@@ -0,0 +1,2 @@
+-- 需要 SQLite 3.35+
+alter table subscriptions drop column status;
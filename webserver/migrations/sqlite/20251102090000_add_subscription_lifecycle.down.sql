@@ -0,0 +1,15 @@
+drop table subscription_events;
+
+create table subscriptions_old(
+    id blob not null,
+    email text not null,
+    name text not null,
+    subscribed_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    status text not null default 'confirmed',
+    primary key (id)
+);
+insert into subscriptions_old (id, email, name, subscribed_at, status)
+select id, email, name, subscribed_at, status from subscriptions;
+drop table subscriptions;
+alter table subscriptions_old rename to subscriptions;
+create index idx_subscriptions_email on subscriptions (email);
@@ -0,0 +1,31 @@
+-- SQLite 不能给已有的表添加 check 约束，需要重建 subscriptions
+create table subscriptions_new(
+    id blob not null,
+    email text not null,
+    name text not null,
+    subscribed_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    status text not null default 'confirmed'
+        check (status in ('pending_confirmation', 'confirmed', 'unsubscribed', 'bounced', 'complained')),
+    primary key (id)
+);
+insert into subscriptions_new (id, email, name, subscribed_at, status)
+select id, email, name, subscribed_at, status from subscriptions;
+drop table subscriptions;
+alter table subscriptions_new rename to subscriptions;
+create index idx_subscriptions_email on subscriptions (email);
+
+create table subscription_events(
+    id blob not null,
+    subscriber_id blob not null references subscriptions (id) on delete cascade,
+    from_status text check (from_status in ('pending_confirmation', 'confirmed', 'unsubscribed', 'bounced', 'complained')),
+    to_status text not null check (to_status in ('pending_confirmation', 'confirmed', 'unsubscribed', 'bounced', 'complained')),
+    reason text not null,
+    occurred_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    primary key (id)
+);
+
+create index idx_subscription_events_subscriber on subscription_events (subscriber_id, occurred_at);
+
+insert into subscription_events (id, subscriber_id, from_status, to_status, reason, occurred_at)
+select randomblob(16), id, null, status, 'existing subscription', subscribed_at
+from subscriptions;
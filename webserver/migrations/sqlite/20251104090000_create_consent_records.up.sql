@@ -0,0 +1,18 @@
+-- 订阅者同意接收邮件的证据：何时、从哪里、看到哪个版本的同意文本
+create table consent_records(
+    id blob not null,
+    subscriber_id blob not null references subscriptions (id) on delete cascade,
+    -- subscribe：提交订阅表单；confirm：点击确认链接
+    action text not null check (action in ('subscribe', 'confirm')),
+    recorded_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    ip_address text,
+    user_agent text,
+    -- 表单所在的页面或来源标识
+    source text,
+    -- 表单上展示的同意文本的版本
+    consent_version text,
+    primary key (id)
+);
+
+create index idx_consent_records_subscriber on consent_records (subscriber_id, recorded_at);
+create index idx_consent_records_recorded_at on consent_records (recorded_at);
@@ -0,0 +1,4 @@
+alter table newsletter_issues drop column draft_version;
+alter table newsletter_issues drop column draft_id;
+drop table newsletter_draft_revisions;
+drop table newsletter_drafts;
@@ -0,0 +1,32 @@
+-- 通讯草稿，title、content 和 version 是最新一次保存的内容
+create table newsletter_drafts(
+    id blob not null,
+    title text not null,
+    content text not null,
+    -- 每次保存加一；保存时必须带上读取时的版本号，不一致说明别人已经改过
+    version integer not null,
+    created_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    created_by text not null,
+    updated_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_by text not null,
+    primary key (id)
+);
+
+create index idx_newsletter_drafts_updated_at on newsletter_drafts (updated_at);
+
+-- 草稿的每一次保存，写入后不再修改
+create table newsletter_draft_revisions(
+    draft_id blob not null references newsletter_drafts (id) on delete cascade,
+    version integer not null,
+    title text not null,
+    content text not null,
+    saved_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    saved_by text not null,
+    -- 从哪个版本恢复而来，普通保存为空
+    restored_from integer,
+    primary key (draft_id, version)
+);
+
+-- 从草稿发布的通讯记录发布的是哪个版本；草稿删除后通讯保留自己的标题和正文
+alter table newsletter_issues add column draft_id blob references newsletter_drafts (id) on delete set null;
+alter table newsletter_issues add column draft_version integer;
@@ -0,0 +1,3 @@
+drop table tracking_events;
+alter table newsletter_issues drop column tracking;
+alter table subscriptions drop column tracking;
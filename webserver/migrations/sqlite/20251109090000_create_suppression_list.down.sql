@@ -0,0 +1 @@
+drop table suppression_list;
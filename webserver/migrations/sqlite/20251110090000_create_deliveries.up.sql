@@ -0,0 +1,22 @@
+-- 每期通讯发给每个订阅者的投递状态：调度任务按页写入 queued，之后随发送结果、退信和打开点击更新
+create table deliveries(
+    issue_id blob not null references newsletter_issues (id) on delete cascade,
+    subscriber_id blob not null references subscriptions (id) on delete cascade,
+    status text not null check (status in ('queued', 'sent', 'failed', 'bounced', 'opened', 'clicked')),
+    -- 服务商返回的邮件 id，退信事件按它找到这一行
+    message_id text,
+    attempts integer not null default 0,
+    -- 最近一次发送失败或退信的原因
+    last_error text,
+    queued_at text not null,
+    sent_at text,
+    opened_at text,
+    clicked_at text,
+    bounced_at text,
+    updated_at text not null,
+    primary key (issue_id, subscriber_id)
+);
+
+create index idx_deliveries_issue_status on deliveries (issue_id, status);
+create index idx_deliveries_subscriber on deliveries (subscriber_id);
+create index idx_deliveries_message_id on deliveries (message_id) where message_id is not null;
@@ -0,0 +1,3 @@
+drop index idx_tracking_events_issue_time;
+create index idx_tracking_events_issue on tracking_events (issue_id, kind);
+alter table deliveries drop column unsubscribed_at;
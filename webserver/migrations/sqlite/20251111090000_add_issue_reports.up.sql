@@ -0,0 +1,6 @@
+-- 通过某一期邮件中的退订链接退订的时间，用于把退订归到这一期；旧链接不带期号，不归到任何一期
+alter table deliveries add column unsubscribed_at text;
+
+-- 报告按时间分段统计打开和点击，索引包含 kind 和 occurred_at，不需要回表
+drop index idx_tracking_events_issue;
+create index idx_tracking_events_issue_time on tracking_events (issue_id, kind, occurred_at);
@@ -0,0 +1,6 @@
+-- 调度任务领取或最近一次续期的时间，发送期间每读取一页收件人续期一次
+-- sending 的一期没有领取时间（停机时主动放弃），或者太久没有续期（实例退出），可以被重新领取，接着发送还没有结果的收件人
+alter table newsletter_issues add column claimed_at text;
+
+-- 升级之前停留在 sending 的通讯按领取时间处理，超时后会被接着发送
+update newsletter_issues set claimed_at = started_at where status = 'sending';
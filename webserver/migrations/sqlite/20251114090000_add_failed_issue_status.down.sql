@@ -0,0 +1,76 @@
+update newsletter_issues set status = 'cancelled' where status = 'failed';
+
+-- 迁移在事务中执行，不能关闭外键检查；删除 newsletter_issues 会级联删除投递和跟踪记录，
+-- 所以先把这两张表的数据存到临时表，删掉它们，重建之后再放回
+create temporary table saved_deliveries as select * from deliveries;
+create temporary table saved_tracking_events as select * from tracking_events;
+drop table tracking_events;
+drop table deliveries;
+
+create table newsletter_issues_new(
+    id blob not null,
+    title text not null,
+    -- Markdown 正文，可以包含合并标签
+    content text not null,
+    -- scheduled：等待发送，可以改期或取消；sending：调度任务已领取；sent：发送完毕；cancelled：已取消
+    status text not null check (status in ('scheduled', 'sending', 'sent', 'cancelled')),
+    scheduled_at text not null,
+    created_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    -- 创建这一期的管理员
+    created_by text not null,
+    started_at text,
+    completed_at text,
+    -- 发送成功和失败的收件人数
+    sent_count integer not null default 0,
+    failed_count integer not null default 0,
+    draft_id blob references newsletter_drafts (id) on delete set null,
+    draft_version integer,
+    tracking boolean not null default true,
+    claimed_at text,
+    primary key (id)
+);
+insert into newsletter_issues_new (id, title, content, status, scheduled_at, created_at, created_by, started_at, completed_at, sent_count, failed_count, draft_id, draft_version, tracking, claimed_at)
+select id, title, content, status, scheduled_at, created_at, created_by, started_at, completed_at, sent_count, failed_count, draft_id, draft_version, tracking, claimed_at from newsletter_issues;
+drop table newsletter_issues;
+alter table newsletter_issues_new rename to newsletter_issues;
+create index idx_newsletter_issues_due on newsletter_issues (scheduled_at) where status = 'scheduled';
+
+create table deliveries(
+    issue_id blob not null references newsletter_issues (id) on delete cascade,
+    subscriber_id blob not null references subscriptions (id) on delete cascade,
+    status text not null check (status in ('queued', 'sent', 'failed', 'bounced', 'opened', 'clicked')),
+    -- 服务商返回的邮件 id，退信事件按它找到这一行
+    message_id text,
+    attempts integer not null default 0,
+    -- 最近一次发送失败或退信的原因
+    last_error text,
+    queued_at text not null,
+    sent_at text,
+    opened_at text,
+    clicked_at text,
+    bounced_at text,
+    updated_at text not null,
+    unsubscribed_at text,
+    primary key (issue_id, subscriber_id)
+);
+insert into deliveries select * from saved_deliveries;
+create index idx_deliveries_issue_status on deliveries (issue_id, status);
+create index idx_deliveries_subscriber on deliveries (subscriber_id);
+create index idx_deliveries_message_id on deliveries (message_id) where message_id is not null;
+
+create table tracking_events(
+    id blob not null,
+    issue_id blob not null references newsletter_issues (id) on delete cascade,
+    subscriber_id blob not null references subscriptions (id) on delete cascade,
+    kind text not null check (kind in ('open', 'click')),
+    -- 点击的目标地址，打开事件为空
+    url text,
+    occurred_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    primary key (id)
+);
+insert into tracking_events select * from saved_tracking_events;
+create index idx_tracking_events_subscriber on tracking_events (subscriber_id);
+create index idx_tracking_events_issue_time on tracking_events (issue_id, kind, occurred_at);
+
+drop table saved_deliveries;
+drop table saved_tracking_events;
@@ -0,0 +1,2 @@
+drop index idx_tracking_events_issue_time;
+create index idx_tracking_events_issue_time on tracking_events (issue_id, kind, occurred_at);
@@ -0,0 +1,4 @@
+-- 按时间分段的统计只读 kind 和 occurred_at；打开/点击人数和每个链接的点击数还要读 subscriber_id 和 url。
+-- SQLite 没有 INCLUDE，把这两列放在索引末尾，这些报告也只读索引
+drop index idx_tracking_events_issue_time;
+create index idx_tracking_events_issue_time on tracking_events (issue_id, kind, occurred_at, subscriber_id, url);
@@ -0,0 +1,16 @@
+-- 只有哈希的记录无法恢复成邮箱，回滚时删除
+create table suppression_list_new(
+    email text not null,
+    reason text not null check (reason in ('bounce', 'complaint')),
+    -- 服务商给出的说明，例如退信原因
+    detail text,
+    -- 服务商的邮件 id，便于对照服务商后台
+    message_id text,
+    suppressed_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    primary key (email)
+);
+insert into suppression_list_new (email, reason, detail, message_id, suppressed_at)
+select email, reason, detail, message_id, suppressed_at from suppression_list where email is not null;
+drop table suppression_list;
+alter table suppression_list_new rename to suppression_list;
+create index idx_suppression_list_suppressed_at on suppression_list (suppressed_at);
@@ -0,0 +1,20 @@
+-- 删除个人数据（erase）时保留停发：清空邮箱、说明和服务商 id，只留下小写邮箱的 SHA-256，发送之前仍然能查到
+-- SQLite 不能修改主键和 not null，需要重建 suppression_list
+create table suppression_list_new(
+    -- 删除个人数据之后为空
+    email text unique,
+    -- 删除个人数据时写入，之前为空
+    email_hash text unique,
+    reason text not null check (reason in ('bounce', 'complaint')),
+    -- 服务商给出的说明，例如退信原因
+    detail text,
+    -- 服务商的邮件 id，便于对照服务商后台
+    message_id text,
+    suppressed_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    check (email is not null or email_hash is not null)
+);
+insert into suppression_list_new (email, reason, detail, message_id, suppressed_at)
+select email, reason, detail, message_id, suppressed_at from suppression_list;
+drop table suppression_list;
+alter table suppression_list_new rename to suppression_list;
+create index idx_suppression_list_suppressed_at on suppression_list (suppressed_at);
@@ -66,7 +66,7 @@ fi
 
 # 运行数据库迁移
 echo "运行数据库迁移..."
-if sqlx migrate run; then
+if sqlx migrate run --source migrations/postgres; then
     echo "✅ 数据库迁移完成"
 else
     echo "⚠️  数据库迁移失败或没有迁移文件"
@@ -0,0 +1,229 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpResponse};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use crate::database::DatabasePool;
+use crate::repository::{RepositoryError, UserRepository};
+use crate::routes::repository_error_response;
+use once_cell::sync::Lazy;
+use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
+
+/// 用 Argon2id 计算密码哈希，返回 PHC 字符串（包含算法参数和盐）
+pub fn compute_password_hash(password: &Secret<String>) -> Result<Secret<String>, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let params = Params::new(15000, 2, 1, None).map_err(|e| e.to_string())?;
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)
+        .map_err(|e| e.to_string())?
+        .to_string();
+    Ok(Secret::new(password_hash))
+}
+
+/// 校验密码是否与 PHC 字符串匹配，参数从哈希本身读取
+pub fn verify_password_hash(expected_password_hash: &Secret<String>, password: &Secret<String>) -> Result<(), String> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .map_err(|e| e.to_string())?;
+    Argon2::default()
+        .verify_password(password.expose_secret().as_bytes(), &expected_password_hash)
+        .map_err(|_| "Invalid password".to_string())
+}
+
+/// 创建管理员；用户名已存在时更新其密码
+#[tracing::instrument(name = "Creating an admin user", skip(pool, password))]
+pub async fn create_admin_user(pool: &DatabasePool, username: &str, password: Secret<String>) -> Result<(), String> {
+    if username.trim().is_empty() {
+        return Err("Username must not be empty".to_string());
+    }
+    let password_hash = compute_password_hash(&password)?;
+    //两种方言都支持 ON CONFLICT ... DO UPDATE
+    const UPSERT: &str = "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)
+         ON CONFLICT (username) DO UPDATE SET password_hash = EXCLUDED.password_hash";
+    let result = match pool {
+        DatabasePool::Postgres(pool) => sqlx::query(UPSERT)
+            .bind(Uuid::new_v4())
+            .bind(username)
+            .bind(password_hash.expose_secret())
+            .execute(pool)
+            .await
+            .map(|_| ()),
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => sqlx::query(UPSERT)
+            .bind(Uuid::new_v4())
+            .bind(username)
+            .bind(password_hash.expose_secret())
+            .execute(pool)
+            .await
+            .map(|_| ()),
+    };
+    result.map_err(|e| {
+        tracing::error!("Failed to store admin user: {}", e);
+        e.to_string()
+    })
+}
+
+/// 通过认证的管理员，认证中间件放入请求的 extensions，处理器用 `web::ReqData<AdminUser>` 读取
+#[derive(Debug, Clone)]
+pub struct AdminUser {
+    pub user_id: Uuid,
+    pub username: String,
+}
+
+#[derive(Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    //缺少或无法解析 Authorization 头
+    MissingCredentials(String),
+    InvalidCredentials,
+    Repository(RepositoryError),
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::MissingCredentials(e) => write!(f, "{}", e),
+            AuthError::InvalidCredentials => write!(f, "Invalid username or password"),
+            AuthError::Repository(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// 解析 `Authorization: Basic base64(username:password)`
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, AuthError> {
+    let missing = |problem: &str| AuthError::MissingCredentials(problem.to_string());
+    let header = headers.get(AUTHORIZATION)
+        .ok_or_else(|| missing("The Authorization header is missing"))?
+        .to_str()
+        .map_err(|_| missing("The Authorization header is not a valid UTF-8 string"))?;
+    let encoded = header.strip_prefix("Basic ").ok_or_else(|| missing("The authorization scheme is not Basic"))?;
+    let decoded = STANDARD.decode(encoded).map_err(|_| missing("Failed to base64-decode Basic credentials"))?;
+    let decoded = String::from_utf8(decoded).map_err(|_| missing("Basic credentials are not valid UTF-8"))?;
+    let (username, password) = decoded.split_once(':').ok_or_else(|| missing("Basic credentials must be username:password"))?;
+    Ok(Credentials { username: username.to_string(), password: Secret::new(password.to_string()) })
+}
+
+//用户不存在时也校验一次哈希，让响应时间不泄露用户名是否存在
+static DUMMY_PASSWORD_HASH: Lazy<Secret<String>> = Lazy::new(|| {
+    compute_password_hash(&Secret::new(Uuid::new_v4().to_string())).expect("Failed to hash the dummy password")
+});
+
+/// 校验用户名和密码，成功时返回 user_id
+#[tracing::instrument(name = "Validating credentials", skip(repository, credentials), fields(username = %credentials.username))]
+pub async fn validate_credentials(repository: &dyn UserRepository, credentials: Credentials) -> Result<Uuid, AuthError> {
+    let stored = repository.find_credentials(&credentials.username).await.map_err(AuthError::Repository)?;
+    let (user_id, expected_password_hash) = match stored {
+        Some(stored) => (Some(stored.user_id), stored.password_hash),
+        None => (None, DUMMY_PASSWORD_HASH.clone()),
+    };
+    //Argon2 是 CPU 密集型计算，不能阻塞 async 运行时
+    let verified = tokio::task::spawn_blocking(move || verify_password_hash(&expected_password_hash, &credentials.password))
+        .await
+        .map_err(|e| AuthError::Repository(RepositoryError::Database(e.to_string())))?;
+    match (user_id, verified) {
+        (Some(user_id), Ok(())) => Ok(user_id),
+        _ => Err(AuthError::InvalidCredentials),
+    }
+}
+
+/// `/admin` 下所有路由的认证中间件（HTTP Basic，账号由 `webserver create-admin` 创建）
+pub async fn require_admin<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let repository = req.app_data::<web::Data<dyn UserRepository>>()
+        .cloned()
+        .expect("UserRepository is not registered");
+    let result = match basic_authentication(req.headers()) {
+        Ok(credentials) => {
+            let username = credentials.username.clone();
+            validate_credentials(repository.get_ref(), credentials).await
+                .map(|user_id| AdminUser { user_id, username })
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(admin) => {
+            req.extensions_mut().insert(admin);
+            next.call(req).await.map(ServiceResponse::map_into_left_body)
+        }
+        Err(AuthError::Repository(e)) => Ok(req.into_response(repository_error_response(&e)).map_into_right_body()),
+        Err(e) => {
+            tracing::warn!("Rejected admin request: {}", e);
+            let response = HttpResponse::Unauthorized()
+                .insert_header((WWW_AUTHENTICATE, HeaderValue::from_static(r#"Basic realm="admin""#)))
+                .finish();
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::InMemoryUserRepository;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn password_hash_is_argon2id_and_verifies() {
+        let password = Secret::new("correct horse battery staple".to_string());
+        let hash = compute_password_hash(&password).unwrap();
+        assert!(hash.expose_secret().starts_with("$argon2id$"));
+        assert_ok!(verify_password_hash(&hash, &password));
+    }
+
+    #[test]
+    fn wrong_password_is_rejected() {
+        let hash = compute_password_hash(&Secret::new("correct horse battery staple".to_string())).unwrap();
+        assert_err!(verify_password_hash(&hash, &Secret::new("Tr0ub4dor&3".to_string())));
+    }
+
+    #[test]
+    fn basic_credentials_are_parsed() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic YWRtaW46cGE6c3M="));
+        let credentials = basic_authentication(&headers).unwrap();
+        assert_eq!(credentials.username, "admin");
+        assert_eq!(credentials.password.expose_secret(), "pa:ss");
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer token"));
+        assert_err!(basic_authentication(&headers));
+        assert_err!(basic_authentication(&HeaderMap::new()));
+    }
+
+    #[tokio::test]
+    async fn credentials_are_checked_against_stored_hashes() {
+        let repository = InMemoryUserRepository::new();
+        let user_id = repository.add_user("admin", "correct horse battery staple");
+        let credentials = |username: &str, password: &str| Credentials {
+            username: username.to_string(),
+            password: Secret::new(password.to_string()),
+        };
+        assert_eq!(validate_credentials(&repository, credentials("admin", "correct horse battery staple")).await.unwrap(), user_id);
+        assert!(matches!(
+            validate_credentials(&repository, credentials("admin", "Tr0ub4dor&3")).await,
+            Err(AuthError::InvalidCredentials)
+        ));
+        assert!(matches!(
+            validate_credentials(&repository, credentials("root", "correct horse battery staple")).await,
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+
+    #[test]
+    fn hashes_are_salted() {
+        let password = Secret::new("correct horse battery staple".to_string());
+        let first = compute_password_hash(&password).unwrap();
+        let second = compute_password_hash(&password).unwrap();
+        assert_ne!(first.expose_secret(), second.expose_secret());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::authentication::create_admin_user;
+use crate::configuration::Settings;
+use crate::domain::{EmailClient, SubscriberEmail};
+use crate::migration::{migration_status, revert_last_migration, run_migrations};
+use crate::startup::{connect_with_retry, Application};
+use clap::{Parser, Subcommand};
+use secrecy::Secret;
+
+/// webserver 的命令行入口，日常运维不需要 psql 或 curl
+#[derive(Parser, Debug)]
+#[command(name = "webserver", version, about)]
+pub struct Cli {
+    //不带子命令时等价于 serve
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 启动 HTTP 服务器（默认）
+    Serve,
+    /// 管理数据库迁移
+    Migrate {
+        #[command(subcommand)]
+        action: Option<MigrateAction>,
+    },
+    /// 创建管理员（已存在时重置密码），密码从终端读取
+    CreateAdmin {
+        #[arg(long, default_value = "admin")]
+        username: String,
+    },
+    /// 用配置中的邮件客户端发送一封测试邮件
+    SendTestEmail {
+        address: String,
+    },
+    /// 打印解析后的配置（secret 已隐藏）
+    CheckConfig,
+}
+
+#[derive(Subcommand, Debug, Clone, Copy)]
+pub enum MigrateAction {
+    /// 应用所有待执行的迁移（默认）
+    Up,
+    /// 回滚最近一次迁移
+    Down,
+    /// 列出每个迁移的状态
+    Status,
+}
+
+impl Command {
+    /// 除 serve 以外的命令都是一次性的，日志输出到 stderr，不干扰命令输出
+    pub fn is_one_shot(&self) -> bool {
+        !matches!(self, Command::Serve)
+    }
+}
+
+pub async fn execute(command: Command, settings: Settings) -> Result<(), String> {
+    match command {
+        Command::Serve => {
+            let application = Application::build(settings).await.map_err(|e| format!("Failed to start: {}", e))?;
+            application.run_until_stopped().await.map_err(|e| e.to_string())
+        }
+        Command::Migrate { action } => migrate(action.unwrap_or(MigrateAction::Up), &settings).await,
+        Command::CreateAdmin { username } => create_admin(&username, &settings).await,
+        Command::SendTestEmail { address } => send_test_email(address, settings).await,
+        Command::CheckConfig => {
+            let resolved = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
+            println!("{}", resolved);
+            Ok(())
+        }
+    }
+}
+
+async fn migrate(action: MigrateAction, settings: &Settings) -> Result<(), String> {
+    let db_pool = connect_with_retry(&settings.database).await.map_err(|e| e.to_string())?;
+    match action {
+        MigrateAction::Up => {
+            let applied = run_migrations(&db_pool).await.map_err(|e| e.to_string())?;
+            if applied.is_empty() {
+                println!("Database schema is up to date");
+            }
+            for version in applied {
+                println!("Applied migration {}", version);
+            }
+        }
+        MigrateAction::Down => match revert_last_migration(&db_pool).await.map_err(|e| e.to_string())? {
+            Some(version) => println!("Reverted migration {}", version),
+            None => println!("No migrations to revert"),
+        },
+        MigrateAction::Status => {
+            for migration in migration_status(&db_pool).await.map_err(|e| e.to_string())? {
+                let state = if migration.applied { "applied" } else { "pending" };
+                println!("{:<16} {:<8} {}", migration.version, state, migration.description);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn create_admin(username: &str, settings: &Settings) -> Result<(), String> {
+    let password = rpassword::prompt_password(format!("Password for {}: ", username)).map_err(|e| e.to_string())?;
+    let confirmation = rpassword::prompt_password("Repeat password: ").map_err(|e| e.to_string())?;
+    if password != confirmation {
+        return Err("Passwords do not match".to_string());
+    }
+    if password.len() < 12 {
+        return Err("Password must be at least 12 characters long".to_string());
+    }
+    let db_pool = connect_with_retry(&settings.database).await.map_err(|e| e.to_string())?;
+    create_admin_user(&db_pool, username, Secret::new(password)).await?;
+    println!("Admin user {} saved", username);
+    Ok(())
+}
+
+async fn send_test_email(address: String, settings: Settings) -> Result<(), String> {
+    let recipient = SubscriberEmail::parse(address)?;
+    let sender = settings.email_client.sender()?;
+    //测试邮件同样遵守停发名单
+    let db_pool = connect_with_retry(&settings.database).await.map_err(|e| e.to_string())?;
+    let timeout = settings.email_client.timeout();
+    let email_client = EmailClient::new(sender, settings.email_client.base_url, settings.email_client.authorization_token, timeout, db_pool.repositories().suppressions);
+    let receipt = email_client.send_email(
+        recipient,
+        "Test email",
+        "<p>This is a test email sent by <code>webserver send-test-email</code>.</p>",
+        "This is a test email sent by webserver send-test-email.",
+    ).await?;
+    match receipt.message_id {
+        Some(message_id) => println!("Test email sent, message id {}", message_id),
+        None => println!("Test email sent"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_subcommand_means_serve() {
+        let cli = Cli::try_parse_from(["webserver"]).unwrap();
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn subcommands_are_parsed() {
+        let cli = Cli::try_parse_from(["webserver", "migrate", "status"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Migrate { action: Some(MigrateAction::Status) })));
+
+        let cli = Cli::try_parse_from(["webserver", "send-test-email", "ursula@example.com"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::SendTestEmail { address }) if address == "ursula@example.com"));
+
+        let cli = Cli::try_parse_from(["webserver", "create-admin"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::CreateAdmin { username }) if username == "admin"));
+    }
+
+    #[test]
+    fn send_test_email_requires_an_address() {
+        assert!(Cli::try_parse_from(["webserver", "send-test-email"]).is_err());
+    }
+}
@@ -0,0 +1,373 @@
+use crate::configuration::{AppEnvironment, DatabaseBackend, Settings};
+use crate::domain::SubscriberEmail;
+use crate::email_templates::EmailTemplates;
+use secrecy::ExposeSecret;
+use sqlx::postgres::PgSslMode;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use url::Url;
+
+/// 一条配置问题：哪个键、来自哪个配置源、哪里不对
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    pub key: String,
+    pub source: String,
+    pub problem: String,
+}
+
+/// 启动时收集到的全部配置问题，一次性报告而不是遇到第一个就退出
+#[derive(Debug)]
+pub struct ConfigurationError {
+    pub issues: Vec<ConfigIssue>,
+}
+
+impl ConfigurationError {
+    pub fn new(issues: Vec<ConfigIssue>) -> Self {
+        Self { issues }
+    }
+
+    pub fn single(key: &str, source: &str, problem: impl ToString) -> Self {
+        Self::new(vec![ConfigIssue { key: key.into(), source: source.into(), problem: problem.to_string() }])
+    }
+}
+
+impl std::fmt::Display for ConfigurationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Invalid configuration ({} problem(s)):", self.issues.len())?;
+        for issue in &self.issues {
+            writeln!(f, "  - {} (from {}): {}", issue.key, issue.source, issue.problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigurationError {}
+
+//生产环境中 application.hmac_secret 的最短长度
+const MIN_HMAC_SECRET_BYTES: usize = 32;
+
+//DATABASE_URL 会覆盖的键
+const DATABASE_URL_KEYS: [&str; 8] = [
+    "database.username", "database.password", "database.host", "database.port",
+    "database.database_name", "database.require_ssl", "database.ssl_mode", "database.options",
+];
+
+/// 记录每个配置键的来源，按优先级从高到低依次查找：
+/// 环境变量 > `*_FILE` > DATABASE_URL > 配置文件 > 默认值
+pub struct ConfigSources {
+    //优先级从高到低
+    files: Vec<(String, Option<config::Config>)>,
+    database_url: Option<String>,
+}
+
+impl ConfigSources {
+    pub fn new(files: &[PathBuf], database_url: Option<String>) -> Self {
+        let files = files.iter().map(|path| {
+            let layer = config::Config::builder()
+                .add_source(config::File::from(path.clone()).required(false))
+                .build()
+                .ok();
+            (path.display().to_string(), layer)
+        }).collect();
+        Self { files, database_url }
+    }
+
+    pub fn source_of(&self, key: &str) -> String {
+        let env_key = format!("APP_{}", key.to_uppercase().replace('.', "__"));
+        if std::env::var(&env_key).is_ok() {
+            return format!("environment variable {}", env_key);
+        }
+        if std::env::var(format!("{}_FILE", env_key)).is_ok() {
+            return format!("environment variable {}_FILE", env_key);
+        }
+        if let Some(database_url) = &self.database_url {
+            if DATABASE_URL_KEYS.iter().any(|k| key == *k || key.starts_with(&format!("{}.", k))) {
+                return format!("environment variable {}", database_url);
+            }
+        }
+        for (name, layer) in &self.files {
+            if let Some(layer) = layer {
+                if layer.get::<config::Value>(key).is_ok() {
+                    return name.clone();
+                }
+            }
+        }
+        "default value".to_string()
+    }
+
+    pub fn issue(&self, key: &str, problem: impl ToString) -> ConfigIssue {
+        ConfigIssue { key: key.into(), source: self.source_of(key), problem: problem.to_string() }
+    }
+}
+
+impl Settings {
+    /// 检查反序列化之后才能发现的问题（URL 格式、发件人、生产环境的安全要求等）
+    pub fn validate(&self, environment: &AppEnvironment, sources: &ConfigSources) -> Result<(), ConfigurationError> {
+        let production = matches!(environment, AppEnvironment::Production);
+        let mut issues = Vec::new();
+
+        if self.application.host.trim().is_empty() {
+            issues.push(sources.issue("application.host", "must not be empty"));
+        }
+        if production && self.application.port == 0 {
+            issues.push(sources.issue("application.port", "port 0 picks a random port and is not allowed in production"));
+        }
+
+        check_http_url("application.base_url", &self.application.base_url, sources, &mut issues);
+        //默认值指向本机，邮件里的退订、确认链接在订阅者那里打不开
+        if !matches!(environment, AppEnvironment::Local) && is_loopback_url(&self.application.base_url) {
+            issues.push(sources.issue("application.base_url", "must be the public address of the service outside the local environment"));
+        }
+        let hmac_secret = self.application.hmac_secret.expose_secret();
+        if hmac_secret.is_empty() {
+            issues.push(sources.issue("application.hmac_secret", "secret is missing"));
+        } else if production && hmac_secret.len() < MIN_HMAC_SECRET_BYTES {
+            issues.push(sources.issue("application.hmac_secret", format!("must be at least {} bytes in production", MIN_HMAC_SECRET_BYTES)));
+        }
+        if self.application.scheduler_interval_seconds == 0 {
+            issues.push(sources.issue("application.scheduler_interval_seconds", "must be at least 1"));
+        }
+        check_http_url("email_client.base_url", &self.email_client.base_url, sources, &mut issues);
+        if let Err(e) = SubscriberEmail::parse(self.email_client.sender_email.clone()) {
+            issues.push(sources.issue("email_client.sender_email", format!("{:?}: {}", self.email_client.sender_email, e)));
+        }
+        if self.email_client.timeout_milliseconds == 0 {
+            issues.push(sources.issue("email_client.timeout_milliseconds", "must be at least 1"));
+        }
+        if self.email_client.authorization_token.expose_secret().is_empty() {
+            issues.push(sources.issue("email_client.authorization_token", "secret is missing"));
+        }
+        if self.email_client.webhook_username.trim().is_empty() {
+            issues.push(sources.issue("email_client.webhook_username", "must not be empty"));
+        }
+        if self.email_client.webhook_password.expose_secret().is_empty() {
+            issues.push(sources.issue("email_client.webhook_password", "secret is missing"));
+        }
+        if let Some(directory) = &self.email_client.templates_dir {
+            let path = Path::new(directory);
+            if !path.is_dir() {
+                issues.push(sources.issue("email_client.templates_dir", format!("{:?} is not a directory", directory)));
+            } else if let Err(e) = EmailTemplates::load(Some(path)) {
+                issues.push(sources.issue("email_client.templates_dir", e));
+            }
+        }
+
+        match self.database.backend {
+            DatabaseBackend::Postgres => {
+                if self.database.host.expose_secret().trim().is_empty() {
+                    issues.push(sources.issue("database.host", "must not be empty"));
+                }
+                if production && self.database.password.expose_secret().is_empty() {
+                    issues.push(sources.issue("database.password", "secret is missing"));
+                }
+                let ssl_mode = match self.database.ssl_mode.as_deref().map(PgSslMode::from_str) {
+                    Some(Ok(mode)) => Some(mode),
+                    Some(Err(_)) => {
+                        issues.push(sources.issue("database.ssl_mode", format!("{:?} is not a valid sslmode", self.database.ssl_mode.as_deref().unwrap_or_default())));
+                        None
+                    }
+                    None => None,
+                };
+                let ssl_required = match ssl_mode {
+                    Some(mode) => matches!(mode, PgSslMode::Require | PgSslMode::VerifyCa | PgSslMode::VerifyFull),
+                    None => self.database.require_ssl,
+                };
+                if production && !ssl_required {
+                    issues.push(sources.issue("database.require_ssl", "SSL must be required in production"));
+                }
+            }
+            DatabaseBackend::Sqlite => {
+                if !cfg!(feature = "sqlite") {
+                    issues.push(sources.issue("database.backend", "this binary was built without SQLite support, rebuild with --features sqlite"));
+                }
+                if self.database.sqlite_path.trim().is_empty() {
+                    issues.push(sources.issue("database.sqlite_path", "must not be empty"));
+                }
+            }
+        }
+        if self.database.pool.max_connections == 0 {
+            issues.push(sources.issue("database.pool.max_connections", "must be at least 1"));
+        }
+        if self.database.pool.min_connections > self.database.pool.max_connections {
+            issues.push(sources.issue("database.pool.min_connections", format!(
+                "{} is greater than max_connections ({})", self.database.pool.min_connections, self.database.pool.max_connections
+            )));
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigurationError::new(issues))
+        }
+    }
+}
+
+fn check_http_url(key: &str, value: &str, sources: &ConfigSources, issues: &mut Vec<ConfigIssue>) {
+    match Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+        Ok(url) => issues.push(sources.issue(key, format!("scheme {} is not supported, expected http or https", url.scheme()))),
+        Err(e) => issues.push(sources.issue(key, format!("{:?} is not a valid URL: {}", value, e))),
+    }
+}
+
+fn is_loopback_url(value: &str) -> bool {
+    match Url::parse(value).ok().as_ref().and_then(Url::host) {
+        Some(url::Host::Domain(domain)) => domain.eq_ignore_ascii_case("localhost"),
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback() || ip.is_unspecified(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback() || ip.is_unspecified(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::{ApplicationSettings, DatabaseSettings, EmailClientSettings, PoolSettings};
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use std::collections::HashMap;
+
+    fn settings() -> Settings {
+        Settings {
+            database: DatabaseSettings {
+                backend: DatabaseBackend::Postgres,
+                sqlite_path: "newsletter.db".into(),
+                username: "postgres".into(),
+                password: Secret::new("123456".into()),
+                port: 5432,
+                host: Secret::new("127.0.0.1".into()),
+                database_name: Secret::new("newsletter".into()),
+                require_ssl: true,
+                ssl_mode: None,
+                options: HashMap::new(),
+                pool: PoolSettings::default(),
+                auto_migrate: true,
+            },
+            application: ApplicationSettings {
+                port: 8080,
+                host: "0.0.0.0".into(),
+                drain_timeout_seconds: 30,
+                readiness_delay_seconds: 5,
+                base_url: "https://newsletter.example.com".into(),
+                hmac_secret: Secret::new("a-production-secret-that-is-long-enough".into()),
+                scheduler_interval_seconds: 30,
+            },
+            email_client: EmailClientSettings {
+                base_url: "https://api.postmarkapp.com".into(),
+                sender_email: "newsletter@example.com".into(),
+                authorization_token: Secret::new("token".into()),
+                webhook_username: "postmark".into(),
+                webhook_password: Secret::new("webhook-password".into()),
+                templates_dir: None,
+                timeout_milliseconds: 10_000,
+            },
+        }
+    }
+
+    fn sources() -> ConfigSources {
+        ConfigSources::new(&[], None)
+    }
+
+    #[test]
+    fn valid_production_settings_pass() {
+        assert_ok!(settings().validate(&AppEnvironment::Production, &sources()));
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let mut settings = settings();
+        settings.application.port = 0;
+        settings.application.base_url = "ftp://newsletter.example.com".into();
+        settings.application.hmac_secret = Secret::new("short".into());
+        settings.email_client.base_url = "not a url".into();
+        settings.email_client.sender_email = "newsletter".into();
+        settings.email_client.authorization_token = Secret::new(String::new());
+        settings.email_client.webhook_username = String::new();
+        settings.email_client.webhook_password = Secret::new(String::new());
+        settings.email_client.templates_dir = Some("/nonexistent/templates".into());
+        settings.database.require_ssl = false;
+
+        let error = settings.validate(&AppEnvironment::Production, &sources()).unwrap_err();
+        let keys: Vec<&str> = error.issues.iter().map(|issue| issue.key.as_str()).collect();
+        assert_eq!(keys, vec![
+            "application.port",
+            "application.base_url",
+            "application.hmac_secret",
+            "email_client.base_url",
+            "email_client.sender_email",
+            "email_client.authorization_token",
+            "email_client.webhook_username",
+            "email_client.webhook_password",
+            "email_client.templates_dir",
+            "database.require_ssl",
+        ]);
+    }
+
+    #[test]
+    fn production_only_rules_do_not_apply_locally() {
+        let mut settings = settings();
+        settings.application.port = 0;
+        settings.application.hmac_secret = Secret::new("short".into());
+        settings.database.require_ssl = false;
+        assert_ok!(settings.validate(&AppEnvironment::Local, &sources()));
+    }
+
+    #[test]
+    fn base_url_must_be_public_outside_the_local_environment() {
+        let mut settings = settings();
+        for base_url in ["http://127.0.0.1:8080", "http://localhost:8080", "http://[::1]:8080"] {
+            settings.application.base_url = base_url.into();
+            for environment in [AppEnvironment::Production, AppEnvironment::Other("staging".into())] {
+                let error = settings.validate(&environment, &sources()).unwrap_err();
+                assert_eq!(error.issues[0].key, "application.base_url");
+            }
+            assert_ok!(settings.validate(&AppEnvironment::Local, &sources()));
+        }
+    }
+
+    #[test]
+    fn pool_bounds_are_checked() {
+        let mut settings = settings();
+        settings.database.pool.max_connections = 2;
+        settings.database.pool.min_connections = 5;
+        let error = settings.validate(&AppEnvironment::Local, &sources()).unwrap_err();
+        assert_eq!(error.issues[0].key, "database.pool.min_connections");
+    }
+
+    #[test]
+    fn postgres_rules_do_not_apply_to_sqlite() {
+        let mut settings = settings();
+        settings.database.backend = DatabaseBackend::Sqlite;
+        settings.database.require_ssl = false;
+        settings.database.password = Secret::new(String::new());
+        let result = settings.validate(&AppEnvironment::Production, &sources());
+        if cfg!(feature = "sqlite") {
+            assert_ok!(result);
+        } else {
+            let error = result.unwrap_err();
+            let keys: Vec<&str> = error.issues.iter().map(|issue| issue.key.as_str()).collect();
+            assert_eq!(keys, vec!["database.backend"]);
+        }
+    }
+
+    #[test]
+    fn ssl_mode_takes_precedence_over_require_ssl() {
+        let mut settings = settings();
+        settings.database.ssl_mode = Some("prefer".into());
+        assert_err!(settings.validate(&AppEnvironment::Production, &sources()));
+    }
+
+    #[test]
+    fn issues_without_a_configured_source_report_the_default() {
+        assert_eq!(sources().source_of("application.port"), "default value");
+    }
+
+    #[test]
+    fn report_lists_key_source_and_problem() {
+        let error = ConfigurationError::single("email_client.sender_email", "configuration/base.yaml", "Email is not valid");
+        assert_eq!(
+            error.to_string(),
+            "Invalid configuration (1 problem(s)):\n  - email_client.sender_email (from configuration/base.yaml): Email is not valid\n"
+        );
+    }
+}
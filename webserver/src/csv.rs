@@ -0,0 +1,294 @@
+//! CSV 读写（RFC 4180），导入和导出共用
+//!
+//! 解析是增量的：按块喂入字节，逐条产出记录，不需要把整个文件读进内存
+
+/// 单条记录的字节上限；引号不配对时后面的内容都会落进同一个字段，超过上限就停止解析
+pub const MAX_RECORD_BYTES: usize = 64 * 1024;
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvRecord {
+    //记录开始的物理行号，从 1 开始，带换行的字段不影响后续行号
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CsvError {
+    InvalidUtf8 { line: usize },
+    UnterminatedQuote { line: usize },
+    RecordTooLong { line: usize },
+}
+
+impl CsvError {
+    pub fn line(&self) -> usize {
+        match self {
+            CsvError::InvalidUtf8 { line } | CsvError::UnterminatedQuote { line } | CsvError::RecordTooLong { line } => *line,
+        }
+    }
+
+    /// 致命错误之后找不到下一条记录的开头，解析器不再产出记录
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, CsvError::RecordTooLong { .. })
+    }
+}
+
+impl std::fmt::Display for CsvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CsvError::InvalidUtf8 { .. } => write!(f, "Line is not valid UTF-8"),
+            CsvError::UnterminatedQuote { .. } => write!(f, "Quoted field is not terminated"),
+            CsvError::RecordTooLong { .. } => {
+                write!(f, "Record is longer than {} bytes, check for an unbalanced quote", MAX_RECORD_BYTES)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    FieldStart,
+    Unquoted,
+    Quoted,
+    //引号字段中遇到 "：后面再跟一个 " 是转义，否则字段结束
+    QuoteInQuoted,
+    Failed,
+}
+
+pub struct CsvParser {
+    delimiter: u8,
+    state: State,
+    field: Vec<u8>,
+    fields: Vec<Vec<u8>>,
+    record_bytes: usize,
+    //当前所在的物理行，以及当前记录开始的行
+    line: usize,
+    record_line: usize,
+    //上一个字节是结束记录的 \r，紧跟的 \n 属于同一个换行
+    after_cr: bool,
+    //文件开头可能有 UTF-8 BOM，第一块可能不足 3 个字节，凑够之后才能判断
+    head: Option<Vec<u8>>,
+}
+
+impl CsvParser {
+    pub fn new(delimiter: u8) -> Self {
+        Self {
+            delimiter,
+            state: State::FieldStart,
+            field: Vec::new(),
+            fields: Vec::new(),
+            record_bytes: 0,
+            line: 1,
+            record_line: 1,
+            after_cr: false,
+            head: Some(Vec::new()),
+        }
+    }
+
+    /// 解析一块输入，返回其中完整的记录；跨块的记录留到下一次
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<Result<CsvRecord, CsvError>> {
+        let mut records = Vec::new();
+        match self.head.take() {
+            Some(mut head) => {
+                head.extend_from_slice(chunk);
+                if head.len() < BOM.len() && BOM.starts_with(&head) {
+                    self.head = Some(head);
+                    return records;
+                }
+                let start = if head.starts_with(BOM) { BOM.len() } else { 0 };
+                self.consume(&head[start..], &mut records);
+            }
+            None => self.consume(chunk, &mut records),
+        }
+        records
+    }
+
+    /// 输入结束，返回最后一条没有以换行结尾的记录
+    pub fn finish(mut self) -> Option<Result<CsvRecord, CsvError>> {
+        if let Some(head) = self.head.take() {
+            //不足 3 个字节的 BOM 前缀，不含换行，不会产出记录
+            self.consume(&head, &mut Vec::new());
+        }
+        match self.state {
+            State::Failed => None,
+            State::Quoted => Some(Err(CsvError::UnterminatedQuote { line: self.record_line })),
+            State::FieldStart if self.fields.is_empty() => None,
+            _ => Some(self.end_record()),
+        }
+    }
+
+    fn consume(&mut self, bytes: &[u8], records: &mut Vec<Result<CsvRecord, CsvError>>) {
+        for &byte in bytes {
+            let after_cr = std::mem::replace(&mut self.after_cr, false);
+            match self.state {
+                State::Failed => return,
+                State::Quoted => {
+                    if byte == b'"' {
+                        self.state = State::QuoteInQuoted;
+                    } else {
+                        if byte == b'\n' {
+                            self.line += 1;
+                        }
+                        self.field.push(byte);
+                    }
+                }
+                State::QuoteInQuoted if byte == b'"' => {
+                    self.field.push(b'"');
+                    self.state = State::Quoted;
+                }
+                _ => match byte {
+                    b'"' if self.state == State::FieldStart => self.state = State::Quoted,
+                    b'\n' if after_cr => {}
+                    b'\r' | b'\n' => {
+                        self.line += 1;
+                        self.after_cr = byte == b'\r';
+                        if self.state == State::FieldStart && self.fields.is_empty() {
+                            //空行
+                            self.record_line = self.line;
+                            self.record_bytes = 0;
+                        } else {
+                            records.push(self.end_record());
+                        }
+                        continue;
+                    }
+                    _ if byte == self.delimiter => {
+                        self.fields.push(std::mem::take(&mut self.field));
+                        self.state = State::FieldStart;
+                    }
+                    //不在字段开头的引号、闭合引号之后的字符都按字面保留
+                    _ => {
+                        self.field.push(byte);
+                        self.state = State::Unquoted;
+                    }
+                },
+            }
+            self.record_bytes += 1;
+            if self.record_bytes > MAX_RECORD_BYTES {
+                self.state = State::Failed;
+                records.push(Err(CsvError::RecordTooLong { line: self.record_line }));
+                return;
+            }
+        }
+    }
+
+    fn end_record(&mut self) -> Result<CsvRecord, CsvError> {
+        let mut fields = std::mem::take(&mut self.fields);
+        fields.push(std::mem::take(&mut self.field));
+        let line = self.record_line;
+        self.record_line = self.line;
+        self.record_bytes = 0;
+        self.state = State::FieldStart;
+        fields.into_iter()
+            .map(String::from_utf8)
+            .collect::<Result<Vec<_>, _>>()
+            .map(|fields| CsvRecord { line, fields })
+            .map_err(|_| CsvError::InvalidUtf8 { line })
+    }
+}
+
+/// 写出一条以 \r\n 结尾的记录；含分隔符、引号或换行的字段加引号，字段中的引号写成两个
+pub fn write_record<'a>(out: &mut Vec<u8>, fields: impl IntoIterator<Item = &'a str>, delimiter: u8) {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            out.push(delimiter);
+        }
+        let needs_quotes = field.bytes().any(|byte| matches!(byte, b'"' | b'\r' | b'\n') || byte == delimiter);
+        if needs_quotes {
+            out.push(b'"');
+            out.extend_from_slice(field.replace('"', "\"\"").as_bytes());
+            out.push(b'"');
+        } else {
+            out.extend_from_slice(field.as_bytes());
+        }
+    }
+    out.extend_from_slice(b"\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_in_chunks(input: &[u8], chunk_size: usize) -> Vec<Result<CsvRecord, CsvError>> {
+        let mut parser = CsvParser::new(b',');
+        let mut records = Vec::new();
+        for chunk in input.chunks(chunk_size) {
+            records.extend(parser.feed(chunk));
+        }
+        records.extend(parser.finish());
+        records
+    }
+
+    fn record(line: usize, fields: &[&str]) -> Result<CsvRecord, CsvError> {
+        Ok(CsvRecord { line, fields: fields.iter().map(|field| field.to_string()).collect() })
+    }
+
+    #[test]
+    fn chunk_boundaries_do_not_change_the_result() {
+        let input = "\u{feff}name,email\r\n\"Le Guin, Ursula\",ursula@example.com\r\n\"Say \"\"hi\"\"\",张三@example.com".as_bytes();
+        let expected = vec![
+            record(1, &["name", "email"]),
+            record(2, &["Le Guin, Ursula", "ursula@example.com"]),
+            record(3, &["Say \"hi\"", "张三@example.com"]),
+        ];
+        for chunk_size in [1, 2, 3, 7, input.len()] {
+            assert_eq!(parse_in_chunks(input, chunk_size), expected, "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn line_numbers_count_newlines_inside_quotes_and_blank_lines() {
+        let input = b"name,email\n\n\"Ursula\nLe Guin\",ursula@example.com\nIain,iain@example.com\n";
+        assert_eq!(
+            parse_in_chunks(input, 4),
+            vec![
+                record(1, &["name", "email"]),
+                record(3, &["Ursula\nLe Guin", "ursula@example.com"]),
+                record(5, &["Iain", "iain@example.com"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn broken_records_are_reported_without_stopping_the_parser() {
+        let input = b"name,email\n\xff\xfe,bad@example.com\nIain,iain@example.com\n\"Ursula,ursula@example.com";
+        assert_eq!(
+            parse_in_chunks(input, 5),
+            vec![
+                record(1, &["name", "email"]),
+                Err(CsvError::InvalidUtf8 { line: 2 }),
+                record(3, &["Iain", "iain@example.com"]),
+                Err(CsvError::UnterminatedQuote { line: 4 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn runaway_records_stop_the_parser() {
+        let mut input = b"name,email\n\"".to_vec();
+        input.extend(vec![b'x'; MAX_RECORD_BYTES + 1]);
+        input.extend(b"\nIain,iain@example.com\n");
+        let records = parse_in_chunks(&input, 1024);
+        assert_eq!(records, vec![record(1, &["name", "email"]), Err(CsvError::RecordTooLong { line: 2 })]);
+        assert!(records[1].as_ref().unwrap_err().is_fatal());
+    }
+
+    #[test]
+    fn other_delimiters_are_supported() {
+        let mut parser = CsvParser::new(b';');
+        let records = parser.feed(b"name;email\nUrsula;ursula@example.com\n");
+        assert_eq!(records, vec![record(1, &["name", "email"]), record(2, &["Ursula", "ursula@example.com"])]);
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn written_records_parse_back_to_the_same_fields() {
+        let fields = ["Le Guin, Ursula", "Say \"hi\"", "two\nlines", "plain"];
+        let mut out = Vec::new();
+        write_record(&mut out, fields, b',');
+        assert_eq!(out, b"\"Le Guin, Ursula\",\"Say \"\"hi\"\"\",\"two\nlines\",plain\r\n");
+        assert_eq!(parse_in_chunks(&out, 3), vec![record(1, &fields)]);
+    }
+}
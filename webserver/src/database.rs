@@ -0,0 +1,133 @@
+use crate::configuration::{DatabaseBackend, DatabaseSettings};
+use crate::repository::{
+    DeliveryRepository, NewsletterRepository, PostgresNewsletterRepository, PostgresSubscriberRepository, PostgresUserRepository,
+    PrivacyRepository, Repositories, SubscriberRepository, SuppressionRepository, TrackingRepository, UserRepository,
+};
+use sqlx::PgPool;
+#[cfg(feature = "sqlite")]
+use sqlx::SqlitePool;
+use std::sync::Arc;
+
+/// 按 `database.backend` 创建的连接池
+///
+/// 只有启动、迁移和 CLI 需要区分后端；处理器通过 repository trait 访问数据库
+#[derive(Clone)]
+pub enum DatabasePool {
+    Postgres(PgPool),
+    #[cfg(feature = "sqlite")]
+    Sqlite(SqlitePool),
+}
+
+impl DatabasePool {
+    /// 懒连接：第一次使用时才建立连接
+    pub fn connect_lazy(settings: &DatabaseSettings) -> Result<Self, sqlx::Error> {
+        match settings.backend {
+            DatabaseBackend::Postgres => Ok(DatabasePool::Postgres(
+                settings.pool_options().connect_lazy_with(settings.with_db()),
+            )),
+            #[cfg(feature = "sqlite")]
+            DatabaseBackend::Sqlite => Ok(DatabasePool::Sqlite(
+                settings.pool_options().connect_lazy_with(settings.sqlite_options()),
+            )),
+            #[cfg(not(feature = "sqlite"))]
+            DatabaseBackend::Sqlite => Err(sqlite_not_compiled()),
+        }
+    }
+
+    /// 立即建立连接，连接失败时返回错误
+    pub async fn connect(settings: &DatabaseSettings) -> Result<Self, sqlx::Error> {
+        match settings.backend {
+            DatabaseBackend::Postgres => Ok(DatabasePool::Postgres(
+                settings.pool_options().connect_with(settings.with_db()).await?,
+            )),
+            #[cfg(feature = "sqlite")]
+            DatabaseBackend::Sqlite => Ok(DatabasePool::Sqlite(
+                settings.pool_options().connect_with(settings.sqlite_options()).await?,
+            )),
+            #[cfg(not(feature = "sqlite"))]
+            DatabaseBackend::Sqlite => Err(sqlite_not_compiled()),
+        }
+    }
+
+    pub fn backend(&self) -> DatabaseBackend {
+        match self {
+            DatabasePool::Postgres(_) => DatabaseBackend::Postgres,
+            #[cfg(feature = "sqlite")]
+            DatabasePool::Sqlite(_) => DatabaseBackend::Sqlite,
+        }
+    }
+
+    pub fn subscriber_repository(&self) -> Arc<dyn SubscriberRepository> {
+        match self {
+            DatabasePool::Postgres(pool) => Arc::new(PostgresSubscriberRepository::new(pool.clone())),
+            #[cfg(feature = "sqlite")]
+            DatabasePool::Sqlite(pool) => Arc::new(crate::repository::SqliteSubscriberRepository::new(pool.clone())),
+        }
+    }
+
+    pub fn user_repository(&self) -> Arc<dyn UserRepository> {
+        match self {
+            DatabasePool::Postgres(pool) => Arc::new(PostgresUserRepository::new(pool.clone())),
+            #[cfg(feature = "sqlite")]
+            DatabasePool::Sqlite(pool) => Arc::new(crate::repository::SqliteUserRepository::new(pool.clone())),
+        }
+    }
+
+    pub fn privacy_repository(&self) -> Arc<dyn PrivacyRepository> {
+        match self {
+            DatabasePool::Postgres(pool) => Arc::new(PostgresSubscriberRepository::new(pool.clone())),
+            #[cfg(feature = "sqlite")]
+            DatabasePool::Sqlite(pool) => Arc::new(crate::repository::SqliteSubscriberRepository::new(pool.clone())),
+        }
+    }
+
+    pub fn newsletter_repository(&self) -> Arc<dyn NewsletterRepository> {
+        match self {
+            DatabasePool::Postgres(pool) => Arc::new(PostgresNewsletterRepository::new(pool.clone())),
+            #[cfg(feature = "sqlite")]
+            DatabasePool::Sqlite(pool) => Arc::new(crate::repository::SqliteNewsletterRepository::new(pool.clone())),
+        }
+    }
+
+    pub fn tracking_repository(&self) -> Arc<dyn TrackingRepository> {
+        match self {
+            DatabasePool::Postgres(pool) => Arc::new(PostgresSubscriberRepository::new(pool.clone())),
+            #[cfg(feature = "sqlite")]
+            DatabasePool::Sqlite(pool) => Arc::new(crate::repository::SqliteSubscriberRepository::new(pool.clone())),
+        }
+    }
+
+    pub fn suppression_repository(&self) -> Arc<dyn SuppressionRepository> {
+        match self {
+            DatabasePool::Postgres(pool) => Arc::new(PostgresSubscriberRepository::new(pool.clone())),
+            #[cfg(feature = "sqlite")]
+            DatabasePool::Sqlite(pool) => Arc::new(crate::repository::SqliteSubscriberRepository::new(pool.clone())),
+        }
+    }
+
+    pub fn delivery_repository(&self) -> Arc<dyn DeliveryRepository> {
+        match self {
+            DatabasePool::Postgres(pool) => Arc::new(PostgresSubscriberRepository::new(pool.clone())),
+            #[cfg(feature = "sqlite")]
+            DatabasePool::Sqlite(pool) => Arc::new(crate::repository::SqliteSubscriberRepository::new(pool.clone())),
+        }
+    }
+
+    /// 服务器注册的全部仓库，共用这一个连接池
+    pub fn repositories(&self) -> Repositories {
+        Repositories {
+            subscribers: self.subscriber_repository(),
+            users: self.user_repository(),
+            privacy: self.privacy_repository(),
+            newsletters: self.newsletter_repository(),
+            tracking: self.tracking_repository(),
+            suppressions: self.suppression_repository(),
+            deliveries: self.delivery_repository(),
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_not_compiled() -> sqlx::Error {
+    sqlx::Error::Configuration("this binary was built without SQLite support, rebuild with --features sqlite".into())
+}
@@ -0,0 +1,66 @@
+/// 一期通讯发给一个订阅者之后的投递状态，数据库中以 snake_case 文本存储
+///
+/// ```text
+/// Queued ──> Sent ──> Opened ──> Clicked
+///    └──> Failed  └──> Bounced
+/// ```
+///
+/// 打开和点击只在 Sent 之后记录，点击同时算作打开
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Queued,
+    Sent,
+    Failed,
+    Bounced,
+    Opened,
+    Clicked,
+}
+
+impl DeliveryStatus {
+    pub const ALL: [DeliveryStatus; 6] = [
+        DeliveryStatus::Queued,
+        DeliveryStatus::Sent,
+        DeliveryStatus::Failed,
+        DeliveryStatus::Bounced,
+        DeliveryStatus::Opened,
+        DeliveryStatus::Clicked,
+    ];
+
+    pub fn parse(status: &str) -> Result<DeliveryStatus, String> {
+        Self::ALL.into_iter()
+            .find(|candidate| candidate.as_str() == status)
+            .ok_or_else(|| format!("{} is not a valid delivery status", status))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Queued => "queued",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Bounced => "bounced",
+            DeliveryStatus::Opened => "opened",
+            DeliveryStatus::Clicked => "clicked",
+        }
+    }
+}
+
+impl std::fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryStatus;
+    use claim::assert_err;
+
+    #[test]
+    fn statuses_round_trip_through_their_text_form() {
+        for status in DeliveryStatus::ALL {
+            assert_eq!(DeliveryStatus::parse(status.as_str()), Ok(status));
+        }
+        assert_err!(DeliveryStatus::parse("delivered"));
+    }
+}
@@ -1,12 +1,18 @@
 use crate::domain::subscriber_email::SubscriberEmail;
+use crate::email_templates::RenderedEmail;
+use crate::repository::SuppressionRepository;
 use reqwest::Client;
-use secrecy::Secret;
+use secrecy::{ExposeSecret, Secret};
+use std::sync::Arc;
+use std::time::Duration;
 #[derive(Clone)]
 pub struct EmailClient {
     sender: SubscriberEmail,
     client: Client,
     base_url: String,
     authorization_token: Secret<String>,
+    //每次发送之前检查的停发名单，没有不检查的客户端
+    suppressions: Arc<dyn SuppressionRepository>,
 }
 #[derive(serde::Serialize)]
 struct SendEmailRequest<'a> {
@@ -16,43 +22,96 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    //额外的邮件头，例如通讯的 List-Unsubscribe；没有时不出现在请求体中
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<EmailHeader<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct EmailHeader<'a> {
+    name: &'a str,
+    value: String,
+}
+
+/// 服务商接受邮件之后的回执
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SendReceipt {
+    //服务商给这封邮件的 id，退信等事件中的 MessageID 就是它；响应中没有时为空
+    pub message_id: Option<String>,
+}
+
+//服务商的响应体，只关心 MessageID
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
 }
 
 impl EmailClient {
-    pub fn new(sender: SubscriberEmail, base_url: String, authorization_token: Secret<String>) -> Self {
-        Self { sender, client: Client::new(), base_url, authorization_token }
+    /// 停发名单是必填的：每次发送之前都会检查，名单中的地址直接返回错误
+    ///
+    /// `timeout` 是每次请求的超时，超时当作发送失败
+    pub fn new(
+        sender: SubscriberEmail,
+        base_url: String,
+        authorization_token: Secret<String>,
+        timeout: Duration,
+        suppressions: Arc<dyn SuppressionRepository>,
+    ) -> Self {
+        let client = Client::builder().timeout(timeout).build().expect("Failed to build the HTTP client");
+        Self { sender, client, base_url, authorization_token, suppressions }
     }
 }
 
 impl EmailClient {
-    pub async fn send_email(&self, recipient: SubscriberEmail, subject: &str, html_content: &str, text_content: &str) -> Result<(), String> {
+    pub async fn send_email(&self, recipient: SubscriberEmail, subject: &str, html_content: &str, text_content: &str) -> Result<SendReceipt, String> {
+        //as_ref() 用于将 SubscriberEmail 转换为 &str
+        let request = SendEmailRequest::new(self.sender.as_ref(), recipient.as_ref(), subject, html_content, text_content);
+        self.send(&recipient, &request).await
+    }
+
+    async fn send(&self, recipient: &SubscriberEmail, request: &SendEmailRequest<'_>) -> Result<SendReceipt, String> {
+        match self.suppressions.find_suppression(recipient).await {
+            Ok(None) => {}
+            Ok(Some(suppression)) => return Err(format!("{} is on the suppression list ({})", recipient, suppression.reason)),
+            //查不到名单时不冒险发送
+            Err(e) => return Err(e.to_string()),
+        }
         let url = format!("{}/email", self.base_url);
-        //创建请求
-        let request = SendEmailRequest::new(
-          //as_ref() 用于将 SubscriberEmail 转换为 &str
-          self.sender.as_ref(),
-          recipient.as_ref(),
-          subject,
-          html_content,
-          text_content,
-        );
         let buffer = self.client
         .post(url)
-        .json(&request)
+        .json(request)
         .header("Authorization", self.authorization_token.expose_secret())
         .send()
         .await
         .map_err(|e| e.to_string())?;
-        match buffer.error_for_status() {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e.to_string()),
+        let response = buffer.error_for_status().map_err(|e| e.to_string())?;
+        //邮件已经被接受，响应体读不出来或不是 JSON 时只是没有 message id
+        let message_id = response.json::<SendEmailResponse>().await.ok().and_then(|response| response.message_id);
+        Ok(SendReceipt { message_id })
+    }
+}
+
+impl EmailClient {
+    /// 发送用 `EmailTemplates` 渲染好的邮件
+    ///
+    /// 带退订地址的邮件同时发送 `List-Unsubscribe` 和 RFC 8058 的 `List-Unsubscribe-Post`，
+    /// 邮件客户端的退订按钮直接 POST 这个地址
+    pub async fn send_rendered(&self, recipient: SubscriberEmail, email: &RenderedEmail) -> Result<SendReceipt, String> {
+        let mut request = SendEmailRequest::new(self.sender.as_ref(), recipient.as_ref(), &email.subject, &email.html, &email.text);
+        if let Some(url) = &email.list_unsubscribe {
+            request.headers = vec![
+                EmailHeader { name: "List-Unsubscribe", value: format!("<{}>", url) },
+                EmailHeader { name: "List-Unsubscribe-Post", value: "List-Unsubscribe=One-Click".to_string() },
+            ];
         }
+        self.send(&recipient, &request).await
     }
 }
 
 impl<'a> SendEmailRequest<'a> {
     pub fn new(from: &'a str, to: &'a str, subject: &'a str, html_body: &'a str, text_body: &'a str) -> Self {
-        Self { from, to, subject, html_body, text_body }
+        Self { from, to, subject, html_body, text_body, headers: Vec::new() }
     }
 }
 #[cfg(test)]
@@ -61,25 +120,25 @@ mod tests {
     use claim::{assert_ok, assert_err};
     use fake::{Fake, Faker};
     use fake::faker::lorem::en::{Sentence, Paragraph};
-    use rand::{rngs::StdRng, SeedableRng};
     use std::sync::Once;
     use wiremock::{MockServer, ResponseTemplate, Mock};
     use fake::faker::internet::en::SafeEmail;
+    use crate::repository::{InMemorySubscriberRepository, Suppression, SuppressionReason};
     use crate::routes::telemetry::{get_subscriber, init_subscriber};
-    use wiremock::matchers::{method, path, header, header_exists, body_json};
+    use wiremock::matchers::{method, path, header, header_exists, body_json, body_partial_json};
 
     static TRACING: Once = Once::new();
     
     fn init() {
         TRACING.call_once(|| {
-            let subscriber = get_subscriber("test".into(), "info".into(), std::io::stdout());
+            let subscriber = get_subscriber("test".into(), "info".into(), std::io::stdout);
             init_subscriber(subscriber);
         });
     }
 
     #[tokio::test]
     async fn send_email_sends_the_expected_request() {
-        let _ = init();
+        init();
         // 1. 启动一个模拟服务器，用于测试邮件发送
         let mock_server = MockServer::start().await;
         // 2. 创建一个邮件客户端
@@ -88,6 +147,8 @@ mod tests {
             //模拟服务器的 URI
             mock_server.uri(),
             Secret::new(Faker.fake::<String>()),
+            Duration::from_secs(2),
+            Arc::new(InMemorySubscriberRepository::new()),
         );
 
         // 3. 模拟请求  Mock::given 用于定义匹配条件和响应，是 WireMock 的核心 API。
@@ -106,17 +167,19 @@ mod tests {
         let html_content: String = Paragraph(1..2).fake();
         let text_content: String = Paragraph(1..2).fake();
         //发送邮件
-        let _ = email_client.send_email(recipient, &subject, &html_content, &text_content).await.unwrap();
+        email_client.send_email(recipient, &subject, &html_content, &text_content).await.unwrap();
     }
 
     #[tokio::test]
     async fn send_email_fails_if_the_server_returns_500() {
-        let _ = init();
+        init();
         let mock_server = MockServer::start().await;
         let email_client = EmailClient::new(
             SubscriberEmail::parse(SafeEmail().fake()).unwrap(),
             mock_server.uri(),
             Secret::new(Faker.fake::<String>()),
+            Duration::from_secs(2),
+            Arc::new(InMemorySubscriberRepository::new()),
         );
         let recipient = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
         let subject: String = Sentence(1..2).fake();
@@ -147,54 +210,129 @@ mod tests {
         let result = email_client.send_email(recipient, &subject, &html_content, &text_content).await;
         assert_err!(result);
     }
-//SendEmailMatcher 是一个自定义的 WireMock 匹配器，用于验证 HTTP 请求的请求体是否符合预期
-#[derive(Debug, Clone)]
-#[serde(rename_all = "pascal_case")]
-//#[serde(rename_all = "pascal_case")] 用于将 JSON 字段名转换为 PascalCase 格式
-//#[derive(Debug, Clone)] 用于将 SendEmailMatcher 类型转换为 Debug 和 Clone 类型
-struct SendEmailMatcher<'a> {
-        from: &'a str,
-        to: &'a str,
-        subject: &'a str,
-        html_body: &'a str,
-        text_body: &'a str,
+    //SendEmailMatcher 是一个自定义的 WireMock 匹配器，用于验证 HTTP 请求的请求体是否符合预期
+    //挂载到 MockServer 上的匹配器必须是 'static，所以字段是 String 而不是借用
+    #[derive(Debug, Clone)]
+    struct SendEmailMatcher {
+        from: String,
+        to: String,
+        subject: String,
+        html_body: String,
+        text_body: String,
     }
-    impl<'a> wiremock::Match for SendEmailMatcher<'a> {
+    impl wiremock::Match for SendEmailMatcher {
         fn matches(&self, request: &wiremock::Request) -> bool {
             //将请求体转换为 JSON
             let request_as_json : Result<serde_json::Value, serde_json::Error>
-             = serde_json::from_slice(request.body);// 注意：request.body 是 &[u8]
+             = serde_json::from_slice(&request.body);// 注意：request.body 是 &[u8]
              //如果请求体转换为 JSON 成功，则判断请求体是否与预期一致 body是serde_json::value::Value类型
              if let Ok(body) = request_as_json {
                 //打印请求体 dbg!是 Rust 标准库提供的一个宏，用于方便地调试代码。
                 // 它会将传入的参数的值打印到标准错误输出（stderr），同时还会输出该代码所在的文件名和行号等信息，有助于快速定位代码中的问题。
                 dbg!(&body);
-                //检查 JSON 是否包含 from、to、subject、html_body、text_body字段
-                body.get("from").is_some() &&
-                body.get("to").is_some() &&// 检查 JSON 是否包含 to 字段        
-                body.get("subject").is_some() &&// 检查 JSON 是否包含 subject 字段
-                body.get("html_body").is_some() &&// 检查 JSON 是否包含 html_body 字段
-                body.get("text_body").is_some()// 检查 JSON 是否包含 text_body 字段
+                //检查 JSON 中的 from、to、subject、html_body、text_body 字段是否与预期一致
+                body.get("from").and_then(|v| v.as_str()) == Some(self.from.as_str()) &&
+                body.get("to").and_then(|v| v.as_str()) == Some(self.to.as_str()) &&
+                body.get("subject").and_then(|v| v.as_str()) == Some(self.subject.as_str()) &&
+                body.get("html_body").and_then(|v| v.as_str()) == Some(self.html_body.as_str()) &&
+                body.get("text_body").and_then(|v| v.as_str()) == Some(self.text_body.as_str())
              }else{
                 //如果请求体转换为 JSON 失败，则返回 false
                 false
              }
         }
-        fn describe(&self) -> String {
-            format!("matches a request with from: {}, to: {}, subject: {}, html_body: {}, text_body: {}", self.from, self.to, self.subject, self.html_body, self.text_body)
-        }
     }
 
 
+    #[tokio::test]
+    async fn send_email_skips_suppressed_recipients() {
+        init();
+        let mock_server = MockServer::start().await;
+        let suppressions = Arc::new(InMemorySubscriberRepository::new());
+        let recipient = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+        suppressions.suppress(&Suppression::new(&recipient, SuppressionReason::Bounce, None, None)).await.unwrap();
+        let email_client = EmailClient::new(
+            SubscriberEmail::parse(SafeEmail().fake()).unwrap(),
+            mock_server.uri(),
+            Secret::new(Faker.fake::<String>()),
+            Duration::from_secs(2),
+            suppressions,
+        );
+        //名单中的地址不应该发出任何请求
+        Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&mock_server)
+        .await;
+        let result = email_client.send_email(recipient, "subject", "<p>html</p>", "text").await;
+        assert_err!(result);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        init();
+        let mock_server = MockServer::start().await;
+        let email_client = EmailClient::new(
+            SubscriberEmail::parse(SafeEmail().fake()).unwrap(),
+            mock_server.uri(),
+            Secret::new(Faker.fake::<String>()),
+            Duration::from_millis(200),
+            Arc::new(InMemorySubscriberRepository::new()),
+        );
+        Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(10)))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+        let recipient = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+        let result = email_client.send_email(recipient, "subject", "<p>html</p>", "text").await;
+        assert_err!(result);
+    }
+
+    #[tokio::test]
+    async fn newsletters_carry_one_click_unsubscribe_headers() {
+        init();
+        let mock_server = MockServer::start().await;
+        let email_client = EmailClient::new(
+            SubscriberEmail::parse(SafeEmail().fake()).unwrap(),
+            mock_server.uri(),
+            Secret::new(Faker.fake::<String>()),
+            Duration::from_secs(2),
+            Arc::new(InMemorySubscriberRepository::new()),
+        );
+        let email = RenderedEmail {
+            subject: "Issue 7".to_string(),
+            html: "<p>Hello</p>".to_string(),
+            text: "Hello".to_string(),
+            list_unsubscribe: Some("https://newsletter.example.com/unsubscribe?token=a.b".to_string()),
+        };
+        Mock::given(method("POST"))
+        .and(path("/email"))
+        .and(body_partial_json(serde_json::json!({
+            "headers": [
+                { "name": "List-Unsubscribe", "value": "<https://newsletter.example.com/unsubscribe?token=a.b>" },
+                { "name": "List-Unsubscribe-Post", "value": "List-Unsubscribe=One-Click" },
+            ]
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+        let recipient = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+        assert_ok!(email_client.send_rendered(recipient, &email).await);
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_the_server_returns_200() {
-        let _ = init();
+        init();
         let mock_server = MockServer::start().await;
         let sender = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
         let email_client = EmailClient::new(
             sender.clone(),
             mock_server.uri(),
             Secret::new(Faker.fake::<String>()),
+            Duration::from_secs(2),
+            Arc::new(InMemorySubscriberRepository::new()),
         );
         let recipient = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
         let subject: String = Sentence(1..2).fake();
@@ -205,11 +343,11 @@ struct SendEmailMatcher<'a> {
         .and(header_exists("Authorization"))
         .and(header("Content-Type", "application/json"))
         .and(SendEmailMatcher {
-            from: sender.as_ref(),
-            to: recipient.as_ref(),
-            subject: &subject,
-            html_body: &html_content,
-            text_body: &text_content,
+            from: sender.as_ref().to_string(),
+            to: recipient.as_ref().to_string(),
+            subject: subject.clone(),
+            html_body: html_content.clone(),
+            text_body: text_content.clone(),
         })//SendEmailMatcher 用于匹配请求体
         .respond_with(ResponseTemplate::new(200))
         .expect(1)
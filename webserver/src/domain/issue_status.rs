@@ -0,0 +1,60 @@
+/// 一期通讯的发送状态，数据库中以 snake_case 文本存储
+///
+/// ```text
+/// Scheduled ──> Sending ──> Sent
+///     │            └──> Failed
+///     └──> Cancelled
+/// ```
+///
+/// 只有 Scheduled 的通讯可以改期或取消，调度任务领取之后就不能再改
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueStatus {
+    Scheduled,
+    Sending,
+    Sent,
+    Cancelled,
+    //无法渲染，没有发给任何人
+    Failed,
+}
+
+impl IssueStatus {
+    pub const ALL: [IssueStatus; 5] =
+        [IssueStatus::Scheduled, IssueStatus::Sending, IssueStatus::Sent, IssueStatus::Cancelled, IssueStatus::Failed];
+
+    pub fn parse(status: &str) -> Result<IssueStatus, String> {
+        Self::ALL.into_iter()
+            .find(|candidate| candidate.as_str() == status)
+            .ok_or_else(|| format!("{} is not a valid issue status", status))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueStatus::Scheduled => "scheduled",
+            IssueStatus::Sending => "sending",
+            IssueStatus::Sent => "sent",
+            IssueStatus::Cancelled => "cancelled",
+            IssueStatus::Failed => "failed",
+        }
+    }
+}
+
+impl std::fmt::Display for IssueStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IssueStatus;
+    use claim::assert_err;
+
+    #[test]
+    fn statuses_round_trip_through_their_text_form() {
+        for status in IssueStatus::ALL {
+            assert_eq!(IssueStatus::parse(status.as_str()), Ok(status));
+        }
+        assert_err!(IssueStatus::parse("draft"));
+    }
+}
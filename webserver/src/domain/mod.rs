@@ -1,10 +1,18 @@
 pub mod subscriber_name;
 pub mod subscriber_email;
 pub mod new_subscriber;
+pub mod subscription_status;
+pub mod subscriber_attributes;
+pub mod issue_status;
+pub mod delivery_status;
 pub mod email_client;
 
 
 pub use subscriber_name::*;
 pub use subscriber_email::*;
 pub use new_subscriber::*;
+pub use subscription_status::*;
+pub use subscriber_attributes::*;
+pub use issue_status::*;
+pub use delivery_status::*;
 pub use email_client::*;
\ No newline at end of file
@@ -1,6 +1,5 @@
 use crate::domain::SubscriberEmail;
 use crate::domain::SubscriberName;
-use claim::{assert_err, assert_ok};
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
@@ -8,11 +7,20 @@ pub struct NewSubscriber {
 }
 #[cfg(test)]
 mod tests {
-    use crate::domain::NewSubscriber;
-    use claim::{assert_err, assert_ok};
+    use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+    use claim::assert_ok;
     #[test]
+    #[allow(non_snake_case)]
     fn a_200_OK_result_indicates_success() {
-        let result = NewSubscriber::parse("Ursula Le Guin".to_string());
-        assert_ok!(result);
+        // NewSubscriber 没有自己的 parse，由已校验的姓名和邮箱组装而成
+        let name = SubscriberName::parse("Ursula Le Guin".to_string());
+        let email = SubscriberEmail::parse("ursula_le_guin@gmail.com".to_string());
+        assert_ok!(&name);
+        assert_ok!(&email);
+        let subscriber = NewSubscriber {
+            email: email.unwrap(),
+            name: name.unwrap(),
+        };
+        assert_eq!(subscriber.name.as_ref(), "Ursula Le Guin");
     }
-}
\ No newline at end of file
+}
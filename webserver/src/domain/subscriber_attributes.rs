@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+const MAX_ATTRIBUTES: usize = 50;
+const MAX_KEY_CHARS: usize = 64;
+const MAX_VALUE_CHARS: usize = 1024;
+
+/// 订阅者的自定义属性（公司、城市等），通讯中用 `{{ subscriber.attributes.<键> }}` 引用
+///
+/// 键只能由 ASCII 字母、数字和下划线组成，数据库中存为 JSON 文本
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize)]
+#[serde(transparent)]
+pub struct SubscriberAttributes(BTreeMap<String, String>);
+
+impl SubscriberAttributes {
+    pub fn parse(attributes: BTreeMap<String, String>) -> Result<SubscriberAttributes, String> {
+        if attributes.len() > MAX_ATTRIBUTES {
+            return Err(format!("A subscriber can have at most {} attributes", MAX_ATTRIBUTES));
+        }
+        for (key, value) in &attributes {
+            if !is_valid_key(key) {
+                return Err(format!(
+                    "{:?} is not a valid attribute name, use up to {} ASCII letters, digits and underscores",
+                    key, MAX_KEY_CHARS
+                ));
+            }
+            if value.chars().count() > MAX_VALUE_CHARS {
+                return Err(format!("The value of attribute {} is longer than {} characters", key, MAX_VALUE_CHARS));
+            }
+        }
+        Ok(Self(attributes))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.0).expect("a string map always serializes")
+    }
+
+    pub fn from_json(json: &str) -> Result<SubscriberAttributes, String> {
+        let attributes = serde_json::from_str(json).map_err(|e| format!("Invalid attributes: {}", e))?;
+        Self::parse(attributes)
+    }
+}
+
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_CHARS && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn keys_are_restricted_to_word_characters() {
+        assert!(SubscriberAttributes::parse(attributes(&[("company", "Acme"), ("first_name", "Ursula")])).is_ok());
+        for key in ["", "first name", "company.name", "名字"] {
+            assert!(SubscriberAttributes::parse(attributes(&[(key, "x")])).is_err(), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn attributes_round_trip_through_json() {
+        let parsed = SubscriberAttributes::parse(attributes(&[("company", "Acme \"Inc\"")])).unwrap();
+        assert_eq!(parsed.to_json(), r#"{"company":"Acme \"Inc\""}"#);
+        assert_eq!(SubscriberAttributes::from_json(&parsed.to_json()).unwrap(), parsed);
+        assert_eq!(SubscriberAttributes::from_json("{}").unwrap(), SubscriberAttributes::default());
+    }
+}
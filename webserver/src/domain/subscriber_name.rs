@@ -1,4 +1,3 @@
-use claim::{assert_err, assert_ok};
 use unicode_segmentation::UnicodeSegmentation;
 
 
@@ -40,6 +39,7 @@ mod tests {
     use crate::domain::SubscriberName;
     use claim::{assert_err, assert_ok};
     #[test]
+    #[allow(non_snake_case)]
     fn a_200_OK_result_indicates_success() {
         let result = SubscriberName::parse("Ursula Le Guin".to_string());
         assert_ok!(result);
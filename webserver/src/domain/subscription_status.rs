@@ -0,0 +1,136 @@
+/// 订阅者的生命周期状态，数据库中以 snake_case 文本存储
+///
+/// ```text
+/// PendingConfirmation ──> Confirmed ──> Unsubscribed ──> PendingConfirmation（重新订阅需要再次确认）
+///         │                   │
+///         └──────────────────>├──> Bounced ──> PendingConfirmation / Unsubscribed
+///                             └──> Complained（终态，投诉过的地址不再发送任何邮件）
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+    Bounced,
+    Complained,
+}
+
+impl SubscriptionStatus {
+    pub const ALL: [SubscriptionStatus; 5] = [
+        SubscriptionStatus::PendingConfirmation,
+        SubscriptionStatus::Confirmed,
+        SubscriptionStatus::Unsubscribed,
+        SubscriptionStatus::Bounced,
+        SubscriptionStatus::Complained,
+    ];
+
+    pub fn parse(status: &str) -> Result<SubscriptionStatus, String> {
+        Self::ALL.into_iter()
+            .find(|candidate| candidate.as_str() == status)
+            .ok_or_else(|| format!("{} is not a valid subscription status", status))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::PendingConfirmation => "pending_confirmation",
+            SubscriptionStatus::Confirmed => "confirmed",
+            SubscriptionStatus::Unsubscribed => "unsubscribed",
+            SubscriptionStatus::Bounced => "bounced",
+            SubscriptionStatus::Complained => "complained",
+        }
+    }
+
+    /// 只有 Confirmed 的订阅者会收到邮件
+    pub fn receives_mail(&self) -> bool {
+        matches!(self, SubscriptionStatus::Confirmed)
+    }
+
+    pub fn can_transition_to(&self, next: SubscriptionStatus) -> bool {
+        use SubscriptionStatus::*;
+        matches!(
+            (self, next),
+            (PendingConfirmation, Confirmed | Unsubscribed | Bounced | Complained)
+                | (Confirmed, Unsubscribed | Bounced | Complained)
+                | (Unsubscribed, PendingConfirmation)
+                | (Bounced, PendingConfirmation | Unsubscribed)
+        )
+    }
+
+    /// 检查状态转换是否合法，合法时返回新状态
+    pub fn transition_to(self, next: SubscriptionStatus) -> Result<SubscriptionStatus, InvalidTransition> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(InvalidTransition { from: self, to: next })
+        }
+    }
+}
+
+impl std::fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvalidTransition {
+    pub from: SubscriptionStatus,
+    pub to: SubscriptionStatus,
+}
+
+impl std::fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cannot change subscription status from {} to {}", self.from, self.to)
+    }
+}
+
+impl std::error::Error for InvalidTransition {}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionStatus::{self, *};
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn statuses_round_trip_through_their_text_form() {
+        for status in SubscriptionStatus::ALL {
+            assert_eq!(SubscriptionStatus::parse(status.as_str()), Ok(status));
+        }
+        assert_err!(SubscriptionStatus::parse("active"));
+    }
+
+    #[test]
+    fn confirmed_subscribers_can_leave() {
+        assert_ok!(Confirmed.transition_to(Unsubscribed));
+        assert_ok!(Confirmed.transition_to(Bounced));
+        assert_ok!(Confirmed.transition_to(Complained));
+    }
+
+    #[test]
+    fn resubscribing_requires_confirmation() {
+        assert_ok!(Unsubscribed.transition_to(PendingConfirmation));
+        assert_err!(Unsubscribed.transition_to(Confirmed));
+        assert_err!(Bounced.transition_to(Confirmed));
+    }
+
+    #[test]
+    fn complaints_are_final() {
+        for status in SubscriptionStatus::ALL {
+            assert!(!Complained.can_transition_to(status));
+        }
+    }
+
+    #[test]
+    fn staying_in_the_same_status_is_not_a_transition() {
+        for status in SubscriptionStatus::ALL {
+            assert!(!status.can_transition_to(status));
+        }
+    }
+
+    #[test]
+    fn invalid_transitions_explain_themselves() {
+        let error = Complained.transition_to(Confirmed).unwrap_err();
+        assert_eq!(error.to_string(), "Cannot change subscription status from complained to confirmed");
+    }
+}
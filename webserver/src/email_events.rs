@@ -0,0 +1,145 @@
+//! 邮件服务商推送的投递事件：请求认证和解析
+//!
+//! 事件格式与 Postmark 的 webhook 相同，每个请求一个事件，以 `RecordType` 区分类型
+use crate::authentication::basic_authentication;
+use actix_web::http::header::HeaderMap;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// Postmark webhook 的 HTTP Basic 认证：在服务商的 webhook 设置中填写同样的用户名和密码
+///
+/// Postmark 不对请求签名，只能用 Basic 认证或来源 IP 确认请求来自服务商
+#[derive(Clone)]
+pub struct WebhookCredentials {
+    username: String,
+    password: Secret<String>,
+}
+
+impl WebhookCredentials {
+    pub fn new(username: String, password: Secret<String>) -> Self {
+        Self { username, password }
+    }
+
+    /// 错误信息只用于日志，不返回给调用方
+    pub fn verify(&self, headers: &HeaderMap) -> Result<(), String> {
+        let credentials = basic_authentication(headers).map_err(|e| e.to_string())?;
+        //用户名和密码都要比较完，不能在第一个不同的地方返回
+        let username = constant_time_eq(credentials.username.as_bytes(), self.username.as_bytes());
+        let password = constant_time_eq(credentials.password.expose_secret().as_bytes(), self.password.expose_secret().as_bytes());
+        if username & password {
+            Ok(())
+        } else {
+            Err("Invalid webhook username or password".to_string())
+        }
+    }
+}
+
+//先取 SHA-256，比较的两边长度固定，耗时与内容和长度无关
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let (a, b) = (Sha256::digest(a), Sha256::digest(b));
+    a.iter().zip(b.iter()).fold(0u8, |difference, (x, y)| difference | (x ^ y)) == 0
+}
+
+/// 服务商推送的一个事件；不处理的类型（打开、点击等）解析为 `Other`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "RecordType")]
+pub enum EmailEvent {
+    Bounce(Bounce),
+    #[serde(rename = "SpamComplaint")]
+    Complaint(Complaint),
+    Delivery(Delivery),
+    #[serde(other)]
+    Other,
+}
+
+impl EmailEvent {
+    pub fn parse(body: &[u8]) -> Result<EmailEvent, String> {
+        serde_json::from_slice(body).map_err(|e| format!("Invalid email event: {}", e))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Bounce {
+    //HardBounce、SoftBounce、Transient 等
+    #[serde(rename = "Type")]
+    pub kind: String,
+    #[serde(rename = "Email")]
+    pub email: String,
+    #[serde(rename = "MessageID")]
+    pub message_id: Option<String>,
+    #[serde(rename = "Description")]
+    pub description: Option<String>,
+}
+
+impl Bounce {
+    /// 只有硬退信说明地址不可用；软退信（邮箱已满、暂时拒收）由服务商重试
+    pub fn is_hard(&self) -> bool {
+        matches!(self.kind.as_str(), "HardBounce" | "BadEmailAddress")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Complaint {
+    #[serde(rename = "Email")]
+    pub email: String,
+    #[serde(rename = "MessageID")]
+    pub message_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Delivery {
+    #[serde(rename = "Recipient")]
+    pub email: String,
+    #[serde(rename = "MessageID")]
+    pub message_id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderValue, AUTHORIZATION};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+
+    fn credentials() -> WebhookCredentials {
+        WebhookCredentials::new("postmark".to_string(), Secret::new("webhook-password".to_string()))
+    }
+
+    fn headers(username: &str, password: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let encoded = STANDARD.encode(format!("{}:{}", username, password));
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Basic {}", encoded)).unwrap());
+        headers
+    }
+
+    #[test]
+    fn only_requests_with_the_configured_credentials_are_accepted() {
+        assert_eq!(credentials().verify(&headers("postmark", "webhook-password")), Ok(()));
+        assert!(credentials().verify(&headers("postmark", "webhook-passwore")).is_err());
+        assert!(credentials().verify(&headers("postmark", "webhook-password-and-more")).is_err());
+        assert!(credentials().verify(&headers("mailgun", "webhook-password")).is_err());
+        assert!(credentials().verify(&headers("", "")).is_err());
+        assert!(credentials().verify(&HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn events_are_parsed_by_record_type() {
+        let bounce = br#"{"RecordType":"Bounce","Type":"HardBounce","TypeCode":1,"Email":"ursula@example.com",
+                          "MessageID":"883953f4","Description":"The server was unable to deliver your message"}"#;
+        let EmailEvent::Bounce(bounce) = EmailEvent::parse(bounce).unwrap() else {
+            panic!("not a bounce");
+        };
+        assert!(bounce.is_hard());
+        assert_eq!((bounce.email.as_str(), bounce.message_id.as_deref()), ("ursula@example.com", Some("883953f4")));
+
+        let soft = br#"{"RecordType":"Bounce","Type":"SoftBounce","Email":"ursula@example.com"}"#;
+        assert!(matches!(EmailEvent::parse(soft).unwrap(), EmailEvent::Bounce(bounce) if !bounce.is_hard()));
+        let complaint = br#"{"RecordType":"SpamComplaint","Email":"ursula@example.com","MessageID":"883953f4"}"#;
+        assert!(matches!(EmailEvent::parse(complaint).unwrap(), EmailEvent::Complaint(_)));
+        let open = br#"{"RecordType":"Open","Recipient":"ursula@example.com"}"#;
+        assert_eq!(EmailEvent::parse(open).unwrap(), EmailEvent::Other);
+        assert!(EmailEvent::parse(br#"{"RecordType":"Bounce"}"#).is_err());
+        assert!(EmailEvent::parse(b"not json").is_err());
+    }
+}
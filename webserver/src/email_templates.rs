@@ -0,0 +1,222 @@
+//! 邮件模板：每个模板有主题、HTML 和纯文本三个文件，HTML 和纯文本各自继承同一个布局
+//!
+//! 内置模板（`templates/email`）编译进二进制；配置了 `email_client.templates_dir` 时，
+//! 目录中的同名文件优先，没有覆盖的文件仍使用内置版本
+use minijinja::{path_loader, Environment, UndefinedBehavior};
+use serde::Serialize;
+use std::path::Path;
+
+//内置模板：(文件名, 内容)
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("layout.html", include_str!("../templates/email/layout.html")),
+    ("layout.txt", include_str!("../templates/email/layout.txt")),
+    ("confirmation.subject", include_str!("../templates/email/confirmation.subject")),
+    ("confirmation.html", include_str!("../templates/email/confirmation.html")),
+    ("confirmation.txt", include_str!("../templates/email/confirmation.txt")),
+    ("welcome.subject", include_str!("../templates/email/welcome.subject")),
+    ("welcome.html", include_str!("../templates/email/welcome.html")),
+    ("welcome.txt", include_str!("../templates/email/welcome.txt")),
+    ("newsletter.subject", include_str!("../templates/email/newsletter.subject")),
+    ("newsletter.html", include_str!("../templates/email/newsletter.html")),
+    ("newsletter.txt", include_str!("../templates/email/newsletter.txt")),
+    ("privacy_link.subject", include_str!("../templates/email/privacy_link.subject")),
+    ("privacy_link.html", include_str!("../templates/email/privacy_link.html")),
+    ("privacy_link.txt", include_str!("../templates/email/privacy_link.txt")),
+];
+
+/// 一个具名模板和它的变量：模板能用的变量就是实现类型序列化后的字段
+pub trait EmailTemplate: Serialize {
+    /// 对应 `<NAME>.subject`、`<NAME>.html` 和 `<NAME>.txt`
+    const NAME: &'static str;
+}
+
+/// 确认订阅
+#[derive(Serialize, Debug)]
+pub struct ConfirmationEmail<'a> {
+    pub name: &'a str,
+    pub confirmation_link: &'a str,
+}
+
+impl EmailTemplate for ConfirmationEmail<'_> {
+    const NAME: &'static str = "confirmation";
+}
+
+/// 确认后的欢迎邮件
+#[derive(Serialize, Debug)]
+pub struct WelcomeEmail<'a> {
+    pub name: &'a str,
+}
+
+impl EmailTemplate for WelcomeEmail<'_> {
+    const NAME: &'static str = "welcome";
+}
+
+/// 一期通讯：正文已经渲染好，HTML 版本原样插入布局，不再转义
+#[derive(Serialize, Debug)]
+pub struct NewsletterEmail<'a> {
+    pub title: &'a str,
+    pub content_html: &'a str,
+    pub content_text: &'a str,
+    //页脚的退订链接
+    pub unsubscribe_url: &'a str,
+}
+
+impl EmailTemplate for NewsletterEmail<'_> {
+    const NAME: &'static str = "newsletter";
+}
+
+/// 查看或删除个人数据的魔法链接
+#[derive(Serialize, Debug)]
+pub struct PrivacyLinkEmail<'a> {
+    pub link: &'a str,
+    pub expires_in_minutes: i64,
+}
+
+impl EmailTemplate for PrivacyLinkEmail<'_> {
+    const NAME: &'static str = "privacy_link";
+}
+
+/// 渲染好的邮件，交给 `EmailClient::send_rendered` 发送
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+    //一键退订地址，发送时放进 List-Unsubscribe 头；只有通讯才有
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub list_unsubscribe: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct EmailTemplates {
+    env: Environment<'static>,
+}
+
+impl EmailTemplates {
+    /// 只使用内置模板
+    pub fn builtin() -> Self {
+        Self::load(None).expect("Built-in email templates failed to render")
+    }
+
+    /// 加载模板并用示例数据渲染一遍，语法错误和未定义的变量在启动时就报告，而不是等到发信时
+    pub fn load(directory: Option<&Path>) -> Result<Self, String> {
+        let mut env = Environment::new();
+        //模板中引用了类型里没有的变量时报错，而不是渲染成空字符串
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        let overrides = directory.map(path_loader);
+        env.set_loader(move |name| {
+            if let Some(overrides) = &overrides {
+                if let Some(source) = overrides(name)? {
+                    return Ok(Some(source));
+                }
+            }
+            Ok(BUILTIN_TEMPLATES.iter().find(|(builtin, _)| *builtin == name).map(|(_, source)| source.to_string()))
+        });
+        let templates = Self { env };
+        templates.check()?;
+        Ok(templates)
+    }
+
+    /// 渲染主题、HTML 和纯文本；`.html` 中的变量会做 HTML 转义，`.subject` 和 `.txt` 不转义
+    pub fn render<T: EmailTemplate>(&self, data: &T) -> Result<RenderedEmail, String> {
+        let render = |extension: &str| {
+            let name = format!("{}.{}", T::NAME, extension);
+            self.env
+                .get_template(&name)
+                .and_then(|template| template.render(data))
+                .map_err(|e| format!("Failed to render email template {}: {:#}", name, e))
+        };
+        Ok(RenderedEmail {
+            //主题只能有一行
+            subject: render("subject")?.split_whitespace().collect::<Vec<_>>().join(" "),
+            html: render("html")?,
+            text: format!("{}\n", render("txt")?.trim()),
+            list_unsubscribe: None,
+        })
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let link = "https://newsletter.example.com/example";
+        self.render(&ConfirmationEmail { name: "Ursula", confirmation_link: link })?;
+        self.render(&WelcomeEmail { name: "Ursula" })?;
+        self.render(&NewsletterEmail { title: "Issue 1", content_html: "<p>Hello</p>", content_text: "Hello", unsubscribe_url: link })?;
+        self.render(&PrivacyLinkEmail { link, expires_in_minutes: 60 })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    //每个测试用自己的目录，避免并行时互相覆盖
+    fn template_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("webserver-templates-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            std::fs::write(dir.join(file), content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn html_and_text_share_the_layout() {
+        let email = EmailTemplates::builtin()
+            .render(&ConfirmationEmail { name: "Ursula <script>", confirmation_link: "https://example.com/confirm?a=1&b=2" })
+            .unwrap();
+        assert_eq!(email.subject, "Confirm your subscription");
+        assert!(email.html.starts_with("<!DOCTYPE html>"));
+        //HTML 中转义，纯文本中保持原样
+        assert!(email.html.contains("Hi Ursula &lt;script&gt;,"));
+        assert!(email.html.contains("href=\"https:&#x2f;&#x2f;example.com&#x2f;confirm?a=1&amp;b=2\""));
+        assert!(email.text.starts_with("Hi Ursula <script>,\n"));
+        assert!(email.text.contains("\nhttps://example.com/confirm?a=1&b=2\n"));
+    }
+
+    #[test]
+    fn newsletter_content_is_inserted_into_the_layout() {
+        let email = EmailTemplates::builtin()
+            .render(&NewsletterEmail {
+                title: "Issue 7",
+                content_html: "<p>Hello <em>world</em></p>",
+                content_text: "Hello world",
+                unsubscribe_url: "https://newsletter.example.com/unsubscribe?token=a.b",
+            })
+            .unwrap();
+        assert_eq!(email.subject, "Issue 7");
+        assert!(email.html.contains("<p>Hello <em>world</em></p>"));
+        assert!(email.html.contains("you subscribed to our newsletter"));
+        assert!(email.text.starts_with("Issue 7\n\nHello world\n"));
+        //HTML 模板中的变量会转义，链接里的 / 变成 &#x2f;，浏览器解析属性时还原
+        assert!(email.html.contains("unsubscribe?token=a.b\" style=\"color:#71717a;\">Unsubscribe</a>"), "{}", email.html);
+        assert!(email.text.contains("Unsubscribe: https://newsletter.example.com/unsubscribe?token=a.b"));
+    }
+
+    #[test]
+    fn files_in_the_directory_override_the_builtins() {
+        let dir = template_dir("override", &[("welcome.subject", "Hello {{ name }}!\n")]);
+        let templates = EmailTemplates::load(Some(&dir)).unwrap();
+        let email = templates.render(&WelcomeEmail { name: "Ursula" }).unwrap();
+        assert_eq!(email.subject, "Hello Ursula!");
+        //没有覆盖的文件使用内置版本
+        assert!(email.text.contains("Thanks for confirming"));
+    }
+
+    #[test]
+    fn undefined_variables_are_an_error() {
+        let dir = template_dir("undefined", &[("welcome.txt", "{% extends \"layout.txt\" %}{% block content %}Hi {{ first_name }}{% endblock %}")]);
+        let error = EmailTemplates::load(Some(&dir)).err().unwrap();
+        assert!(error.contains("welcome.txt"), "{}", error);
+        assert!(error.contains("undefined"), "{}", error);
+    }
+
+    #[test]
+    fn syntax_errors_are_reported_at_load_time() {
+        let dir = template_dir("syntax", &[("layout.html", "{% block content %}")]);
+        assert!(EmailTemplates::load(Some(&dir)).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+//! 从 CSV 批量导入订阅者，供 `POST /admin/subscribers/import` 使用
+use crate::csv::{CsvError, CsvRecord};
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::repository::{ImportOptions, RepositoryError, SubscriberRepository};
+use std::collections::{HashMap, HashSet};
+
+/// 每批写入的行数；插入时每行 5 个参数，远低于 Postgres 和 SQLite 的参数上限
+pub const BATCH_SIZE: usize = 500;
+
+/// 姓名和邮箱所在的列，按表头匹配，忽略大小写和首尾空白
+#[derive(Debug, Clone)]
+pub struct ColumnMapping {
+    pub name: String,
+    pub email: String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping { name: "name".to_string(), email: "email".to_string() }
+    }
+}
+
+impl ColumnMapping {
+    /// 返回 (姓名列, 邮箱列) 的下标
+    fn resolve(&self, header: &[String]) -> Result<(usize, usize), ImportError> {
+        let find = |column: &str| {
+            header.iter()
+                .position(|candidate| candidate.trim().eq_ignore_ascii_case(column.trim()))
+                .ok_or_else(|| ImportError::MissingColumn(column.to_string()))
+        };
+        Ok((find(&self.name)?, find(&self.email)?))
+    }
+}
+
+/// 未写入的一行及原因
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct RowIssue {
+    pub line: usize,
+    pub reason: String,
+}
+
+/// 导入结果；dry run 时各项数量表示实际导入时会发生的情况
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    //表头之后的非空记录数
+    pub rows: usize,
+    pub imported: usize,
+    pub merged: usize,
+    //文件内重复或邮箱已存在而跳过的行
+    pub skipped: Vec<RowIssue>,
+    //校验失败的行
+    pub rejected: Vec<RowIssue>,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    //请求体为空，没有表头
+    MissingHeader,
+    //表头中找不到映射的列
+    MissingColumn(String),
+    //无法继续解析的 CSV 错误
+    Csv(CsvError),
+    Repository(RepositoryError),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::MissingHeader => write!(f, "CSV has no header line"),
+            ImportError::MissingColumn(column) => write!(f, "CSV header has no column named {}", column),
+            ImportError::Csv(e) => write!(f, "Line {}: {}", e.line(), e),
+            ImportError::Repository(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<RepositoryError> for ImportError {
+    fn from(e: RepositoryError) -> Self {
+        ImportError::Repository(e)
+    }
+}
+
+/// 逐条接收 CSV 记录：第一条是表头，之后每行校验、在文件内去重，攒满一批就交给仓库写入
+///
+/// 每批是一个独立的事务；中途出错时之前的批次已经写入，用 `on_duplicate=skip` 重新导入整个文件即可
+pub struct SubscriberImport<'a> {
+    repository: &'a dyn SubscriberRepository,
+    mapping: ColumnMapping,
+    options: ImportOptions,
+    //解析表头之后得到的 (姓名列, 邮箱列)
+    columns: Option<(usize, usize)>,
+    //文件中已出现的邮箱及其所在行
+    seen: HashMap<String, usize>,
+    batch: Vec<NewSubscriber>,
+    batch_lines: Vec<usize>,
+    report: ImportReport,
+}
+
+impl<'a> SubscriberImport<'a> {
+    pub fn new(repository: &'a dyn SubscriberRepository, mapping: ColumnMapping, options: ImportOptions) -> Self {
+        let report = ImportReport { dry_run: options.dry_run, ..ImportReport::default() };
+        Self {
+            repository,
+            mapping,
+            options,
+            columns: None,
+            seen: HashMap::new(),
+            batch: Vec::with_capacity(BATCH_SIZE),
+            batch_lines: Vec::with_capacity(BATCH_SIZE),
+            report,
+        }
+    }
+
+    pub async fn push(&mut self, record: Result<CsvRecord, CsvError>) -> Result<(), ImportError> {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) if e.is_fatal() || self.columns.is_none() => return Err(ImportError::Csv(e)),
+            Err(e) => {
+                self.report.rows += 1;
+                self.reject(e.line(), e.to_string());
+                return Ok(());
+            }
+        };
+        let (name_column, email_column) = match self.columns {
+            Some(columns) => columns,
+            None => {
+                self.columns = Some(self.mapping.resolve(&record.fields)?);
+                return Ok(());
+            }
+        };
+        self.report.rows += 1;
+        let line = record.line;
+        let mut fields = record.fields;
+        let expected = name_column.max(email_column) + 1;
+        if fields.len() < expected {
+            self.reject(line, format!("Expected at least {} fields, found {}", expected, fields.len()));
+            return Ok(());
+        }
+        let name = match SubscriberName::parse(std::mem::take(&mut fields[name_column]).trim().to_string()) {
+            Ok(name) => name,
+            Err(e) => {
+                self.reject(line, e);
+                return Ok(());
+            }
+        };
+        let email = match SubscriberEmail::parse(std::mem::take(&mut fields[email_column]).trim().to_string()) {
+            Ok(email) => email,
+            Err(e) => {
+                self.reject(line, e);
+                return Ok(());
+            }
+        };
+        //邮箱不区分大小写，与 subscriptions 上的唯一索引一致
+        let key = email.as_ref().to_lowercase();
+        if let Some(first) = self.seen.get(&key) {
+            self.report.skipped.push(RowIssue { line, reason: format!("Duplicate of line {}", first) });
+            return Ok(());
+        }
+        self.seen.insert(key, line);
+        self.batch.push(NewSubscriber { email, name });
+        self.batch_lines.push(line);
+        if self.batch.len() >= BATCH_SIZE {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// 写入最后一批并返回报告
+    pub async fn finish(mut self) -> Result<ImportReport, ImportError> {
+        if self.columns.is_none() {
+            return Err(ImportError::MissingHeader);
+        }
+        self.flush().await?;
+        self.report.skipped.sort_by_key(|issue| issue.line);
+        Ok(self.report)
+    }
+
+    fn reject(&mut self, line: usize, reason: String) {
+        self.report.rejected.push(RowIssue { line, reason });
+    }
+
+    async fn flush(&mut self) -> Result<(), ImportError> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let outcome = self.repository.import(&self.batch, &self.options).await?;
+        self.report.imported += outcome.inserted;
+        self.report.merged += outcome.merged;
+        let skipped: HashSet<&str> = outcome.skipped.iter().map(String::as_str).collect();
+        for (subscriber, line) in self.batch.iter().zip(&self.batch_lines) {
+            if skipped.contains(subscriber.email.as_ref()) {
+                self.report.skipped.push(RowIssue { line: *line, reason: "Email is already subscribed".to_string() });
+            }
+        }
+        self.batch.clear();
+        self.batch_lines.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv::CsvParser;
+    use crate::domain::SubscriptionStatus;
+    use crate::repository::{DuplicatePolicy, InMemorySubscriberRepository, NewConsent, SubscriberQuery};
+
+    fn options(on_duplicate: DuplicatePolicy, dry_run: bool) -> ImportOptions {
+        ImportOptions {
+            status: SubscriptionStatus::Confirmed,
+            on_duplicate,
+            dry_run,
+            reason: "imported".to_string(),
+            consent: NewConsent::imported(None, None),
+        }
+    }
+
+    async fn run(repository: &InMemorySubscriberRepository, csv: &str, options: ImportOptions) -> Result<ImportReport, ImportError> {
+        let mut parser = CsvParser::new(b',');
+        let mut import = SubscriberImport::new(repository, ColumnMapping::default(), options);
+        for record in parser.feed(csv.as_bytes()) {
+            import.push(record).await?;
+        }
+        if let Some(record) = parser.finish() {
+            import.push(record).await?;
+        }
+        import.finish().await
+    }
+
+    #[tokio::test]
+    async fn invalid_and_duplicate_rows_are_reported_by_line() {
+        let repository = InMemorySubscriberRepository::new();
+        run(&repository, "name,email\nUrsula,ursula@example.com\n", options(DuplicatePolicy::Skip, false)).await.unwrap();
+
+        let csv = "Email,Name\nursula@example.com,Ursula\nnot-an-email,Iain\niain@example.com,<script>\nIAIN@example.com,Iain\niain@example.com,Iain M.\nonly-one-field\n";
+        let report = run(&repository, csv, options(DuplicatePolicy::Skip, false)).await.unwrap();
+        assert_eq!(report.rows, 6);
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.skipped.iter().map(|issue| issue.line).collect::<Vec<_>>(), vec![2, 6]);
+        assert_eq!(report.rejected.iter().map(|issue| issue.line).collect::<Vec<_>>(), vec![3, 4, 7]);
+        assert_eq!(report.rejected[0].reason, "Email is not valid");
+        assert_eq!(repository.search(&SubscriberQuery::default()).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn merging_overwrites_the_name_of_existing_subscribers() {
+        let repository = InMemorySubscriberRepository::new();
+        run(&repository, "name,email\nUrsula,ursula@example.com\n", options(DuplicatePolicy::Skip, false)).await.unwrap();
+
+        let report = run(&repository, "name,email\nUrsula Le Guin,ursula@example.com\n", options(DuplicatePolicy::Merge, false)).await.unwrap();
+        assert_eq!((report.imported, report.merged), (0, 1));
+        let subscribers = repository.search(&SubscriberQuery::default()).await.unwrap();
+        assert_eq!(subscribers.len(), 1);
+        assert_eq!(subscribers[0].name, "Ursula Le Guin");
+    }
+
+    #[tokio::test]
+    async fn dry_runs_do_not_write_anything() {
+        let repository = InMemorySubscriberRepository::new();
+        let csv: String = std::iter::once("name,email\n".to_string())
+            .chain((0..BATCH_SIZE + 10).map(|i| format!("Reader {},reader{}@example.com\n", i, i)))
+            .collect();
+        let report = run(&repository, &csv, options(DuplicatePolicy::Skip, true)).await.unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.imported, BATCH_SIZE + 10);
+        assert!(repository.search(&SubscriberQuery::default()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn the_header_must_contain_the_mapped_columns() {
+        let repository = InMemorySubscriberRepository::new();
+        let result = run(&repository, "full_name,email\nUrsula,ursula@example.com\n", options(DuplicatePolicy::Skip, false)).await;
+        assert!(matches!(result, Err(ImportError::MissingColumn(column)) if column == "name"));
+        assert!(matches!(run(&repository, "", options(DuplicatePolicy::Skip, false)).await, Err(ImportError::MissingHeader)));
+    }
+}
@@ -2,3 +2,18 @@ pub mod startup;
 pub mod routes;
 pub mod configuration;
 pub mod domain;  // 添加这一行
+pub mod shutdown;
+pub mod migration;
+pub mod authentication;
+pub mod cli;
+pub mod repository;
+pub mod csv;
+pub mod database;
+pub mod import;
+pub mod privacy;
+pub mod email_templates;
+pub mod newsletter;
+pub mod links;
+pub mod email_events;
+#[cfg(test)]
+pub(crate) mod testing;
@@ -0,0 +1,189 @@
+//! 邮件中带签名的链接：令牌内容是明文，但没有 `application.hmac_secret` 无法伪造或篡改
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+//不同用途的令牌互不通用，例如退订令牌不能拿去当点击跟踪令牌
+const UNSUBSCRIBE: &str = "unsubscribe";
+const OPEN: &str = "open";
+const CLICK: &str = "click";
+const CONFIRM: &str = "confirm";
+
+/// 确认订阅的链接有效的天数
+pub const CONFIRMATION_LINK_LIFETIME_DAYS: i64 = 7;
+
+/// 生成和校验邮件链接，`base_url` 是 `application.base_url`
+#[derive(Clone)]
+pub struct EmailLinks {
+    base_url: String,
+    key: Secret<String>,
+}
+
+impl EmailLinks {
+    pub fn new(base_url: String, key: Secret<String>) -> Self {
+        Self { base_url: base_url.trim_end_matches('/').to_string(), key }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// 退订链接，合并标签 `{{ unsubscribe_url }}` 的值；带上期号时退订会归到那一期的报告中
+    pub fn unsubscribe_url(&self, subscriber_id: Uuid, issue_id: Option<Uuid>) -> String {
+        let payload = match issue_id {
+            Some(issue_id) => format!("{}:{}", subscriber_id, issue_id),
+            None => subscriber_id.to_string(),
+        };
+        format!("{}/unsubscribe?token={}", self.base_url, self.sign(UNSUBSCRIBE, &payload))
+    }
+
+    /// 返回 (subscriber_id, issue_id)；之前发出的链接只有 subscriber_id
+    pub fn verify_unsubscribe(&self, token: &str) -> Option<(Uuid, Option<Uuid>)> {
+        let payload = self.verify(UNSUBSCRIBE, token)?;
+        match payload.split_once(':') {
+            Some((subscriber_id, issue_id)) => Some((subscriber_id.parse().ok()?, Some(issue_id.parse().ok()?))),
+            None => Some((payload.parse().ok()?, None)),
+        }
+    }
+
+    /// 确认邮件中的链接，`CONFIRMATION_LINK_LIFETIME_DAYS` 天后失效
+    pub fn confirmation_url(&self, subscriber_id: Uuid, now: DateTime<Utc>) -> String {
+        let expires_at = (now + Duration::days(CONFIRMATION_LINK_LIFETIME_DAYS)).timestamp();
+        format!("{}/subscriptions/confirm?token={}", self.base_url, self.sign(CONFIRM, &format!("{}:{}", subscriber_id, expires_at)))
+    }
+
+    /// 返回 subscriber_id；过期的链接和签名不对的一样返回 None
+    pub fn verify_confirmation(&self, token: &str, now: DateTime<Utc>) -> Option<Uuid> {
+        let payload = self.verify(CONFIRM, token)?;
+        let (subscriber_id, expires_at) = payload.split_once(':')?;
+        if expires_at.parse::<i64>().ok()? < now.timestamp() {
+            return None;
+        }
+        subscriber_id.parse().ok()
+    }
+
+    /// 跟踪像素的地址，每个收件人每期一个
+    pub fn open_url(&self, issue_id: Uuid, subscriber_id: Uuid) -> String {
+        format!("{}/t/o/{}", self.base_url, self.sign(OPEN, &format!("{}:{}", issue_id, subscriber_id)))
+    }
+
+    /// 返回 (issue_id, subscriber_id)
+    pub fn verify_open(&self, token: &str) -> Option<(Uuid, Uuid)> {
+        let payload = self.verify(OPEN, token)?;
+        let (issue_id, subscriber_id) = payload.split_once(':')?;
+        Some((issue_id.parse().ok()?, subscriber_id.parse().ok()?))
+    }
+
+    /// 改写后的链接，目标地址在签名范围内，不能被改成别的网站
+    pub fn click_url(&self, issue_id: Uuid, subscriber_id: Uuid, url: &str) -> String {
+        format!("{}/t/c/{}", self.base_url, self.sign(CLICK, &format!("{}:{}:{}", issue_id, subscriber_id, url)))
+    }
+
+    /// 返回 (issue_id, subscriber_id, 目标地址)
+    pub fn verify_click(&self, token: &str) -> Option<(Uuid, Uuid, String)> {
+        let payload = self.verify(CLICK, token)?;
+        //地址中可能有冒号，只分前两段
+        let mut parts = payload.splitn(3, ':');
+        let issue_id = parts.next()?.parse().ok()?;
+        let subscriber_id = parts.next()?.parse().ok()?;
+        Some((issue_id, subscriber_id, parts.next()?.to_string()))
+    }
+
+    fn mac(&self, purpose: &str, payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.expose_secret().as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(purpose.as_bytes());
+        mac.update(b"\n");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    //<base64url(payload)>.<base64url(HMAC-SHA256)>
+    fn sign(&self, purpose: &str, payload: &str) -> String {
+        let signature = self.mac(purpose, payload).finalize().into_bytes();
+        format!("{}.{}", URL_SAFE_NO_PAD.encode(payload), URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    fn verify(&self, purpose: &str, token: &str) -> Option<String> {
+        let (payload, signature) = token.split_once('.')?;
+        let payload = String::from_utf8(URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        //verify_slice 的比较是常数时间的
+        self.mac(purpose, &payload).verify_slice(&signature).ok()?;
+        Some(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(key: &str) -> EmailLinks {
+        EmailLinks::new("https://newsletter.example.com/".to_string(), Secret::new(key.to_string()))
+    }
+
+    fn token(url: &str) -> &str {
+        url.split_once("token=").unwrap().1
+    }
+
+    #[test]
+    fn unsubscribe_links_round_trip() {
+        let (id, issue_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let url = links("secret").unsubscribe_url(id, None);
+        assert!(url.starts_with("https://newsletter.example.com/unsubscribe?token="));
+        assert_eq!(links("secret").verify_unsubscribe(token(&url)), Some((id, None)));
+        let url = links("secret").unsubscribe_url(id, Some(issue_id));
+        assert_eq!(links("secret").verify_unsubscribe(token(&url)), Some((id, Some(issue_id))));
+    }
+
+    #[test]
+    fn tampered_or_foreign_tokens_are_rejected() {
+        let url = links("secret").unsubscribe_url(Uuid::new_v4(), Some(Uuid::new_v4()));
+        let token = token(&url);
+        assert_eq!(links("another secret").verify_unsubscribe(token), None);
+
+        //换成别人的 id，签名不变
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", URL_SAFE_NO_PAD.encode(Uuid::new_v4().to_string()), signature);
+        assert_eq!(links("secret").verify_unsubscribe(&forged), None);
+
+        for garbage in ["", ".", "abc", "abc.def", "%%%.%%%"] {
+            assert_eq!(links("secret").verify_unsubscribe(garbage), None);
+        }
+    }
+
+    #[test]
+    fn confirmation_links_expire() {
+        let (id, now) = (Uuid::new_v4(), Utc::now());
+        let url = links("secret").confirmation_url(id, now);
+        assert!(url.starts_with("https://newsletter.example.com/subscriptions/confirm?token="));
+        assert_eq!(links("secret").verify_confirmation(token(&url), now + Duration::days(6)), Some(id));
+        assert_eq!(links("secret").verify_confirmation(token(&url), now + Duration::days(8)), None);
+        //确认令牌不能当作退订令牌
+        assert_eq!(links("secret").verify_unsubscribe(token(&url)), None);
+    }
+
+    #[test]
+    fn tracking_tokens_carry_the_signed_destination() {
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let url = links("secret").click_url(issue_id, subscriber_id, "https://example.com/a?b=1#c");
+        let token = url.strip_prefix("https://newsletter.example.com/t/c/").unwrap();
+        assert_eq!(links("secret").verify_click(token), Some((issue_id, subscriber_id, "https://example.com/a?b=1#c".to_string())));
+        //点击令牌不能当作打开或退订令牌，反之亦然
+        assert_eq!(links("secret").verify_open(token), None);
+        assert_eq!(links("secret").verify_unsubscribe(token), None);
+
+        let url = links("secret").open_url(issue_id, subscriber_id);
+        let token = url.strip_prefix("https://newsletter.example.com/t/o/").unwrap();
+        assert_eq!(links("secret").verify_open(token), Some((issue_id, subscriber_id)));
+        assert_eq!(links("secret").verify_click(token), None);
+
+        //换掉目标地址，签名不变
+        let (_, signature) = token.split_once('.').unwrap();
+        let payload = format!("{}:{}:https://evil.example.com/", issue_id, subscriber_id);
+        assert_eq!(links("secret").verify_click(&format!("{}.{}", URL_SAFE_NO_PAD.encode(payload), signature)), None);
+    }
+}
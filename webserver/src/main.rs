@@ -1,28 +1,30 @@
-use webserver::startup::run;
+use clap::Parser;
+use webserver::cli::{execute, Cli, Command};
 use webserver::configuration::get_configuration;
-use sqlx::{PgConnection};
-use std::net::TcpListener;
-use tracing_subscriber::{EnvFilter};
 use webserver::routes::telemetry::{get_subscriber, init_subscriber};
-use crate::domain::email_client::EmailClient;
 
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
-
-    let subscriber = get_subscriber("webserver".into(), "info".to_string(), std::io::stdout);
-    init_subscriber(subscriber);
-
-    let settings=get_configuration().expect("Failed to get configuration");
-
-    let db_pool=PgConnectOptions::connect_lazy_with(settings.database.with_db());
-
-    let email_client_settings = settings.email_client.sender().expect("Invalid sender email");
-
-    let email_client = EmailClient::new(email_client_settings, settings.email_client.base_url, settings.email_client.authorization_token);
-
-    let listener=TcpListener::bind(format!("{}:{}", settings.application.host, settings.application.port)).expect("Failed to bind port");
-   
-    run(listener, db_pool, email_client)?.await?;
-    Ok(())
+async fn main() {
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Serve);
+
+    //一次性命令的日志写到 stderr，stdout 只留给命令输出
+    if command.is_one_shot() {
+        init_subscriber(get_subscriber("webserver".into(), "warn".to_string(), std::io::stderr));
+    } else {
+        init_subscriber(get_subscriber("webserver".into(), "info".to_string(), std::io::stdout));
+    }
+
+    let settings = match get_configuration() {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = execute(command, settings).await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
-
@@ -0,0 +1,219 @@
+use crate::database::DatabasePool;
+use sqlx::migrate::{MigrateError, Migrator};
+
+/// 编译时嵌入的 migrations/postgres/ 目录，生产镜像中不需要 sqlx CLI
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// SQLite 方言的迁移，版本号与 Postgres 的一一对应
+#[cfg(feature = "sqlite")]
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// 连接池对应后端的迁移
+pub fn migrator(pool: &DatabasePool) -> &'static Migrator {
+    match pool {
+        DatabasePool::Postgres(_) => &MIGRATOR,
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(_) => &SQLITE_MIGRATOR,
+    }
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    //数据库里有本程序不认识的迁移，说明数据库已被更新版本的程序迁移过
+    SchemaTooNew { database_version: i64, binary_version: i64 },
+    //最近一次迁移没有对应的 .down.sql，无法回滚
+    NotReversible(i64),
+    Database(sqlx::Error),
+    Migrate(MigrateError),
+}
+
+impl std::fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::SchemaTooNew { database_version, binary_version } => write!(
+                f,
+                "Database schema is at migration {} but this binary only knows migrations up to {}; deploy a newer binary or roll the database back",
+                database_version, binary_version
+            ),
+            MigrationError::NotReversible(version) => write!(f, "Migration {} has no down migration and cannot be reverted", version),
+            MigrationError::Database(e) => write!(f, "Failed to read applied migrations: {}", e),
+            MigrationError::Migrate(e) => write!(f, "Failed to apply migrations: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+/// 嵌入的迁移版本号，升序（可回滚迁移的 .down.sql 不计入）
+pub fn known_versions(migrator: &Migrator) -> Vec<i64> {
+    migrator.iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| migration.version)
+        .collect()
+}
+
+/// 已成功应用到数据库的迁移版本号，升序；还没有迁移表时返回空
+pub async fn applied_versions(pool: &DatabasePool) -> Result<Vec<i64>, sqlx::Error> {
+    const APPLIED: &str = "SELECT version FROM _sqlx_migrations WHERE success ORDER BY version";
+    match pool {
+        DatabasePool::Postgres(pool) => {
+            let table_exists: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+                .fetch_one(pool)
+                .await?;
+            if !table_exists {
+                return Ok(Vec::new());
+            }
+            sqlx::query_scalar(APPLIED).fetch_all(pool).await
+        }
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => {
+            let table_exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations')")
+                .fetch_one(pool)
+                .await?;
+            if !table_exists {
+                return Ok(Vec::new());
+            }
+            sqlx::query_scalar(APPLIED).fetch_all(pool).await
+        }
+    }
+}
+
+/// 对比嵌入的迁移和已应用的迁移，返回待应用的版本号
+///
+/// 数据库中存在本程序不认识的版本时返回 `SchemaTooNew`
+pub fn pending_versions(known: &[i64], applied: &[i64]) -> Result<Vec<i64>, MigrationError> {
+    if let Some(&unknown) = applied.iter().filter(|version| !known.contains(version)).max() {
+        return Err(MigrationError::SchemaTooNew {
+            database_version: unknown,
+            binary_version: known.iter().copied().max().unwrap_or_default(),
+        });
+    }
+    Ok(known.iter().copied().filter(|version| !applied.contains(version)).collect())
+}
+
+/// 启动检查：数据库结构比程序新时拒绝启动，返回待应用的迁移
+pub async fn check_schema(pool: &DatabasePool) -> Result<Vec<i64>, MigrationError> {
+    let applied = applied_versions(pool).await.map_err(MigrationError::Database)?;
+    pending_versions(&known_versions(migrator(pool)), &applied)
+}
+
+/// 应用所有待执行的迁移，返回这次应用的版本号，已是最新时为空
+///
+/// Postgres 上 `Migrator::run` 会先获取 advisory lock，多个副本同时启动时只有一个会执行迁移
+#[tracing::instrument(name = "Running database migrations", skip(pool))]
+pub async fn run_migrations(pool: &DatabasePool) -> Result<Vec<i64>, MigrationError> {
+    let pending = check_schema(pool).await?;
+    if pending.is_empty() {
+        tracing::info!("Database schema is up to date");
+        return Ok(pending);
+    }
+    tracing::info!("Applying migrations {:?}", pending);
+    let result = match pool {
+        DatabasePool::Postgres(pool) => MIGRATOR.run(pool).await,
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => SQLITE_MIGRATOR.run(pool).await,
+    };
+    result.map_err(MigrationError::Migrate)?;
+    Ok(pending)
+}
+
+/// 回滚最近一次应用的迁移，返回被回滚的版本号；没有已应用的迁移时返回 None
+#[tracing::instrument(name = "Reverting the last migration", skip(pool))]
+pub async fn revert_last_migration(pool: &DatabasePool) -> Result<Option<i64>, MigrationError> {
+    let applied = applied_versions(pool).await.map_err(MigrationError::Database)?;
+    let last = match applied.last() {
+        Some(&last) => last,
+        None => return Ok(None),
+    };
+    let reversible = migrator(pool).iter()
+        .any(|migration| migration.version == last && migration.migration_type.is_down_migration());
+    if !reversible {
+        return Err(MigrationError::NotReversible(last));
+    }
+    //undo 会回滚所有版本号大于 target 的迁移
+    let target = applied.iter().rev().nth(1).copied().unwrap_or(0);
+    let result = match pool {
+        DatabasePool::Postgres(pool) => MIGRATOR.undo(pool, target).await,
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => SQLITE_MIGRATOR.undo(pool, target).await,
+    };
+    result.map_err(MigrationError::Migrate)?;
+    Ok(Some(last))
+}
+
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+/// 每个迁移的状态；数据库中本程序不认识的迁移也会列出
+pub async fn migration_status(pool: &DatabasePool) -> Result<Vec<MigrationStatus>, MigrationError> {
+    let applied = applied_versions(pool).await.map_err(MigrationError::Database)?;
+    let mut status: Vec<MigrationStatus> = migrator(pool).iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: applied.contains(&migration.version),
+        })
+        .collect();
+    let known = known_versions(migrator(pool));
+    status.extend(applied.iter().filter(|version| !known.contains(version)).map(|&version| MigrationStatus {
+        version,
+        description: "(unknown to this binary)".to_string(),
+        applied: true,
+    }));
+    status.sort_by_key(|migration| migration.version);
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn migrations_are_embedded() {
+        assert!(known_versions(&MIGRATOR).contains(&20251022202150));
+    }
+
+    #[test]
+    fn reversible_migrations_are_counted_once() {
+        let known = known_versions(&MIGRATOR);
+        let mut deduplicated = known.clone();
+        deduplicated.dedup();
+        assert_eq!(known, deduplicated);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn both_dialects_have_the_same_migrations() {
+        assert_eq!(known_versions(&MIGRATOR), known_versions(&SQLITE_MIGRATOR));
+        let reversible = |migrator: &Migrator| migrator.iter()
+            .filter(|migration| migration.migration_type.is_down_migration())
+            .map(|migration| migration.version)
+            .collect::<Vec<_>>();
+        assert_eq!(reversible(&MIGRATOR), reversible(&SQLITE_MIGRATOR));
+    }
+
+    #[test]
+    fn unapplied_migrations_are_pending() {
+        assert_eq!(pending_versions(&[1, 2, 3], &[1]).unwrap(), vec![2, 3]);
+        assert_eq!(pending_versions(&[1, 2, 3], &[]).unwrap(), vec![1, 2, 3]);
+        assert_ok!(pending_versions(&[1, 2], &[1, 2]));
+    }
+
+    #[test]
+    fn a_schema_newer_than_the_binary_is_rejected() {
+        let result = pending_versions(&[1, 2], &[1, 2, 3, 4]);
+        assert_err!(&result);
+        match result {
+            Err(MigrationError::SchemaTooNew { database_version, binary_version }) => {
+                assert_eq!(database_version, 4);
+                assert_eq!(binary_version, 2);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
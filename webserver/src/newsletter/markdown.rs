@@ -0,0 +1,272 @@
+//! Markdown 正文转换成邮件的 HTML 和纯文本两个版本
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+fn parser(markdown: &str) -> Parser<'_> {
+    Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH)
+}
+
+/// 渲染成 HTML 并清理：去掉脚本、事件属性、`javascript:` 链接等，编辑写的原始 HTML 也不例外
+pub fn markdown_to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, parser(markdown));
+    ammonia::clean(&html)
+}
+
+/// 渲染成纯文本：链接和图片在文字后面标上 `[1]` 这样的编号，地址按编号列在末尾
+pub fn markdown_to_text(markdown: &str) -> String {
+    let mut writer = TextWriter::new();
+    for event in parser(markdown) {
+        writer.event(event);
+    }
+    writer.finish()
+}
+
+struct TextWriter {
+    //最外层是正文，每层引用块压入一个新的缓冲区，结束时整体加上 "> "
+    buffers: Vec<String>,
+    //嵌套的列表，有序列表保存下一个序号
+    lists: Vec<Option<u64>>,
+    //脚注中的地址，同一个地址只编号一次
+    links: Vec<String>,
+    //当前链接或图片的地址，以及链接文字在缓冲区中的起始位置
+    link: Option<(String, usize)>,
+    heading_start: usize,
+    in_code_block: bool,
+}
+
+impl TextWriter {
+    fn new() -> Self {
+        Self {
+            buffers: vec![String::new()],
+            lists: Vec::new(),
+            links: Vec::new(),
+            link: None,
+            heading_start: 0,
+            in_code_block: false,
+        }
+    }
+
+    fn out(&mut self) -> &mut String {
+        self.buffers.last_mut().expect("the body buffer is never popped")
+    }
+
+    //保证以空行结尾，开头不加
+    fn block_break(&mut self) {
+        let out = self.out();
+        if out.is_empty() {
+            return;
+        }
+        let newlines = out.len() - out.trim_end_matches('\n').len();
+        for _ in newlines..2 {
+            out.push('\n');
+        }
+    }
+
+    fn line_break(&mut self) {
+        let out = self.out();
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+
+    fn footnote(&mut self, url: String) -> usize {
+        match self.links.iter().position(|link| *link == url) {
+            Some(index) => index + 1,
+            None => {
+                self.links.push(url);
+                self.links.len()
+            }
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) if self.in_code_block => {
+                //代码块缩进四个空格
+                for line in text.split_inclusive('\n') {
+                    let out = self.out();
+                    if out.is_empty() || out.ends_with('\n') {
+                        out.push_str("    ");
+                    }
+                    out.push_str(line);
+                }
+            }
+            Event::Text(text) | Event::Code(text) => self.out().push_str(&text),
+            Event::SoftBreak => self.out().push(' '),
+            Event::HardBreak => self.out().push('\n'),
+            Event::Rule => {
+                self.block_break();
+                self.out().push_str("----------");
+                self.block_break();
+            }
+            //原始 HTML 在纯文本中没有意义
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            //列表项中的段落紧跟在项目符号后面
+            Tag::Paragraph if self.lists.is_empty() => self.block_break(),
+            Tag::Heading { .. } => {
+                self.block_break();
+                self.heading_start = self.out().len();
+            }
+            Tag::BlockQuote(_) => {
+                self.block_break();
+                self.buffers.push(String::new());
+            }
+            Tag::CodeBlock(_) => {
+                self.block_break();
+                self.in_code_block = true;
+            }
+            Tag::List(first) => {
+                if self.lists.is_empty() {
+                    self.block_break();
+                } else {
+                    self.line_break();
+                }
+                self.lists.push(first);
+            }
+            Tag::Item => {
+                self.line_break();
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                self.out().push_str(&format!("{}{}", indent, marker));
+            }
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                let start = self.out().len();
+                self.link = Some((dest_url.to_string(), start));
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                if self.lists.is_empty() {
+                    self.block_break();
+                } else {
+                    self.line_break();
+                }
+            }
+            TagEnd::Heading(level) => {
+                //一、二级标题加下划线
+                let underline = match level {
+                    HeadingLevel::H1 => Some('='),
+                    HeadingLevel::H2 => Some('-'),
+                    _ => None,
+                };
+                if let Some(underline) = underline {
+                    let start = self.heading_start;
+                    let width = self.out()[start..].chars().count();
+                    let line = underline.to_string().repeat(width);
+                    self.out().push('\n');
+                    self.out().push_str(&line);
+                }
+                self.block_break();
+            }
+            TagEnd::BlockQuote(_) => {
+                let quoted = self.buffers.pop().unwrap_or_default();
+                let quoted: Vec<String> = quoted
+                    .trim_end()
+                    .lines()
+                    .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) })
+                    .collect();
+                self.out().push_str(&quoted.join("\n"));
+                self.block_break();
+            }
+            TagEnd::CodeBlock => {
+                self.in_code_block = false;
+                self.block_break();
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.block_break();
+                }
+            }
+            TagEnd::Item => self.line_break(),
+            TagEnd::Link | TagEnd::Image => {
+                if let Some((url, start)) = self.link.take() {
+                    //自动链接（<https://...>）的文字就是地址，不再加脚注
+                    let text = &self.out()[start..];
+                    if text == url || url.strip_prefix("mailto:") == Some(text) {
+                        return;
+                    }
+                    let number = self.footnote(url);
+                    self.out().push_str(&format!(" [{}]", number));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let mut text = self.out().trim().to_string();
+        if !self.links.is_empty() {
+            text.push_str("\n\n");
+            for (index, url) in self.links.iter().enumerate() {
+                text.push_str(&format!("[{}] {}\n", index + 1, url));
+            }
+        }
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_is_sanitized() {
+        let html = markdown_to_html(
+            "Hello *world*\n\n<script>alert(1)</script>\n\n[click](javascript:alert(1)) <a href=\"https://example.com\" onclick=\"steal()\">site</a>",
+        );
+        assert!(html.contains("<p>Hello <em>world</em></p>"));
+        assert!(!html.contains("script"));
+        assert!(!html.contains("javascript"));
+        assert!(!html.contains("onclick"));
+        assert!(html.contains("href=\"https://example.com\""));
+    }
+
+    #[test]
+    fn links_become_numbered_footnotes() {
+        let text = markdown_to_text(
+            "Read [the post](https://example.com/post) and [its sequel](https://example.com/sequel).\n\n\
+             Or [the post again](https://example.com/post), or <https://example.com/home>.",
+        );
+        assert_eq!(
+            text,
+            "Read the post [1] and its sequel [2].\n\n\
+             Or the post again [1], or https://example.com/home.\n\n\
+             [1] https://example.com/post\n\
+             [2] https://example.com/sequel\n"
+        );
+    }
+
+    #[test]
+    fn block_structure_is_kept_readable() {
+        let markdown = "# Issue 7\n\nIntro with **bold** and `code`.\n\n## News\n\n- one\n- two\n  1. nested\n  2. again\n\n> quoted\n> text\n\n```\nlet x = 1;\n```\n";
+        assert_eq!(
+            markdown_to_text(markdown),
+            "Issue 7\n=======\n\n\
+             Intro with bold and code.\n\n\
+             News\n----\n\n\
+             - one\n- two\n  1. nested\n  2. again\n\n\
+             > quoted text\n\n    let x = 1;\n"
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! 通讯中的合并标签，例如 `{{ subscriber.name }}`，发送时按收件人替换
+//!
+//! 支持的标签：
+//! - `{{ subscriber.name }}`、`{{ subscriber.email }}`
+//! - `{{ subscriber.attributes.<键> }}`：自定义属性
+//! - `{{ unsubscribe_url }}`：该收件人的退订链接
+//!
+//! 任何标签都可以加上 `| default("...")`，值为空或属性不存在时使用
+use std::ops::Range;
+use crate::domain::{is_valid_key, SubscriberAttributes};
+
+const AVAILABLE_TAGS: &str = "subscriber.name, subscriber.email, subscriber.attributes.<name>, unsubscribe_url";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeField {
+    Name,
+    Email,
+    UnsubscribeUrl,
+    Attribute(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeTag {
+    pub field: MergeField,
+    pub fallback: Option<String>,
+}
+
+/// 替换合并标签需要的收件人信息
+pub struct Recipient<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub attributes: &'a SubscriberAttributes,
+    pub unsubscribe_url: &'a str,
+}
+
+impl MergeTag {
+    /// `{{` 和 `}}` 之间的内容
+    pub fn parse(expression: &str) -> Result<MergeTag, String> {
+        let (path, filter) = match expression.split_once('|') {
+            Some((path, filter)) => (path.trim(), Some(filter.trim())),
+            None => (expression.trim(), None),
+        };
+        let field = match path {
+            "subscriber.name" => MergeField::Name,
+            "subscriber.email" => MergeField::Email,
+            "unsubscribe_url" => MergeField::UnsubscribeUrl,
+            other => match other.strip_prefix("subscriber.attributes.") {
+                Some(key) if is_valid_key(key) => MergeField::Attribute(key.to_string()),
+                _ => return Err(format!("unknown merge tag {{{{ {} }}}}", path)),
+            },
+        };
+        let fallback = filter.map(|filter| parse_default(filter).ok_or_else(|| {
+            format!("unsupported filter \"{}\" in {{{{ {} }}}}, only default(\"...\") is allowed", filter, path)
+        })).transpose()?;
+        Ok(MergeTag { field, fallback })
+    }
+
+    /// 这个收件人的值；值为空时使用 default 中的文字
+    pub fn resolve(&self, recipient: &Recipient) -> String {
+        let value = match &self.field {
+            MergeField::Name => Some(recipient.name),
+            MergeField::Email => Some(recipient.email),
+            MergeField::UnsubscribeUrl => Some(recipient.unsubscribe_url),
+            MergeField::Attribute(key) => recipient.attributes.get(key),
+        };
+        match value {
+            Some(value) if !value.trim().is_empty() => value.to_string(),
+            _ => self.fallback.clone().unwrap_or_default(),
+        }
+    }
+}
+
+//default("...") 或 default('...')
+fn parse_default(filter: &str) -> Option<String> {
+    let argument = filter.strip_prefix("default")?.trim().strip_prefix('(')?.strip_suffix(')')?.trim();
+    let quote = argument.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let text = argument.strip_prefix(quote)?.strip_suffix(quote)?;
+    if text.contains(quote) {
+        return None;
+    }
+    Some(text.to_string())
+}
+
+/// 找出文本中的全部合并标签；有不认识的标签或没有闭合的 `{{` 时，把所有问题一起报告
+pub fn parse_merge_tags(text: &str) -> Result<Vec<(Range<usize>, MergeTag)>, String> {
+    let mut tags = Vec::new();
+    let mut problems = Vec::new();
+    let mut position = 0;
+    while let Some(offset) = text[position..].find("{{") {
+        let start = position + offset;
+        let Some(length) = text[start + 2..].find("}}") else {
+            problems.push(format!("unclosed merge tag at byte {}", start));
+            break;
+        };
+        let end = start + 2 + length + 2;
+        match MergeTag::parse(&text[start + 2..end - 2]) {
+            Ok(tag) => tags.push((start..end, tag)),
+            Err(e) => problems.push(e),
+        }
+        position = end;
+    }
+    if problems.is_empty() {
+        Ok(tags)
+    } else {
+        Err(format!("{} (available merge tags: {})", problems.join("; "), AVAILABLE_TAGS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipient(attributes: &SubscriberAttributes) -> Recipient<'_> {
+        Recipient {
+            name: "Ursula Le Guin",
+            email: "ursula@example.com",
+            attributes,
+            unsubscribe_url: "https://newsletter.example.com/unsubscribe?token=abc",
+        }
+    }
+
+    #[test]
+    fn known_tags_are_parsed_with_their_fallbacks() {
+        let tags = parse_merge_tags("Hi {{subscriber.name}}, how is {{ subscriber.attributes.company | default(\"work\") }}?").unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0], (3..22, MergeTag { field: MergeField::Name, fallback: None }));
+        assert_eq!(tags[1].1, MergeTag { field: MergeField::Attribute("company".into()), fallback: Some("work".into()) });
+        assert_eq!(MergeTag::parse(" unsubscribe_url|default('x') ").unwrap().fallback.as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let error = parse_merge_tags("{{ subscriber.first_name }} {{ subscriber.name | upper }} {{ subscriber.attributes.a-b }} {{ oops").unwrap_err();
+        assert!(error.contains("unknown merge tag {{ subscriber.first_name }}"), "{}", error);
+        assert!(error.contains("unsupported filter \"upper\""), "{}", error);
+        assert!(error.contains("unknown merge tag {{ subscriber.attributes.a-b }}"), "{}", error);
+        assert!(error.contains("unclosed merge tag"), "{}", error);
+    }
+
+    #[test]
+    fn fallbacks_apply_to_missing_or_empty_values() {
+        let attributes = SubscriberAttributes::parse([("company".to_string(), "Acme".to_string()), ("city".to_string(), " ".to_string())].into()).unwrap();
+        let resolve = |expression: &str| MergeTag::parse(expression).unwrap().resolve(&recipient(&attributes));
+        assert_eq!(resolve("subscriber.name | default(\"friend\")"), "Ursula Le Guin");
+        assert_eq!(resolve("subscriber.attributes.company | default(\"work\")"), "Acme");
+        assert_eq!(resolve("subscriber.attributes.city | default(\"your city\")"), "your city");
+        assert_eq!(resolve("subscriber.attributes.team | default(\"your team\")"), "your team");
+        assert_eq!(resolve("subscriber.attributes.team"), "");
+        assert_eq!(resolve("unsubscribe_url"), "https://newsletter.example.com/unsubscribe?token=abc");
+    }
+}
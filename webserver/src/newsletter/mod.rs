@@ -0,0 +1,136 @@
+//! 通讯期刊：编辑只写一份 Markdown，发送时生成套用通讯布局的 HTML 和纯文本
+pub mod markdown;
+pub mod merge_tags;
+pub mod scheduler;
+pub mod tracking;
+
+pub use markdown::*;
+pub use merge_tags::*;
+pub use scheduler::*;
+pub use tracking::*;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use crate::email_templates::{EmailTemplates, NewsletterEmail, RenderedEmail};
+
+/// 检查标题和正文中的合并标签，保存或预览时调用，错误信息可以直接返回给编辑
+pub fn validate_issue(title: &str, content: &str) -> Result<(), String> {
+    parse_merge_tags(title).map_err(|e| format!("title: {}", e))?;
+    parse_merge_tags(content).map_err(|e| format!("content: {}", e))?;
+    Ok(())
+}
+
+/// 渲染好的一期通讯，合并标签的位置是占位符，`personalize` 按收件人替换
+///
+/// Markdown 和布局只渲染一次，之后每个收件人只做字符串替换
+pub struct IssueTemplate {
+    email: RenderedEmail,
+    tags: Vec<(String, MergeTag)>,
+}
+
+impl IssueTemplate {
+    pub fn render(templates: &EmailTemplates, title: &str, content: &str) -> Result<Self, String> {
+        //占位符只含字母和数字，Markdown、HTML 清理和转义都不会改动它；随机前缀避免和正文中的文字撞上
+        let mut nonce = [0u8; 8];
+        OsRng.fill_bytes(&mut nonce);
+        let prefix = format!("mergetag{}", nonce.iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+        let mut tags = Vec::new();
+        let title = insert_placeholders(title, &prefix, &mut tags)?;
+        let content = insert_placeholders(content, &prefix, &mut tags)?;
+
+        //页脚的退订链接也是一个占位符
+        let unsubscribe_url = format!("{}x{}x", prefix, tags.len());
+        tags.push((unsubscribe_url.clone(), MergeTag { field: MergeField::UnsubscribeUrl, fallback: None }));
+
+        let content_html = markdown_to_html(&content);
+        let content_text = markdown_to_text(&content);
+        let email = templates.render(&NewsletterEmail {
+            title: &title,
+            content_html: &content_html,
+            content_text: &content_text,
+            unsubscribe_url: &unsubscribe_url,
+        })?;
+        Ok(Self { email, tags })
+    }
+
+    /// 这个收件人的邮件：HTML 中的值做转义，主题和纯文本中原样插入
+    pub fn personalize(&self, recipient: &Recipient) -> RenderedEmail {
+        let mut email = self.email.clone();
+        email.list_unsubscribe = Some(recipient.unsubscribe_url.to_string());
+        for (placeholder, tag) in &self.tags {
+            let value = tag.resolve(recipient);
+            email.subject = email.subject.replace(placeholder, &value.split_whitespace().collect::<Vec<_>>().join(" "));
+            email.html = email.html.replace(placeholder, &escape_html(&value));
+            email.text = email.text.replace(placeholder, &value);
+        }
+        email
+    }
+}
+
+fn insert_placeholders(text: &str, prefix: &str, tags: &mut Vec<(String, MergeTag)>) -> Result<String, String> {
+    let mut output = String::with_capacity(text.len());
+    let mut position = 0;
+    for (range, tag) in parse_merge_tags(text)? {
+        //结尾的 x 把编号隔开，x1x 不会是 x10x 的前缀
+        let placeholder = format!("{}x{}x", prefix, tags.len());
+        output.push_str(&text[position..range.start]);
+        output.push_str(&placeholder);
+        tags.push((placeholder, tag));
+        position = range.end;
+    }
+    output.push_str(&text[position..]);
+    Ok(output)
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::SubscriberAttributes;
+
+    fn personalize(title: &str, content: &str, name: &str, attributes: &SubscriberAttributes) -> RenderedEmail {
+        let issue = IssueTemplate::render(&EmailTemplates::builtin(), title, content).unwrap();
+        issue.personalize(&Recipient {
+            name,
+            email: "ursula@example.com",
+            attributes,
+            unsubscribe_url: "https://newsletter.example.com/unsubscribe?token=a.b",
+        })
+    }
+
+    #[test]
+    fn tags_are_resolved_per_recipient_and_escaped_in_html() {
+        let content = "Hi **{{ subscriber.name }}** from {{ subscriber.attributes.company | default(\"your company\") }}.\n\n\
+                       [Unsubscribe]({{ unsubscribe_url }})";
+        let email = personalize("News for {{ subscriber.name }}", content, "Ursula <b>Le Guin</b>", &SubscriberAttributes::default());
+        assert_eq!(email.subject, "News for Ursula <b>Le Guin</b>");
+        assert!(email.html.contains("Hi <strong>Ursula &lt;b&gt;Le Guin&lt;/b&gt;</strong> from your company."), "{}", email.html);
+        assert!(email.html.contains("href=\"https://newsletter.example.com/unsubscribe?token=a.b\""), "{}", email.html);
+        assert!(email.text.contains("Hi Ursula <b>Le Guin</b> from your company."), "{}", email.text);
+        assert!(email.text.contains("Unsubscribe [1]"));
+        assert!(email.text.contains("[1] https://newsletter.example.com/unsubscribe?token=a.b"));
+        assert!(!email.html.contains("mergetag") && !email.text.contains("mergetag"));
+        assert_eq!(email.list_unsubscribe.as_deref(), Some("https://newsletter.example.com/unsubscribe?token=a.b"));
+    }
+
+    #[test]
+    fn unknown_tags_are_rejected_before_rendering() {
+        let error = validate_issue("Issue 7", "Hi {{ subscriber.first_name }}").unwrap_err();
+        assert!(error.starts_with("content: unknown merge tag {{ subscriber.first_name }}"), "{}", error);
+        assert!(validate_issue("{{ issue.number }}", "Hi").unwrap_err().starts_with("title:"));
+        assert!(IssueTemplate::render(&EmailTemplates::builtin(), "Issue 7", "{{ nope }}").is_err());
+    }
+}
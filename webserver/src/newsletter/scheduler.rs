@@ -0,0 +1,386 @@
+//! 定时发送：服务器进程中的调度任务定期领取到期的通讯，逐个发给 confirmed 的订阅者
+//!
+//! 每一期由 `NewsletterRepository::claim_due_issue` 领取，多个实例同时运行时不会重复发送；
+//! 每个收件人的发送结果记在 deliveries 表中。停机或实例退出时没发完的一期会被重新领取，
+//! 已经有发送结果的收件人不会再收到
+use crate::domain::{EmailClient, SendReceipt, SubscriberEmail, SubscriptionStatus};
+use crate::email_templates::EmailTemplates;
+use crate::links::EmailLinks;
+use crate::newsletter::{add_tracking, IssueTemplate, Recipient};
+use crate::repository::{Cursor, DeliveryRepository, NewsletterIssue, NewsletterRepository, RepositoryError, SubscriberQuery, SubscriberRecord, SubscriberRepository};
+use crate::shutdown::Shutdown;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+//每次从数据库读取的收件人数，发送期间不长时间占用连接
+const RECIPIENT_PAGE: usize = 200;
+
+//发送一页可能很慢（每封邮件最长等到 email_client.timeout_milliseconds），
+//距离上一次续期超过这个时间就在两封邮件之间续期，远小于 CLAIM_TIMEOUT
+const CLAIM_RENEWAL_INTERVAL: chrono::Duration = chrono::Duration::seconds(60);
+
+//一期通讯这一次有没有发完
+enum Progress {
+    Finished,
+    //发送期间收到关闭信号，剩下的收件人留给下一次领取
+    Interrupted,
+    //无法渲染，一封也没有发出
+    Failed,
+}
+
+/// 发送到期通讯的后台任务，由 `Application::build` 放进 `BackgroundTasks`
+pub struct Scheduler {
+    newsletters: Arc<dyn NewsletterRepository>,
+    subscribers: Arc<dyn SubscriberRepository>,
+    deliveries: Arc<dyn DeliveryRepository>,
+    email_client: EmailClient,
+    templates: EmailTemplates,
+    links: EmailLinks,
+    interval: Duration,
+}
+
+impl Scheduler {
+    pub fn new(
+        newsletters: Arc<dyn NewsletterRepository>,
+        subscribers: Arc<dyn SubscriberRepository>,
+        deliveries: Arc<dyn DeliveryRepository>,
+        email_client: EmailClient,
+        templates: EmailTemplates,
+        links: EmailLinks,
+        interval: Duration,
+    ) -> Self {
+        Self { newsletters, subscribers, deliveries, email_client, templates, links, interval }
+    }
+
+    /// 每隔 `interval` 检查一次，直到收到关闭信号；正在发送的一期发完当前这一页收件人就停下
+    pub async fn run(self, shutdown: Shutdown) {
+        loop {
+            if let Err(e) = self.send_due_issues(&shutdown).await {
+                tracing::error!("Failed to send scheduled issues: {}", e);
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.interval) => {}
+                _ = shutdown.wait() => return,
+            }
+        }
+    }
+
+    /// 依次发送所有已到期的通讯，返回发完的期数；收到关闭信号后不再领取新的一期
+    pub async fn send_due_issues(&self, shutdown: &Shutdown) -> Result<usize, RepositoryError> {
+        let mut issues = 0;
+        while !shutdown.is_triggered() {
+            let Some(issue) = self.newsletters.claim_due_issue(Utc::now()).await? else {
+                break;
+            };
+            if let Progress::Finished = self.send_issue(&issue, shutdown).await? {
+                issues += 1;
+            }
+        }
+        Ok(issues)
+    }
+
+    //读取收件人失败时返回错误，这一期停留在 sending，领取过期之后会被接着发送
+    #[tracing::instrument(name = "Sending a newsletter issue", skip(self, issue, shutdown), fields(issue_id = %issue.id))]
+    async fn send_issue(&self, issue: &NewsletterIssue, shutdown: &Shutdown) -> Result<Progress, RepositoryError> {
+        let template = match IssueTemplate::render(&self.templates, &issue.title, &issue.content) {
+            Ok(template) => template,
+            //创建时已经检查过合并标签，这里失败通常是模板目录中的文件有问题；
+            //改为 failed 而不是 sent，也不放弃领取，否则下一次检查又会立刻领取它
+            Err(e) => {
+                tracing::error!("Failed to render issue {}: {}", issue.id, e);
+                self.newsletters.fail_issue(issue.id, &e).await?;
+                return Ok(Progress::Failed);
+            }
+        };
+        let (sent, failed) = match self.send_to_subscribers(issue, &template, shutdown).await? {
+            (Progress::Interrupted, sent, failed) => {
+                tracing::info!("Stopped issue {} after {} sent and {} failed, the rest is left for the next claim", issue.id, sent, failed);
+                self.newsletters.release_claim(issue.id).await?;
+                return Ok(Progress::Interrupted);
+            }
+            (_, sent, failed) => (sent, failed),
+        };
+        //中断之后接着发送的一期，前几次的结果也算在内
+        let summary = self.deliveries.delivery_summary(issue.id).await?;
+        tracing::info!(
+            "Issue {} sent to {} subscriber(s), {} failed ({} sent and {} failed in this run)",
+            issue.id, summary.sent, summary.failed, sent, failed
+        );
+        self.newsletters.complete_issue(issue.id, summary.sent.unsigned_abs(), summary.failed.unsigned_abs()).await?;
+        Ok(Progress::Finished)
+    }
+
+    //按 subscribed_at 分页读取，发送期间新确认的订阅者排在后面，也会收到；
+    //每一页之前和发送途中定期续期领取，收到关闭信号时在两页之间停下
+    async fn send_to_subscribers(
+        &self,
+        issue: &NewsletterIssue,
+        template: &IssueTemplate,
+        shutdown: &Shutdown,
+    ) -> Result<(Progress, u64, u64), RepositoryError> {
+        let mut query = SubscriberQuery {
+            status: Some(SubscriptionStatus::Confirmed),
+            limit: RECIPIENT_PAGE,
+            ..SubscriberQuery::default()
+        };
+        let (mut sent, mut failed) = (0, 0);
+        loop {
+            if shutdown.is_triggered() {
+                return Ok((Progress::Interrupted, sent, failed));
+            }
+            let mut renewed_at = Utc::now();
+            self.newsletters.renew_claim(issue.id, renewed_at).await?;
+            let page = self.subscribers.search(&query).await?;
+            let ids: Vec<Uuid> = page.iter().map(|subscriber| subscriber.id).collect();
+            //已经有结果（上一次领取时发出或失败）的收件人跳过
+            let pending = self.deliveries.queue_deliveries(issue.id, &ids).await?;
+            for subscriber in page.iter().filter(|subscriber| pending.contains(&subscriber.id)) {
+                if Utc::now() - renewed_at >= CLAIM_RENEWAL_INTERVAL {
+                    renewed_at = Utc::now();
+                    self.newsletters.renew_claim(issue.id, renewed_at).await?;
+                }
+                //邮件已经发出（或确定发不出），投递状态写入失败只记日志，不中断这一期
+                let recorded = match self.send_to(issue, template, subscriber).await {
+                    Ok(receipt) => {
+                        sent += 1;
+                        self.deliveries.record_sent(issue.id, subscriber.id, receipt.message_id.as_deref()).await
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to send issue to subscriber {}: {}", subscriber.id, e);
+                        failed += 1;
+                        self.deliveries.record_failure(issue.id, subscriber.id, &e).await
+                    }
+                };
+                if let Err(e) = recorded {
+                    tracing::error!("Failed to record the delivery to subscriber {}: {}", subscriber.id, e);
+                }
+            }
+            match page.last() {
+                Some(last) if page.len() == RECIPIENT_PAGE => query.after = Some(Cursor::after(last, &query.sort)),
+                _ => return Ok((Progress::Finished, sent, failed)),
+            }
+        }
+    }
+
+    async fn send_to(&self, issue: &NewsletterIssue, template: &IssueTemplate, subscriber: &SubscriberRecord) -> Result<SendReceipt, String> {
+        let recipient = SubscriberEmail::parse(subscriber.email.clone())?;
+        let mut email = template.personalize(&Recipient {
+            name: &subscriber.name,
+            email: &subscriber.email,
+            attributes: &subscriber.attributes,
+            unsubscribe_url: &self.links.unsubscribe_url(subscriber.id, Some(issue.id)),
+        });
+        //合并标签替换之后再改写，链接中的标签已经是这个收件人的值
+        if issue.tracking && subscriber.tracking {
+            add_tracking(&mut email, &self.links, issue.id, subscriber.id);
+        }
+        self.email_client.send_rendered(recipient, &email).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{DeliveryStatus, IssueStatus};
+    use crate::repository::{
+        DeliveryQuery, InMemoryNewsletterRepository, InMemorySubscriberRepository, NewIssue, Suppression, SuppressionReason,
+        SuppressionRepository, CLAIM_TIMEOUT,
+    };
+    use crate::testing::{self, subscriber};
+    use std::collections::HashSet;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+    //收到第一封邮件时触发关闭，模拟发送途中收到 SIGTERM
+    struct ShutdownOnFirstEmail(Shutdown);
+
+    impl Respond for ShutdownOnFirstEmail {
+        fn respond(&self, _: &Request) -> ResponseTemplate {
+            self.0.trigger();
+            ResponseTemplate::new(200)
+        }
+    }
+
+    fn issue(title: &str, minutes_from_now: i64) -> NewIssue {
+        NewIssue {
+            title: title.to_string(),
+            content: "Hi {{ subscriber.name }}, [unsubscribe]({{ unsubscribe_url }})".to_string(),
+            scheduled_at: Utc::now() + chrono::Duration::minutes(minutes_from_now),
+            created_by: "admin".to_string(),
+            tracking: true,
+        }
+    }
+
+    fn scheduler(newsletters: &Arc<InMemoryNewsletterRepository>, subscribers: &Arc<InMemorySubscriberRepository>, server: &MockServer) -> Scheduler {
+        Scheduler::new(
+            newsletters.clone(),
+            subscribers.clone(),
+            subscribers.clone(),
+            testing::email_client(subscribers, server),
+            EmailTemplates::builtin(),
+            testing::links(),
+            Duration::from_secs(60),
+        )
+    }
+
+    #[tokio::test]
+    async fn due_issues_are_sent_to_confirmed_subscribers_once() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/email")).respond_with(ResponseTemplate::new(200)).expect(2).mount(&server).await;
+        let subscribers = Arc::new(InMemorySubscriberRepository::new());
+        subscriber(subscribers.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let octavia = subscriber(subscribers.as_ref(), "octavia@example.com", SubscriptionStatus::Confirmed).await;
+        subscribers.update_details(octavia, None, None, None, Some(false)).await.unwrap();
+        subscriber(subscribers.as_ref(), "ada@example.com", SubscriptionStatus::Unsubscribed).await;
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        let due = newsletters.create_issue(&issue("Issue 7", -1)).await.unwrap();
+        let later = newsletters.create_issue(&issue("Issue 8", 60)).await.unwrap();
+        let cancelled = newsletters.create_issue(&issue("Issue 9", -1)).await.unwrap();
+        newsletters.cancel_issue(cancelled.id).await.unwrap();
+
+        let scheduler = scheduler(&newsletters, &subscribers, &server);
+        assert_eq!(scheduler.send_due_issues(&Shutdown::new()).await.unwrap(), 1);
+        assert_eq!(scheduler.send_due_issues(&Shutdown::new()).await.unwrap(), 0);
+
+        let due = newsletters.find_issue(due.id).await.unwrap();
+        assert_eq!((due.status, due.sent_count, due.failed_count), (IssueStatus::Sent, 2, 0));
+        assert!(due.started_at.is_some() && due.completed_at.is_some());
+        assert_eq!(newsletters.find_issue(later.id).await.unwrap().status, IssueStatus::Scheduled);
+        assert_eq!(newsletters.find_issue(cancelled.id).await.unwrap().status, IssueStatus::Cancelled);
+
+        let bodies: Vec<serde_json::Value> = server.received_requests().await.unwrap().iter().map(|r| r.body_json().unwrap()).collect();
+        assert!(bodies.iter().all(|body| body["text_body"].as_str().unwrap().contains("Hi Ursula Le Guin")
+            && body["text_body"].as_str().unwrap().contains("https://newsletter.example.com/unsubscribe?token=")));
+        //关闭跟踪的订阅者收到的 HTML 没有跟踪像素
+        let pixels: Vec<(&str, bool)> = bodies.iter()
+            .map(|body| (body["to"].as_str().unwrap(), body["html_body"].as_str().unwrap().contains("/t/o/")))
+            .collect();
+        assert!(pixels.contains(&("ursula@example.com", true)) && pixels.contains(&("octavia@example.com", false)), "{:?}", pixels);
+    }
+
+    #[tokio::test]
+    async fn failed_sends_are_counted_and_shutdown_stops_claiming() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST")).respond_with(ResponseTemplate::new(500)).mount(&server).await;
+        let subscribers = Arc::new(InMemorySubscriberRepository::new());
+        subscriber(subscribers.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        let first = newsletters.create_issue(&issue("Issue 7", -2)).await.unwrap();
+        let second = newsletters.create_issue(&issue("Issue 8", -1)).await.unwrap();
+        let scheduler = scheduler(&newsletters, &subscribers, &server);
+
+        let shutdown = Shutdown::new();
+        shutdown.trigger();
+        assert_eq!(scheduler.send_due_issues(&shutdown).await.unwrap(), 0);
+        assert_eq!(newsletters.find_issue(first.id).await.unwrap().status, IssueStatus::Scheduled);
+
+        assert_eq!(scheduler.send_due_issues(&Shutdown::new()).await.unwrap(), 2);
+        let first = newsletters.find_issue(first.id).await.unwrap();
+        assert_eq!((first.status, first.sent_count, first.failed_count), (IssueStatus::Sent, 0, 1));
+        assert_eq!(newsletters.find_issue(second.id).await.unwrap().status, IssueStatus::Sent);
+    }
+
+    #[tokio::test]
+    async fn issues_that_cannot_be_rendered_are_marked_failed() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST")).respond_with(ResponseTemplate::new(200)).expect(1).mount(&server).await;
+        let subscribers = Arc::new(InMemorySubscriberRepository::new());
+        subscriber(subscribers.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        //接口会拒绝不认识的合并标签，这里绕过接口直接写入
+        let mut broken = issue("Issue 7", -2);
+        broken.content = "Hi {{ subscriber.first_name }}".to_string();
+        let broken = newsletters.create_issue(&broken).await.unwrap();
+        let next = newsletters.create_issue(&issue("Issue 8", -1)).await.unwrap();
+        let scheduler = scheduler(&newsletters, &subscribers, &server);
+
+        assert_eq!(scheduler.send_due_issues(&Shutdown::new()).await.unwrap(), 1);
+        let broken = newsletters.find_issue(broken.id).await.unwrap();
+        assert_eq!((broken.status, broken.sent_count, broken.claimed_at), (IssueStatus::Failed, 0, None));
+        assert!(broken.failure_reason.unwrap().contains("subscriber.first_name"));
+        assert_eq!(newsletters.find_issue(next.id).await.unwrap().status, IssueStatus::Sent);
+    }
+
+    #[tokio::test]
+    async fn suppressed_addresses_are_not_sent_to() {
+        let server = MockServer::start().await;
+        let accepted = serde_json::json!({ "To": "ursula@example.com", "MessageID": "883953f4", "ErrorCode": 0, "Message": "OK" });
+        Mock::given(method("POST")).and(path("/email")).respond_with(ResponseTemplate::new(200).set_body_json(accepted)).expect(1).mount(&server).await;
+        let subscribers = Arc::new(InMemorySubscriberRepository::new());
+        let ursula = subscriber(subscribers.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let octavia_id = subscriber(subscribers.as_ref(), "octavia@example.com", SubscriptionStatus::Confirmed).await;
+        //例如投诉事件先于状态变化到达
+        let octavia = SubscriberEmail::parse("octavia@example.com".to_string()).unwrap();
+        subscribers.suppress(&Suppression::new(&octavia, SuppressionReason::Complaint, None, None)).await.unwrap();
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        let issue = newsletters.create_issue(&issue("Issue 7", -1)).await.unwrap();
+
+        assert_eq!(scheduler(&newsletters, &subscribers, &server).send_due_issues(&Shutdown::new()).await.unwrap(), 1);
+        let issue = newsletters.find_issue(issue.id).await.unwrap();
+        assert_eq!((issue.sent_count, issue.failed_count), (1, 1));
+        let request = &server.received_requests().await.unwrap()[0];
+        assert_eq!(request.body_json::<serde_json::Value>().unwrap()["to"], "ursula@example.com");
+
+        //每个收件人一条投递记录：发出的带服务商的 message id，停发的记为失败
+        let sent = subscribers.find_delivery(issue.id, ursula).await.unwrap();
+        assert_eq!((sent.status, sent.message_id.as_deref(), sent.attempts), (DeliveryStatus::Sent, Some("883953f4"), 1));
+        assert!(sent.sent_at.is_some());
+        let failed = subscribers.find_delivery(issue.id, octavia_id).await.unwrap();
+        assert_eq!((failed.status, failed.attempts), (DeliveryStatus::Failed, 1));
+        assert!(failed.last_error.unwrap().contains("suppression list"));
+        let query = DeliveryQuery { status: Some(DeliveryStatus::Failed), after: None, limit: 10 };
+        assert_eq!(subscribers.list_deliveries(issue.id, &query).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn shutdown_stops_between_pages_and_the_next_claim_resumes() {
+        let server = MockServer::start().await;
+        let shutdown = Shutdown::new();
+        Mock::given(method("POST")).and(path("/email")).respond_with(ShutdownOnFirstEmail(shutdown.clone())).mount(&server).await;
+        let subscribers = Arc::new(InMemorySubscriberRepository::new());
+        for i in 0..=RECIPIENT_PAGE {
+            subscriber(subscribers.as_ref(), &format!("reader{}@example.com", i), SubscriptionStatus::Confirmed).await;
+        }
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        let issue = newsletters.create_issue(&issue("Issue 7", -1)).await.unwrap();
+        let scheduler = scheduler(&newsletters, &subscribers, &server);
+
+        //第一页发完之后停下，这一期保持 sending 并放弃领取
+        assert_eq!(scheduler.send_due_issues(&shutdown).await.unwrap(), 0);
+        let interrupted = newsletters.find_issue(issue.id).await.unwrap();
+        assert_eq!((interrupted.status, interrupted.claimed_at), (IssueStatus::Sending, None));
+        assert_eq!(server.received_requests().await.unwrap().len(), RECIPIENT_PAGE);
+
+        //重启之后接着发送，第一页的收件人不会再收到
+        assert_eq!(scheduler.send_due_issues(&Shutdown::new()).await.unwrap(), 1);
+        let requests = server.received_requests().await.unwrap();
+        let recipients: HashSet<String> = requests.iter()
+            .map(|request| request.body_json::<serde_json::Value>().unwrap()["to"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!((requests.len(), recipients.len()), (RECIPIENT_PAGE + 1, RECIPIENT_PAGE + 1));
+        let sent = newsletters.find_issue(issue.id).await.unwrap();
+        assert_eq!((sent.status, sent.sent_count, sent.failed_count), (IssueStatus::Sent, RECIPIENT_PAGE as i64 + 1, 0));
+        assert_eq!(sent.started_at, interrupted.started_at);
+    }
+
+    #[tokio::test]
+    async fn only_expired_claims_are_taken_over() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/email")).respond_with(ResponseTemplate::new(200)).expect(1).mount(&server).await;
+        let subscribers = Arc::new(InMemorySubscriberRepository::new());
+        subscriber(subscribers.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        let abandoned = newsletters.create_issue(&issue("Issue 7", -60)).await.unwrap();
+        let active = newsletters.create_issue(&issue("Issue 8", -30)).await.unwrap();
+        //另一个实例很久之前领取了第一期之后退出了；第二期几分钟前被领取，还在发送
+        let long_ago = Utc::now() - chrono::Duration::from_std(CLAIM_TIMEOUT).unwrap() - chrono::Duration::minutes(1);
+        assert_eq!(newsletters.claim_due_issue(long_ago).await.unwrap().unwrap().id, abandoned.id);
+        assert_eq!(newsletters.claim_due_issue(Utc::now() - chrono::Duration::minutes(5)).await.unwrap().unwrap().id, active.id);
+
+        assert_eq!(scheduler(&newsletters, &subscribers, &server).send_due_issues(&Shutdown::new()).await.unwrap(), 1);
+        assert_eq!(newsletters.find_issue(abandoned.id).await.unwrap().status, IssueStatus::Sent);
+        assert_eq!(newsletters.find_issue(active.id).await.unwrap().status, IssueStatus::Sending);
+    }
+}
@@ -0,0 +1,109 @@
+//! 打开和点击跟踪：发送前给收件人的 HTML 加上跟踪像素，外部链接改写为经由 `/t/c/` 的跳转
+use super::escape_html;
+use crate::email_templates::RenderedEmail;
+use crate::links::EmailLinks;
+use url::Url;
+use uuid::Uuid;
+
+/// 只改 HTML；纯文本版本中的地址保持原样，读者看到的就是真实的目标
+///
+/// 指向本站的链接（退订等）和 `mailto:` 之类的非 http(s) 链接不改写
+pub fn add_tracking(email: &mut RenderedEmail, links: &EmailLinks, issue_id: Uuid, subscriber_id: Uuid) {
+    //按解析后的 origin（协议、主机、端口）判断是否本站，前缀比较会把 newsletter.example.com.evil.com 当成本站
+    let site = Url::parse(links.base_url()).ok().map(|url| url.origin());
+    email.html = rewrite_links(&email.html, |url| {
+        let external = match Url::parse(url) {
+            Ok(target) => matches!(target.scheme(), "http" | "https") && Some(target.origin()) != site,
+            Err(_) => false,
+        };
+        external.then(|| links.click_url(issue_id, subscriber_id, url))
+    });
+    let pixel = format!(
+        "<img src=\"{}\" width=\"1\" height=\"1\" alt=\"\" style=\"display:block;border:0\">",
+        escape_html(&links.open_url(issue_id, subscriber_id))
+    );
+    match email.html.rfind("</body>") {
+        Some(position) => email.html.insert_str(position, &pixel),
+        None => email.html.push_str(&pixel),
+    }
+}
+
+//HTML 经过 ammonia 清理和模板渲染，属性值都用双引号；正文文字中的 " 不转义，所以只改标签里面的 href
+fn rewrite_links(html: &str, mut rewrite: impl FnMut(&str) -> Option<String>) -> String {
+    const HREF: &str = " href=\"";
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    let mut in_tag = false;
+    while let Some(start) = rest.find(HREF) {
+        let value_start = start + HREF.len();
+        let Some(length) = rest[value_start..].find('"') else {
+            break;
+        };
+        //上一个 < 在上一个 > 之后，说明 href 在标签里面
+        let before = &rest[..start];
+        in_tag = match (before.rfind('<'), before.rfind('>')) {
+            (Some(open), Some(close)) => open > close,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => in_tag,
+        };
+        let value = &rest[value_start..value_start + length];
+        output.push_str(&rest[..value_start]);
+        match in_tag.then(|| rewrite(&unescape_html(value))).flatten() {
+            Some(url) => output.push_str(&escape_html(&url)),
+            None => output.push_str(value),
+        }
+        rest = &rest[value_start + length..];
+    }
+    output.push_str(rest);
+    output
+}
+
+//escape_html 和 HTML 序列化会产生的实体，&amp; 最后替换
+fn unescape_html(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::links;
+
+    fn token(html: &str, prefix: &str) -> String {
+        let start = html.find(prefix).unwrap() + prefix.len();
+        html[start..start + html[start..].find('"').unwrap()].to_string()
+    }
+
+    #[test]
+    fn external_links_are_rewritten_and_a_pixel_is_added() {
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut email = RenderedEmail {
+            subject: "Issue 7".to_string(),
+            html: "<html><body><p>See <a href=\"https://example.com/?a=1&amp;b=2\">the docs</a>, \
+                   <a href=\"mailto:editor@example.com\">write to us</a> or \
+                   <a href=\"https://newsletter.example.com.evil.com/\">a lookalike</a>, \
+                   <a href=\"https://newsletter.example.com/unsubscribe?token=x\">unsubscribe</a>. \
+                   Text with href=\"https://example.com/\" stays.</p></body></html>".to_string(),
+            text: "See the docs [1]\n\n[1] https://example.com/?a=1&b=2".to_string(),
+            list_unsubscribe: None,
+        };
+        add_tracking(&mut email, &links(), issue_id, subscriber_id);
+
+        let click = token(&email.html, "<a href=\"https://newsletter.example.com/t/c/");
+        assert_eq!(links().verify_click(&click), Some((issue_id, subscriber_id, "https://example.com/?a=1&b=2".to_string())));
+        assert!(email.html.contains("href=\"mailto:editor@example.com\""));
+        assert!(!email.html.contains("href=\"https://newsletter.example.com.evil.com/\""), "{}", email.html);
+        assert!(email.html.contains("href=\"https://newsletter.example.com/unsubscribe?token=x\""));
+        assert!(email.html.contains("Text with href=\"https://example.com/\" stays."));
+        assert!(email.html.ends_with("\"></body></html>"), "{}", email.html);
+        let open = token(&email.html, "<img src=\"https://newsletter.example.com/t/o/");
+        assert_eq!(links().verify_open(&open), Some((issue_id, subscriber_id)));
+        assert!(email.text.ends_with("[1] https://example.com/?a=1&b=2"));
+    }
+}
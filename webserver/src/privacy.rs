@@ -0,0 +1,77 @@
+//! 订阅者自助查看和删除个人数据用的魔法链接令牌
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+
+//链接的有效期
+pub const LINK_LIFETIME_MINUTES: i64 = 60;
+
+/// 新生成的令牌：明文只出现在邮件里，数据库只保存哈希
+pub struct PrivacyToken {
+    pub token: String,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl PrivacyToken {
+    pub fn generate() -> Self {
+        //32 字节随机数，base64url 编码后可以直接放在查询参数里
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token = URL_SAFE_NO_PAD.encode(bytes);
+        Self {
+            token_hash: hash_token(&token),
+            token,
+            expires_at: Utc::now() + Duration::minutes(LINK_LIFETIME_MINUTES),
+        }
+    }
+
+    /// 邮件中的链接，`base_url` 是 `application.base_url`
+    pub fn link(&self, base_url: &str) -> String {
+        format!("{}/privacy/data?token={}", base_url.trim_end_matches('/'), self.token)
+    }
+}
+
+/// 令牌随机且足够长，不需要加盐或慢哈希，SHA-256 即可
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// 删除个人数据之后停发名单中保存的邮箱：小写邮箱的 SHA-256，只用来判断这个地址是否被停发
+pub fn hash_email(email: &str) -> String {
+    format!("{:x}", Sha256::digest(email.to_lowercase().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_random_and_stored_as_their_hash() {
+        let first = PrivacyToken::generate();
+        let second = PrivacyToken::generate();
+        assert_ne!(first.token, second.token);
+        assert_eq!(first.token.len(), 43);
+        assert_eq!(first.token_hash, hash_token(&first.token));
+        assert_eq!(first.token_hash.len(), 64);
+        assert_ne!(first.token_hash, first.token);
+    }
+
+    #[test]
+    fn email_hashes_ignore_case() {
+        assert_eq!(hash_email("ursula@example.com"), hash_email("Ursula@Example.com"));
+        assert_eq!(hash_email("ursula@example.com").len(), 64);
+        assert_ne!(hash_email("ursula@example.com"), hash_email("iain@example.com"));
+    }
+
+    #[test]
+    fn links_point_at_the_data_endpoint() {
+        let token = PrivacyToken::generate();
+        assert_eq!(
+            token.link("https://newsletter.example.com/"),
+            format!("https://newsletter.example.com/privacy/data?token={}", token.token)
+        );
+    }
+}
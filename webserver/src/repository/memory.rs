@@ -0,0 +1,981 @@
+use crate::authentication::compute_password_hash;
+use crate::domain::{DeliveryStatus, IssueStatus, NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::repository::{
+    ActivityBucket, ConsentQuery, ConsentRecord, Cursor, CursorKey, Delivery, DeliveryQuery, DeliveryRepository, DeliverySummary, DraftEdit, DraftRevision, DuplicatePolicy, ErasureTombstone,
+    ImportOptions, ImportOutcome, IssueAnalytics, LinkClicks, NewConsent, NewIssue, NewsletterDraft, NewsletterIssue,
+    NewsletterRepository, PersonalData, PrivacyRepository, RepositoryError, StoredCredentials, SubscriberQuery,
+    SubscriberRecord, SubscriberRepository, SubscriberStream, SubscriptionEvent, Suppression, SuppressionRepository,
+    TrackingEvent, TrackingKind, TrackingRepository, UserRepository,
+};
+use crate::privacy::hash_email;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use secrecy::Secret;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// 内存实现，用于不需要数据库的测试
+#[derive(Default)]
+pub struct InMemorySubscriberRepository {
+    records: Mutex<Vec<SubscriberRecord>>,
+    events: Mutex<Vec<SubscriptionEvent>>,
+    consents: Mutex<Vec<ConsentRecord>>,
+    tracking_events: Mutex<Vec<TrackingEvent>>,
+    suppressions: Mutex<Vec<Suppression>>,
+    //删除个人数据之后的停发记录，按邮箱哈希保存，对应数据库中 email 为空的行
+    erased_suppressions: Mutex<HashMap<String, Suppression>>,
+    //按 (issue_id, subscriber_id) 排序，与数据库的主键顺序一致
+    deliveries: Mutex<BTreeMap<(Uuid, Uuid), Delivery>>,
+    //(令牌哈希, 邮箱, 过期时间)
+    tokens: Mutex<Vec<(String, String, DateTime<Utc>)>>,
+    tombstones: Mutex<Vec<ErasureTombstone>>,
+}
+
+//订阅者的邮箱不区分大小写，对应数据库中的 lower(email)
+fn same_email(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+impl InMemorySubscriberRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record_event(&self, subscriber_id: Uuid, from_status: Option<SubscriptionStatus>, to_status: SubscriptionStatus, reason: &str) {
+        self.events.lock().unwrap().push(SubscriptionEvent {
+            id: Uuid::new_v4(),
+            subscriber_id,
+            from_status,
+            to_status,
+            reason: reason.to_string(),
+            occurred_at: chrono::Utc::now(),
+        });
+    }
+
+    fn select(&self, query: &SubscriberQuery) -> Vec<SubscriberRecord> {
+        let sort = query.sort;
+        let compare = |a: &SubscriberRecord, b: &SubscriberRecord| {
+            let ordering = compare_keys(&Cursor::after(a, &sort).key, &Cursor::after(b, &sort).key).then(a.id.cmp(&b.id));
+            if sort.descending { ordering.reverse() } else { ordering }
+        };
+        let mut records: Vec<SubscriberRecord> = self.records.lock().unwrap().iter()
+            .filter(|record| query.matches(record))
+            .filter(|record| match &query.after {
+                Some(cursor) => {
+                    let ordering = compare_keys(&Cursor::after(record, &sort).key, &cursor.key).then(record.id.cmp(&cursor.id));
+                    if sort.descending { ordering == Ordering::Less } else { ordering == Ordering::Greater }
+                }
+                None => true,
+            })
+            .cloned()
+            .collect();
+        records.sort_by(compare);
+        records.truncate(query.limit);
+        records
+    }
+}
+
+#[async_trait]
+impl SubscriberRepository for InMemorySubscriberRepository {
+    async fn insert(&self, subscriber: &NewSubscriber, status: SubscriptionStatus, reason: &str) -> Result<Uuid, RepositoryError> {
+        let id = Uuid::new_v4();
+        let now = chrono::Utc::now();
+        let mut records = self.records.lock().unwrap();
+        //与数据库中 lower(email) 上的唯一索引一致
+        if records.iter().any(|record| same_email(&record.email, subscriber.email.as_ref())) {
+            return Err(RepositoryError::Conflict(format!("{} is already subscribed", subscriber.email)));
+        }
+        records.push(SubscriberRecord {
+            id,
+            email: subscriber.email.as_ref().to_string(),
+            name: subscriber.name.as_ref().to_string(),
+            subscribed_at: now,
+            status,
+            attributes: SubscriberAttributes::default(),
+            tracking: true,
+        });
+        drop(records);
+        self.record_event(id, None, status, reason);
+        Ok(id)
+    }
+
+    async fn insert_with_consent(
+        &self,
+        subscriber: &NewSubscriber,
+        status: SubscriptionStatus,
+        reason: &str,
+        consent: &NewConsent,
+    ) -> Result<Uuid, RepositoryError> {
+        let id = self.insert(subscriber, status, reason).await?;
+        self.consents.lock().unwrap().push(ConsentRecord::new(id, consent, Utc::now()));
+        Ok(id)
+    }
+
+    async fn find(&self, id: Uuid) -> Result<SubscriberRecord, RepositoryError> {
+        let records = self.records.lock().unwrap();
+        records.iter().find(|record| record.id == id).cloned().ok_or(RepositoryError::NotFound)
+    }
+
+    async fn find_by_email(&self, email: &SubscriberEmail) -> Result<Option<SubscriberRecord>, RepositoryError> {
+        let records = self.records.lock().unwrap();
+        Ok(records.iter().find(|record| same_email(&record.email, email.as_ref())).cloned())
+    }
+
+    async fn update_details(
+        &self,
+        id: Uuid,
+        name: Option<&SubscriberName>,
+        email: Option<&SubscriberEmail>,
+        attributes: Option<&SubscriberAttributes>,
+        tracking: Option<bool>,
+    ) -> Result<SubscriberRecord, RepositoryError> {
+        let mut records = self.records.lock().unwrap();
+        if let Some(email) = email {
+            if records.iter().any(|record| record.id != id && same_email(&record.email, email.as_ref())) {
+                return Err(RepositoryError::Conflict(format!("{} is already subscribed", email)));
+            }
+        }
+        let record = records.iter_mut().find(|record| record.id == id).ok_or(RepositoryError::NotFound)?;
+        if let Some(name) = name {
+            record.name = name.as_ref().to_string();
+        }
+        if let Some(email) = email {
+            record.email = email.as_ref().to_string();
+        }
+        if let Some(attributes) = attributes {
+            record.attributes = attributes.clone();
+        }
+        if let Some(tracking) = tracking {
+            record.tracking = tracking;
+        }
+        Ok(record.clone())
+    }
+
+    async fn change_status(&self, id: Uuid, to: SubscriptionStatus, reason: &str) -> Result<SubscriptionStatus, RepositoryError> {
+        let from = {
+            let mut records = self.records.lock().unwrap();
+            let record = records.iter_mut().find(|record| record.id == id).ok_or(RepositoryError::NotFound)?;
+            let from = record.status;
+            record.status = from.transition_to(to)?;
+            from
+        };
+        self.record_event(id, Some(from), to, reason);
+        Ok(from)
+    }
+
+    async fn change_status_with_consent(
+        &self,
+        id: Uuid,
+        to: SubscriptionStatus,
+        reason: &str,
+        consent: &NewConsent,
+    ) -> Result<SubscriptionStatus, RepositoryError> {
+        let from = self.change_status(id, to, reason).await?;
+        self.consents.lock().unwrap().push(ConsentRecord::new(id, consent, Utc::now()));
+        Ok(from)
+    }
+
+    async fn events(&self, id: Uuid) -> Result<Vec<SubscriptionEvent>, RepositoryError> {
+        let events = self.events.lock().unwrap();
+        Ok(events.iter().filter(|event| event.subscriber_id == id).cloned().collect())
+    }
+
+    async fn consents(&self, query: &ConsentQuery) -> Result<Vec<ConsentRecord>, RepositoryError> {
+        let consents = self.consents.lock().unwrap();
+        //按插入顺序保存，倒序即按时间倒序
+        Ok(consents.iter().rev().filter(|record| query.matches(record)).take(query.limit).cloned().collect())
+    }
+
+    async fn search(&self, query: &SubscriberQuery) -> Result<Vec<SubscriberRecord>, RepositoryError> {
+        Ok(self.select(query))
+    }
+
+    fn stream(&self, query: SubscriberQuery) -> SubscriberStream {
+        let records = self.select(&SubscriberQuery { limit: usize::MAX, ..query });
+        futures_util::stream::iter(records.into_iter().map(Ok)).boxed()
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<(), RepositoryError> {
+        let mut records = self.records.lock().unwrap();
+        let before = records.len();
+        records.retain(|record| record.id != id);
+        if records.len() == before {
+            return Err(RepositoryError::NotFound);
+        }
+        //与数据库中的 on delete cascade 一致
+        self.events.lock().unwrap().retain(|event| event.subscriber_id != id);
+        self.consents.lock().unwrap().retain(|record| record.subscriber_id != id);
+        self.tracking_events.lock().unwrap().retain(|event| event.subscriber_id != id);
+        self.deliveries.lock().unwrap().retain(|_, delivery| delivery.subscriber_id != id);
+        Ok(())
+    }
+
+    async fn import(&self, batch: &[NewSubscriber], options: &ImportOptions) -> Result<ImportOutcome, RepositoryError> {
+        let existing: HashSet<String> = {
+            let records = self.records.lock().unwrap();
+            batch.iter()
+                .map(|subscriber| subscriber.email.as_ref())
+                .filter(|email| records.iter().any(|record| same_email(&record.email, email)))
+                .map(|email| email.to_lowercase())
+                .collect()
+        };
+        let (new, duplicates) = ImportOutcome::partition(batch, &existing);
+        let mut outcome = ImportOutcome { inserted: new.len(), ..ImportOutcome::default() };
+        match options.on_duplicate {
+            DuplicatePolicy::Skip => {
+                outcome.skipped = duplicates.iter().map(|subscriber| subscriber.email.as_ref().to_string()).collect()
+            }
+            DuplicatePolicy::Merge => outcome.merged = duplicates.len(),
+        }
+        if options.dry_run {
+            return Ok(outcome);
+        }
+        for subscriber in new {
+            self.insert_with_consent(subscriber, options.status, &options.reason, &options.consent).await?;
+        }
+        if options.on_duplicate == DuplicatePolicy::Merge {
+            let mut records = self.records.lock().unwrap();
+            for subscriber in duplicates {
+                for record in records.iter_mut().filter(|record| same_email(&record.email, subscriber.email.as_ref())) {
+                    record.name = subscriber.name.as_ref().to_string();
+                }
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+#[async_trait]
+impl PrivacyRepository for InMemorySubscriberRepository {
+    async fn personal_data(&self, email: &SubscriberEmail) -> Result<PersonalData, RepositoryError> {
+        let subscriptions: Vec<SubscriberRecord> = self.records.lock().unwrap().iter()
+            .filter(|record| same_email(&record.email, email.as_ref()))
+            .cloned()
+            .collect();
+        let subscription_events = self.events.lock().unwrap().iter()
+            .filter(|event| subscriptions.iter().any(|record| record.id == event.subscriber_id))
+            .cloned()
+            .collect();
+        let consent_records = self.consents.lock().unwrap().iter()
+            .filter(|record| subscriptions.iter().any(|subscription| subscription.id == record.subscriber_id))
+            .cloned()
+            .collect();
+        let tracking_events = self.tracking_events.lock().unwrap().iter()
+            .filter(|event| subscriptions.iter().any(|subscription| subscription.id == event.subscriber_id))
+            .cloned()
+            .collect();
+        let suppressions = self.suppressions.lock().unwrap().iter()
+            .filter(|suppression| suppression.email == email.as_ref())
+            .cloned()
+            .collect();
+        let mut deliveries: Vec<Delivery> = self.deliveries.lock().unwrap().values()
+            .filter(|delivery| subscriptions.iter().any(|subscription| subscription.id == delivery.subscriber_id))
+            .cloned()
+            .collect();
+        deliveries.sort_by_key(|delivery| (delivery.queued_at, delivery.issue_id));
+        Ok(PersonalData {
+            email: email.as_ref().to_string(),
+            exported_at: Utc::now(),
+            subscriptions,
+            subscription_events,
+            consent_records,
+            tracking_events,
+            suppressions,
+            deliveries,
+        })
+    }
+
+    async fn erase(&self, email: &SubscriberEmail, requested_by: &str, reason: &str) -> Result<ErasureTombstone, RepositoryError> {
+        let mut records = self.records.lock().unwrap();
+        let ids: HashSet<Uuid> = records.iter().filter(|record| same_email(&record.email, email.as_ref())).map(|record| record.id).collect();
+        let mut events = self.events.lock().unwrap();
+        let mut consents = self.consents.lock().unwrap();
+        let mut tracking_events = self.tracking_events.lock().unwrap();
+        let mut tokens = self.tokens.lock().unwrap();
+        let mut suppressions = self.suppressions.lock().unwrap();
+        let mut hashed_suppressions = self.erased_suppressions.lock().unwrap();
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let erased_events = events.iter().filter(|event| ids.contains(&event.subscriber_id)).count();
+        let erased_consents = consents.iter().filter(|record| ids.contains(&record.subscriber_id)).count();
+        let erased_tracking_events = tracking_events.iter().filter(|event| ids.contains(&event.subscriber_id)).count();
+        let erased_tokens = tokens.iter().filter(|(_, token_email, _)| token_email == email.as_ref()).count();
+        let erased_suppressions = suppressions.iter().filter(|suppression| suppression.email == email.as_ref()).count();
+        let erased_deliveries = deliveries.values().filter(|delivery| ids.contains(&delivery.subscriber_id)).count();
+        let erased_rows = BTreeMap::from([
+            ("subscriptions".to_string(), ids.len() as u64),
+            ("subscription_events".to_string(), erased_events as u64),
+            ("consent_records".to_string(), erased_consents as u64),
+            ("tracking_events".to_string(), erased_tracking_events as u64),
+            ("privacy_tokens".to_string(), erased_tokens as u64),
+            ("suppression_list".to_string(), erased_suppressions as u64),
+            ("deliveries".to_string(), erased_deliveries as u64),
+        ]);
+        let tombstone = ErasureTombstone::new(requested_by, reason, erased_rows);
+        if tombstone.is_empty() {
+            return Err(RepositoryError::NotFound);
+        }
+        records.retain(|record| !ids.contains(&record.id));
+        events.retain(|event| !ids.contains(&event.subscriber_id));
+        consents.retain(|record| !ids.contains(&record.subscriber_id));
+        tracking_events.retain(|event| !ids.contains(&event.subscriber_id));
+        tokens.retain(|(_, token_email, _)| token_email != email.as_ref());
+        //停发记录只清空邮箱和服务商信息，按哈希保留
+        for suppression in suppressions.iter().filter(|suppression| suppression.email == email.as_ref()) {
+            hashed_suppressions.insert(hash_email(email.as_ref()), Suppression {
+                email: String::new(),
+                detail: None,
+                message_id: None,
+                ..suppression.clone()
+            });
+        }
+        suppressions.retain(|suppression| suppression.email != email.as_ref());
+        deliveries.retain(|_, delivery| !ids.contains(&delivery.subscriber_id));
+        self.tombstones.lock().unwrap().push(tombstone.clone());
+        Ok(tombstone)
+    }
+
+    async fn tombstones(&self, limit: usize) -> Result<Vec<ErasureTombstone>, RepositoryError> {
+        let tombstones = self.tombstones.lock().unwrap();
+        Ok(tombstones.iter().rev().take(limit).cloned().collect())
+    }
+
+    async fn insert_token(&self, token_hash: &str, email: &SubscriberEmail, expires_at: DateTime<Utc>) -> Result<(), RepositoryError> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let now = Utc::now();
+        tokens.retain(|(_, _, expires_at)| *expires_at > now);
+        tokens.push((token_hash.to_string(), email.as_ref().to_string(), expires_at));
+        Ok(())
+    }
+
+    async fn token_email(&self, token_hash: &str) -> Result<Option<String>, RepositoryError> {
+        let tokens = self.tokens.lock().unwrap();
+        let now = Utc::now();
+        Ok(tokens.iter()
+            .find(|(hash, _, expires_at)| hash == token_hash && *expires_at > now)
+            .map(|(_, email, _)| email.clone()))
+    }
+}
+
+#[async_trait]
+impl SuppressionRepository for InMemorySubscriberRepository {
+    async fn suppress(&self, suppression: &Suppression) -> Result<bool, RepositoryError> {
+        let mut suppressions = self.suppressions.lock().unwrap();
+        if suppressions.iter().any(|existing| existing.email == suppression.email)
+            || self.erased_suppressions.lock().unwrap().contains_key(&hash_email(&suppression.email))
+        {
+            return Ok(false);
+        }
+        suppressions.push(suppression.clone());
+        Ok(true)
+    }
+
+    async fn find_suppression(&self, email: &SubscriberEmail) -> Result<Option<Suppression>, RepositoryError> {
+        let suppressions = self.suppressions.lock().unwrap();
+        if let Some(suppression) = suppressions.iter().find(|suppression| suppression.email == email.as_ref()) {
+            return Ok(Some(suppression.clone()));
+        }
+        let erased = self.erased_suppressions.lock().unwrap();
+        Ok(erased.get(&hash_email(email.as_ref())).map(|suppression| Suppression { email: email.as_ref().to_string(), ..suppression.clone() }))
+    }
+
+    async fn list_suppressions(&self, limit: usize) -> Result<Vec<Suppression>, RepositoryError> {
+        let suppressions = self.suppressions.lock().unwrap();
+        Ok(suppressions.iter().rev().take(limit).cloned().collect())
+    }
+
+    async fn lift_suppression(&self, email: &SubscriberEmail) -> Result<(), RepositoryError> {
+        let mut suppressions = self.suppressions.lock().unwrap();
+        let count = suppressions.len();
+        suppressions.retain(|suppression| suppression.email != email.as_ref());
+        let erased = self.erased_suppressions.lock().unwrap().remove(&hash_email(email.as_ref())).is_some();
+        if suppressions.len() == count && !erased {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TrackingRepository for InMemorySubscriberRepository {
+    async fn record_event(&self, event: &TrackingEvent) -> Result<bool, RepositoryError> {
+        let records = self.records.lock().unwrap();
+        if !records.iter().any(|record| record.id == event.subscriber_id && record.tracking) {
+            return Ok(false);
+        }
+        self.tracking_events.lock().unwrap().push(event.clone());
+        //与 TRACK_DELIVERY 一致
+        if let Some(delivery) = self.deliveries.lock().unwrap().get_mut(&(event.issue_id, event.subscriber_id)) {
+            let click = event.kind == TrackingKind::Click.as_str();
+            let sent = matches!(delivery.status, DeliveryStatus::Sent | DeliveryStatus::Opened | DeliveryStatus::Clicked);
+            delivery.status = match delivery.status {
+                DeliveryStatus::Sent | DeliveryStatus::Opened if click => DeliveryStatus::Clicked,
+                DeliveryStatus::Sent => DeliveryStatus::Opened,
+                status => status,
+            };
+            if sent {
+                delivery.opened_at.get_or_insert(event.occurred_at);
+                if click {
+                    delivery.clicked_at.get_or_insert(event.occurred_at);
+                }
+            }
+            delivery.updated_at = event.occurred_at;
+        }
+        Ok(true)
+    }
+
+    async fn issue_analytics(&self, issue_id: Uuid) -> Result<IssueAnalytics, RepositoryError> {
+        let events = self.tracking_events.lock().unwrap();
+        let mut analytics = IssueAnalytics::default();
+        let (mut openers, mut clickers) = (HashSet::new(), HashSet::new());
+        //url -> (点击数, 点击过的订阅者)
+        let mut links: BTreeMap<&str, (i64, HashSet<Uuid>)> = BTreeMap::new();
+        for event in events.iter().filter(|event| event.issue_id == issue_id) {
+            match event.url.as_deref() {
+                None => {
+                    analytics.opens += 1;
+                    openers.insert(event.subscriber_id);
+                }
+                Some(url) => {
+                    analytics.clicks += 1;
+                    clickers.insert(event.subscriber_id);
+                    let link = links.entry(url).or_default();
+                    link.0 += 1;
+                    link.1.insert(event.subscriber_id);
+                }
+            }
+        }
+        analytics.unique_opens = openers.len() as i64;
+        analytics.unique_clicks = clickers.len() as i64;
+        analytics.links = links.into_iter()
+            .map(|(url, (clicks, subscribers))| LinkClicks { url: url.to_string(), clicks, unique_clicks: subscribers.len() as i64 })
+            .collect();
+        //与 SQL 的 ORDER BY clicks DESC, url 一致；sort_by_key 是稳定排序
+        analytics.links.sort_by_key(|link| std::cmp::Reverse(link.clicks));
+        Ok(analytics)
+    }
+
+    async fn link_clicks(&self, issue_id: Uuid) -> Result<Vec<LinkClicks>, RepositoryError> {
+        Ok(self.issue_analytics(issue_id).await?.links)
+    }
+
+    async fn activity(&self, issue_id: Uuid, since: DateTime<Utc>, bucket_seconds: i64) -> Result<Vec<ActivityBucket>, RepositoryError> {
+        let events = self.tracking_events.lock().unwrap();
+        let mut buckets: BTreeMap<i64, ActivityBucket> = BTreeMap::new();
+        for event in events.iter().filter(|event| event.issue_id == issue_id && event.occurred_at >= since) {
+            let bucket = (event.occurred_at - since).num_seconds() / bucket_seconds;
+            let counts = buckets.entry(bucket).or_insert(ActivityBucket { bucket, opens: 0, clicks: 0 });
+            if event.url.is_some() {
+                counts.clicks += 1;
+            } else {
+                counts.opens += 1;
+            }
+        }
+        Ok(buckets.into_values().collect())
+    }
+}
+
+fn queued(issue_id: Uuid, subscriber_id: Uuid, now: DateTime<Utc>) -> Delivery {
+    Delivery {
+        issue_id,
+        subscriber_id,
+        status: DeliveryStatus::Queued,
+        message_id: None,
+        attempts: 0,
+        last_error: None,
+        queued_at: now,
+        sent_at: None,
+        opened_at: None,
+        clicked_at: None,
+        bounced_at: None,
+        unsubscribed_at: None,
+        updated_at: now,
+    }
+}
+
+impl InMemorySubscriberRepository {
+    //与 SQL 版本的 INSERT ... ON CONFLICT DO UPDATE 一致，没有记录时新建一行
+    fn upsert_delivery(&self, issue_id: Uuid, subscriber_id: Uuid, update: impl FnOnce(&mut Delivery)) {
+        let now = Utc::now();
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let delivery = deliveries.entry((issue_id, subscriber_id)).or_insert_with(|| queued(issue_id, subscriber_id, now));
+        delivery.attempts += 1;
+        delivery.updated_at = now;
+        update(delivery);
+    }
+}
+
+#[async_trait]
+impl DeliveryRepository for InMemorySubscriberRepository {
+    async fn queue_deliveries(&self, issue_id: Uuid, subscriber_ids: &[Uuid]) -> Result<Vec<Uuid>, RepositoryError> {
+        let records = self.records.lock().unwrap();
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let now = Utc::now();
+        let mut pending = Vec::new();
+        for &subscriber_id in subscriber_ids.iter().filter(|id| records.iter().any(|record| record.id == **id)) {
+            let delivery = deliveries.entry((issue_id, subscriber_id)).or_insert_with(|| queued(issue_id, subscriber_id, now));
+            if delivery.status == DeliveryStatus::Queued {
+                pending.push(subscriber_id);
+            }
+        }
+        Ok(pending)
+    }
+
+    async fn record_sent(&self, issue_id: Uuid, subscriber_id: Uuid, message_id: Option<&str>) -> Result<(), RepositoryError> {
+        self.upsert_delivery(issue_id, subscriber_id, |delivery| {
+            delivery.status = DeliveryStatus::Sent;
+            delivery.message_id = message_id.map(str::to_string);
+            delivery.sent_at = Some(delivery.updated_at);
+        });
+        Ok(())
+    }
+
+    async fn record_failure(&self, issue_id: Uuid, subscriber_id: Uuid, error: &str) -> Result<(), RepositoryError> {
+        self.upsert_delivery(issue_id, subscriber_id, |delivery| {
+            delivery.status = DeliveryStatus::Failed;
+            delivery.last_error = Some(error.to_string());
+        });
+        Ok(())
+    }
+
+    async fn record_bounce(&self, message_id: &str, detail: Option<&str>) -> Result<bool, RepositoryError> {
+        let now = Utc::now();
+        let mut bounced = false;
+        for delivery in self.deliveries.lock().unwrap().values_mut().filter(|delivery| delivery.message_id.as_deref() == Some(message_id)) {
+            delivery.status = DeliveryStatus::Bounced;
+            if let Some(detail) = detail {
+                delivery.last_error = Some(detail.to_string());
+            }
+            delivery.bounced_at.get_or_insert(now);
+            delivery.updated_at = now;
+            bounced = true;
+        }
+        Ok(bounced)
+    }
+
+    async fn find_delivery_by_message_id(&self, message_id: &str) -> Result<Option<Delivery>, RepositoryError> {
+        let deliveries = self.deliveries.lock().unwrap();
+        Ok(deliveries.values().find(|delivery| delivery.message_id.as_deref() == Some(message_id)).cloned())
+    }
+
+    async fn find_delivery(&self, issue_id: Uuid, subscriber_id: Uuid) -> Result<Delivery, RepositoryError> {
+        let deliveries = self.deliveries.lock().unwrap();
+        deliveries.get(&(issue_id, subscriber_id)).cloned().ok_or(RepositoryError::NotFound)
+    }
+
+    async fn list_deliveries(&self, issue_id: Uuid, query: &DeliveryQuery) -> Result<Vec<Delivery>, RepositoryError> {
+        let deliveries = self.deliveries.lock().unwrap();
+        Ok(deliveries.values()
+            .filter(|delivery| delivery.issue_id == issue_id)
+            .filter(|delivery| query.status.is_none() || query.status == Some(delivery.status))
+            .filter(|delivery| !matches!(query.after, Some(after) if delivery.subscriber_id <= after))
+            .take(query.limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn record_unsubscribe(&self, issue_id: Uuid, subscriber_id: Uuid) -> Result<(), RepositoryError> {
+        if let Some(delivery) = self.deliveries.lock().unwrap().get_mut(&(issue_id, subscriber_id)) {
+            let now = Utc::now();
+            delivery.unsubscribed_at.get_or_insert(now);
+            delivery.updated_at = now;
+        }
+        Ok(())
+    }
+
+    async fn delivery_summary(&self, issue_id: Uuid) -> Result<DeliverySummary, RepositoryError> {
+        let deliveries = self.deliveries.lock().unwrap();
+        let mut summary = DeliverySummary::default();
+        for delivery in deliveries.values().filter(|delivery| delivery.issue_id == issue_id) {
+            summary.recipients += 1;
+            summary.sent += i64::from(delivery.sent_at.is_some());
+            summary.failed += i64::from(delivery.status == DeliveryStatus::Failed);
+            summary.bounced += i64::from(delivery.status == DeliveryStatus::Bounced);
+            summary.unique_opens += i64::from(delivery.opened_at.is_some());
+            summary.unique_clicks += i64::from(delivery.clicked_at.is_some());
+            summary.unsubscribes += i64::from(delivery.unsubscribed_at.is_some());
+        }
+        Ok(summary)
+    }
+}
+
+fn compare_keys(a: &CursorKey, b: &CursorKey) -> Ordering {
+    match (a, b) {
+        (CursorKey::SubscribedAt(a), CursorKey::SubscribedAt(b)) => a.cmp(b),
+        (CursorKey::Text(a), CursorKey::Text(b)) => a.cmp(b),
+        _ => Ordering::Equal,
+    }
+}
+
+/// 内存中的管理员账号，用于测试管理后台
+#[derive(Default)]
+pub struct InMemoryUserRepository {
+    users: Mutex<HashMap<String, StoredCredentials>>,
+}
+
+impl InMemoryUserRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_user(&self, username: &str, password: &str) -> Uuid {
+        let user_id = Uuid::new_v4();
+        let password_hash = compute_password_hash(&Secret::new(password.to_string())).expect("Failed to hash password");
+        self.users.lock().unwrap().insert(username.to_string(), StoredCredentials { user_id, password_hash });
+        user_id
+    }
+}
+
+#[async_trait]
+impl UserRepository for InMemoryUserRepository {
+    async fn find_credentials(&self, username: &str) -> Result<Option<StoredCredentials>, RepositoryError> {
+        let users = self.users.lock().unwrap();
+        Ok(users.get(username).map(|credentials| StoredCredentials {
+            user_id: credentials.user_id,
+            password_hash: credentials.password_hash.clone(),
+        }))
+    }
+}
+
+/// 通讯仓库的内存实现
+#[derive(Default)]
+pub struct InMemoryNewsletterRepository {
+    issues: Mutex<Vec<NewsletterIssue>>,
+    //同时需要两个锁时先锁 drafts
+    drafts: Mutex<Vec<NewsletterDraft>>,
+    revisions: Mutex<Vec<DraftRevision>>,
+}
+
+impl InMemoryNewsletterRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //只修改 scheduled 的通讯，与 SQL 版本的 WHERE status = 'scheduled' 一致
+    fn update_scheduled(&self, id: Uuid, update: impl FnOnce(&mut NewsletterIssue)) -> Result<NewsletterIssue, RepositoryError> {
+        let mut issues = self.issues.lock().unwrap();
+        let issue = issues.iter_mut().find(|issue| issue.id == id).ok_or(RepositoryError::NotFound)?;
+        if issue.status != IssueStatus::Scheduled {
+            return Err(NewsletterIssue::not_scheduled(issue.status));
+        }
+        update(issue);
+        Ok(issue.clone())
+    }
+}
+
+#[async_trait]
+impl NewsletterRepository for InMemoryNewsletterRepository {
+    async fn create_issue(&self, issue: &NewIssue) -> Result<NewsletterIssue, RepositoryError> {
+        let issue = NewsletterIssue::new(issue);
+        self.issues.lock().unwrap().push(issue.clone());
+        Ok(issue)
+    }
+
+    async fn find_issue(&self, id: Uuid) -> Result<NewsletterIssue, RepositoryError> {
+        self.issues.lock().unwrap().iter().find(|issue| issue.id == id).cloned().ok_or(RepositoryError::NotFound)
+    }
+
+    async fn list_issues(&self, status: Option<IssueStatus>, limit: usize) -> Result<Vec<NewsletterIssue>, RepositoryError> {
+        let mut issues: Vec<NewsletterIssue> = self.issues.lock().unwrap().iter()
+            .filter(|issue| status.is_none() || status == Some(issue.status))
+            .cloned()
+            .collect();
+        issues.sort_by(|a, b| b.scheduled_at.cmp(&a.scheduled_at).then(b.id.cmp(&a.id)));
+        issues.truncate(limit);
+        Ok(issues)
+    }
+
+    async fn reschedule_issue(&self, id: Uuid, scheduled_at: DateTime<Utc>) -> Result<NewsletterIssue, RepositoryError> {
+        self.update_scheduled(id, |issue| issue.scheduled_at = scheduled_at)
+    }
+
+    async fn cancel_issue(&self, id: Uuid) -> Result<NewsletterIssue, RepositoryError> {
+        self.update_scheduled(id, |issue| {
+            issue.status = IssueStatus::Cancelled;
+            issue.completed_at = Some(Utc::now());
+        })
+    }
+
+    async fn claim_due_issue(&self, now: DateTime<Utc>) -> Result<Option<NewsletterIssue>, RepositoryError> {
+        let mut issues = self.issues.lock().unwrap();
+        let expired = NewsletterIssue::claims_expire_before(now);
+        let due = issues.iter_mut()
+            .filter(|issue| match issue.status {
+                IssueStatus::Scheduled => issue.scheduled_at <= now,
+                IssueStatus::Sending => issue.claimed_at.is_none_or(|claimed_at| claimed_at < expired),
+                _ => false,
+            })
+            .min_by(|a, b| a.scheduled_at.cmp(&b.scheduled_at).then(a.id.cmp(&b.id)));
+        Ok(due.map(|issue| {
+            issue.status = IssueStatus::Sending;
+            issue.started_at.get_or_insert(now);
+            issue.claimed_at = Some(now);
+            issue.clone()
+        }))
+    }
+
+    async fn renew_claim(&self, id: Uuid, now: DateTime<Utc>) -> Result<(), RepositoryError> {
+        let mut issues = self.issues.lock().unwrap();
+        if let Some(issue) = issues.iter_mut().find(|issue| issue.id == id && issue.status == IssueStatus::Sending) {
+            issue.claimed_at = Some(now);
+        }
+        Ok(())
+    }
+
+    async fn release_claim(&self, id: Uuid) -> Result<(), RepositoryError> {
+        let mut issues = self.issues.lock().unwrap();
+        if let Some(issue) = issues.iter_mut().find(|issue| issue.id == id && issue.status == IssueStatus::Sending) {
+            issue.claimed_at = None;
+        }
+        Ok(())
+    }
+
+    async fn complete_issue(&self, id: Uuid, sent: u64, failed: u64) -> Result<(), RepositoryError> {
+        let mut issues = self.issues.lock().unwrap();
+        let issue = issues.iter_mut().find(|issue| issue.id == id).ok_or(RepositoryError::NotFound)?;
+        issue.status = IssueStatus::Sent;
+        issue.completed_at = Some(Utc::now());
+        issue.sent_count = i64::try_from(sent).unwrap_or(i64::MAX);
+        issue.failed_count = i64::try_from(failed).unwrap_or(i64::MAX);
+        Ok(())
+    }
+
+    async fn fail_issue(&self, id: Uuid, reason: &str) -> Result<(), RepositoryError> {
+        let mut issues = self.issues.lock().unwrap();
+        let issue = issues.iter_mut().find(|issue| issue.id == id).ok_or(RepositoryError::NotFound)?;
+        issue.status = IssueStatus::Failed;
+        issue.completed_at = Some(Utc::now());
+        issue.failure_reason = Some(reason.to_string());
+        issue.claimed_at = None;
+        Ok(())
+    }
+
+    async fn create_draft(&self, edit: &DraftEdit) -> Result<NewsletterDraft, RepositoryError> {
+        let draft = NewsletterDraft::new(edit);
+        let mut drafts = self.drafts.lock().unwrap();
+        self.revisions.lock().unwrap().push(draft.revision(None));
+        drafts.push(draft.clone());
+        Ok(draft)
+    }
+
+    async fn find_draft(&self, id: Uuid) -> Result<NewsletterDraft, RepositoryError> {
+        self.drafts.lock().unwrap().iter().find(|draft| draft.id == id).cloned().ok_or(RepositoryError::NotFound)
+    }
+
+    async fn list_drafts(&self, limit: usize) -> Result<Vec<NewsletterDraft>, RepositoryError> {
+        let mut drafts = self.drafts.lock().unwrap().clone();
+        drafts.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then(b.id.cmp(&a.id)));
+        drafts.truncate(limit);
+        Ok(drafts)
+    }
+
+    async fn update_draft(&self, id: Uuid, expected_version: i64, edit: &DraftEdit) -> Result<NewsletterDraft, RepositoryError> {
+        let mut drafts = self.drafts.lock().unwrap();
+        let draft = drafts.iter_mut().find(|draft| draft.id == id).ok_or(RepositoryError::NotFound)?;
+        if draft.version != expected_version {
+            return Err(draft.stale_version(expected_version));
+        }
+        draft.title = edit.title.clone();
+        draft.content = edit.content.clone();
+        draft.version += 1;
+        draft.updated_at = Utc::now();
+        draft.updated_by = edit.saved_by.clone();
+        self.revisions.lock().unwrap().push(draft.revision(edit.restored_from));
+        Ok(draft.clone())
+    }
+
+    async fn delete_draft(&self, id: Uuid) -> Result<(), RepositoryError> {
+        let mut drafts = self.drafts.lock().unwrap();
+        let before = drafts.len();
+        drafts.retain(|draft| draft.id != id);
+        if drafts.len() == before {
+            return Err(RepositoryError::NotFound);
+        }
+        self.revisions.lock().unwrap().retain(|revision| revision.draft_id != id);
+        for issue in self.issues.lock().unwrap().iter_mut().filter(|issue| issue.draft_id == Some(id)) {
+            issue.draft_id = None;
+        }
+        Ok(())
+    }
+
+    async fn draft_revisions(&self, id: Uuid) -> Result<Vec<DraftRevision>, RepositoryError> {
+        let revisions: Vec<DraftRevision> = self.revisions.lock().unwrap().iter()
+            .filter(|revision| revision.draft_id == id)
+            .cloned()
+            .collect();
+        if revisions.is_empty() {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(revisions)
+    }
+
+    async fn draft_revision(&self, id: Uuid, version: i64) -> Result<DraftRevision, RepositoryError> {
+        self.revisions.lock().unwrap().iter()
+            .find(|revision| revision.draft_id == id && revision.version == version)
+            .cloned()
+            .ok_or(RepositoryError::NotFound)
+    }
+
+    async fn publish_draft(
+        &self,
+        id: Uuid,
+        version: i64,
+        scheduled_at: DateTime<Utc>,
+        created_by: &str,
+        tracking: bool,
+    ) -> Result<NewsletterIssue, RepositoryError> {
+        let drafts = self.drafts.lock().unwrap();
+        let draft = drafts.iter().find(|draft| draft.id == id).ok_or(RepositoryError::NotFound)?;
+        if draft.version != version {
+            return Err(draft.stale_version(version));
+        }
+        let mut issue = NewsletterIssue::new(&NewIssue {
+            title: draft.title.clone(),
+            content: draft.content.clone(),
+            scheduled_at,
+            created_by: created_by.to_string(),
+            tracking,
+        });
+        issue.draft_id = Some(draft.id);
+        issue.draft_version = Some(draft.version);
+        self.issues.lock().unwrap().push(issue.clone());
+        Ok(issue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{SubscriberSort, SuppressionReason};
+    use crate::testing::new_subscriber;
+    use claim::{assert_err, assert_none, assert_ok};
+
+    #[tokio::test]
+    async fn inserted_subscribers_can_be_found_by_email() {
+        let repository = InMemorySubscriberRepository::new();
+        let id = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        let record = repository.find_by_email(&email).await.unwrap().unwrap();
+        assert_eq!(record.id, id);
+        assert_eq!(record.name, "Ursula Le Guin");
+    }
+
+    #[tokio::test]
+    async fn status_changes_and_deletes_require_an_existing_subscriber() {
+        let repository = InMemorySubscriberRepository::new();
+        assert_err!(repository.change_status(Uuid::new_v4(), SubscriptionStatus::Unsubscribed, "unsubscribed by reader").await);
+        assert_err!(repository.delete(Uuid::new_v4()).await);
+
+        let id = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        assert_ok!(repository.change_status(id, SubscriptionStatus::Unsubscribed, "unsubscribed by reader").await);
+        assert_eq!(repository.find(id).await.unwrap().status, SubscriptionStatus::Unsubscribed);
+        assert_ok!(repository.delete(id).await);
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        assert_none!(repository.find_by_email(&email).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn search_filters_and_pages_through_subscribers() {
+        let repository = InMemorySubscriberRepository::new();
+        for email in ["ada@example.com", "grace@navy.mil", "ursula@example.com", "octavia@EXAMPLE.com"] {
+            repository.insert(&new_subscriber(email), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        }
+        let unsubscribed = repository.find_by_email(&SubscriberEmail::parse("ada@example.com".into()).unwrap()).await.unwrap().unwrap();
+        repository.change_status(unsubscribed.id, SubscriptionStatus::Unsubscribed, "unsubscribed by reader").await.unwrap();
+
+        let query = SubscriberQuery {
+            email_domain: Some("Example.com".into()),
+            sort: SubscriberSort::parse("-email").unwrap(),
+            limit: 2,
+            ..SubscriberQuery::default()
+        };
+        let first_page = repository.search(&query).await.unwrap();
+        let emails: Vec<&str> = first_page.iter().map(|record| record.email.as_str()).collect();
+        assert_eq!(emails, vec!["ursula@example.com", "octavia@example.com"]);
+
+        let query = SubscriberQuery { after: Some(Cursor::after(&first_page[1], &query.sort)), ..query };
+        let second_page = repository.search(&query).await.unwrap();
+        let emails: Vec<&str> = second_page.iter().map(|record| record.email.as_str()).collect();
+        assert_eq!(emails, vec!["ada@example.com"]);
+
+        let query = SubscriberQuery {
+            status: Some(SubscriptionStatus::Confirmed),
+            search: Some("GRACE".into()),
+            ..SubscriberQuery::default()
+        };
+        assert_eq!(repository.search(&query).await.unwrap().len(), 1);
+        let query = SubscriberQuery { search: Some("%".into()), ..SubscriberQuery::default() };
+        assert!(repository.search(&query).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn details_can_be_updated() {
+        let repository = InMemorySubscriberRepository::new();
+        let id = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let name = SubscriberName::parse("Ursula K. Le Guin".to_string()).unwrap();
+        let record = repository.update_details(id, Some(&name), None, None, None).await.unwrap();
+        assert_eq!(record.name, "Ursula K. Le Guin");
+        assert_eq!(record.email, "ursula@example.com");
+        assert_err!(repository.update_details(Uuid::new_v4(), Some(&name), None, None, None).await);
+    }
+
+    #[tokio::test]
+    async fn status_changes_follow_the_state_machine_and_are_recorded() {
+        let repository = InMemorySubscriberRepository::new();
+        let id = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        assert_eq!(repository.change_status(id, SubscriptionStatus::Complained, "spam complaint").await.unwrap(), SubscriptionStatus::Confirmed);
+        assert!(matches!(
+            repository.change_status(id, SubscriptionStatus::Confirmed, "resubscribed").await,
+            Err(RepositoryError::InvalidTransition(_))
+        ));
+
+        let events = repository.events(id).await.unwrap();
+        let transitions: Vec<_> = events.iter().map(|event| (event.from_status, event.to_status, event.reason.as_str())).collect();
+        assert_eq!(transitions, vec![
+            (None, SubscriptionStatus::Confirmed, "subscribed"),
+            (Some(SubscriptionStatus::Confirmed), SubscriptionStatus::Complained, "spam complaint"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn tracking_does_not_mark_unsent_deliveries_as_opened() {
+        let repository = InMemorySubscriberRepository::new();
+        let issue_id = Uuid::new_v4();
+        let ursula = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let iain = repository.insert(&new_subscriber("iain@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let octavia = repository.insert(&new_subscriber("octavia@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        repository.queue_deliveries(issue_id, &[ursula, iain, octavia]).await.unwrap();
+        repository.record_failure(issue_id, ursula, "500 Internal Server Error").await.unwrap();
+        repository.record_sent(issue_id, iain, Some("883953f4")).await.unwrap();
+        repository.record_bounce("883953f4", None).await.unwrap();
+        let summary = repository.delivery_summary(issue_id).await.unwrap();
+
+        //失败、退信和还在排队的邮件没有送到，像素和链接的请求不算打开或点击
+        for (subscriber_id, status) in [(ursula, DeliveryStatus::Failed), (iain, DeliveryStatus::Bounced), (octavia, DeliveryStatus::Queued)] {
+            for (kind, url) in [(TrackingKind::Click, Some("https://example.com/docs")), (TrackingKind::Open, None)] {
+                assert!(TrackingRepository::record_event(&repository, &TrackingEvent::new(issue_id, subscriber_id, kind, url)).await.unwrap());
+            }
+            let delivery = repository.find_delivery(issue_id, subscriber_id).await.unwrap();
+            assert_eq!((delivery.status, delivery.opened_at, delivery.clicked_at), (status, None, None));
+        }
+        assert_eq!(repository.delivery_summary(issue_id).await.unwrap(), summary);
+    }
+
+    #[tokio::test]
+    async fn erased_addresses_stay_suppressed_by_their_hash() {
+        let repository = InMemorySubscriberRepository::new();
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        repository.suppress(&Suppression::new(&email, SuppressionReason::Bounce, Some("Mailbox does not exist"), Some("883953f4"))).await.unwrap();
+        let tombstone = repository.erase(&email, "subscriber", "requested via privacy link").await.unwrap();
+        assert_eq!(tombstone.erased_rows["suppression_list"], 1);
+
+        let kept = repository.find_suppression(&email).await.unwrap().unwrap();
+        assert_eq!((kept.reason.as_str(), kept.detail, kept.message_id), ("bounce", None, None));
+        assert!(repository.list_suppressions(10).await.unwrap().is_empty());
+        assert!(!repository.suppress(&Suppression::new(&email, SuppressionReason::Complaint, None, None)).await.unwrap());
+        repository.lift_suppression(&email).await.unwrap();
+        assert_none!(repository.find_suppression(&email).await.unwrap());
+    }
+}
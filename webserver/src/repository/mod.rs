@@ -0,0 +1,912 @@
+pub mod postgres;
+pub mod memory;
+pub mod query;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use postgres::*;
+pub use memory::*;
+pub use query::*;
+#[cfg(feature = "sqlite")]
+pub use sqlite::*;
+
+use crate::domain::{DeliveryStatus, InvalidTransition, IssueStatus, NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use secrecy::Secret;
+use sqlx::database::{Database, HasArguments, HasValueRef};
+use sqlx::encode::IsNull;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::future::Future;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// subscriptions 表中的一行
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct SubscriberRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub status: SubscriptionStatus,
+    pub attributes: SubscriberAttributes,
+    //false 时不跟踪打开和点击
+    pub tracking: bool,
+}
+
+/// subscription_events 表中的一行，记录一次状态变化
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct SubscriptionEvent {
+    pub id: Uuid,
+    pub subscriber_id: Uuid,
+    //创建订阅时为空
+    pub from_status: Option<SubscriptionStatus>,
+    pub to_status: SubscriptionStatus,
+    pub reason: String,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// 同意是怎样给出的
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsentAction {
+    //提交订阅表单
+    Subscribe,
+    //点击确认邮件中的链接（双重确认）
+    Confirm,
+}
+
+impl ConsentAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentAction::Subscribe => "subscribe",
+            ConsentAction::Confirm => "confirm",
+        }
+    }
+}
+
+/// 记录一次同意时从请求中取得的信息，取不到的字段为空
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewConsent {
+    pub action: ConsentAction,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    //表单所在的页面或来源标识
+    pub source: Option<String>,
+    //表单上展示的同意文本的版本
+    pub consent_version: Option<String>,
+}
+
+impl NewConsent {
+    /// 批量导入的订阅者：没有经过本服务的表单，请求的 IP 和 User-Agent 是管理员的，不记录；
+    /// 来源为 import，给出原来的工具或名单时为 import: <名称>
+    pub fn imported(source: Option<&str>, consent_version: Option<String>) -> Self {
+        let source = match source.map(str::trim) {
+            Some(source) if !source.is_empty() => format!("import: {}", source),
+            _ => "import".to_string(),
+        };
+        NewConsent { action: ConsentAction::Subscribe, ip_address: None, user_agent: None, source: Some(source), consent_version }
+    }
+}
+
+/// consent_records 表中的一行
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct ConsentRecord {
+    pub id: Uuid,
+    pub subscriber_id: Uuid,
+    //subscribe 或 confirm
+    pub action: String,
+    pub recorded_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: Option<String>,
+    pub consent_version: Option<String>,
+}
+
+impl ConsentRecord {
+    pub fn new(subscriber_id: Uuid, consent: &NewConsent, recorded_at: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            subscriber_id,
+            action: consent.action.as_str().to_string(),
+            recorded_at,
+            ip_address: consent.ip_address.clone(),
+            user_agent: consent.user_agent.clone(),
+            source: consent.source.clone(),
+            consent_version: consent.consent_version.clone(),
+        }
+    }
+}
+
+//SubscriptionStatus 在两种数据库中都存为文本
+impl<DB: Database> sqlx::Type<DB> for SubscriptionStatus
+where
+    String: sqlx::Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as sqlx::Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as sqlx::Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, DB: Database> sqlx::Encode<'q, DB> for SubscriptionStatus
+where
+    &'q str: sqlx::Encode<'q, DB>,
+{
+    fn encode_by_ref(&self, buf: &mut <DB as HasArguments<'q>>::ArgumentBuffer) -> IsNull {
+        self.as_str().encode_by_ref(buf)
+    }
+}
+
+impl<'r, DB: Database> sqlx::Decode<'r, DB> for SubscriptionStatus
+where
+    String: sqlx::Decode<'r, DB>,
+{
+    fn decode(value: <DB as HasValueRef<'r>>::ValueRef) -> Result<Self, sqlx::error::BoxDynError> {
+        let status = <String as sqlx::Decode<'r, DB>>::decode(value)?;
+        Ok(SubscriptionStatus::parse(&status)?)
+    }
+}
+
+//IssueStatus 同样存为文本
+impl<DB: Database> sqlx::Type<DB> for IssueStatus
+where
+    String: sqlx::Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as sqlx::Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as sqlx::Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, DB: Database> sqlx::Encode<'q, DB> for IssueStatus
+where
+    &'q str: sqlx::Encode<'q, DB>,
+{
+    fn encode_by_ref(&self, buf: &mut <DB as HasArguments<'q>>::ArgumentBuffer) -> IsNull {
+        self.as_str().encode_by_ref(buf)
+    }
+}
+
+impl<'r, DB: Database> sqlx::Decode<'r, DB> for IssueStatus
+where
+    String: sqlx::Decode<'r, DB>,
+{
+    fn decode(value: <DB as HasValueRef<'r>>::ValueRef) -> Result<Self, sqlx::error::BoxDynError> {
+        let status = <String as sqlx::Decode<'r, DB>>::decode(value)?;
+        Ok(IssueStatus::parse(&status)?)
+    }
+}
+
+//DeliveryStatus 同样存为文本
+impl<DB: Database> sqlx::Type<DB> for DeliveryStatus
+where
+    String: sqlx::Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as sqlx::Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as sqlx::Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, DB: Database> sqlx::Encode<'q, DB> for DeliveryStatus
+where
+    &'q str: sqlx::Encode<'q, DB>,
+{
+    fn encode_by_ref(&self, buf: &mut <DB as HasArguments<'q>>::ArgumentBuffer) -> IsNull {
+        self.as_str().encode_by_ref(buf)
+    }
+}
+
+impl<'r, DB: Database> sqlx::Decode<'r, DB> for DeliveryStatus
+where
+    String: sqlx::Decode<'r, DB>,
+{
+    fn decode(value: <DB as HasValueRef<'r>>::ValueRef) -> Result<Self, sqlx::error::BoxDynError> {
+        let status = <String as sqlx::Decode<'r, DB>>::decode(value)?;
+        Ok(DeliveryStatus::parse(&status)?)
+    }
+}
+
+//自定义属性在两种数据库中都存为 JSON 文本
+impl<DB: Database> sqlx::Type<DB> for SubscriberAttributes
+where
+    String: sqlx::Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as sqlx::Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as sqlx::Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'r, DB: Database> sqlx::Decode<'r, DB> for SubscriberAttributes
+where
+    String: sqlx::Decode<'r, DB>,
+{
+    fn decode(value: <DB as HasValueRef<'r>>::ValueRef) -> Result<Self, sqlx::error::BoxDynError> {
+        let attributes = <String as sqlx::Decode<'r, DB>>::decode(value)?;
+        Ok(SubscriberAttributes::from_json(&attributes)?)
+    }
+}
+
+/// 导入时邮箱已存在的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    //保留已有记录，跳过这一行
+    #[default]
+    Skip,
+    //用导入的姓名覆盖已有记录，状态不变
+    Merge,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    //新订阅者的初始状态
+    pub status: SubscriptionStatus,
+    pub on_duplicate: DuplicatePolicy,
+    //只查重，不写入
+    pub dry_run: bool,
+    //记录到 subscription_events
+    pub reason: String,
+    //为每个新插入的订阅者写入的同意记录，来源标明是导入
+    pub consent: NewConsent,
+}
+
+/// 一批导入的结果；dry run 时是"将会"插入和合并的数量
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportOutcome {
+    pub inserted: usize,
+    pub merged: usize,
+    //已存在而被跳过的邮箱
+    pub skipped: Vec<String>,
+}
+
+impl ImportOutcome {
+    /// 按已存在的邮箱（小写形式）把一批订阅者分为新插入和已存在两部分，各个实现共用
+    pub fn partition<'a>(batch: &'a [NewSubscriber], existing: &HashSet<String>) -> (Vec<&'a NewSubscriber>, Vec<&'a NewSubscriber>) {
+        batch.iter().partition(|subscriber| !existing.contains(&subscriber.email.as_ref().to_lowercase()))
+    }
+}
+
+#[derive(Debug)]
+pub enum RepositoryError {
+    //要查找或修改的记录不存在，订阅者、通讯、草稿等共用
+    NotFound,
+    //状态机不允许的状态变化
+    InvalidTransition(InvalidTransition),
+    //记录当前的状态不允许这个操作，例如改期已经开始发送的通讯
+    Conflict(String),
+    //连接池耗尽或已关闭，稍后重试可能成功
+    Unavailable(String),
+    Database(String),
+}
+
+impl std::fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepositoryError::NotFound => write!(f, "Record not found"),
+            RepositoryError::InvalidTransition(e) => write!(f, "{}", e),
+            RepositoryError::Conflict(e) => write!(f, "{}", e),
+            RepositoryError::Unavailable(e) => write!(f, "Database is unavailable: {}", e),
+            RepositoryError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RepositoryError {}
+
+impl From<InvalidTransition> for RepositoryError {
+    fn from(e: InvalidTransition) -> Self {
+        RepositoryError::InvalidTransition(e)
+    }
+}
+
+impl From<sqlx::Error> for RepositoryError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => RepositoryError::NotFound,
+            sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed => RepositoryError::Unavailable(e.to_string()),
+            //违反唯一约束，例如邮箱已被另一个订阅者使用；23505 是 Postgres 的错误码，2067 是 SQLite 的扩展错误码
+            sqlx::Error::Database(ref database_error) if matches!(database_error.code().as_deref(), Some("23505") | Some("2067")) => {
+                RepositoryError::Conflict(database_error.message().to_string())
+            }
+            other => RepositoryError::Database(other.to_string()),
+        }
+    }
+}
+
+/// 订阅者的持久化接口，处理器只依赖这个 trait，测试可以换成内存实现
+///
+/// 在 `App` 中以 `web::Data<dyn SubscriberRepository>` 注入
+#[async_trait]
+pub trait SubscriberRepository: Send + Sync {
+    /// 插入订阅者并记录一条创建事件
+    async fn insert(&self, subscriber: &NewSubscriber, status: SubscriptionStatus, reason: &str) -> Result<Uuid, RepositoryError>;
+    /// 与 `insert` 相同，并在同一个事务中写入同意记录
+    async fn insert_with_consent(
+        &self,
+        subscriber: &NewSubscriber,
+        status: SubscriptionStatus,
+        reason: &str,
+        consent: &NewConsent,
+    ) -> Result<Uuid, RepositoryError>;
+    async fn find(&self, id: Uuid) -> Result<SubscriberRecord, RepositoryError>;
+    async fn find_by_email(&self, email: &SubscriberEmail) -> Result<Option<SubscriberRecord>, RepositoryError>;
+    /// 修改姓名、邮箱、自定义属性和/或跟踪设置，返回修改后的记录
+    async fn update_details(
+        &self,
+        id: Uuid,
+        name: Option<&SubscriberName>,
+        email: Option<&SubscriberEmail>,
+        attributes: Option<&SubscriberAttributes>,
+        tracking: Option<bool>,
+    ) -> Result<SubscriberRecord, RepositoryError>;
+    /// 在同一个事务中检查状态转换、更新状态并记录事件，返回之前的状态
+    async fn change_status(&self, id: Uuid, to: SubscriptionStatus, reason: &str) -> Result<SubscriptionStatus, RepositoryError>;
+    /// 与 `change_status` 相同，并在同一个事务中写入同意记录，例如点击确认链接或重新订阅
+    async fn change_status_with_consent(
+        &self,
+        id: Uuid,
+        to: SubscriptionStatus,
+        reason: &str,
+        consent: &NewConsent,
+    ) -> Result<SubscriptionStatus, RepositoryError>;
+    /// 订阅者的全部状态变化，按时间升序
+    async fn events(&self, id: Uuid) -> Result<Vec<SubscriptionEvent>, RepositoryError>;
+    /// 按条件过滤的同意记录，按时间倒序，最多 `query.limit` 条
+    async fn consents(&self, query: &ConsentQuery) -> Result<Vec<ConsentRecord>, RepositoryError>;
+    /// 按条件过滤、排序，从游标之后返回最多 `query.limit` 条
+    async fn search(&self, query: &SubscriberQuery) -> Result<Vec<SubscriberRecord>, RepositoryError>;
+    /// 与 `search` 相同的过滤和排序，但忽略 `limit`，逐行返回全部匹配的订阅者
+    ///
+    /// 数据库实现在导出期间占用一个连接
+    fn stream(&self, query: SubscriberQuery) -> SubscriberStream;
+    async fn delete(&self, id: Uuid) -> Result<(), RepositoryError>;
+    /// 在一个事务中导入一批订阅者并为新插入的记录写创建事件
+    ///
+    /// 调用方负责校验和去除批内重复；与已有记录的重复按 `options.on_duplicate` 处理
+    async fn import(&self, batch: &[NewSubscriber], options: &ImportOptions) -> Result<ImportOutcome, RepositoryError>;
+}
+
+/// 导出时逐行产出的订阅者
+pub type SubscriberStream = BoxStream<'static, Result<SubscriberRecord, RepositoryError>>;
+
+//后台任务最多领先消费者的行数；HTTP 响应写得慢时 fetch 在这里等待，内存不随总行数增长
+const STREAM_BUFFER: usize = 256;
+
+/// 在后台任务中运行 `produce`，它把 sqlx `fetch` 得到的行写入有界 channel，调用方从返回的流中读取
+///
+/// `fetch` 的流借用了 QueryBuilder，不能直接作为 'static 的响应体返回；
+/// 客户端断开后接收端被丢弃，`send` 失败，任务随之结束并归还连接
+pub(crate) fn channel_stream<F, Fut>(produce: F) -> SubscriberStream
+where
+    F: FnOnce(mpsc::Sender<Result<SubscriberRecord, RepositoryError>>) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+    tokio::spawn(produce(sender));
+    futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|row| (row, receiver))
+    })
+    .boxed()
+}
+
+/// 管理员登录时需要的信息
+pub struct StoredCredentials {
+    pub user_id: Uuid,
+    pub password_hash: Secret<String>,
+}
+
+/// users 表的访问接口，管理后台的认证中间件通过它查找管理员
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn find_credentials(&self, username: &str) -> Result<Option<StoredCredentials>, RepositoryError>;
+}
+
+/// 某个邮箱在各表中保存的全部个人数据，用于答复数据访问请求（GDPR 第 15 条）
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct PersonalData {
+    pub email: String,
+    pub exported_at: DateTime<Utc>,
+    pub subscriptions: Vec<SubscriberRecord>,
+    pub subscription_events: Vec<SubscriptionEvent>,
+    pub consent_records: Vec<ConsentRecord>,
+    pub tracking_events: Vec<TrackingEvent>,
+    //这个邮箱在停发名单中时有一条
+    pub suppressions: Vec<Suppression>,
+    pub deliveries: Vec<Delivery>,
+}
+
+/// 一次彻底删除的审计记录，只有删除了多少行，不含邮箱
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ErasureTombstone {
+    pub id: Uuid,
+    pub erased_at: DateTime<Utc>,
+    //subscriber 或 admin <用户名>
+    pub requested_by: String,
+    pub reason: String,
+    //表名 -> 删除的行数
+    pub erased_rows: BTreeMap<String, u64>,
+}
+
+/// erasure_tombstones 表中的一行，erased_rows 存为 JSON 文本
+pub(crate) type TombstoneRow = (Uuid, DateTime<Utc>, String, String, String);
+
+impl ErasureTombstone {
+    pub fn new(requested_by: &str, reason: &str, erased_rows: BTreeMap<String, u64>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            erased_at: Utc::now(),
+            requested_by: requested_by.to_string(),
+            reason: reason.to_string(),
+            erased_rows,
+        }
+    }
+
+    /// 所有表都没有这个邮箱的数据
+    pub fn is_empty(&self) -> bool {
+        self.erased_rows.values().all(|rows| *rows == 0)
+    }
+
+    pub(crate) fn erased_rows_json(&self) -> String {
+        serde_json::to_string(&self.erased_rows).expect("Failed to serialize erased row counts")
+    }
+
+    pub(crate) fn from_row((id, erased_at, requested_by, reason, erased_rows): TombstoneRow) -> Result<Self, RepositoryError> {
+        let erased_rows = serde_json::from_str(&erased_rows)
+            .map_err(|e| RepositoryError::Database(format!("Invalid erased_rows in tombstone {}: {}", id, e)))?;
+        Ok(Self { id, erased_at, requested_by, reason, erased_rows })
+    }
+}
+
+/// 数据访问和删除请求用到的接口
+///
+/// 数据库实现与 `SubscriberRepository` 是同一个类型；以 `web::Data<dyn PrivacyRepository>` 注入
+#[async_trait]
+pub trait PrivacyRepository: Send + Sync {
+    /// 该邮箱在各表中的全部数据，没有数据时各列表为空
+    async fn personal_data(&self, email: &SubscriberEmail) -> Result<PersonalData, RepositoryError>;
+    /// 在一个事务中删除该邮箱在各表中的数据并写入墓碑记录；没有任何数据时返回 NotFound
+    async fn erase(&self, email: &SubscriberEmail, requested_by: &str, reason: &str) -> Result<ErasureTombstone, RepositoryError>;
+    /// 最近的墓碑记录，按时间倒序
+    async fn tombstones(&self, limit: usize) -> Result<Vec<ErasureTombstone>, RepositoryError>;
+    /// 保存魔法链接令牌的哈希，同时清理已过期的令牌
+    async fn insert_token(&self, token_hash: &str, email: &SubscriberEmail, expires_at: DateTime<Utc>) -> Result<(), RepositoryError>;
+    /// 令牌未过期时返回对应的邮箱
+    async fn token_email(&self, token_hash: &str) -> Result<Option<String>, RepositoryError>;
+}
+
+/// 跟踪到的读者行为
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackingKind {
+    //跟踪像素被加载
+    Open,
+    //经由 /t/c/ 打开了邮件中的链接
+    Click,
+}
+
+impl TrackingKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackingKind::Open => "open",
+            TrackingKind::Click => "click",
+        }
+    }
+}
+
+/// tracking_events 表中的一行
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct TrackingEvent {
+    pub id: Uuid,
+    pub issue_id: Uuid,
+    pub subscriber_id: Uuid,
+    //open 或 click
+    pub kind: String,
+    //点击的目标地址，打开事件为空
+    pub url: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl TrackingEvent {
+    pub fn new(issue_id: Uuid, subscriber_id: Uuid, kind: TrackingKind, url: Option<&str>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            issue_id,
+            subscriber_id,
+            kind: kind.as_str().to_string(),
+            url: url.map(str::to_string),
+            occurred_at: Utc::now(),
+        }
+    }
+}
+
+/// 一个链接的点击数
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct LinkClicks {
+    pub url: String,
+    pub clicks: i64,
+    //点击过的订阅者人数
+    pub unique_clicks: i64,
+}
+
+/// 一期通讯的打开和点击统计，unique_ 开头的是人数
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize)]
+pub struct IssueAnalytics {
+    pub opens: i64,
+    pub unique_opens: i64,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+    //按点击数从多到少
+    pub links: Vec<LinkClicks>,
+}
+
+/// 一段时间内的打开和点击数（事件数，不按人去重）
+#[derive(Debug, Clone, Copy, PartialEq, sqlx::FromRow)]
+pub struct ActivityBucket {
+    //从开始时间算起的第几段，从 0 开始
+    pub bucket: i64,
+    pub opens: i64,
+    pub clicks: i64,
+}
+
+/// 打开和点击跟踪
+///
+/// 事件按订阅者保存，数据库实现与 `SubscriberRepository` 是同一个类型；以 `web::Data<dyn TrackingRepository>` 注入
+#[async_trait]
+pub trait TrackingRepository: Send + Sync {
+    /// 记录一次打开或点击；订阅者已删除或关闭了跟踪时不记录，返回 false
+    async fn record_event(&self, event: &TrackingEvent) -> Result<bool, RepositoryError>;
+    /// 一期通讯的统计，还没有事件时各项为 0
+    async fn issue_analytics(&self, issue_id: Uuid) -> Result<IssueAnalytics, RepositoryError>;
+    /// 每个链接的点击数，按点击数从多到少
+    async fn link_clicks(&self, issue_id: Uuid) -> Result<Vec<LinkClicks>, RepositoryError>;
+    /// 从 `since` 开始每 `bucket_seconds` 秒一段的打开和点击数，按段的先后排列；没有事件的段不返回
+    async fn activity(&self, issue_id: Uuid, since: DateTime<Utc>, bucket_seconds: i64) -> Result<Vec<ActivityBucket>, RepositoryError>;
+}
+
+/// 地址被停发的原因
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SuppressionReason {
+    //服务商报告硬退信，地址不存在或被永久拒收
+    Bounce,
+    //收件人把邮件标记为垃圾邮件
+    Complaint,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::Bounce => "bounce",
+            SuppressionReason::Complaint => "complaint",
+        }
+    }
+}
+
+/// suppression_list 表中的一行
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct Suppression {
+    pub email: String,
+    //bounce 或 complaint
+    pub reason: String,
+    pub detail: Option<String>,
+    pub message_id: Option<String>,
+    pub suppressed_at: DateTime<Utc>,
+}
+
+impl Suppression {
+    pub fn new(email: &SubscriberEmail, reason: SuppressionReason, detail: Option<&str>, message_id: Option<&str>) -> Self {
+        Self {
+            email: email.as_ref().to_string(),
+            reason: reason.as_str().to_string(),
+            detail: detail.map(str::to_string),
+            message_id: message_id.map(str::to_string),
+            suppressed_at: Utc::now(),
+        }
+    }
+}
+
+/// 停发名单，`EmailClient` 每次发送之前都会检查
+///
+/// 按邮箱保存，数据库实现与 `SubscriberRepository` 是同一个类型；以 `web::Data<dyn SuppressionRepository>` 注入
+#[async_trait]
+pub trait SuppressionRepository: Send + Sync {
+    /// 加入名单；已经在名单中时保留原来的记录，返回 false
+    async fn suppress(&self, suppression: &Suppression) -> Result<bool, RepositoryError>;
+    async fn find_suppression(&self, email: &SubscriberEmail) -> Result<Option<Suppression>, RepositoryError>;
+    /// 按加入时间倒序
+    async fn list_suppressions(&self, limit: usize) -> Result<Vec<Suppression>, RepositoryError>;
+    /// 解除停发，不在名单中时返回 NotFound
+    async fn lift_suppression(&self, email: &SubscriberEmail) -> Result<(), RepositoryError>;
+}
+
+/// deliveries 表中的一行：一期通讯发给一个订阅者的情况
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct Delivery {
+    pub issue_id: Uuid,
+    pub subscriber_id: Uuid,
+    pub status: DeliveryStatus,
+    //服务商返回的邮件 id
+    pub message_id: Option<String>,
+    pub attempts: i64,
+    //最近一次发送失败或退信的原因
+    pub last_error: Option<String>,
+    pub queued_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub opened_at: Option<DateTime<Utc>>,
+    pub clicked_at: Option<DateTime<Utc>>,
+    pub bounced_at: Option<DateTime<Utc>>,
+    //通过这一期邮件中的退订链接退订的时间
+    pub unsubscribed_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// 一期通讯投递情况的汇总，unique_ 开头的是人数
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, sqlx::FromRow)]
+pub struct DeliverySummary {
+    pub recipients: i64,
+    //服务商接受过的，之后退信、打开的也算在内
+    pub sent: i64,
+    pub failed: i64,
+    pub bounced: i64,
+    pub unique_opens: i64,
+    pub unique_clicks: i64,
+    //通过这一期的退订链接退订的人数
+    pub unsubscribes: i64,
+}
+
+/// 列出一期通讯的投递记录，按 subscriber_id 分页
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryQuery {
+    pub status: Option<DeliveryStatus>,
+    //上一页最后一行的 subscriber_id
+    pub after: Option<Uuid>,
+    pub limit: usize,
+}
+
+/// 每期通讯发给每个订阅者的投递状态
+///
+/// 按订阅者保存，数据库实现与 `SubscriberRepository` 是同一个类型；以 `web::Data<dyn DeliveryRepository>` 注入。
+/// 打开和点击由 `TrackingRepository::record_event` 在记录事件的同时更新
+#[async_trait]
+pub trait DeliveryRepository: Send + Sync {
+    /// 把一页收件人记为 queued；已经有记录的保持不变
+    ///
+    /// 返回其中仍是 queued、还需要发送的收件人，接着发送中断的一期时跳过已经有结果的
+    async fn queue_deliveries(&self, issue_id: Uuid, subscriber_ids: &[Uuid]) -> Result<Vec<Uuid>, RepositoryError>;
+    /// 服务商接受了邮件：记为 sent 并保存 message id，尝试次数加一
+    async fn record_sent(&self, issue_id: Uuid, subscriber_id: Uuid, message_id: Option<&str>) -> Result<(), RepositoryError>;
+    /// 发送失败（包括停发名单中的地址）：记为 failed 并保存错误，尝试次数加一
+    async fn record_failure(&self, issue_id: Uuid, subscriber_id: Uuid, error: &str) -> Result<(), RepositoryError>;
+    /// 服务商报告这封邮件硬退信；没有这个 message id 的记录时返回 false
+    async fn record_bounce(&self, message_id: &str, detail: Option<&str>) -> Result<bool, RepositoryError>;
+    /// 服务商返回的 message id 对应的投递记录，测试邮件等不在 deliveries 中的返回 None
+    async fn find_delivery_by_message_id(&self, message_id: &str) -> Result<Option<Delivery>, RepositoryError>;
+    /// 这个订阅者没有收到过这一期时返回 NotFound
+    async fn find_delivery(&self, issue_id: Uuid, subscriber_id: Uuid) -> Result<Delivery, RepositoryError>;
+    async fn list_deliveries(&self, issue_id: Uuid, query: &DeliveryQuery) -> Result<Vec<Delivery>, RepositoryError>;
+    /// 订阅者通过这一期的退订链接退订；保留最早的时间，没有投递记录时什么都不做
+    async fn record_unsubscribe(&self, issue_id: Uuid, subscriber_id: Uuid) -> Result<(), RepositoryError>;
+    /// 还没有投递记录时各项为 0
+    async fn delivery_summary(&self, issue_id: Uuid) -> Result<DeliverySummary, RepositoryError>;
+}
+
+/// newsletter_issues 表中的一行
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct NewsletterIssue {
+    pub id: Uuid,
+    pub title: String,
+    //Markdown
+    pub content: String,
+    pub status: IssueStatus,
+    pub scheduled_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub created_by: String,
+    //调度任务领取的时间
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub sent_count: i64,
+    pub failed_count: i64,
+    //从草稿发布时记录草稿和发布的版本，草稿删除后 draft_id 为空
+    pub draft_id: Option<Uuid>,
+    pub draft_version: Option<i64>,
+    //false 时不加跟踪像素、不改写链接
+    pub tracking: bool,
+    //领取或最近一次续期的时间，停机时放弃领取会清空
+    pub claimed_at: Option<DateTime<Utc>>,
+    //failed 的原因（渲染错误）
+    pub failure_reason: Option<String>,
+}
+
+/// 发送中的一期超过这个时间没有续期，就认为领取它的实例已经退出，可以被重新领取
+pub const CLAIM_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+/// 新建一期通讯，调用方负责校验标题、正文和合并标签
+#[derive(Debug, Clone)]
+pub struct NewIssue {
+    pub title: String,
+    pub content: String,
+    pub scheduled_at: DateTime<Utc>,
+    pub created_by: String,
+    pub tracking: bool,
+}
+
+impl NewsletterIssue {
+    pub fn new(issue: &NewIssue) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            title: issue.title.clone(),
+            content: issue.content.clone(),
+            status: IssueStatus::Scheduled,
+            scheduled_at: issue.scheduled_at,
+            created_at: Utc::now(),
+            created_by: issue.created_by.clone(),
+            started_at: None,
+            completed_at: None,
+            sent_count: 0,
+            failed_count: 0,
+            draft_id: None,
+            draft_version: None,
+            tracking: issue.tracking,
+            claimed_at: None,
+            failure_reason: None,
+        }
+    }
+
+    //早于这个时间的领取已经过期
+    pub(crate) fn claims_expire_before(now: DateTime<Utc>) -> DateTime<Utc> {
+        now - chrono::Duration::from_std(CLAIM_TIMEOUT).expect("CLAIM_TIMEOUT fits in chrono::Duration")
+    }
+
+    //改期或取消失败时的说明
+    pub(crate) fn not_scheduled(status: IssueStatus) -> RepositoryError {
+        RepositoryError::Conflict(format!("Issue is {} and can no longer be changed", status))
+    }
+}
+
+/// newsletter_drafts 表中的一行，标题和正文是最新版本的内容
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct NewsletterDraft {
+    pub id: Uuid,
+    pub title: String,
+    pub content: String,
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+    pub created_by: String,
+    pub updated_at: DateTime<Utc>,
+    pub updated_by: String,
+}
+
+/// newsletter_draft_revisions 表中的一行，草稿每保存一次写入一条，之后不再修改
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct DraftRevision {
+    pub draft_id: Uuid,
+    pub version: i64,
+    pub title: String,
+    pub content: String,
+    pub saved_at: DateTime<Utc>,
+    pub saved_by: String,
+    pub restored_from: Option<i64>,
+}
+
+/// 保存一次草稿，调用方负责校验标题和合并标签
+#[derive(Debug, Clone)]
+pub struct DraftEdit {
+    pub title: String,
+    pub content: String,
+    pub saved_by: String,
+    //恢复旧版本时是旧版本的版本号
+    pub restored_from: Option<i64>,
+}
+
+impl NewsletterDraft {
+    pub fn new(edit: &DraftEdit) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            title: edit.title.clone(),
+            content: edit.content.clone(),
+            version: 1,
+            created_at: now,
+            created_by: edit.saved_by.clone(),
+            updated_at: now,
+            updated_by: edit.saved_by.clone(),
+        }
+    }
+
+    /// 这个版本对应的修订记录
+    pub fn revision(&self, restored_from: Option<i64>) -> DraftRevision {
+        DraftRevision {
+            draft_id: self.id,
+            version: self.version,
+            title: self.title.clone(),
+            content: self.content.clone(),
+            saved_at: self.updated_at,
+            saved_by: self.updated_by.clone(),
+            restored_from,
+        }
+    }
+
+    //保存或发布时带的版本号不是最新版本
+    pub(crate) fn stale_version(&self, expected: i64) -> RepositoryError {
+        RepositoryError::Conflict(format!(
+            "Draft is at version {} (last saved by {}), not version {}; reload it and try again",
+            self.version, self.updated_by, expected
+        ))
+    }
+}
+
+/// 通讯期刊的持久化接口，以 `web::Data<dyn NewsletterRepository>` 注入；调度任务也通过它领取到期的通讯
+#[async_trait]
+pub trait NewsletterRepository: Send + Sync {
+    async fn create_issue(&self, issue: &NewIssue) -> Result<NewsletterIssue, RepositoryError>;
+    async fn find_issue(&self, id: Uuid) -> Result<NewsletterIssue, RepositoryError>;
+    /// 按计划发送时间倒序，最多 `limit` 条
+    async fn list_issues(&self, status: Option<IssueStatus>, limit: usize) -> Result<Vec<NewsletterIssue>, RepositoryError>;
+    /// 改期；已经开始发送、发送完毕或取消的通讯返回 Conflict
+    async fn reschedule_issue(&self, id: Uuid, scheduled_at: DateTime<Utc>) -> Result<NewsletterIssue, RepositoryError>;
+    /// 取消；同样只对 scheduled 的通讯有效
+    async fn cancel_issue(&self, id: Uuid) -> Result<NewsletterIssue, RepositoryError>;
+    /// 把最早到期（`scheduled_at <= now`）的一期改为 sending 并返回，没有到期的通讯时返回 None
+    ///
+    /// 停机时放弃了领取、或超过 `CLAIM_TIMEOUT` 没有续期的 sending 通讯也会被领取，由调用方接着发送。
+    /// 多个实例同时调用时，每一期只会被其中一个领取
+    async fn claim_due_issue(&self, now: DateTime<Utc>) -> Result<Option<NewsletterIssue>, RepositoryError>;
+    /// 发送期间续期领取，避免被其它实例当作中断的发送重新领取
+    async fn renew_claim(&self, id: Uuid, now: DateTime<Utc>) -> Result<(), RepositoryError>;
+    /// 停机时放弃领取；这一期保持 sending，下一次检查时立刻被接着发送
+    async fn release_claim(&self, id: Uuid) -> Result<(), RepositoryError>;
+    /// 发送结束，记录成功和失败的收件人数
+    async fn complete_issue(&self, id: Uuid, sent: u64, failed: u64) -> Result<(), RepositoryError>;
+    /// 无法发送（渲染失败），改为 failed 并记录原因，不会再被领取
+    async fn fail_issue(&self, id: Uuid, reason: &str) -> Result<(), RepositoryError>;
+    /// 新建草稿，同时写入版本 1 的修订记录
+    async fn create_draft(&self, edit: &DraftEdit) -> Result<NewsletterDraft, RepositoryError>;
+    async fn find_draft(&self, id: Uuid) -> Result<NewsletterDraft, RepositoryError>;
+    /// 按最后保存时间倒序，最多 `limit` 条
+    async fn list_drafts(&self, limit: usize) -> Result<Vec<NewsletterDraft>, RepositoryError>;
+    /// 保存为新版本；`expected_version` 不是当前版本时返回 Conflict，不会覆盖别人的修改
+    async fn update_draft(&self, id: Uuid, expected_version: i64, edit: &DraftEdit) -> Result<NewsletterDraft, RepositoryError>;
+    /// 删除草稿和它的修订记录，已发布的通讯保留
+    async fn delete_draft(&self, id: Uuid) -> Result<(), RepositoryError>;
+    /// 全部修订记录，按版本号排序
+    async fn draft_revisions(&self, id: Uuid) -> Result<Vec<DraftRevision>, RepositoryError>;
+    async fn draft_revision(&self, id: Uuid, version: i64) -> Result<DraftRevision, RepositoryError>;
+    /// 把 `version` 这个版本发布为一期通讯；`version` 不是当前版本时返回 Conflict
+    ///
+    /// 检查版本和复制内容在同一条语句中完成，发出去的一定是编辑看到的那个版本
+    async fn publish_draft(
+        &self,
+        id: Uuid,
+        version: i64,
+        scheduled_at: DateTime<Utc>,
+        created_by: &str,
+        tracking: bool,
+    ) -> Result<NewsletterIssue, RepositoryError>;
+}
+
+/// 处理器用到的全部仓库，由 `DatabasePool::repositories` 创建，测试中可以换成内存实现
+#[derive(Clone)]
+pub struct Repositories {
+    pub subscribers: Arc<dyn SubscriberRepository>,
+    pub users: Arc<dyn UserRepository>,
+    pub privacy: Arc<dyn PrivacyRepository>,
+    pub newsletters: Arc<dyn NewsletterRepository>,
+    pub tracking: Arc<dyn TrackingRepository>,
+    pub suppressions: Arc<dyn SuppressionRepository>,
+    pub deliveries: Arc<dyn DeliveryRepository>,
+}
@@ -0,0 +1,1024 @@
+use crate::domain::{IssueStatus, NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::repository::{
+    channel_stream, consent_query, delivery_query, existing_emails_query, export_query, insert_consents_query, insert_creation_events_query,
+    insert_subscribers_query, issue_query, merge_names_query, queue_deliveries_query, queued_deliveries_query, search_query, ConsentQuery, ConsentRecord,
+    ActivityBucket, Delivery, DeliveryQuery, DeliveryRepository, DeliverySummary, DraftEdit, DraftRevision, DuplicatePolicy, ErasureTombstone, ImportOptions, ImportOutcome, IssueAnalytics,
+    LinkClicks, NewConsent, NewIssue, NewsletterDraft, NewsletterIssue, NewsletterRepository, PersonalData,
+    PrivacyRepository, RepositoryError, StoredCredentials, SubscriberQuery, SubscriberRecord, SubscriberRepository,
+    SubscriberStream, SubscriptionEvent, Suppression, SuppressionRepository, TombstoneRow, TrackingEvent, TrackingRepository, UserRepository,
+    CLAIMABLE_ISSUE, DELIVERY_COLUMNS, DELIVERY_SUMMARY, DRAFT_COLUMNS, ISSUE_COLUMNS, LINK_CLICKS, REVISION_COLUMNS, TRACK_DELIVERY,
+};
+use crate::privacy::hash_email;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use secrecy::Secret;
+use sqlx::{PgExecutor, PgPool};
+use std::collections::{BTreeMap, HashSet};
+use uuid::Uuid;
+
+pub struct PostgresSubscriberRepository {
+    pool: PgPool,
+}
+
+impl PostgresSubscriberRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    //插入订阅者、创建事件和（可选的）同意记录，在同一个事务中完成
+    async fn insert_subscription(
+        &self,
+        subscriber: &NewSubscriber,
+        status: SubscriptionStatus,
+        reason: &str,
+        consent: Option<&NewConsent>,
+    ) -> Result<Uuid, RepositoryError> {
+        let id = Uuid::new_v4();
+        let now = chrono::Utc::now();
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("INSERT INTO subscriptions (id, email, name, subscribed_at, status) VALUES ($1, $2, $3, $4, $5)")
+            .bind(id)
+            .bind(subscriber.email.as_ref())
+            .bind(subscriber.name.as_ref())
+            .bind(now)
+            .bind(status)
+            .execute(&mut transaction)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to insert subscriber: {}", e);
+                e
+            })?;
+        sqlx::query("INSERT INTO subscription_events (id, subscriber_id, from_status, to_status, reason, occurred_at) VALUES ($1, $2, NULL, $3, $4, $5)")
+            .bind(Uuid::new_v4())
+            .bind(id)
+            .bind(status)
+            .bind(reason)
+            .bind(now)
+            .execute(&mut transaction)
+            .await?;
+        if let Some(consent) = consent {
+            insert_consents_query(&[id], consent, now).build().execute(&mut transaction).await?;
+        }
+        transaction.commit().await?;
+        Ok(id)
+    }
+
+    //检查状态转换、更新状态、记录事件和（可选的）同意记录，在同一个事务中完成
+    async fn update_status(
+        &self,
+        id: Uuid,
+        to: SubscriptionStatus,
+        reason: &str,
+        consent: Option<&NewConsent>,
+    ) -> Result<SubscriptionStatus, RepositoryError> {
+        let now = chrono::Utc::now();
+        let mut transaction = self.pool.begin().await?;
+        //FOR UPDATE 锁住这一行，并发的状态变化依次执行
+        let from: SubscriptionStatus = sqlx::query_scalar("SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut transaction)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+        from.transition_to(to)?;
+        sqlx::query("UPDATE subscriptions SET status = $1 WHERE id = $2")
+            .bind(to)
+            .bind(id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("INSERT INTO subscription_events (id, subscriber_id, from_status, to_status, reason, occurred_at) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(Uuid::new_v4())
+            .bind(id)
+            .bind(from)
+            .bind(to)
+            .bind(reason)
+            .bind(now)
+            .execute(&mut transaction)
+            .await?;
+        if let Some(consent) = consent {
+            insert_consents_query(&[id], consent, now).build().execute(&mut transaction).await?;
+        }
+        transaction.commit().await?;
+        Ok(from)
+    }
+}
+
+#[async_trait]
+impl SubscriberRepository for PostgresSubscriberRepository {
+    #[tracing::instrument(name = "Inserting a new subscriber", skip(self, subscriber))]
+    async fn insert(&self, subscriber: &NewSubscriber, status: SubscriptionStatus, reason: &str) -> Result<Uuid, RepositoryError> {
+        self.insert_subscription(subscriber, status, reason, None).await
+    }
+
+    #[tracing::instrument(name = "Inserting a new subscriber with consent", skip(self, subscriber, consent))]
+    async fn insert_with_consent(
+        &self,
+        subscriber: &NewSubscriber,
+        status: SubscriptionStatus,
+        reason: &str,
+        consent: &NewConsent,
+    ) -> Result<Uuid, RepositoryError> {
+        self.insert_subscription(subscriber, status, reason, Some(consent)).await
+    }
+
+    async fn find(&self, id: Uuid) -> Result<SubscriberRecord, RepositoryError> {
+        let record = sqlx::query_as::<_, SubscriberRecord>(
+            "SELECT id, email, name, subscribed_at, status, attributes, tracking FROM subscriptions WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        record.ok_or(RepositoryError::NotFound)
+    }
+
+    async fn find_by_email(&self, email: &SubscriberEmail) -> Result<Option<SubscriberRecord>, RepositoryError> {
+        let record = sqlx::query_as::<_, SubscriberRecord>(
+            "SELECT id, email, name, subscribed_at, status, attributes, tracking FROM subscriptions WHERE lower(email) = lower($1)",
+        )
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    #[tracing::instrument(name = "Updating subscriber details", skip(self, name, email, attributes))]
+    async fn update_details(
+        &self,
+        id: Uuid,
+        name: Option<&SubscriberName>,
+        email: Option<&SubscriberEmail>,
+        attributes: Option<&SubscriberAttributes>,
+        tracking: Option<bool>,
+    ) -> Result<SubscriberRecord, RepositoryError> {
+        let record = sqlx::query_as::<_, SubscriberRecord>(
+            "UPDATE subscriptions
+             SET name = COALESCE($1, name), email = COALESCE($2, email), attributes = COALESCE($3, attributes), tracking = COALESCE($4, tracking)
+             WHERE id = $5
+             RETURNING id, email, name, subscribed_at, status, attributes, tracking",
+        )
+        .bind(name.map(|name| name.as_ref()))
+        .bind(email.map(|email| email.as_ref()))
+        .bind(attributes.map(SubscriberAttributes::to_json))
+        .bind(tracking)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        record.ok_or(RepositoryError::NotFound)
+    }
+
+    #[tracing::instrument(name = "Changing subscriber status", skip(self))]
+    async fn change_status(&self, id: Uuid, to: SubscriptionStatus, reason: &str) -> Result<SubscriptionStatus, RepositoryError> {
+        self.update_status(id, to, reason, None).await
+    }
+
+    async fn change_status_with_consent(
+        &self,
+        id: Uuid,
+        to: SubscriptionStatus,
+        reason: &str,
+        consent: &NewConsent,
+    ) -> Result<SubscriptionStatus, RepositoryError> {
+        self.update_status(id, to, reason, Some(consent)).await
+    }
+
+    async fn events(&self, id: Uuid) -> Result<Vec<SubscriptionEvent>, RepositoryError> {
+        let events = sqlx::query_as::<_, SubscriptionEvent>(
+            "SELECT id, subscriber_id, from_status, to_status, reason, occurred_at FROM subscription_events WHERE subscriber_id = $1 ORDER BY occurred_at, id",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(events)
+    }
+
+    async fn consents(&self, query: &ConsentQuery) -> Result<Vec<ConsentRecord>, RepositoryError> {
+        let records = consent_query(query)
+            .build_query_as::<ConsentRecord>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(records)
+    }
+
+    async fn search(&self, query: &SubscriberQuery) -> Result<Vec<SubscriberRecord>, RepositoryError> {
+        let records = search_query(query)
+            .build_query_as::<SubscriberRecord>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(records)
+    }
+
+    fn stream(&self, query: SubscriberQuery) -> SubscriberStream {
+        let pool = self.pool.clone();
+        channel_stream(move |sender| async move {
+            let mut builder = export_query::<sqlx::Postgres>(&query);
+            let mut rows = builder.build_query_as::<SubscriberRecord>().fetch(&pool);
+            while let Some(row) = rows.next().await {
+                let failed = row.is_err();
+                if sender.send(row.map_err(RepositoryError::from)).await.is_err() || failed {
+                    break;
+                }
+            }
+        })
+    }
+
+    #[tracing::instrument(name = "Deleting a subscriber", skip(self))]
+    async fn delete(&self, id: Uuid) -> Result<(), RepositoryError> {
+        let result = sqlx::query("DELETE FROM subscriptions WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(())
+    }
+    #[tracing::instrument(name = "Importing subscribers", skip(self, batch), fields(rows = batch.len()))]
+    async fn import(&self, batch: &[NewSubscriber], options: &ImportOptions) -> Result<ImportOutcome, RepositoryError> {
+        if batch.is_empty() {
+            return Ok(ImportOutcome::default());
+        }
+        let mut transaction = self.pool.begin().await?;
+        let emails: Vec<&str> = batch.iter().map(|subscriber| subscriber.email.as_ref()).collect();
+        let existing: HashSet<String> = existing_emails_query(&emails)
+            .build_query_as::<(String,)>()
+            .fetch_all(&mut transaction)
+            .await?
+            .into_iter()
+            .map(|(email,)| email)
+            .collect();
+        let (new, duplicates) = ImportOutcome::partition(batch, &existing);
+        let mut outcome = ImportOutcome { inserted: new.len(), ..ImportOutcome::default() };
+        match options.on_duplicate {
+            DuplicatePolicy::Skip => {
+                outcome.skipped = duplicates.iter().map(|subscriber| subscriber.email.as_ref().to_string()).collect()
+            }
+            DuplicatePolicy::Merge => outcome.merged = duplicates.len(),
+        }
+        if options.dry_run {
+            return Ok(outcome);
+        }
+
+        let now = chrono::Utc::now();
+        if !new.is_empty() {
+            let rows: Vec<(Uuid, &NewSubscriber)> = new.into_iter().map(|subscriber| (Uuid::new_v4(), subscriber)).collect();
+            insert_subscribers_query(&rows, options.status, now)
+                .build()
+                .execute(&mut transaction)
+                .await?;
+            let ids: Vec<Uuid> = rows.iter().map(|(id, _)| *id).collect();
+            insert_creation_events_query(&ids, options.status, &options.reason, now)
+                .build()
+                .execute(&mut transaction)
+                .await?;
+            insert_consents_query(&ids, &options.consent, now).build().execute(&mut transaction).await?;
+        }
+        if options.on_duplicate == DuplicatePolicy::Merge && !duplicates.is_empty() {
+            merge_names_query(&duplicates).build().execute(&mut transaction).await?;
+        }
+        transaction.commit().await?;
+        Ok(outcome)
+    }
+}
+
+#[async_trait]
+impl PrivacyRepository for PostgresSubscriberRepository {
+    async fn personal_data(&self, email: &SubscriberEmail) -> Result<PersonalData, RepositoryError> {
+        let subscriptions = sqlx::query_as::<_, SubscriberRecord>(
+            "SELECT id, email, name, subscribed_at, status, attributes, tracking FROM subscriptions WHERE lower(email) = lower($1) ORDER BY subscribed_at, id",
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await?;
+        let subscription_events = sqlx::query_as::<_, SubscriptionEvent>(
+            "SELECT e.id, e.subscriber_id, e.from_status, e.to_status, e.reason, e.occurred_at
+             FROM subscription_events e JOIN subscriptions s ON s.id = e.subscriber_id
+             WHERE lower(s.email) = lower($1) ORDER BY e.occurred_at, e.id",
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await?;
+        let consent_records = sqlx::query_as::<_, ConsentRecord>(
+            "SELECT c.id, c.subscriber_id, c.action, c.recorded_at, c.ip_address, c.user_agent, c.source, c.consent_version
+             FROM consent_records c JOIN subscriptions s ON s.id = c.subscriber_id
+             WHERE lower(s.email) = lower($1) ORDER BY c.recorded_at, c.id",
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await?;
+        let tracking_events = sqlx::query_as::<_, TrackingEvent>(
+            "SELECT t.id, t.issue_id, t.subscriber_id, t.kind, t.url, t.occurred_at
+             FROM tracking_events t JOIN subscriptions s ON s.id = t.subscriber_id
+             WHERE lower(s.email) = lower($1) ORDER BY t.occurred_at, t.id",
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await?;
+        let suppressions = sqlx::query_as::<_, Suppression>(
+            "SELECT email, reason, detail, message_id, suppressed_at FROM suppression_list WHERE email = $1",
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await?;
+        let deliveries = sqlx::query_as::<_, Delivery>(&format!(
+            "SELECT {} FROM deliveries WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1)) ORDER BY queued_at, issue_id",
+            DELIVERY_COLUMNS
+        ))
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(PersonalData {
+            email: email.as_ref().to_string(),
+            exported_at: Utc::now(),
+            subscriptions,
+            subscription_events,
+            consent_records,
+            tracking_events,
+            suppressions,
+            deliveries,
+        })
+    }
+
+    #[tracing::instrument(name = "Erasing personal data", skip(self, email))]
+    async fn erase(&self, email: &SubscriberEmail, requested_by: &str, reason: &str) -> Result<ErasureTombstone, RepositoryError> {
+        let mut transaction = self.pool.begin().await?;
+        //事件和同意记录都有 on delete cascade，先单独删除是为了记录行数
+        let consents = sqlx::query("DELETE FROM consent_records WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))")
+            .bind(email.as_ref())
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let events = sqlx::query("DELETE FROM subscription_events WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))")
+            .bind(email.as_ref())
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let tracking = sqlx::query("DELETE FROM tracking_events WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))")
+            .bind(email.as_ref())
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let deliveries = sqlx::query("DELETE FROM deliveries WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))")
+            .bind(email.as_ref())
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let subscriptions = sqlx::query("DELETE FROM subscriptions WHERE lower(email) = lower($1)")
+            .bind(email.as_ref())
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let tokens = sqlx::query("DELETE FROM privacy_tokens WHERE email = $1")
+            .bind(email.as_ref())
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        //停发记录不删除，只清空邮箱和服务商信息，保留哈希，之后仍然不会发给这个地址
+        let suppressions = sqlx::query("UPDATE suppression_list SET email = NULL, email_hash = $2, detail = NULL, message_id = NULL WHERE email = $1")
+            .bind(email.as_ref())
+            .bind(hash_email(email.as_ref()))
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let erased_rows = BTreeMap::from([
+            ("subscriptions".to_string(), subscriptions),
+            ("subscription_events".to_string(), events),
+            ("consent_records".to_string(), consents),
+            ("tracking_events".to_string(), tracking),
+            ("privacy_tokens".to_string(), tokens),
+            ("suppression_list".to_string(), suppressions),
+            ("deliveries".to_string(), deliveries),
+        ]);
+        let tombstone = ErasureTombstone::new(requested_by, reason, erased_rows);
+        if tombstone.is_empty() {
+            return Err(RepositoryError::NotFound);
+        }
+        sqlx::query("INSERT INTO erasure_tombstones (id, erased_at, requested_by, reason, erased_rows) VALUES ($1, $2, $3, $4, $5)")
+            .bind(tombstone.id)
+            .bind(tombstone.erased_at)
+            .bind(&tombstone.requested_by)
+            .bind(&tombstone.reason)
+            .bind(tombstone.erased_rows_json())
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(tombstone)
+    }
+
+    async fn tombstones(&self, limit: usize) -> Result<Vec<ErasureTombstone>, RepositoryError> {
+        let rows = sqlx::query_as::<_, TombstoneRow>(
+            "SELECT id, erased_at, requested_by, reason, erased_rows FROM erasure_tombstones ORDER BY erased_at DESC, id LIMIT $1",
+        )
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter().map(ErasureTombstone::from_row).collect()
+    }
+
+    async fn insert_token(&self, token_hash: &str, email: &SubscriberEmail, expires_at: DateTime<Utc>) -> Result<(), RepositoryError> {
+        let now = Utc::now();
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("DELETE FROM privacy_tokens WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("INSERT INTO privacy_tokens (token_hash, email, created_at, expires_at) VALUES ($1, $2, $3, $4)")
+            .bind(token_hash)
+            .bind(email.as_ref())
+            .bind(now)
+            .bind(expires_at)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn token_email(&self, token_hash: &str) -> Result<Option<String>, RepositoryError> {
+        let email = sqlx::query_as::<_, (String,)>("SELECT email FROM privacy_tokens WHERE token_hash = $1 AND expires_at > $2")
+            .bind(token_hash)
+            .bind(Utc::now())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(email.map(|(email,)| email))
+    }
+}
+
+pub struct PostgresUserRepository {
+    pool: PgPool,
+}
+
+impl PostgresUserRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserRepository for PostgresUserRepository {
+    async fn find_credentials(&self, username: &str) -> Result<Option<StoredCredentials>, RepositoryError> {
+        let row: Option<(Uuid, String)> = sqlx::query_as("SELECT user_id, password_hash FROM users WHERE username = $1")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|(user_id, password_hash)| StoredCredentials { user_id, password_hash: Secret::new(password_hash) }))
+    }
+}
+
+#[async_trait]
+impl TrackingRepository for PostgresSubscriberRepository {
+    async fn record_event(&self, event: &TrackingEvent) -> Result<bool, RepositoryError> {
+        let mut transaction = self.pool.begin().await?;
+        //订阅者的 tracking 在同一条语句中检查，关闭跟踪之后点击旧邮件中的链接也不会记录
+        let result = sqlx::query(
+            "INSERT INTO tracking_events (id, issue_id, subscriber_id, kind, url, occurred_at)
+             SELECT $1, $2, id, $3, $4, $5 FROM subscriptions WHERE id = $6 AND tracking",
+        )
+        .bind(event.id)
+        .bind(event.issue_id)
+        .bind(&event.kind)
+        .bind(&event.url)
+        .bind(event.occurred_at)
+        .bind(event.subscriber_id)
+        .execute(&mut transaction)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query(TRACK_DELIVERY)
+            .bind(&event.kind)
+            .bind(event.occurred_at)
+            .bind(&event.kind)
+            .bind(event.occurred_at)
+            .bind(event.occurred_at)
+            .bind(event.issue_id)
+            .bind(event.subscriber_id)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    async fn issue_analytics(&self, issue_id: Uuid) -> Result<IssueAnalytics, RepositoryError> {
+        let (opens, unique_opens, clicks, unique_clicks): (i64, i64, i64, i64) = sqlx::query_as(
+            "SELECT COALESCE(SUM(CASE WHEN kind = 'open' THEN 1 ELSE 0 END), 0),
+                    COUNT(DISTINCT CASE WHEN kind = 'open' THEN subscriber_id END),
+                    COALESCE(SUM(CASE WHEN kind = 'click' THEN 1 ELSE 0 END), 0),
+                    COUNT(DISTINCT CASE WHEN kind = 'click' THEN subscriber_id END)
+             FROM tracking_events WHERE issue_id = $1",
+        )
+        .bind(issue_id)
+        .fetch_one(&self.pool)
+        .await?;
+        let links = self.link_clicks(issue_id).await?;
+        Ok(IssueAnalytics { opens, unique_opens, clicks, unique_clicks, links })
+    }
+
+    async fn link_clicks(&self, issue_id: Uuid) -> Result<Vec<LinkClicks>, RepositoryError> {
+        let links = sqlx::query_as::<_, LinkClicks>(LINK_CLICKS)
+            .bind(issue_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(links)
+    }
+
+    async fn activity(&self, issue_id: Uuid, since: DateTime<Utc>, bucket_seconds: i64) -> Result<Vec<ActivityBucket>, RepositoryError> {
+        let buckets = sqlx::query_as::<_, ActivityBucket>(
+            "SELECT FLOOR(EXTRACT(EPOCH FROM occurred_at - $1) / $2)::bigint AS bucket,
+                    COALESCE(SUM(CASE WHEN kind = 'open' THEN 1 ELSE 0 END), 0) AS opens,
+                    COALESCE(SUM(CASE WHEN kind = 'click' THEN 1 ELSE 0 END), 0) AS clicks
+             FROM tracking_events WHERE issue_id = $3 AND occurred_at >= $4
+             GROUP BY 1 ORDER BY 1",
+        )
+        .bind(since)
+        .bind(bucket_seconds)
+        .bind(issue_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(buckets)
+    }
+}
+
+#[async_trait]
+impl SuppressionRepository for PostgresSubscriberRepository {
+    async fn suppress(&self, suppression: &Suppression) -> Result<bool, RepositoryError> {
+        let result = sqlx::query(
+            "INSERT INTO suppression_list (email, reason, detail, message_id, suppressed_at)
+             SELECT $1, $2, $3, $4, $5 WHERE NOT EXISTS (SELECT 1 FROM suppression_list WHERE email_hash = $6)
+             ON CONFLICT (email) DO NOTHING",
+        )
+        .bind(&suppression.email)
+        .bind(&suppression.reason)
+        .bind(&suppression.detail)
+        .bind(&suppression.message_id)
+        .bind(suppression.suppressed_at)
+        .bind(hash_email(&suppression.email))
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn find_suppression(&self, email: &SubscriberEmail) -> Result<Option<Suppression>, RepositoryError> {
+        let suppression = sqlx::query_as::<_, Suppression>(
+            "SELECT coalesce(email, $1) AS email, reason, detail, message_id, suppressed_at FROM suppression_list WHERE email = $1 OR email_hash = $2",
+        )
+        .bind(email.as_ref())
+        .bind(hash_email(email.as_ref()))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(suppression)
+    }
+
+    async fn list_suppressions(&self, limit: usize) -> Result<Vec<Suppression>, RepositoryError> {
+        let suppressions = sqlx::query_as::<_, Suppression>(
+            "SELECT email, reason, detail, message_id, suppressed_at FROM suppression_list WHERE email IS NOT NULL ORDER BY suppressed_at DESC, email LIMIT $1",
+        )
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(suppressions)
+    }
+
+    async fn lift_suppression(&self, email: &SubscriberEmail) -> Result<(), RepositoryError> {
+        let result = sqlx::query("DELETE FROM suppression_list WHERE email = $1 OR email_hash = $2")
+            .bind(email.as_ref())
+            .bind(hash_email(email.as_ref()))
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeliveryRepository for PostgresSubscriberRepository {
+    async fn queue_deliveries(&self, issue_id: Uuid, subscriber_ids: &[Uuid]) -> Result<Vec<Uuid>, RepositoryError> {
+        if subscriber_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        queue_deliveries_query(issue_id, subscriber_ids, Utc::now())
+            .build()
+            .execute(&self.pool)
+            .await?;
+        let queued: Vec<(Uuid,)> = queued_deliveries_query(issue_id, subscriber_ids)
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(queued.into_iter().map(|(subscriber_id,)| subscriber_id).collect())
+    }
+
+    async fn record_sent(&self, issue_id: Uuid, subscriber_id: Uuid, message_id: Option<&str>) -> Result<(), RepositoryError> {
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO deliveries (issue_id, subscriber_id, status, message_id, attempts, queued_at, sent_at, updated_at)
+             VALUES ($1, $2, 'sent', $3, 1, $4, $5, $6)
+             ON CONFLICT (issue_id, subscriber_id) DO UPDATE SET status = 'sent', message_id = excluded.message_id,
+                 attempts = deliveries.attempts + 1, sent_at = excluded.sent_at, updated_at = excluded.updated_at",
+        )
+        .bind(issue_id)
+        .bind(subscriber_id)
+        .bind(message_id)
+        .bind(now)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn record_failure(&self, issue_id: Uuid, subscriber_id: Uuid, error: &str) -> Result<(), RepositoryError> {
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO deliveries (issue_id, subscriber_id, status, attempts, last_error, queued_at, updated_at)
+             VALUES ($1, $2, 'failed', 1, $3, $4, $5)
+             ON CONFLICT (issue_id, subscriber_id) DO UPDATE SET status = 'failed', last_error = excluded.last_error,
+                 attempts = deliveries.attempts + 1, updated_at = excluded.updated_at",
+        )
+        .bind(issue_id)
+        .bind(subscriber_id)
+        .bind(error)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn record_bounce(&self, message_id: &str, detail: Option<&str>) -> Result<bool, RepositoryError> {
+        let now = Utc::now();
+        let result = sqlx::query(
+            "UPDATE deliveries SET status = 'bounced', last_error = COALESCE($1, last_error),
+                 bounced_at = COALESCE(bounced_at, $2), updated_at = $3
+             WHERE message_id = $4",
+        )
+        .bind(detail)
+        .bind(now)
+        .bind(now)
+        .bind(message_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn find_delivery_by_message_id(&self, message_id: &str) -> Result<Option<Delivery>, RepositoryError> {
+        let delivery = sqlx::query_as::<_, Delivery>(&format!("SELECT {} FROM deliveries WHERE message_id = $1 LIMIT 1", DELIVERY_COLUMNS))
+            .bind(message_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(delivery)
+    }
+
+    async fn find_delivery(&self, issue_id: Uuid, subscriber_id: Uuid) -> Result<Delivery, RepositoryError> {
+        let delivery = sqlx::query_as::<_, Delivery>(&format!("SELECT {} FROM deliveries WHERE issue_id = $1 AND subscriber_id = $2", DELIVERY_COLUMNS))
+            .bind(issue_id)
+            .bind(subscriber_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        delivery.ok_or(RepositoryError::NotFound)
+    }
+
+    async fn list_deliveries(&self, issue_id: Uuid, query: &DeliveryQuery) -> Result<Vec<Delivery>, RepositoryError> {
+        let deliveries = delivery_query(issue_id, query)
+            .build_query_as::<Delivery>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(deliveries)
+    }
+
+    async fn record_unsubscribe(&self, issue_id: Uuid, subscriber_id: Uuid) -> Result<(), RepositoryError> {
+        let now = Utc::now();
+        sqlx::query(
+            "UPDATE deliveries SET unsubscribed_at = COALESCE(unsubscribed_at, $1), updated_at = $2
+             WHERE issue_id = $3 AND subscriber_id = $4",
+        )
+        .bind(now)
+        .bind(now)
+        .bind(issue_id)
+        .bind(subscriber_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delivery_summary(&self, issue_id: Uuid) -> Result<DeliverySummary, RepositoryError> {
+        let summary = sqlx::query_as::<_, DeliverySummary>(DELIVERY_SUMMARY)
+            .bind(issue_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(summary)
+    }
+}
+
+pub struct PostgresNewsletterRepository {
+    pool: PgPool,
+}
+
+impl PostgresNewsletterRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+async fn insert_revision<'c, E: PgExecutor<'c>>(executor: E, revision: &DraftRevision) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO newsletter_draft_revisions (draft_id, version, title, content, saved_at, saved_by, restored_from)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(revision.draft_id)
+    .bind(revision.version)
+    .bind(&revision.title)
+    .bind(&revision.content)
+    .bind(revision.saved_at)
+    .bind(&revision.saved_by)
+    .bind(revision.restored_from)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+//调度任务的事务级 advisory lock 的键（"newslett" 的 ASCII）
+const SCHEDULER_LOCK_KEY: i64 = 0x6e65_7773_6c65_7474;
+
+#[async_trait]
+impl NewsletterRepository for PostgresNewsletterRepository {
+    #[tracing::instrument(name = "Creating a newsletter issue", skip(self, issue))]
+    async fn create_issue(&self, issue: &NewIssue) -> Result<NewsletterIssue, RepositoryError> {
+        let issue = NewsletterIssue::new(issue);
+        sqlx::query(
+            "INSERT INTO newsletter_issues (id, title, content, status, scheduled_at, created_at, created_by, tracking)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(issue.id)
+        .bind(&issue.title)
+        .bind(&issue.content)
+        .bind(issue.status)
+        .bind(issue.scheduled_at)
+        .bind(issue.created_at)
+        .bind(&issue.created_by)
+        .bind(issue.tracking)
+        .execute(&self.pool)
+        .await?;
+        Ok(issue)
+    }
+
+    async fn find_issue(&self, id: Uuid) -> Result<NewsletterIssue, RepositoryError> {
+        let issue = sqlx::query_as::<_, NewsletterIssue>(&format!("SELECT {} FROM newsletter_issues WHERE id = $1", ISSUE_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        issue.ok_or(RepositoryError::NotFound)
+    }
+
+    async fn list_issues(&self, status: Option<IssueStatus>, limit: usize) -> Result<Vec<NewsletterIssue>, RepositoryError> {
+        let issues = issue_query(status, limit)
+            .build_query_as::<NewsletterIssue>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(issues)
+    }
+
+    #[tracing::instrument(name = "Rescheduling a newsletter issue", skip(self))]
+    async fn reschedule_issue(&self, id: Uuid, scheduled_at: DateTime<Utc>) -> Result<NewsletterIssue, RepositoryError> {
+        let issue = sqlx::query_as::<_, NewsletterIssue>(&format!(
+            "UPDATE newsletter_issues SET scheduled_at = $1 WHERE id = $2 AND status = 'scheduled' RETURNING {}",
+            ISSUE_COLUMNS
+        ))
+        .bind(scheduled_at)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        match issue {
+            Some(issue) => Ok(issue),
+            None => Err(NewsletterIssue::not_scheduled(self.find_issue(id).await?.status)),
+        }
+    }
+
+    #[tracing::instrument(name = "Cancelling a newsletter issue", skip(self))]
+    async fn cancel_issue(&self, id: Uuid) -> Result<NewsletterIssue, RepositoryError> {
+        let issue = sqlx::query_as::<_, NewsletterIssue>(&format!(
+            "UPDATE newsletter_issues SET status = 'cancelled', completed_at = $1 WHERE id = $2 AND status = 'scheduled' RETURNING {}",
+            ISSUE_COLUMNS
+        ))
+        .bind(Utc::now())
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        match issue {
+            Some(issue) => Ok(issue),
+            None => Err(NewsletterIssue::not_scheduled(self.find_issue(id).await?.status)),
+        }
+    }
+
+    async fn claim_due_issue(&self, now: DateTime<Utc>) -> Result<Option<NewsletterIssue>, RepositoryError> {
+        let mut transaction = self.pool.begin().await?;
+        //UPDATE 中的条件已经保证每期只被领取一次（领取之后 claimed_at 是当前时间，不再可领取）；
+        //锁让同一时刻只有一个实例在领取，其它实例这一轮直接跳过，事务结束时自动释放
+        let (locked,): (bool,) = sqlx::query_as("SELECT pg_try_advisory_xact_lock($1)")
+            .bind(SCHEDULER_LOCK_KEY)
+            .fetch_one(&mut transaction)
+            .await?;
+        if !locked {
+            return Ok(None);
+        }
+        //外层再检查一次条件：子查询选中之后、更新之前被取消或续期的通讯不会被领取
+        let issue = sqlx::query_as::<_, NewsletterIssue>(&format!(
+            "UPDATE newsletter_issues SET status = 'sending', started_at = COALESCE(started_at, $1), claimed_at = $1
+             WHERE {claimable} AND id = (
+                 SELECT id FROM newsletter_issues WHERE {claimable} ORDER BY scheduled_at, id LIMIT 1
+             )
+             RETURNING {}",
+            ISSUE_COLUMNS,
+            claimable = CLAIMABLE_ISSUE,
+        ))
+        .bind(now)
+        .bind(now)
+        .bind(NewsletterIssue::claims_expire_before(now))
+        .fetch_optional(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(issue)
+    }
+
+    async fn renew_claim(&self, id: Uuid, now: DateTime<Utc>) -> Result<(), RepositoryError> {
+        sqlx::query("UPDATE newsletter_issues SET claimed_at = $1 WHERE id = $2 AND status = 'sending'")
+            .bind(now)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn release_claim(&self, id: Uuid) -> Result<(), RepositoryError> {
+        sqlx::query("UPDATE newsletter_issues SET claimed_at = NULL WHERE id = $1 AND status = 'sending'")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn complete_issue(&self, id: Uuid, sent: u64, failed: u64) -> Result<(), RepositoryError> {
+        sqlx::query(
+            "UPDATE newsletter_issues SET status = 'sent', completed_at = $1, sent_count = $2, failed_count = $3 WHERE id = $4",
+        )
+        .bind(Utc::now())
+        .bind(i64::try_from(sent).unwrap_or(i64::MAX))
+        .bind(i64::try_from(failed).unwrap_or(i64::MAX))
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn fail_issue(&self, id: Uuid, reason: &str) -> Result<(), RepositoryError> {
+        sqlx::query(
+            "UPDATE newsletter_issues SET status = 'failed', completed_at = $1, failure_reason = $2, claimed_at = NULL WHERE id = $3",
+        )
+        .bind(Utc::now())
+        .bind(reason)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Creating a newsletter draft", skip(self, edit))]
+    async fn create_draft(&self, edit: &DraftEdit) -> Result<NewsletterDraft, RepositoryError> {
+        let draft = NewsletterDraft::new(edit);
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO newsletter_drafts (id, title, content, version, created_at, created_by, updated_at, updated_by)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(draft.id)
+        .bind(&draft.title)
+        .bind(&draft.content)
+        .bind(draft.version)
+        .bind(draft.created_at)
+        .bind(&draft.created_by)
+        .bind(draft.updated_at)
+        .bind(&draft.updated_by)
+        .execute(&mut transaction)
+        .await?;
+        insert_revision(&mut transaction, &draft.revision(None)).await?;
+        transaction.commit().await?;
+        Ok(draft)
+    }
+
+    async fn find_draft(&self, id: Uuid) -> Result<NewsletterDraft, RepositoryError> {
+        let draft = sqlx::query_as::<_, NewsletterDraft>(&format!("SELECT {} FROM newsletter_drafts WHERE id = $1", DRAFT_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        draft.ok_or(RepositoryError::NotFound)
+    }
+
+    async fn list_drafts(&self, limit: usize) -> Result<Vec<NewsletterDraft>, RepositoryError> {
+        let drafts = sqlx::query_as::<_, NewsletterDraft>(&format!(
+            "SELECT {} FROM newsletter_drafts ORDER BY updated_at DESC, id DESC LIMIT $1",
+            DRAFT_COLUMNS
+        ))
+        .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(drafts)
+    }
+
+    #[tracing::instrument(name = "Saving a newsletter draft", skip(self, edit))]
+    async fn update_draft(&self, id: Uuid, expected_version: i64, edit: &DraftEdit) -> Result<NewsletterDraft, RepositoryError> {
+        let mut transaction = self.pool.begin().await?;
+        //版本号条件让并发的两次保存只有一次成功，另一次看不到行，回到下面报告冲突
+        let draft = sqlx::query_as::<_, NewsletterDraft>(&format!(
+            "UPDATE newsletter_drafts SET title = $1, content = $2, version = version + 1, updated_at = $3, updated_by = $4
+             WHERE id = $5 AND version = $6
+             RETURNING {}",
+            DRAFT_COLUMNS
+        ))
+        .bind(&edit.title)
+        .bind(&edit.content)
+        .bind(Utc::now())
+        .bind(&edit.saved_by)
+        .bind(id)
+        .bind(expected_version)
+        .fetch_optional(&mut transaction)
+        .await?;
+        let Some(draft) = draft else {
+            transaction.rollback().await?;
+            return Err(self.find_draft(id).await?.stale_version(expected_version));
+        };
+        insert_revision(&mut transaction, &draft.revision(edit.restored_from)).await?;
+        transaction.commit().await?;
+        Ok(draft)
+    }
+
+    #[tracing::instrument(name = "Deleting a newsletter draft", skip(self))]
+    async fn delete_draft(&self, id: Uuid) -> Result<(), RepositoryError> {
+        //修订记录随草稿级联删除，通讯的 draft_id 置空
+        let result = sqlx::query("DELETE FROM newsletter_drafts WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn draft_revisions(&self, id: Uuid) -> Result<Vec<DraftRevision>, RepositoryError> {
+        let revisions = sqlx::query_as::<_, DraftRevision>(&format!(
+            "SELECT {} FROM newsletter_draft_revisions WHERE draft_id = $1 ORDER BY version",
+            REVISION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        //草稿存在时至少有版本 1
+        if revisions.is_empty() {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(revisions)
+    }
+
+    async fn draft_revision(&self, id: Uuid, version: i64) -> Result<DraftRevision, RepositoryError> {
+        let revision = sqlx::query_as::<_, DraftRevision>(&format!(
+            "SELECT {} FROM newsletter_draft_revisions WHERE draft_id = $1 AND version = $2",
+            REVISION_COLUMNS
+        ))
+        .bind(id)
+        .bind(version)
+        .fetch_optional(&self.pool)
+        .await?;
+        revision.ok_or(RepositoryError::NotFound)
+    }
+
+    #[tracing::instrument(name = "Publishing a newsletter draft", skip(self))]
+    async fn publish_draft(
+        &self,
+        id: Uuid,
+        version: i64,
+        scheduled_at: DateTime<Utc>,
+        created_by: &str,
+        tracking: bool,
+    ) -> Result<NewsletterIssue, RepositoryError> {
+        //版本检查和复制标题、正文在同一条语句中完成，不会发出检查之后才保存的内容
+        let issue = sqlx::query_as::<_, NewsletterIssue>(&format!(
+            "INSERT INTO newsletter_issues (id, title, content, status, scheduled_at, created_at, created_by, tracking, draft_id, draft_version)
+             SELECT $1, title, content, 'scheduled', $2, $3, $4, $5, id, version FROM newsletter_drafts WHERE id = $6 AND version = $7
+             RETURNING {}",
+            ISSUE_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(scheduled_at)
+        .bind(Utc::now())
+        .bind(created_by)
+        .bind(tracking)
+        .bind(id)
+        .bind(version)
+        .fetch_optional(&self.pool)
+        .await?;
+        match issue {
+            Some(issue) => Ok(issue),
+            None => Err(self.find_draft(id).await?.stale_version(version)),
+        }
+    }
+}
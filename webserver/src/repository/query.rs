@@ -0,0 +1,602 @@
+use crate::domain::{DeliveryStatus, IssueStatus, NewSubscriber, SubscriptionStatus};
+use crate::repository::{ConsentRecord, DeliveryQuery, NewConsent, SubscriberRecord};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use sqlx::{Database, Encode, QueryBuilder, Type};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortField {
+    SubscribedAt,
+    Email,
+    Name,
+}
+
+impl SortField {
+    pub fn column(&self) -> &'static str {
+        match self {
+            SortField::SubscribedAt => "subscribed_at",
+            SortField::Email => "email",
+            SortField::Name => "name",
+        }
+    }
+}
+
+/// 排序方式，`id` 始终作为第二排序键，保证顺序稳定
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SubscriberSort {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+impl SubscriberSort {
+    /// `subscribed_at`、`-subscribed_at`、`email`、`-name` 等，`-` 表示降序
+    pub fn parse(sort: &str) -> Result<SubscriberSort, String> {
+        let (descending, column) = match sort.strip_prefix('-') {
+            Some(column) => (true, column),
+            None => (false, sort),
+        };
+        let field = [SortField::SubscribedAt, SortField::Email, SortField::Name].into_iter()
+            .find(|field| field.column() == column)
+            .ok_or_else(|| format!("Cannot sort by {}", column))?;
+        Ok(SubscriberSort { field, descending })
+    }
+
+    pub fn as_string(&self) -> String {
+        format!("{}{}", if self.descending { "-" } else { "" }, self.field.column())
+    }
+}
+
+impl Default for SubscriberSort {
+    fn default() -> Self {
+        SubscriberSort { field: SortField::SubscribedAt, descending: false }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CursorKey {
+    SubscribedAt(DateTime<Utc>),
+    Text(String),
+}
+
+/// 上一页最后一条记录的 (排序键, id)，下一页从它之后开始
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub key: CursorKey,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    pub fn after(record: &SubscriberRecord, sort: &SubscriberSort) -> Cursor {
+        let key = match sort.field {
+            SortField::SubscribedAt => CursorKey::SubscribedAt(record.subscribed_at),
+            SortField::Email => CursorKey::Text(record.email.clone()),
+            SortField::Name => CursorKey::Text(record.name.clone()),
+        };
+        Cursor { key, id: record.id }
+    }
+
+    /// 编码为不透明的字符串；游标包含排序方式，换了排序之后旧游标会被拒绝
+    pub fn encode(&self, sort: &SubscriberSort) -> String {
+        let key = match &self.key {
+            CursorKey::SubscribedAt(subscribed_at) => subscribed_at.to_rfc3339(),
+            CursorKey::Text(text) => text.clone(),
+        };
+        URL_SAFE_NO_PAD.encode(format!("{}\n{}\n{}", sort.as_string(), self.id, key))
+    }
+
+    pub fn decode(cursor: &str, sort: &SubscriberSort) -> Result<Cursor, String> {
+        let invalid = || "Cursor is not valid".to_string();
+        let decoded = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let mut parts = decoded.splitn(3, '\n');
+        let (cursor_sort, id, key) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(cursor_sort), Some(id), Some(key)) => (cursor_sort, id, key),
+            _ => return Err(invalid()),
+        };
+        if cursor_sort != sort.as_string() {
+            return Err("Cursor was created for a different sort order".to_string());
+        }
+        let id = Uuid::parse_str(id).map_err(|_| invalid())?;
+        let key = match sort.field {
+            SortField::SubscribedAt => CursorKey::SubscribedAt(
+                DateTime::parse_from_rfc3339(key).map_err(|_| invalid())?.with_timezone(&Utc),
+            ),
+            SortField::Email | SortField::Name => CursorKey::Text(key.to_string()),
+        };
+        Ok(Cursor { key, id })
+    }
+}
+
+/// 管理后台列出订阅者的条件，所有过滤条件之间是 AND
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriberQuery {
+    pub status: Option<SubscriptionStatus>,
+    //邮箱 @ 之后的部分，不区分大小写
+    pub email_domain: Option<String>,
+    //subscribed_at >= subscribed_from 且 < subscribed_to
+    pub subscribed_from: Option<DateTime<Utc>>,
+    pub subscribed_to: Option<DateTime<Utc>>,
+    //在姓名和邮箱中查找，不区分大小写
+    pub search: Option<String>,
+    pub sort: SubscriberSort,
+    pub after: Option<Cursor>,
+    pub limit: usize,
+}
+
+impl Default for SubscriberQuery {
+    fn default() -> Self {
+        SubscriberQuery {
+            status: None,
+            email_domain: None,
+            subscribed_from: None,
+            subscribed_to: None,
+            search: None,
+            sort: SubscriberSort::default(),
+            after: None,
+            limit: 50,
+        }
+    }
+}
+
+impl SubscriberQuery {
+    /// 内存实现使用的过滤逻辑，与 SQL 版本保持一致
+    pub fn matches(&self, record: &SubscriberRecord) -> bool {
+        let email = record.email.to_lowercase();
+        if let Some(status) = self.status {
+            if record.status != status {
+                return false;
+            }
+        }
+        if let Some(domain) = &self.email_domain {
+            if !email.ends_with(&format!("@{}", domain.to_lowercase())) {
+                return false;
+            }
+        }
+        if let Some(from) = self.subscribed_from {
+            if record.subscribed_at < from {
+                return false;
+            }
+        }
+        if let Some(to) = self.subscribed_to {
+            if record.subscribed_at >= to {
+                return false;
+            }
+        }
+        match &self.search {
+            Some(search) => {
+                let search = search.to_lowercase();
+                record.name.to_lowercase().contains(&search) || email.contains(&search)
+            }
+            None => true,
+        }
+    }
+}
+
+/// 管理后台查询同意记录的条件，所有过滤条件之间是 AND
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsentQuery {
+    pub subscriber_id: Option<Uuid>,
+    pub source: Option<String>,
+    pub consent_version: Option<String>,
+    //recorded_at >= recorded_from 且 < recorded_to
+    pub recorded_from: Option<DateTime<Utc>>,
+    pub recorded_to: Option<DateTime<Utc>>,
+    pub limit: usize,
+}
+
+impl Default for ConsentQuery {
+    fn default() -> Self {
+        ConsentQuery {
+            subscriber_id: None,
+            source: None,
+            consent_version: None,
+            recorded_from: None,
+            recorded_to: None,
+            limit: 50,
+        }
+    }
+}
+
+impl ConsentQuery {
+    /// 某个订阅者的全部同意记录
+    pub fn for_subscriber(subscriber_id: Uuid) -> Self {
+        ConsentQuery { subscriber_id: Some(subscriber_id), limit: usize::MAX, ..ConsentQuery::default() }
+    }
+
+    /// 内存实现使用的过滤逻辑，与 SQL 版本保持一致
+    pub fn matches(&self, record: &ConsentRecord) -> bool {
+        if let Some(subscriber_id) = self.subscriber_id {
+            if record.subscriber_id != subscriber_id {
+                return false;
+            }
+        }
+        if self.source.is_some() && record.source != self.source {
+            return false;
+        }
+        if self.consent_version.is_some() && record.consent_version != self.consent_version {
+            return false;
+        }
+        if let Some(from) = self.recorded_from {
+            if record.recorded_at < from {
+                return false;
+            }
+        }
+        match self.recorded_to {
+            Some(to) => record.recorded_at < to,
+            None => true,
+        }
+    }
+}
+
+/// 生成查询同意记录的 SQL，按时间倒序
+pub fn consent_query<'a, DB>(query: &ConsentQuery) -> QueryBuilder<'a, DB>
+where
+    DB: Database,
+    String: Encode<'a, DB> + Type<DB>,
+    Uuid: Encode<'a, DB> + Type<DB>,
+    DateTime<Utc>: Encode<'a, DB> + Type<DB>,
+    i64: Encode<'a, DB> + Type<DB>,
+{
+    let mut builder = QueryBuilder::new(
+        "SELECT id, subscriber_id, action, recorded_at, ip_address, user_agent, source, consent_version FROM consent_records WHERE 1 = 1",
+    );
+    if let Some(subscriber_id) = query.subscriber_id {
+        builder.push(" AND subscriber_id = ").push_bind(subscriber_id);
+    }
+    if let Some(source) = &query.source {
+        builder.push(" AND source = ").push_bind(source.clone());
+    }
+    if let Some(consent_version) = &query.consent_version {
+        builder.push(" AND consent_version = ").push_bind(consent_version.clone());
+    }
+    if let Some(from) = query.recorded_from {
+        builder.push(" AND recorded_at >= ").push_bind(from);
+    }
+    if let Some(to) = query.recorded_to {
+        builder.push(" AND recorded_at < ").push_bind(to);
+    }
+    //for_subscriber 用 usize::MAX 表示不限条数
+    builder.push(" ORDER BY recorded_at DESC, id DESC LIMIT ").push_bind(i64::try_from(query.limit).unwrap_or(i64::MAX));
+    builder
+}
+
+/// newsletter_issues 的全部列，顺序与 `NewsletterIssue` 一致
+pub(crate) const ISSUE_COLUMNS: &str =
+    "id, title, content, status, scheduled_at, created_at, created_by, started_at, completed_at, sent_count, failed_count, draft_id, draft_version, tracking, claimed_at, failure_reason";
+
+/// 可以被调度任务领取的通讯：到期的 scheduled，或者领取已经放弃、过期的 sending
+///
+/// $2 是当前时间，$3 是 `NewsletterIssue::claims_expire_before` 的结果
+pub(crate) const CLAIMABLE_ISSUE: &str =
+    "((status = 'scheduled' AND scheduled_at <= $2) OR (status = 'sending' AND (claimed_at IS NULL OR claimed_at < $3)))";
+
+/// newsletter_drafts 的全部列，顺序与 `NewsletterDraft` 一致
+pub(crate) const DRAFT_COLUMNS: &str = "id, title, content, version, created_at, created_by, updated_at, updated_by";
+
+/// newsletter_draft_revisions 的全部列，顺序与 `DraftRevision` 一致
+pub(crate) const REVISION_COLUMNS: &str = "draft_id, version, title, content, saved_at, saved_by, restored_from";
+
+/// deliveries 的全部列，顺序与 `Delivery` 一致
+pub(crate) const DELIVERY_COLUMNS: &str =
+    "issue_id, subscriber_id, status, message_id, attempts, last_error, queued_at, sent_at, opened_at, clicked_at, bounced_at, unsubscribed_at, updated_at";
+
+/// 汇总一期通讯的投递记录，参数是 issue_id；按主键的 issue_id 前缀扫描一遍
+pub(crate) const DELIVERY_SUMMARY: &str =
+    "SELECT COUNT(*) AS recipients,
+            COUNT(sent_at) AS sent,
+            COALESCE(SUM(CASE WHEN status = 'failed' THEN 1 ELSE 0 END), 0) AS failed,
+            COALESCE(SUM(CASE WHEN status = 'bounced' THEN 1 ELSE 0 END), 0) AS bounced,
+            COUNT(opened_at) AS unique_opens,
+            COUNT(clicked_at) AS unique_clicks,
+            COUNT(unsubscribed_at) AS unsubscribes
+     FROM deliveries WHERE issue_id = $1";
+
+/// 每个链接的点击数，参数是 issue_id
+pub(crate) const LINK_CLICKS: &str =
+    "SELECT url, COUNT(*) AS clicks, COUNT(DISTINCT subscriber_id) AS unique_clicks
+     FROM tracking_events WHERE issue_id = $1 AND kind = 'click'
+     GROUP BY url ORDER BY clicks DESC, url";
+
+/// 记录了一次打开或点击之后更新投递状态，参数依次是 kind、时间、kind、时间、时间、issue_id、subscriber_id
+///
+/// 只有已经发出的才会变为 opened / clicked 并记下时间，点击同时算作打开；排队、失败和退信的保持原样。
+/// SET 中的 status 都是更新前的值
+pub(crate) const TRACK_DELIVERY: &str =
+    "UPDATE deliveries SET
+         status = CASE WHEN $1 = 'click' AND status IN ('sent', 'opened') THEN 'clicked'
+                       WHEN status = 'sent' THEN 'opened'
+                       ELSE status END,
+         opened_at = CASE WHEN status IN ('sent', 'opened', 'clicked') THEN COALESCE(opened_at, $2) ELSE opened_at END,
+         clicked_at = CASE WHEN $3 = 'click' AND status IN ('sent', 'opened', 'clicked') THEN COALESCE(clicked_at, $4) ELSE clicked_at END,
+         updated_at = $5
+     WHERE issue_id = $6 AND subscriber_id = $7";
+
+/// 生成列出通讯的 SQL，按计划发送时间倒序
+pub fn issue_query<'a, DB>(status: Option<IssueStatus>, limit: usize) -> QueryBuilder<'a, DB>
+where
+    DB: Database,
+    IssueStatus: Encode<'a, DB> + Type<DB>,
+    i64: Encode<'a, DB> + Type<DB>,
+{
+    let mut builder = QueryBuilder::new(format!("SELECT {} FROM newsletter_issues WHERE 1 = 1", ISSUE_COLUMNS));
+    if let Some(status) = status {
+        builder.push(" AND status = ").push_bind(status);
+    }
+    builder.push(" ORDER BY scheduled_at DESC, id DESC LIMIT ").push_bind(i64::try_from(limit).unwrap_or(i64::MAX));
+    builder
+}
+
+//LIKE 模式中的 %、_ 和转义符本身需要转义
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// 生成列出订阅者的 SQL；占位符由 QueryBuilder 按数据库方言生成
+pub fn search_query<'a, DB>(query: &SubscriberQuery) -> QueryBuilder<'a, DB>
+where
+    DB: Database,
+    String: Encode<'a, DB> + Type<DB>,
+    Uuid: Encode<'a, DB> + Type<DB>,
+    DateTime<Utc>: Encode<'a, DB> + Type<DB>,
+    SubscriptionStatus: Encode<'a, DB> + Type<DB>,
+    i64: Encode<'a, DB> + Type<DB>,
+{
+    let mut builder = export_query(query);
+    builder.push(" LIMIT ").push_bind(query.limit as i64);
+    builder
+}
+
+/// 与 `search_query` 相同的过滤和排序，但不限制条数，用于逐行导出
+pub fn export_query<'a, DB>(query: &SubscriberQuery) -> QueryBuilder<'a, DB>
+where
+    DB: Database,
+    String: Encode<'a, DB> + Type<DB>,
+    Uuid: Encode<'a, DB> + Type<DB>,
+    DateTime<Utc>: Encode<'a, DB> + Type<DB>,
+    SubscriptionStatus: Encode<'a, DB> + Type<DB>,
+{
+    let mut builder = QueryBuilder::new("SELECT id, email, name, subscribed_at, status, attributes, tracking FROM subscriptions WHERE 1 = 1");
+    if let Some(status) = query.status {
+        builder.push(" AND status = ").push_bind(status);
+    }
+    if let Some(domain) = &query.email_domain {
+        builder.push(" AND lower(email) LIKE ")
+            .push_bind(format!("%@{}", escape_like(&domain.to_lowercase())))
+            .push(" ESCAPE '\\'");
+    }
+    if let Some(from) = query.subscribed_from {
+        builder.push(" AND subscribed_at >= ").push_bind(from);
+    }
+    if let Some(to) = query.subscribed_to {
+        builder.push(" AND subscribed_at < ").push_bind(to);
+    }
+    if let Some(search) = &query.search {
+        let pattern = format!("%{}%", escape_like(&search.to_lowercase()));
+        builder.push(" AND (lower(name) LIKE ")
+            .push_bind(pattern.clone())
+            .push(" ESCAPE '\\' OR lower(email) LIKE ")
+            .push_bind(pattern)
+            .push(" ESCAPE '\\')");
+    }
+    let column = query.sort.field.column();
+    let direction = if query.sort.descending { "DESC" } else { "ASC" };
+    if let Some(cursor) = &query.after {
+        //行值比较：(key, id) 严格在游标之后
+        let comparison = if query.sort.descending { "<" } else { ">" };
+        builder.push(format!(" AND ({}, id) {} (", column, comparison));
+        match &cursor.key {
+            CursorKey::SubscribedAt(subscribed_at) => builder.push_bind(*subscribed_at),
+            CursorKey::Text(text) => builder.push_bind(text.clone()),
+        };
+        builder.push(", ").push_bind(cursor.id).push(")");
+    }
+    builder.push(format!(" ORDER BY {} {}, id {}", column, direction, direction));
+    builder
+}
+
+/// 一批邮箱中已经存在于 subscriptions 的那些，不区分大小写，返回小写形式
+pub fn existing_emails_query<'a, DB>(emails: &[&str]) -> QueryBuilder<'a, DB>
+where
+    DB: Database,
+    String: Encode<'a, DB> + Type<DB>,
+{
+    let mut builder = QueryBuilder::new("SELECT DISTINCT lower(email) FROM subscriptions WHERE lower(email) IN (");
+    let mut separated = builder.separated(", ");
+    for email in emails {
+        separated.push_bind(email.to_lowercase());
+    }
+    separated.push_unseparated(")");
+    builder
+}
+
+/// 把一页收件人记为 queued；从 subscriptions 中选取，发送期间被删除的订阅者不会违反外键
+pub fn queue_deliveries_query<'a, DB>(issue_id: Uuid, subscriber_ids: &[Uuid], queued_at: DateTime<Utc>) -> QueryBuilder<'a, DB>
+where
+    DB: Database,
+    Uuid: Encode<'a, DB> + Type<DB>,
+    DateTime<Utc>: Encode<'a, DB> + Type<DB>,
+{
+    let mut builder = QueryBuilder::new("INSERT INTO deliveries (issue_id, subscriber_id, status, attempts, queued_at, updated_at) SELECT ");
+    builder.push_bind(issue_id)
+        .push(", id, 'queued', 0, ")
+        .push_bind(queued_at)
+        .push(", ")
+        .push_bind(queued_at)
+        .push(" FROM subscriptions WHERE id IN (");
+    let mut separated = builder.separated(", ");
+    for subscriber_id in subscriber_ids {
+        separated.push_bind(*subscriber_id);
+    }
+    separated.push_unseparated(") ON CONFLICT (issue_id, subscriber_id) DO NOTHING");
+    builder
+}
+
+/// 一页收件人中投递记录仍是 queued 的，走 (issue_id, subscriber_id) 主键
+pub fn queued_deliveries_query<'a, DB>(issue_id: Uuid, subscriber_ids: &[Uuid]) -> QueryBuilder<'a, DB>
+where
+    DB: Database,
+    Uuid: Encode<'a, DB> + Type<DB>,
+{
+    let mut builder = QueryBuilder::new("SELECT subscriber_id FROM deliveries WHERE status = 'queued' AND issue_id = ");
+    builder.push_bind(issue_id).push(" AND subscriber_id IN (");
+    let mut separated = builder.separated(", ");
+    for subscriber_id in subscriber_ids {
+        separated.push_bind(*subscriber_id);
+    }
+    separated.push_unseparated(")");
+    builder
+}
+
+/// 生成列出一期通讯投递记录的 SQL，按 subscriber_id 排序，走 (issue_id, subscriber_id) 主键
+pub fn delivery_query<'a, DB>(issue_id: Uuid, query: &DeliveryQuery) -> QueryBuilder<'a, DB>
+where
+    DB: Database,
+    Uuid: Encode<'a, DB> + Type<DB>,
+    DeliveryStatus: Encode<'a, DB> + Type<DB>,
+    i64: Encode<'a, DB> + Type<DB>,
+{
+    let mut builder = QueryBuilder::new(format!("SELECT {} FROM deliveries WHERE issue_id = ", DELIVERY_COLUMNS));
+    builder.push_bind(issue_id);
+    if let Some(status) = query.status {
+        builder.push(" AND status = ").push_bind(status);
+    }
+    if let Some(after) = query.after {
+        builder.push(" AND subscriber_id > ").push_bind(after);
+    }
+    builder.push(" ORDER BY subscriber_id LIMIT ").push_bind(i64::try_from(query.limit).unwrap_or(i64::MAX));
+    builder
+}
+
+/// 多行 INSERT；每行 5 个参数，批大小需要留在数据库的参数上限以内
+pub fn insert_subscribers_query<'a, DB>(
+    rows: &[(Uuid, &NewSubscriber)],
+    status: SubscriptionStatus,
+    subscribed_at: DateTime<Utc>,
+) -> QueryBuilder<'a, DB>
+where
+    DB: Database,
+    String: Encode<'a, DB> + Type<DB>,
+    Uuid: Encode<'a, DB> + Type<DB>,
+    DateTime<Utc>: Encode<'a, DB> + Type<DB>,
+    SubscriptionStatus: Encode<'a, DB> + Type<DB>,
+{
+    let mut builder = QueryBuilder::new("INSERT INTO subscriptions (id, email, name, subscribed_at, status) ");
+    builder.push_values(rows, |mut row, (id, subscriber)| {
+        row.push_bind(*id)
+            .push_bind(subscriber.email.as_ref().to_string())
+            .push_bind(subscriber.name.as_ref().to_string())
+            .push_bind(subscribed_at)
+            .push_bind(status);
+    });
+    builder
+}
+
+/// 为新插入的订阅者各写一条创建事件
+pub fn insert_creation_events_query<'a, DB>(
+    subscriber_ids: &[Uuid],
+    status: SubscriptionStatus,
+    reason: &str,
+    occurred_at: DateTime<Utc>,
+) -> QueryBuilder<'a, DB>
+where
+    DB: Database,
+    String: Encode<'a, DB> + Type<DB>,
+    Uuid: Encode<'a, DB> + Type<DB>,
+    DateTime<Utc>: Encode<'a, DB> + Type<DB>,
+    SubscriptionStatus: Encode<'a, DB> + Type<DB>,
+{
+    let mut builder = QueryBuilder::new(
+        "INSERT INTO subscription_events (id, subscriber_id, from_status, to_status, reason, occurred_at) ",
+    );
+    builder.push_values(subscriber_ids, |mut row, subscriber_id| {
+        row.push_bind(Uuid::new_v4())
+            .push_bind(*subscriber_id)
+            .push("NULL")
+            .push_bind(status)
+            .push_bind(reason.to_string())
+            .push_bind(occurred_at);
+    });
+    builder
+}
+
+/// 为每个订阅者写一条相同内容的同意记录
+pub fn insert_consents_query<'a, DB>(subscriber_ids: &[Uuid], consent: &NewConsent, recorded_at: DateTime<Utc>) -> QueryBuilder<'a, DB>
+where
+    DB: Database,
+    String: Encode<'a, DB> + Type<DB>,
+    Option<String>: Encode<'a, DB> + Type<DB>,
+    Uuid: Encode<'a, DB> + Type<DB>,
+    DateTime<Utc>: Encode<'a, DB> + Type<DB>,
+{
+    let mut builder = QueryBuilder::new(
+        "INSERT INTO consent_records (id, subscriber_id, action, recorded_at, ip_address, user_agent, source, consent_version) ",
+    );
+    builder.push_values(subscriber_ids, |mut row, subscriber_id| {
+        let record = ConsentRecord::new(*subscriber_id, consent, recorded_at);
+        row.push_bind(record.id)
+            .push_bind(record.subscriber_id)
+            .push_bind(record.action)
+            .push_bind(record.recorded_at)
+            .push_bind(record.ip_address)
+            .push_bind(record.user_agent)
+            .push_bind(record.source)
+            .push_bind(record.consent_version);
+    });
+    builder
+}
+
+/// 用导入的姓名覆盖邮箱相同（不区分大小写）的已有记录
+///
+/// `VALUES` 的列在两种数据库中都叫 column1、column2，所以不给子查询起列名
+pub fn merge_names_query<'a, DB>(subscribers: &[&NewSubscriber]) -> QueryBuilder<'a, DB>
+where
+    DB: Database,
+    String: Encode<'a, DB> + Type<DB>,
+{
+    let mut builder = QueryBuilder::new("UPDATE subscriptions SET name = merged.column2 FROM (");
+    builder.push_values(subscribers, |mut row, subscriber| {
+        row.push_bind(subscriber.email.as_ref().to_string())
+            .push_bind(subscriber.name.as_ref().to_string());
+    });
+    builder.push(") AS merged WHERE lower(subscriptions.email) = lower(merged.column1)");
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::assert_err;
+
+    #[test]
+    fn sort_order_is_parsed() {
+        assert_eq!(SubscriberSort::parse("-email"), Ok(SubscriberSort { field: SortField::Email, descending: true }));
+        assert_eq!(SubscriberSort::parse("subscribed_at"), Ok(SubscriberSort::default()));
+        assert_err!(SubscriberSort::parse("password"));
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let sort = SubscriberSort::parse("-subscribed_at").unwrap();
+        let cursor = Cursor { key: CursorKey::SubscribedAt(Utc::now()), id: Uuid::new_v4() };
+        assert_eq!(Cursor::decode(&cursor.encode(&sort), &sort), Ok(cursor));
+
+        let sort = SubscriberSort::parse("name").unwrap();
+        let cursor = Cursor { key: CursorKey::Text("Ursula\nLe Guin".into()), id: Uuid::new_v4() };
+        assert_eq!(Cursor::decode(&cursor.encode(&sort), &sort), Ok(cursor));
+    }
+
+    #[test]
+    fn cursors_are_tied_to_their_sort_order() {
+        let cursor = Cursor { key: CursorKey::Text("ursula@example.com".into()), id: Uuid::new_v4() };
+        let encoded = cursor.encode(&SubscriberSort::parse("email").unwrap());
+        assert_err!(Cursor::decode(&encoded, &SubscriberSort::parse("-email").unwrap()));
+        assert_err!(Cursor::decode("not a cursor", &SubscriberSort::default()));
+    }
+
+    #[test]
+    fn like_wildcards_in_user_input_are_escaped() {
+        assert_eq!(escape_like("100%_off\\"), "100\\%\\_off\\\\");
+    }
+}
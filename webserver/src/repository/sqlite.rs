@@ -0,0 +1,1519 @@
+use crate::domain::{IssueStatus, NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::repository::{
+    channel_stream, consent_query, delivery_query, existing_emails_query, export_query, insert_consents_query, insert_creation_events_query,
+    insert_subscribers_query, issue_query, merge_names_query, queue_deliveries_query, queued_deliveries_query, search_query, ConsentQuery, ConsentRecord,
+    ActivityBucket, Delivery, DeliveryQuery, DeliveryRepository, DeliverySummary, DraftEdit, DraftRevision, DuplicatePolicy, ErasureTombstone, ImportOptions, ImportOutcome, IssueAnalytics,
+    LinkClicks, NewConsent, NewIssue, NewsletterDraft, NewsletterIssue, NewsletterRepository, PersonalData,
+    PrivacyRepository, RepositoryError, StoredCredentials, SubscriberQuery, SubscriberRecord, SubscriberRepository,
+    SubscriberStream, SubscriptionEvent, Suppression, SuppressionRepository, TombstoneRow, TrackingEvent, TrackingRepository, UserRepository,
+    CLAIMABLE_ISSUE, DELIVERY_COLUMNS, DELIVERY_SUMMARY, DRAFT_COLUMNS, ISSUE_COLUMNS, LINK_CLICKS, REVISION_COLUMNS, TRACK_DELIVERY,
+};
+use crate::privacy::hash_email;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use secrecy::Secret;
+use sqlx::{SqliteExecutor, SqlitePool};
+use std::collections::{BTreeMap, HashSet};
+use uuid::Uuid;
+
+/// SQLite 实现，SQL 与 Postgres 版本相同：uuid 存为 BLOB，时间存为 RFC 3339 文本
+///
+/// `UPDATE ... RETURNING` 用 `fetch_all` 读完：语句没有执行完时自动提交的事务还没有提交，
+/// 连接池中的其它连接会读到旧数据
+pub struct SqliteSubscriberRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteSubscriberRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    //插入订阅者、创建事件和（可选的）同意记录，在同一个事务中完成
+    async fn insert_subscription(
+        &self,
+        subscriber: &NewSubscriber,
+        status: SubscriptionStatus,
+        reason: &str,
+        consent: Option<&NewConsent>,
+    ) -> Result<Uuid, RepositoryError> {
+        let id = Uuid::new_v4();
+        let now = chrono::Utc::now();
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("INSERT INTO subscriptions (id, email, name, subscribed_at, status) VALUES ($1, $2, $3, $4, $5)")
+            .bind(id)
+            .bind(subscriber.email.as_ref())
+            .bind(subscriber.name.as_ref())
+            .bind(now)
+            .bind(status)
+            .execute(&mut transaction)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to insert subscriber: {}", e);
+                e
+            })?;
+        sqlx::query("INSERT INTO subscription_events (id, subscriber_id, from_status, to_status, reason, occurred_at) VALUES ($1, $2, NULL, $3, $4, $5)")
+            .bind(Uuid::new_v4())
+            .bind(id)
+            .bind(status)
+            .bind(reason)
+            .bind(now)
+            .execute(&mut transaction)
+            .await?;
+        if let Some(consent) = consent {
+            insert_consents_query(&[id], consent, now).build().execute(&mut transaction).await?;
+        }
+        transaction.commit().await?;
+        Ok(id)
+    }
+
+    //检查状态转换、更新状态、记录事件和（可选的）同意记录，在同一个事务中完成
+    async fn update_status(
+        &self,
+        id: Uuid,
+        to: SubscriptionStatus,
+        reason: &str,
+        consent: Option<&NewConsent>,
+    ) -> Result<SubscriptionStatus, RepositoryError> {
+        let now = chrono::Utc::now();
+        let mut transaction = self.pool.begin().await?;
+        //SQLite 没有 FOR UPDATE；写操作是串行的，读取之后状态被并发修改时 UPDATE 会以 SQLITE_BUSY 失败，不会覆盖
+        let from: SubscriptionStatus = sqlx::query_scalar("SELECT status FROM subscriptions WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&mut transaction)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+        from.transition_to(to)?;
+        sqlx::query("UPDATE subscriptions SET status = $1 WHERE id = $2")
+            .bind(to)
+            .bind(id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("INSERT INTO subscription_events (id, subscriber_id, from_status, to_status, reason, occurred_at) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(Uuid::new_v4())
+            .bind(id)
+            .bind(from)
+            .bind(to)
+            .bind(reason)
+            .bind(now)
+            .execute(&mut transaction)
+            .await?;
+        if let Some(consent) = consent {
+            insert_consents_query(&[id], consent, now).build().execute(&mut transaction).await?;
+        }
+        transaction.commit().await?;
+        Ok(from)
+    }
+}
+
+#[async_trait]
+impl SubscriberRepository for SqliteSubscriberRepository {
+    #[tracing::instrument(name = "Inserting a new subscriber", skip(self, subscriber))]
+    async fn insert(&self, subscriber: &NewSubscriber, status: SubscriptionStatus, reason: &str) -> Result<Uuid, RepositoryError> {
+        self.insert_subscription(subscriber, status, reason, None).await
+    }
+
+    #[tracing::instrument(name = "Inserting a new subscriber with consent", skip(self, subscriber, consent))]
+    async fn insert_with_consent(
+        &self,
+        subscriber: &NewSubscriber,
+        status: SubscriptionStatus,
+        reason: &str,
+        consent: &NewConsent,
+    ) -> Result<Uuid, RepositoryError> {
+        self.insert_subscription(subscriber, status, reason, Some(consent)).await
+    }
+
+    async fn find(&self, id: Uuid) -> Result<SubscriberRecord, RepositoryError> {
+        let record = sqlx::query_as::<_, SubscriberRecord>(
+            "SELECT id, email, name, subscribed_at, status, attributes, tracking FROM subscriptions WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        record.ok_or(RepositoryError::NotFound)
+    }
+
+    async fn find_by_email(&self, email: &SubscriberEmail) -> Result<Option<SubscriberRecord>, RepositoryError> {
+        let record = sqlx::query_as::<_, SubscriberRecord>(
+            "SELECT id, email, name, subscribed_at, status, attributes, tracking FROM subscriptions WHERE lower(email) = lower($1)",
+        )
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(record)
+    }
+
+    #[tracing::instrument(name = "Updating subscriber details", skip(self, name, email, attributes))]
+    async fn update_details(
+        &self,
+        id: Uuid,
+        name: Option<&SubscriberName>,
+        email: Option<&SubscriberEmail>,
+        attributes: Option<&SubscriberAttributes>,
+        tracking: Option<bool>,
+    ) -> Result<SubscriberRecord, RepositoryError> {
+        let record = sqlx::query_as::<_, SubscriberRecord>(
+            "UPDATE subscriptions
+             SET name = COALESCE($1, name), email = COALESCE($2, email), attributes = COALESCE($3, attributes), tracking = COALESCE($4, tracking)
+             WHERE id = $5
+             RETURNING id, email, name, subscribed_at, status, attributes, tracking",
+        )
+        .bind(name.map(|name| name.as_ref()))
+        .bind(email.map(|email| email.as_ref()))
+        .bind(attributes.map(SubscriberAttributes::to_json))
+        .bind(tracking)
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .pop();
+        record.ok_or(RepositoryError::NotFound)
+    }
+
+    #[tracing::instrument(name = "Changing subscriber status", skip(self))]
+    async fn change_status(&self, id: Uuid, to: SubscriptionStatus, reason: &str) -> Result<SubscriptionStatus, RepositoryError> {
+        self.update_status(id, to, reason, None).await
+    }
+
+    async fn change_status_with_consent(
+        &self,
+        id: Uuid,
+        to: SubscriptionStatus,
+        reason: &str,
+        consent: &NewConsent,
+    ) -> Result<SubscriptionStatus, RepositoryError> {
+        self.update_status(id, to, reason, Some(consent)).await
+    }
+
+    async fn events(&self, id: Uuid) -> Result<Vec<SubscriptionEvent>, RepositoryError> {
+        let events = sqlx::query_as::<_, SubscriptionEvent>(
+            "SELECT id, subscriber_id, from_status, to_status, reason, occurred_at FROM subscription_events WHERE subscriber_id = $1 ORDER BY occurred_at, id",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(events)
+    }
+
+    async fn consents(&self, query: &ConsentQuery) -> Result<Vec<ConsentRecord>, RepositoryError> {
+        let records = consent_query(query)
+            .build_query_as::<ConsentRecord>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(records)
+    }
+
+    async fn search(&self, query: &SubscriberQuery) -> Result<Vec<SubscriberRecord>, RepositoryError> {
+        let records = search_query(query)
+            .build_query_as::<SubscriberRecord>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(records)
+    }
+
+    fn stream(&self, query: SubscriberQuery) -> SubscriberStream {
+        let pool = self.pool.clone();
+        channel_stream(move |sender| async move {
+            let mut builder = export_query::<sqlx::Sqlite>(&query);
+            let mut rows = builder.build_query_as::<SubscriberRecord>().fetch(&pool);
+            while let Some(row) = rows.next().await {
+                let failed = row.is_err();
+                if sender.send(row.map_err(RepositoryError::from)).await.is_err() || failed {
+                    break;
+                }
+            }
+        })
+    }
+
+    #[tracing::instrument(name = "Deleting a subscriber", skip(self))]
+    async fn delete(&self, id: Uuid) -> Result<(), RepositoryError> {
+        let result = sqlx::query("DELETE FROM subscriptions WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(())
+    }
+    #[tracing::instrument(name = "Importing subscribers", skip(self, batch), fields(rows = batch.len()))]
+    async fn import(&self, batch: &[NewSubscriber], options: &ImportOptions) -> Result<ImportOutcome, RepositoryError> {
+        if batch.is_empty() {
+            return Ok(ImportOutcome::default());
+        }
+        let mut transaction = self.pool.begin().await?;
+        let emails: Vec<&str> = batch.iter().map(|subscriber| subscriber.email.as_ref()).collect();
+        let existing: HashSet<String> = existing_emails_query(&emails)
+            .build_query_as::<(String,)>()
+            .fetch_all(&mut transaction)
+            .await?
+            .into_iter()
+            .map(|(email,)| email)
+            .collect();
+        let (new, duplicates) = ImportOutcome::partition(batch, &existing);
+        let mut outcome = ImportOutcome { inserted: new.len(), ..ImportOutcome::default() };
+        match options.on_duplicate {
+            DuplicatePolicy::Skip => {
+                outcome.skipped = duplicates.iter().map(|subscriber| subscriber.email.as_ref().to_string()).collect()
+            }
+            DuplicatePolicy::Merge => outcome.merged = duplicates.len(),
+        }
+        if options.dry_run {
+            return Ok(outcome);
+        }
+
+        let now = chrono::Utc::now();
+        if !new.is_empty() {
+            let rows: Vec<(Uuid, &NewSubscriber)> = new.into_iter().map(|subscriber| (Uuid::new_v4(), subscriber)).collect();
+            insert_subscribers_query(&rows, options.status, now)
+                .build()
+                .execute(&mut transaction)
+                .await?;
+            let ids: Vec<Uuid> = rows.iter().map(|(id, _)| *id).collect();
+            insert_creation_events_query(&ids, options.status, &options.reason, now)
+                .build()
+                .execute(&mut transaction)
+                .await?;
+            insert_consents_query(&ids, &options.consent, now).build().execute(&mut transaction).await?;
+        }
+        if options.on_duplicate == DuplicatePolicy::Merge && !duplicates.is_empty() {
+            merge_names_query(&duplicates).build().execute(&mut transaction).await?;
+        }
+        transaction.commit().await?;
+        Ok(outcome)
+    }
+}
+
+#[async_trait]
+impl PrivacyRepository for SqliteSubscriberRepository {
+    async fn personal_data(&self, email: &SubscriberEmail) -> Result<PersonalData, RepositoryError> {
+        let subscriptions = sqlx::query_as::<_, SubscriberRecord>(
+            "SELECT id, email, name, subscribed_at, status, attributes, tracking FROM subscriptions WHERE lower(email) = lower($1) ORDER BY subscribed_at, id",
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await?;
+        let subscription_events = sqlx::query_as::<_, SubscriptionEvent>(
+            "SELECT e.id, e.subscriber_id, e.from_status, e.to_status, e.reason, e.occurred_at
+             FROM subscription_events e JOIN subscriptions s ON s.id = e.subscriber_id
+             WHERE lower(s.email) = lower($1) ORDER BY e.occurred_at, e.id",
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await?;
+        let consent_records = sqlx::query_as::<_, ConsentRecord>(
+            "SELECT c.id, c.subscriber_id, c.action, c.recorded_at, c.ip_address, c.user_agent, c.source, c.consent_version
+             FROM consent_records c JOIN subscriptions s ON s.id = c.subscriber_id
+             WHERE lower(s.email) = lower($1) ORDER BY c.recorded_at, c.id",
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await?;
+        let tracking_events = sqlx::query_as::<_, TrackingEvent>(
+            "SELECT t.id, t.issue_id, t.subscriber_id, t.kind, t.url, t.occurred_at
+             FROM tracking_events t JOIN subscriptions s ON s.id = t.subscriber_id
+             WHERE lower(s.email) = lower($1) ORDER BY t.occurred_at, t.id",
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await?;
+        let suppressions = sqlx::query_as::<_, Suppression>(
+            "SELECT email, reason, detail, message_id, suppressed_at FROM suppression_list WHERE email = $1",
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await?;
+        let deliveries = sqlx::query_as::<_, Delivery>(&format!(
+            "SELECT {} FROM deliveries WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1)) ORDER BY queued_at, issue_id",
+            DELIVERY_COLUMNS
+        ))
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(PersonalData {
+            email: email.as_ref().to_string(),
+            exported_at: Utc::now(),
+            subscriptions,
+            subscription_events,
+            consent_records,
+            tracking_events,
+            suppressions,
+            deliveries,
+        })
+    }
+
+    #[tracing::instrument(name = "Erasing personal data", skip(self, email))]
+    async fn erase(&self, email: &SubscriberEmail, requested_by: &str, reason: &str) -> Result<ErasureTombstone, RepositoryError> {
+        let mut transaction = self.pool.begin().await?;
+        //事件和同意记录都有 on delete cascade，先单独删除是为了记录行数
+        let consents = sqlx::query("DELETE FROM consent_records WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))")
+            .bind(email.as_ref())
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let events = sqlx::query("DELETE FROM subscription_events WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))")
+            .bind(email.as_ref())
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let tracking = sqlx::query("DELETE FROM tracking_events WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))")
+            .bind(email.as_ref())
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let deliveries = sqlx::query("DELETE FROM deliveries WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))")
+            .bind(email.as_ref())
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let subscriptions = sqlx::query("DELETE FROM subscriptions WHERE lower(email) = lower($1)")
+            .bind(email.as_ref())
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let tokens = sqlx::query("DELETE FROM privacy_tokens WHERE email = $1")
+            .bind(email.as_ref())
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        //停发记录不删除，只清空邮箱和服务商信息，保留哈希，之后仍然不会发给这个地址
+        let suppressions = sqlx::query("UPDATE suppression_list SET email = NULL, email_hash = $2, detail = NULL, message_id = NULL WHERE email = $1")
+            .bind(email.as_ref())
+            .bind(hash_email(email.as_ref()))
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let erased_rows = BTreeMap::from([
+            ("subscriptions".to_string(), subscriptions),
+            ("subscription_events".to_string(), events),
+            ("consent_records".to_string(), consents),
+            ("tracking_events".to_string(), tracking),
+            ("privacy_tokens".to_string(), tokens),
+            ("suppression_list".to_string(), suppressions),
+            ("deliveries".to_string(), deliveries),
+        ]);
+        let tombstone = ErasureTombstone::new(requested_by, reason, erased_rows);
+        if tombstone.is_empty() {
+            return Err(RepositoryError::NotFound);
+        }
+        sqlx::query("INSERT INTO erasure_tombstones (id, erased_at, requested_by, reason, erased_rows) VALUES ($1, $2, $3, $4, $5)")
+            .bind(tombstone.id)
+            .bind(tombstone.erased_at)
+            .bind(&tombstone.requested_by)
+            .bind(&tombstone.reason)
+            .bind(tombstone.erased_rows_json())
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(tombstone)
+    }
+
+    async fn tombstones(&self, limit: usize) -> Result<Vec<ErasureTombstone>, RepositoryError> {
+        let rows = sqlx::query_as::<_, TombstoneRow>(
+            "SELECT id, erased_at, requested_by, reason, erased_rows FROM erasure_tombstones ORDER BY erased_at DESC, id LIMIT $1",
+        )
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter().map(ErasureTombstone::from_row).collect()
+    }
+
+    async fn insert_token(&self, token_hash: &str, email: &SubscriberEmail, expires_at: DateTime<Utc>) -> Result<(), RepositoryError> {
+        let now = Utc::now();
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("DELETE FROM privacy_tokens WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query("INSERT INTO privacy_tokens (token_hash, email, created_at, expires_at) VALUES ($1, $2, $3, $4)")
+            .bind(token_hash)
+            .bind(email.as_ref())
+            .bind(now)
+            .bind(expires_at)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn token_email(&self, token_hash: &str) -> Result<Option<String>, RepositoryError> {
+        let email = sqlx::query_as::<_, (String,)>("SELECT email FROM privacy_tokens WHERE token_hash = $1 AND expires_at > $2")
+            .bind(token_hash)
+            .bind(Utc::now())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(email.map(|(email,)| email))
+    }
+}
+
+pub struct SqliteUserRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteUserRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserRepository for SqliteUserRepository {
+    async fn find_credentials(&self, username: &str) -> Result<Option<StoredCredentials>, RepositoryError> {
+        let row: Option<(Uuid, String)> = sqlx::query_as("SELECT user_id, password_hash FROM users WHERE username = $1")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|(user_id, password_hash)| StoredCredentials { user_id, password_hash: Secret::new(password_hash) }))
+    }
+}
+
+#[async_trait]
+impl TrackingRepository for SqliteSubscriberRepository {
+    async fn record_event(&self, event: &TrackingEvent) -> Result<bool, RepositoryError> {
+        let mut transaction = self.pool.begin().await?;
+        //订阅者的 tracking 在同一条语句中检查，关闭跟踪之后点击旧邮件中的链接也不会记录
+        let result = sqlx::query(
+            "INSERT INTO tracking_events (id, issue_id, subscriber_id, kind, url, occurred_at)
+             SELECT $1, $2, id, $3, $4, $5 FROM subscriptions WHERE id = $6 AND tracking",
+        )
+        .bind(event.id)
+        .bind(event.issue_id)
+        .bind(&event.kind)
+        .bind(&event.url)
+        .bind(event.occurred_at)
+        .bind(event.subscriber_id)
+        .execute(&mut transaction)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query(TRACK_DELIVERY)
+            .bind(&event.kind)
+            .bind(event.occurred_at)
+            .bind(&event.kind)
+            .bind(event.occurred_at)
+            .bind(event.occurred_at)
+            .bind(event.issue_id)
+            .bind(event.subscriber_id)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    async fn issue_analytics(&self, issue_id: Uuid) -> Result<IssueAnalytics, RepositoryError> {
+        let (opens, unique_opens, clicks, unique_clicks): (i64, i64, i64, i64) = sqlx::query_as(
+            "SELECT COALESCE(SUM(CASE WHEN kind = 'open' THEN 1 ELSE 0 END), 0),
+                    COUNT(DISTINCT CASE WHEN kind = 'open' THEN subscriber_id END),
+                    COALESCE(SUM(CASE WHEN kind = 'click' THEN 1 ELSE 0 END), 0),
+                    COUNT(DISTINCT CASE WHEN kind = 'click' THEN subscriber_id END)
+             FROM tracking_events WHERE issue_id = $1",
+        )
+        .bind(issue_id)
+        .fetch_one(&self.pool)
+        .await?;
+        let links = self.link_clicks(issue_id).await?;
+        Ok(IssueAnalytics { opens, unique_opens, clicks, unique_clicks, links })
+    }
+
+    async fn link_clicks(&self, issue_id: Uuid) -> Result<Vec<LinkClicks>, RepositoryError> {
+        let links = sqlx::query_as::<_, LinkClicks>(LINK_CLICKS)
+            .bind(issue_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(links)
+    }
+
+    async fn activity(&self, issue_id: Uuid, since: DateTime<Utc>, bucket_seconds: i64) -> Result<Vec<ActivityBucket>, RepositoryError> {
+        let buckets = sqlx::query_as::<_, ActivityBucket>(
+            //时间存为 RFC 3339 文本，先换成 Unix 秒再按整数除法分段
+            "SELECT (CAST(strftime('%s', occurred_at) AS INTEGER) - $1) / $2 AS bucket,
+                    COALESCE(SUM(CASE WHEN kind = 'open' THEN 1 ELSE 0 END), 0) AS opens,
+                    COALESCE(SUM(CASE WHEN kind = 'click' THEN 1 ELSE 0 END), 0) AS clicks
+             FROM tracking_events WHERE issue_id = $3 AND occurred_at >= $4
+             GROUP BY 1 ORDER BY 1",
+        )
+        .bind(since.timestamp())
+        .bind(bucket_seconds)
+        .bind(issue_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(buckets)
+    }
+}
+
+#[async_trait]
+impl SuppressionRepository for SqliteSubscriberRepository {
+    async fn suppress(&self, suppression: &Suppression) -> Result<bool, RepositoryError> {
+        let result = sqlx::query(
+            "INSERT INTO suppression_list (email, reason, detail, message_id, suppressed_at)
+             SELECT $1, $2, $3, $4, $5 WHERE NOT EXISTS (SELECT 1 FROM suppression_list WHERE email_hash = $6)
+             ON CONFLICT (email) DO NOTHING",
+        )
+        .bind(&suppression.email)
+        .bind(&suppression.reason)
+        .bind(&suppression.detail)
+        .bind(&suppression.message_id)
+        .bind(suppression.suppressed_at)
+        .bind(hash_email(&suppression.email))
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn find_suppression(&self, email: &SubscriberEmail) -> Result<Option<Suppression>, RepositoryError> {
+        let suppression = sqlx::query_as::<_, Suppression>(
+            "SELECT coalesce(email, $1) AS email, reason, detail, message_id, suppressed_at FROM suppression_list WHERE email = $1 OR email_hash = $2",
+        )
+        .bind(email.as_ref())
+        .bind(hash_email(email.as_ref()))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(suppression)
+    }
+
+    async fn list_suppressions(&self, limit: usize) -> Result<Vec<Suppression>, RepositoryError> {
+        let suppressions = sqlx::query_as::<_, Suppression>(
+            "SELECT email, reason, detail, message_id, suppressed_at FROM suppression_list WHERE email IS NOT NULL ORDER BY suppressed_at DESC, email LIMIT $1",
+        )
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(suppressions)
+    }
+
+    async fn lift_suppression(&self, email: &SubscriberEmail) -> Result<(), RepositoryError> {
+        let result = sqlx::query("DELETE FROM suppression_list WHERE email = $1 OR email_hash = $2")
+            .bind(email.as_ref())
+            .bind(hash_email(email.as_ref()))
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeliveryRepository for SqliteSubscriberRepository {
+    async fn queue_deliveries(&self, issue_id: Uuid, subscriber_ids: &[Uuid]) -> Result<Vec<Uuid>, RepositoryError> {
+        if subscriber_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        queue_deliveries_query(issue_id, subscriber_ids, Utc::now())
+            .build()
+            .execute(&self.pool)
+            .await?;
+        let queued: Vec<(Uuid,)> = queued_deliveries_query(issue_id, subscriber_ids)
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(queued.into_iter().map(|(subscriber_id,)| subscriber_id).collect())
+    }
+
+    async fn record_sent(&self, issue_id: Uuid, subscriber_id: Uuid, message_id: Option<&str>) -> Result<(), RepositoryError> {
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO deliveries (issue_id, subscriber_id, status, message_id, attempts, queued_at, sent_at, updated_at)
+             VALUES ($1, $2, 'sent', $3, 1, $4, $5, $6)
+             ON CONFLICT (issue_id, subscriber_id) DO UPDATE SET status = 'sent', message_id = excluded.message_id,
+                 attempts = deliveries.attempts + 1, sent_at = excluded.sent_at, updated_at = excluded.updated_at",
+        )
+        .bind(issue_id)
+        .bind(subscriber_id)
+        .bind(message_id)
+        .bind(now)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn record_failure(&self, issue_id: Uuid, subscriber_id: Uuid, error: &str) -> Result<(), RepositoryError> {
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO deliveries (issue_id, subscriber_id, status, attempts, last_error, queued_at, updated_at)
+             VALUES ($1, $2, 'failed', 1, $3, $4, $5)
+             ON CONFLICT (issue_id, subscriber_id) DO UPDATE SET status = 'failed', last_error = excluded.last_error,
+                 attempts = deliveries.attempts + 1, updated_at = excluded.updated_at",
+        )
+        .bind(issue_id)
+        .bind(subscriber_id)
+        .bind(error)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn record_bounce(&self, message_id: &str, detail: Option<&str>) -> Result<bool, RepositoryError> {
+        let now = Utc::now();
+        let result = sqlx::query(
+            "UPDATE deliveries SET status = 'bounced', last_error = COALESCE($1, last_error),
+                 bounced_at = COALESCE(bounced_at, $2), updated_at = $3
+             WHERE message_id = $4",
+        )
+        .bind(detail)
+        .bind(now)
+        .bind(now)
+        .bind(message_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn find_delivery_by_message_id(&self, message_id: &str) -> Result<Option<Delivery>, RepositoryError> {
+        let delivery = sqlx::query_as::<_, Delivery>(&format!("SELECT {} FROM deliveries WHERE message_id = $1 LIMIT 1", DELIVERY_COLUMNS))
+            .bind(message_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(delivery)
+    }
+
+    async fn find_delivery(&self, issue_id: Uuid, subscriber_id: Uuid) -> Result<Delivery, RepositoryError> {
+        let delivery = sqlx::query_as::<_, Delivery>(&format!("SELECT {} FROM deliveries WHERE issue_id = $1 AND subscriber_id = $2", DELIVERY_COLUMNS))
+            .bind(issue_id)
+            .bind(subscriber_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        delivery.ok_or(RepositoryError::NotFound)
+    }
+
+    async fn list_deliveries(&self, issue_id: Uuid, query: &DeliveryQuery) -> Result<Vec<Delivery>, RepositoryError> {
+        let deliveries = delivery_query(issue_id, query)
+            .build_query_as::<Delivery>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(deliveries)
+    }
+
+    async fn record_unsubscribe(&self, issue_id: Uuid, subscriber_id: Uuid) -> Result<(), RepositoryError> {
+        let now = Utc::now();
+        sqlx::query(
+            "UPDATE deliveries SET unsubscribed_at = COALESCE(unsubscribed_at, $1), updated_at = $2
+             WHERE issue_id = $3 AND subscriber_id = $4",
+        )
+        .bind(now)
+        .bind(now)
+        .bind(issue_id)
+        .bind(subscriber_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delivery_summary(&self, issue_id: Uuid) -> Result<DeliverySummary, RepositoryError> {
+        let summary = sqlx::query_as::<_, DeliverySummary>(DELIVERY_SUMMARY)
+            .bind(issue_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(summary)
+    }
+}
+
+pub struct SqliteNewsletterRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteNewsletterRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+async fn insert_revision<'c, E: SqliteExecutor<'c>>(executor: E, revision: &DraftRevision) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO newsletter_draft_revisions (draft_id, version, title, content, saved_at, saved_by, restored_from)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(revision.draft_id)
+    .bind(revision.version)
+    .bind(&revision.title)
+    .bind(&revision.content)
+    .bind(revision.saved_at)
+    .bind(&revision.saved_by)
+    .bind(revision.restored_from)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[async_trait]
+impl NewsletterRepository for SqliteNewsletterRepository {
+    #[tracing::instrument(name = "Creating a newsletter issue", skip(self, issue))]
+    async fn create_issue(&self, issue: &NewIssue) -> Result<NewsletterIssue, RepositoryError> {
+        let issue = NewsletterIssue::new(issue);
+        sqlx::query(
+            "INSERT INTO newsletter_issues (id, title, content, status, scheduled_at, created_at, created_by, tracking)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(issue.id)
+        .bind(&issue.title)
+        .bind(&issue.content)
+        .bind(issue.status)
+        .bind(issue.scheduled_at)
+        .bind(issue.created_at)
+        .bind(&issue.created_by)
+        .bind(issue.tracking)
+        .execute(&self.pool)
+        .await?;
+        Ok(issue)
+    }
+
+    async fn find_issue(&self, id: Uuid) -> Result<NewsletterIssue, RepositoryError> {
+        let issue = sqlx::query_as::<_, NewsletterIssue>(&format!("SELECT {} FROM newsletter_issues WHERE id = $1", ISSUE_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        issue.ok_or(RepositoryError::NotFound)
+    }
+
+    async fn list_issues(&self, status: Option<IssueStatus>, limit: usize) -> Result<Vec<NewsletterIssue>, RepositoryError> {
+        let issues = issue_query(status, limit)
+            .build_query_as::<NewsletterIssue>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(issues)
+    }
+
+    #[tracing::instrument(name = "Rescheduling a newsletter issue", skip(self))]
+    async fn reschedule_issue(&self, id: Uuid, scheduled_at: DateTime<Utc>) -> Result<NewsletterIssue, RepositoryError> {
+        let issue = sqlx::query_as::<_, NewsletterIssue>(&format!(
+            "UPDATE newsletter_issues SET scheduled_at = $1 WHERE id = $2 AND status = 'scheduled' RETURNING {}",
+            ISSUE_COLUMNS
+        ))
+        .bind(scheduled_at)
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .pop();
+        match issue {
+            Some(issue) => Ok(issue),
+            None => Err(NewsletterIssue::not_scheduled(self.find_issue(id).await?.status)),
+        }
+    }
+
+    #[tracing::instrument(name = "Cancelling a newsletter issue", skip(self))]
+    async fn cancel_issue(&self, id: Uuid) -> Result<NewsletterIssue, RepositoryError> {
+        let issue = sqlx::query_as::<_, NewsletterIssue>(&format!(
+            "UPDATE newsletter_issues SET status = 'cancelled', completed_at = $1 WHERE id = $2 AND status = 'scheduled' RETURNING {}",
+            ISSUE_COLUMNS
+        ))
+        .bind(Utc::now())
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .pop();
+        match issue {
+            Some(issue) => Ok(issue),
+            None => Err(NewsletterIssue::not_scheduled(self.find_issue(id).await?.status)),
+        }
+    }
+
+    //SQLite 只用于单实例部署，而且同一时刻只有一个写者，不需要 advisory lock；
+    //单实例时过期的 sending 只可能是上次进程异常退出留下的
+    async fn claim_due_issue(&self, now: DateTime<Utc>) -> Result<Option<NewsletterIssue>, RepositoryError> {
+        let issue = sqlx::query_as::<_, NewsletterIssue>(&format!(
+            "UPDATE newsletter_issues SET status = 'sending', started_at = COALESCE(started_at, $1), claimed_at = $1
+             WHERE {claimable} AND id = (
+                 SELECT id FROM newsletter_issues WHERE {claimable} ORDER BY scheduled_at, id LIMIT 1
+             )
+             RETURNING {}",
+            ISSUE_COLUMNS,
+            claimable = CLAIMABLE_ISSUE,
+        ))
+        .bind(now)
+        .bind(now)
+        .bind(NewsletterIssue::claims_expire_before(now))
+        .fetch_all(&self.pool)
+        .await?
+        .pop();
+        Ok(issue)
+    }
+
+    async fn renew_claim(&self, id: Uuid, now: DateTime<Utc>) -> Result<(), RepositoryError> {
+        sqlx::query("UPDATE newsletter_issues SET claimed_at = $1 WHERE id = $2 AND status = 'sending'")
+            .bind(now)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn release_claim(&self, id: Uuid) -> Result<(), RepositoryError> {
+        sqlx::query("UPDATE newsletter_issues SET claimed_at = NULL WHERE id = $1 AND status = 'sending'")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn complete_issue(&self, id: Uuid, sent: u64, failed: u64) -> Result<(), RepositoryError> {
+        sqlx::query(
+            "UPDATE newsletter_issues SET status = 'sent', completed_at = $1, sent_count = $2, failed_count = $3 WHERE id = $4",
+        )
+        .bind(Utc::now())
+        .bind(i64::try_from(sent).unwrap_or(i64::MAX))
+        .bind(i64::try_from(failed).unwrap_or(i64::MAX))
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn fail_issue(&self, id: Uuid, reason: &str) -> Result<(), RepositoryError> {
+        sqlx::query(
+            "UPDATE newsletter_issues SET status = 'failed', completed_at = $1, failure_reason = $2, claimed_at = NULL WHERE id = $3",
+        )
+        .bind(Utc::now())
+        .bind(reason)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Creating a newsletter draft", skip(self, edit))]
+    async fn create_draft(&self, edit: &DraftEdit) -> Result<NewsletterDraft, RepositoryError> {
+        let draft = NewsletterDraft::new(edit);
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO newsletter_drafts (id, title, content, version, created_at, created_by, updated_at, updated_by)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(draft.id)
+        .bind(&draft.title)
+        .bind(&draft.content)
+        .bind(draft.version)
+        .bind(draft.created_at)
+        .bind(&draft.created_by)
+        .bind(draft.updated_at)
+        .bind(&draft.updated_by)
+        .execute(&mut transaction)
+        .await?;
+        insert_revision(&mut transaction, &draft.revision(None)).await?;
+        transaction.commit().await?;
+        Ok(draft)
+    }
+
+    async fn find_draft(&self, id: Uuid) -> Result<NewsletterDraft, RepositoryError> {
+        let draft = sqlx::query_as::<_, NewsletterDraft>(&format!("SELECT {} FROM newsletter_drafts WHERE id = $1", DRAFT_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        draft.ok_or(RepositoryError::NotFound)
+    }
+
+    async fn list_drafts(&self, limit: usize) -> Result<Vec<NewsletterDraft>, RepositoryError> {
+        let drafts = sqlx::query_as::<_, NewsletterDraft>(&format!(
+            "SELECT {} FROM newsletter_drafts ORDER BY updated_at DESC, id DESC LIMIT $1",
+            DRAFT_COLUMNS
+        ))
+        .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(drafts)
+    }
+
+    #[tracing::instrument(name = "Saving a newsletter draft", skip(self, edit))]
+    async fn update_draft(&self, id: Uuid, expected_version: i64, edit: &DraftEdit) -> Result<NewsletterDraft, RepositoryError> {
+        let mut transaction = self.pool.begin().await?;
+        let draft = sqlx::query_as::<_, NewsletterDraft>(&format!(
+            "UPDATE newsletter_drafts SET title = $1, content = $2, version = version + 1, updated_at = $3, updated_by = $4
+             WHERE id = $5 AND version = $6
+             RETURNING {}",
+            DRAFT_COLUMNS
+        ))
+        .bind(&edit.title)
+        .bind(&edit.content)
+        .bind(Utc::now())
+        .bind(&edit.saved_by)
+        .bind(id)
+        .bind(expected_version)
+        .fetch_all(&mut transaction)
+        .await?
+        .pop();
+        let Some(draft) = draft else {
+            transaction.rollback().await?;
+            return Err(self.find_draft(id).await?.stale_version(expected_version));
+        };
+        insert_revision(&mut transaction, &draft.revision(edit.restored_from)).await?;
+        transaction.commit().await?;
+        Ok(draft)
+    }
+
+    #[tracing::instrument(name = "Deleting a newsletter draft", skip(self))]
+    async fn delete_draft(&self, id: Uuid) -> Result<(), RepositoryError> {
+        let result = sqlx::query("DELETE FROM newsletter_drafts WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn draft_revisions(&self, id: Uuid) -> Result<Vec<DraftRevision>, RepositoryError> {
+        let revisions = sqlx::query_as::<_, DraftRevision>(&format!(
+            "SELECT {} FROM newsletter_draft_revisions WHERE draft_id = $1 ORDER BY version",
+            REVISION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        if revisions.is_empty() {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(revisions)
+    }
+
+    async fn draft_revision(&self, id: Uuid, version: i64) -> Result<DraftRevision, RepositoryError> {
+        let revision = sqlx::query_as::<_, DraftRevision>(&format!(
+            "SELECT {} FROM newsletter_draft_revisions WHERE draft_id = $1 AND version = $2",
+            REVISION_COLUMNS
+        ))
+        .bind(id)
+        .bind(version)
+        .fetch_optional(&self.pool)
+        .await?;
+        revision.ok_or(RepositoryError::NotFound)
+    }
+
+    #[tracing::instrument(name = "Publishing a newsletter draft", skip(self))]
+    async fn publish_draft(
+        &self,
+        id: Uuid,
+        version: i64,
+        scheduled_at: DateTime<Utc>,
+        created_by: &str,
+        tracking: bool,
+    ) -> Result<NewsletterIssue, RepositoryError> {
+        let issue = sqlx::query_as::<_, NewsletterIssue>(&format!(
+            "INSERT INTO newsletter_issues (id, title, content, status, scheduled_at, created_at, created_by, tracking, draft_id, draft_version)
+             SELECT $1, title, content, 'scheduled', $2, $3, $4, $5, id, version FROM newsletter_drafts WHERE id = $6 AND version = $7
+             RETURNING {}",
+            ISSUE_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(scheduled_at)
+        .bind(Utc::now())
+        .bind(created_by)
+        .bind(tracking)
+        .bind(id)
+        .bind(version)
+        .fetch_all(&self.pool)
+        .await?
+        .pop();
+        match issue {
+            Some(issue) => Ok(issue),
+            None => Err(self.find_draft(id).await?.stale_version(version)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::DeliveryStatus;
+    use crate::repository::{Cursor, SubscriberSort, SuppressionReason, TrackingKind, CLAIM_TIMEOUT};
+    use crate::migration::SQLITE_MIGRATOR;
+    use crate::testing::new_subscriber;
+    use claim::{assert_err, assert_none, assert_ok};
+    use sqlx::sqlite::SqliteConnectOptions;
+
+    //每个测试一个临时数据库文件，应用完整的 SQLite 迁移
+    async fn repository() -> SqliteSubscriberRepository {
+        let path = std::env::temp_dir().join(format!("webserver-{}.db", Uuid::new_v4()));
+        let pool = SqlitePool::connect_with(SqliteConnectOptions::new().filename(path).create_if_missing(true))
+            .await
+            .unwrap();
+        SQLITE_MIGRATOR.run(&pool).await.unwrap();
+        SqliteSubscriberRepository::new(pool)
+    }
+
+    fn consent(source: &str) -> NewConsent {
+        NewConsent {
+            action: crate::repository::ConsentAction::Subscribe,
+            ip_address: Some("203.0.113.7".to_string()),
+            user_agent: Some("Mozilla/5.0".to_string()),
+            source: Some(source.to_string()),
+            consent_version: Some("v1".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn inserted_subscribers_round_trip() {
+        let repository = repository().await;
+        let id = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        let record = repository.find_by_email(&email).await.unwrap().unwrap();
+        assert_eq!(record.id, id);
+        assert_eq!(record.name, "Ursula Le Guin");
+        assert_eq!(record.status, SubscriptionStatus::Confirmed);
+    }
+
+    #[tokio::test]
+    async fn emails_are_unique_regardless_of_case() {
+        let repository = repository().await;
+        let id = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let result = repository.insert(&new_subscriber("Ursula@Example.com"), SubscriptionStatus::Confirmed, "subscribed").await;
+        assert!(matches!(result, Err(RepositoryError::Conflict(_))));
+        let email = SubscriberEmail::parse("URSULA@example.com".to_string()).unwrap();
+        assert_eq!(repository.find_by_email(&email).await.unwrap().unwrap().id, id);
+
+        let other = repository.insert(&new_subscriber("iain@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let result = repository.update_details(other, None, Some(&email), None, None).await;
+        assert!(matches!(result, Err(RepositoryError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn status_changes_and_deletes_require_an_existing_subscriber() {
+        let repository = repository().await;
+        assert_err!(repository.change_status(Uuid::new_v4(), SubscriptionStatus::Unsubscribed, "unsubscribed by reader").await);
+        assert_err!(repository.delete(Uuid::new_v4()).await);
+
+        let id = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        assert_ok!(repository.change_status(id, SubscriptionStatus::Unsubscribed, "unsubscribed by reader").await);
+        assert_eq!(repository.find(id).await.unwrap().status, SubscriptionStatus::Unsubscribed);
+        assert_ok!(repository.delete(id).await);
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        assert_none!(repository.find_by_email(&email).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn search_filters_and_pages_through_subscribers() {
+        let repository = repository().await;
+        for email in ["ada@example.com", "grace@navy.mil", "ursula@example.com", "octavia@EXAMPLE.com"] {
+            repository.insert(&new_subscriber(email), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        }
+        let unsubscribed = repository.find_by_email(&SubscriberEmail::parse("ada@example.com".into()).unwrap()).await.unwrap().unwrap();
+        repository.change_status(unsubscribed.id, SubscriptionStatus::Unsubscribed, "unsubscribed by reader").await.unwrap();
+
+        let query = SubscriberQuery {
+            email_domain: Some("Example.com".into()),
+            sort: SubscriberSort::parse("-email").unwrap(),
+            limit: 2,
+            ..SubscriberQuery::default()
+        };
+        let first_page = repository.search(&query).await.unwrap();
+        let emails: Vec<&str> = first_page.iter().map(|record| record.email.as_str()).collect();
+        assert_eq!(emails, vec!["ursula@example.com", "octavia@example.com"]);
+
+        let query = SubscriberQuery { after: Some(Cursor::after(&first_page[1], &query.sort)), ..query };
+        let second_page = repository.search(&query).await.unwrap();
+        let emails: Vec<&str> = second_page.iter().map(|record| record.email.as_str()).collect();
+        assert_eq!(emails, vec!["ada@example.com"]);
+
+        let query = SubscriberQuery {
+            status: Some(SubscriptionStatus::Confirmed),
+            search: Some("GRACE".into()),
+            ..SubscriberQuery::default()
+        };
+        assert_eq!(repository.search(&query).await.unwrap().len(), 1);
+        let query = SubscriberQuery { search: Some("%".into()), ..SubscriberQuery::default() };
+        assert!(repository.search(&query).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn details_can_be_updated() {
+        let repository = repository().await;
+        let id = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let name = SubscriberName::parse("Ursula K. Le Guin".to_string()).unwrap();
+        let record = repository.update_details(id, Some(&name), None, None, None).await.unwrap();
+        assert_eq!(record.name, "Ursula K. Le Guin");
+        assert_eq!(record.email, "ursula@example.com");
+        assert_err!(repository.update_details(Uuid::new_v4(), Some(&name), None, None, None).await);
+
+        let attributes = SubscriberAttributes::parse([("company".to_string(), "Acme".to_string())].into()).unwrap();
+        repository.update_details(id, None, None, Some(&attributes), None).await.unwrap();
+        assert_eq!(repository.find(id).await.unwrap().attributes.get("company"), Some("Acme"));
+        //没有传属性时保持不变
+        let record = repository.update_details(id, Some(&name), None, None, None).await.unwrap();
+        assert_eq!(record.attributes, attributes);
+    }
+
+    #[tokio::test]
+    async fn status_changes_follow_the_state_machine_and_are_recorded() {
+        let repository = repository().await;
+        let id = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        assert_eq!(repository.change_status(id, SubscriptionStatus::Complained, "spam complaint").await.unwrap(), SubscriptionStatus::Confirmed);
+        assert!(matches!(
+            repository.change_status(id, SubscriptionStatus::Confirmed, "resubscribed").await,
+            Err(RepositoryError::InvalidTransition(_))
+        ));
+
+        let events = repository.events(id).await.unwrap();
+        let transitions: Vec<_> = events.iter().map(|event| (event.from_status, event.to_status, event.reason.as_str())).collect();
+        assert_eq!(transitions, vec![
+            (None, SubscriptionStatus::Confirmed, "subscribed"),
+            (Some(SubscriptionStatus::Confirmed), SubscriptionStatus::Complained, "spam complaint"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn batches_are_imported_skipped_and_merged() {
+        let repository = repository().await;
+        repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let mut options = ImportOptions {
+            status: SubscriptionStatus::PendingConfirmation,
+            on_duplicate: DuplicatePolicy::Skip,
+            dry_run: true,
+            reason: "imported".to_string(),
+            consent: NewConsent::imported(Some("mailchimp"), None),
+        };
+        let mut renamed = new_subscriber("ursula@example.com");
+        renamed.name = SubscriberName::parse("Ursula K. Le Guin".to_string()).unwrap();
+        let batch = vec![renamed, new_subscriber("iain@example.com")];
+
+        let outcome = repository.import(&batch, &options).await.unwrap();
+        assert_eq!(outcome, ImportOutcome { inserted: 1, merged: 0, skipped: vec!["ursula@example.com".to_string()] });
+        assert_eq!(repository.search(&SubscriberQuery::default()).await.unwrap().len(), 1);
+
+        options.dry_run = false;
+        options.on_duplicate = DuplicatePolicy::Merge;
+        let outcome = repository.import(&batch, &options).await.unwrap();
+        assert_eq!((outcome.inserted, outcome.merged), (1, 1));
+        let query = SubscriberQuery { sort: SubscriberSort::parse("email").unwrap(), ..SubscriberQuery::default() };
+        let subscribers = repository.search(&query).await.unwrap();
+        assert_eq!(subscribers.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec!["Ursula Le Guin", "Ursula K. Le Guin"]);
+        assert_eq!(subscribers[0].status, SubscriptionStatus::PendingConfirmation);
+        assert_eq!(repository.events(subscribers[0].id).await.unwrap()[0].reason, "imported");
+        let consents = repository.consents(&ConsentQuery::for_subscriber(subscribers[0].id)).await.unwrap();
+        assert_eq!(consents[0].source.as_deref(), Some("import: mailchimp"));
+    }
+
+    #[tokio::test]
+    async fn streams_return_every_matching_row() {
+        let repository = repository().await;
+        for i in 0..5 {
+            repository.insert(&new_subscriber(&format!("reader{}@example.com", i)), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        }
+        repository.insert(&new_subscriber("ursula@example.org"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+
+        let query = SubscriberQuery {
+            email_domain: Some("example.com".to_string()),
+            sort: SubscriberSort::parse("-email").unwrap(),
+            limit: 2,
+            ..SubscriberQuery::default()
+        };
+        let rows: Vec<SubscriberRecord> = repository.stream(query).map(|row| row.unwrap()).collect().await;
+        let emails: Vec<&str> = rows.iter().map(|row| row.email.as_str()).collect();
+        assert_eq!(emails, vec!["reader4@example.com", "reader3@example.com", "reader2@example.com", "reader1@example.com", "reader0@example.com"]);
+    }
+
+    #[tokio::test]
+    async fn erasures_remove_every_row_and_leave_a_tombstone() {
+        let repository = repository().await;
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        let id = repository.insert_with_consent(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed", &consent("footer")).await.unwrap();
+        repository.insert(&new_subscriber("iain@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        repository.change_status(id, SubscriptionStatus::Unsubscribed, "unsubscribed by reader").await.unwrap();
+        repository.insert_token("hash", &email, Utc::now() + chrono::Duration::minutes(5)).await.unwrap();
+        repository.insert_token("expired", &email, Utc::now() - chrono::Duration::minutes(5)).await.unwrap();
+        assert_eq!(repository.token_email("hash").await.unwrap().as_deref(), Some("ursula@example.com"));
+        assert_none!(repository.token_email("expired").await.unwrap());
+
+        let data = repository.personal_data(&email).await.unwrap();
+        assert_eq!(data.subscriptions.len(), 1);
+        assert_eq!(data.subscription_events.len(), 2);
+        assert_eq!(data.consent_records.len(), 1);
+
+        let tombstone = repository.erase(&email, "admin alice", "ticket 42").await.unwrap();
+        assert_eq!(tombstone.erased_rows["subscriptions"], 1);
+        assert_eq!(tombstone.erased_rows["subscription_events"], 2);
+        assert_eq!(tombstone.erased_rows["consent_records"], 1);
+        assert_eq!(tombstone.erased_rows["privacy_tokens"], 2);
+        assert!(repository.personal_data(&email).await.unwrap().subscriptions.is_empty());
+        assert_none!(repository.token_email("hash").await.unwrap());
+        assert!(matches!(repository.erase(&email, "admin alice", "ticket 42").await, Err(RepositoryError::NotFound)));
+        //其他订阅者不受影响
+        assert_eq!(repository.search(&SubscriberQuery::default()).await.unwrap().len(), 1);
+        assert_eq!(repository.tombstones(10).await.unwrap(), vec![tombstone]);
+    }
+
+    #[tokio::test]
+    async fn consents_are_stored_with_the_subscription() {
+        let repository = repository().await;
+        let id = repository.insert_with_consent(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed", &consent("footer")).await.unwrap();
+        repository.insert_with_consent(&new_subscriber("iain@example.com"), SubscriptionStatus::Confirmed, "subscribed", &consent("landing")).await.unwrap();
+
+        let records = repository.consents(&ConsentQuery::for_subscriber(id)).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].action, "subscribe");
+        assert_eq!(records[0].ip_address.as_deref(), Some("203.0.113.7"));
+        assert_eq!(records[0].recorded_at, repository.find(id).await.unwrap().subscribed_at);
+
+        let query = ConsentQuery { source: Some("landing".into()), ..ConsentQuery::default() };
+        assert_eq!(repository.consents(&query).await.unwrap().len(), 1);
+        let query = ConsentQuery { recorded_to: Some(Utc::now() - chrono::Duration::days(1)), ..ConsentQuery::default() };
+        assert!(repository.consents(&query).await.unwrap().is_empty());
+
+        repository.delete(id).await.unwrap();
+        assert!(repository.consents(&ConsentQuery::for_subscriber(id)).await.unwrap().is_empty());
+    }
+
+    fn new_issue(title: &str, scheduled_at: DateTime<Utc>) -> NewIssue {
+        NewIssue { title: title.to_string(), content: "Hello".to_string(), scheduled_at, created_by: "admin".to_string(), tracking: true }
+    }
+
+    #[tokio::test]
+    async fn due_issues_are_claimed_in_order_and_only_while_scheduled() {
+        let newsletters = SqliteNewsletterRepository::new(repository().await.pool);
+        let now = Utc::now();
+        let second = newsletters.create_issue(&new_issue("Issue 8", now - chrono::Duration::minutes(1))).await.unwrap();
+        let first = newsletters.create_issue(&new_issue("Issue 7", now - chrono::Duration::minutes(2))).await.unwrap();
+        let later = newsletters.create_issue(&new_issue("Issue 9", now + chrono::Duration::minutes(1))).await.unwrap();
+        assert_eq!(newsletters.find_issue(first.id).await.unwrap(), first);
+
+        assert_eq!(newsletters.claim_due_issue(now).await.unwrap().unwrap().id, first.id);
+        assert!(matches!(newsletters.cancel_issue(first.id).await, Err(RepositoryError::Conflict(_))));
+        newsletters.cancel_issue(second.id).await.unwrap();
+        assert_none!(newsletters.claim_due_issue(now).await.unwrap());
+
+        let rescheduled = newsletters.reschedule_issue(later.id, now - chrono::Duration::seconds(1)).await.unwrap();
+        assert_eq!(rescheduled.status, IssueStatus::Scheduled);
+        assert_eq!(newsletters.claim_due_issue(now).await.unwrap().unwrap().id, later.id);
+        assert!(matches!(newsletters.reschedule_issue(Uuid::new_v4(), now).await, Err(RepositoryError::NotFound)));
+
+        newsletters.complete_issue(first.id, 2, 1).await.unwrap();
+        let first = newsletters.find_issue(first.id).await.unwrap();
+        assert_eq!((first.status, first.sent_count, first.failed_count), (IssueStatus::Sent, 2, 1));
+        let statuses: Vec<IssueStatus> = newsletters.list_issues(None, 10).await.unwrap().iter().map(|issue| issue.status).collect();
+        assert_eq!(statuses, vec![IssueStatus::Sending, IssueStatus::Cancelled, IssueStatus::Sent]);
+        assert_eq!(newsletters.list_issues(Some(IssueStatus::Sent), 10).await.unwrap().len(), 1);
+
+        //failed 的一期不会再被领取
+        newsletters.fail_issue(later.id, "Failed to render email template newsletter.html").await.unwrap();
+        let failed = newsletters.find_issue(later.id).await.unwrap();
+        assert_eq!((failed.status, failed.claimed_at), (IssueStatus::Failed, None));
+        assert_eq!(failed.failure_reason.as_deref(), Some("Failed to render email template newsletter.html"));
+        assert_none!(newsletters.claim_due_issue(now + chrono::Duration::hours(1)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn released_and_expired_claims_are_claimed_again() {
+        let newsletters = SqliteNewsletterRepository::new(repository().await.pool);
+        let now = Utc::now();
+        let issue = newsletters.create_issue(&new_issue("Issue 7", now - chrono::Duration::hours(1))).await.unwrap();
+        let started_at = newsletters.claim_due_issue(now).await.unwrap().unwrap().started_at;
+
+        //续期之后，领取在 CLAIM_TIMEOUT 之内不会过期
+        let later = now + chrono::Duration::from_std(CLAIM_TIMEOUT).unwrap();
+        newsletters.renew_claim(issue.id, now + chrono::Duration::minutes(1)).await.unwrap();
+        assert_none!(newsletters.claim_due_issue(later).await.unwrap());
+        let expired = later + chrono::Duration::minutes(2);
+        let reclaimed = newsletters.claim_due_issue(expired).await.unwrap().unwrap();
+        assert_eq!((reclaimed.id, reclaimed.started_at, reclaimed.claimed_at), (issue.id, started_at, Some(expired)));
+
+        //放弃领取的一期马上可以被领取
+        newsletters.release_claim(issue.id).await.unwrap();
+        assert_eq!(newsletters.find_issue(issue.id).await.unwrap().claimed_at, None);
+        assert_eq!(newsletters.claim_due_issue(expired).await.unwrap().unwrap().id, issue.id);
+        newsletters.complete_issue(issue.id, 1, 0).await.unwrap();
+        assert_none!(newsletters.claim_due_issue(expired + chrono::Duration::hours(1)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn tracking_events_are_counted_and_erased_with_the_subscriber() {
+        let repository = repository().await;
+        let newsletters = SqliteNewsletterRepository::new(repository.pool.clone());
+        let issue = newsletters.create_issue(&new_issue("Issue 7", Utc::now())).await.unwrap();
+        let ursula = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let iain = repository.insert(&new_subscriber("iain@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        for (subscriber_id, kind, url) in [
+            (ursula, TrackingKind::Open, None),
+            (ursula, TrackingKind::Open, None),
+            (ursula, TrackingKind::Click, Some("https://example.com/docs")),
+            (iain, TrackingKind::Click, Some("https://example.com/blog")),
+            (iain, TrackingKind::Click, Some("https://example.com/docs")),
+        ] {
+            assert!(repository.record_event(&TrackingEvent::new(issue.id, subscriber_id, kind, url)).await.unwrap());
+        }
+        repository.update_details(iain, None, None, None, Some(false)).await.unwrap();
+        assert!(!repository.find(iain).await.unwrap().tracking);
+        assert!(!repository.record_event(&TrackingEvent::new(issue.id, iain, TrackingKind::Open, None)).await.unwrap());
+
+        let analytics = repository.issue_analytics(issue.id).await.unwrap();
+        assert_eq!((analytics.opens, analytics.unique_opens, analytics.clicks, analytics.unique_clicks), (2, 1, 3, 2));
+        let links: Vec<(&str, i64, i64)> = analytics.links.iter().map(|l| (l.url.as_str(), l.clicks, l.unique_clicks)).collect();
+        assert_eq!(links, vec![("https://example.com/docs", 2, 2), ("https://example.com/blog", 1, 1)]);
+        assert_eq!(repository.issue_analytics(Uuid::new_v4()).await.unwrap(), IssueAnalytics::default());
+
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        assert_eq!(repository.personal_data(&email).await.unwrap().tracking_events.len(), 3);
+        let tombstone = repository.erase(&email, "admin alice", "ticket 42").await.unwrap();
+        assert_eq!(tombstone.erased_rows["tracking_events"], 3);
+        assert_eq!(repository.issue_analytics(issue.id).await.unwrap().opens, 0);
+    }
+
+    #[tokio::test]
+    async fn suppressions_are_kept_once_per_address_and_outlive_erasure() {
+        let repository = repository().await;
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        let bounce = Suppression::new(&email, SuppressionReason::Bounce, Some("Mailbox does not exist"), Some("883953f4"));
+        assert!(repository.suppress(&bounce).await.unwrap());
+        assert!(!repository.suppress(&Suppression::new(&email, SuppressionReason::Complaint, None, None)).await.unwrap());
+        let stored = repository.find_suppression(&email).await.unwrap().unwrap();
+        assert_eq!((stored.reason.as_str(), stored.message_id.as_deref()), ("bounce", Some("883953f4")));
+        let other = SubscriberEmail::parse("iain@example.com".to_string()).unwrap();
+        repository.suppress(&Suppression::new(&other, SuppressionReason::Complaint, None, None)).await.unwrap();
+        let emails: Vec<String> = repository.list_suppressions(10).await.unwrap().into_iter().map(|s| s.email).collect();
+        assert_eq!(emails, vec!["iain@example.com", "ursula@example.com"]);
+
+        repository.lift_suppression(&other).await.unwrap();
+        assert!(matches!(repository.lift_suppression(&other).await, Err(RepositoryError::NotFound)));
+        assert_none!(repository.find_suppression(&other).await.unwrap());
+
+        //只有停发记录也可以导出和删除
+        assert_eq!(repository.personal_data(&email).await.unwrap().suppressions, vec![stored]);
+        let tombstone = repository.erase(&email, "admin alice", "ticket 42").await.unwrap();
+        assert_eq!(tombstone.erased_rows["suppression_list"], 1);
+        assert!(matches!(repository.erase(&email, "admin alice", "ticket 42").await, Err(RepositoryError::NotFound)));
+
+        //删除之后按哈希仍然停发，但不再保存邮箱和服务商信息
+        let kept = repository.find_suppression(&email).await.unwrap().unwrap();
+        assert_eq!((kept.email.as_str(), kept.reason.as_str()), ("ursula@example.com", "bounce"));
+        assert_eq!((kept.detail, kept.message_id), (None, None));
+        assert!(repository.list_suppressions(10).await.unwrap().is_empty());
+        assert!(repository.personal_data(&email).await.unwrap().suppressions.is_empty());
+        assert!(!repository.suppress(&Suppression::new(&email, SuppressionReason::Complaint, None, None)).await.unwrap());
+        repository.lift_suppression(&email).await.unwrap();
+        assert_none!(repository.find_suppression(&email).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn deliveries_follow_sends_bounces_and_tracking() {
+        let repository = repository().await;
+        let newsletters = SqliteNewsletterRepository::new(repository.pool.clone());
+        let issue = newsletters.create_issue(&new_issue("Issue 42", Utc::now())).await.unwrap();
+        let ursula = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let iain = repository.insert(&new_subscriber("iain@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let octavia = repository.insert(&new_subscriber("octavia@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        //已经删除的订阅者不会被写入
+        repository.queue_deliveries(issue.id, &[ursula, iain, octavia, Uuid::new_v4()]).await.unwrap();
+        repository.queue_deliveries(issue.id, &[]).await.unwrap();
+        let query = DeliveryQuery { status: Some(DeliveryStatus::Queued), after: None, limit: 10 };
+        assert_eq!(repository.list_deliveries(issue.id, &query).await.unwrap().len(), 3);
+
+        repository.record_failure(issue.id, ursula, "500 Internal Server Error").await.unwrap();
+        repository.record_sent(issue.id, ursula, Some("883953f4")).await.unwrap();
+        repository.record_sent(issue.id, iain, Some("b7bc2f4a")).await.unwrap();
+        //再次排队只返回还没有结果的收件人
+        assert_eq!(repository.queue_deliveries(issue.id, &[ursula, iain, octavia]).await.unwrap(), vec![octavia]);
+        repository.record_sent(issue.id, octavia, None).await.unwrap();
+        let delivery = repository.find_delivery(issue.id, ursula).await.unwrap();
+        assert_eq!((delivery.status, delivery.attempts, delivery.message_id.as_deref()), (DeliveryStatus::Sent, 2, Some("883953f4")));
+        assert_eq!(delivery.last_error.as_deref(), Some("500 Internal Server Error"));
+
+        //点击同时算作打开；之后的打开不会把状态改回 opened
+        for (subscriber_id, kind, url) in [
+            (ursula, TrackingKind::Open, None),
+            (iain, TrackingKind::Click, Some("https://example.com/docs")),
+            (iain, TrackingKind::Open, None),
+        ] {
+            assert!(repository.record_event(&TrackingEvent::new(issue.id, subscriber_id, kind, url)).await.unwrap());
+        }
+        assert_eq!(repository.find_delivery(issue.id, ursula).await.unwrap().status, DeliveryStatus::Opened);
+        let iain_delivery = repository.find_delivery(issue.id, iain).await.unwrap();
+        assert_eq!(iain_delivery.status, DeliveryStatus::Clicked);
+        assert!(iain_delivery.opened_at.is_some() && iain_delivery.clicked_at.is_some());
+
+        assert!(repository.record_bounce("b7bc2f4a", Some("Mailbox does not exist")).await.unwrap());
+        assert!(!repository.record_bounce("unknown", None).await.unwrap());
+        assert_eq!(repository.find_delivery_by_message_id("b7bc2f4a").await.unwrap().map(|delivery| delivery.subscriber_id), Some(iain));
+        assert_eq!(repository.find_delivery_by_message_id("unknown").await.unwrap(), None);
+        let bounced = repository.find_delivery(issue.id, iain).await.unwrap();
+        assert_eq!((bounced.status, bounced.last_error.as_deref()), (DeliveryStatus::Bounced, Some("Mailbox does not exist")));
+        assert!(bounced.bounced_at.is_some());
+        //退信之后的打开只记事件，状态不变
+        assert!(repository.record_event(&TrackingEvent::new(issue.id, iain, TrackingKind::Open, None)).await.unwrap());
+        assert_eq!(repository.find_delivery(issue.id, iain).await.unwrap().status, DeliveryStatus::Bounced);
+
+        let all = repository.list_deliveries(issue.id, &DeliveryQuery { status: None, after: None, limit: 10 }).await.unwrap();
+        let mut ids: Vec<Uuid> = vec![ursula, iain, octavia];
+        ids.sort();
+        assert_eq!(all.iter().map(|delivery| delivery.subscriber_id).collect::<Vec<_>>(), ids);
+        let page = repository.list_deliveries(issue.id, &DeliveryQuery { status: None, after: Some(ids[1]), limit: 10 }).await.unwrap();
+        assert_eq!(page.iter().map(|delivery| delivery.subscriber_id).collect::<Vec<_>>(), vec![ids[2]]);
+
+        let email = SubscriberEmail::parse("iain@example.com".to_string()).unwrap();
+        assert_eq!(repository.personal_data(&email).await.unwrap().deliveries, vec![repository.find_delivery(issue.id, iain).await.unwrap()]);
+        let tombstone = repository.erase(&email, "admin alice", "ticket 42").await.unwrap();
+        assert_eq!(tombstone.erased_rows["deliveries"], 1);
+        assert!(matches!(repository.find_delivery(issue.id, iain).await, Err(RepositoryError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn reports_are_summarized_and_bucketed_in_sql() {
+        let repository = repository().await;
+        let newsletters = SqliteNewsletterRepository::new(repository.pool.clone());
+        let issue = newsletters.create_issue(&new_issue("Issue 42", Utc::now())).await.unwrap();
+        let ursula = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let iain = repository.insert(&new_subscriber("iain@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let octavia = repository.insert(&new_subscriber("octavia@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        repository.queue_deliveries(issue.id, &[ursula, iain, octavia]).await.unwrap();
+        repository.record_sent(issue.id, ursula, Some("883953f4")).await.unwrap();
+        repository.record_sent(issue.id, iain, Some("b7bc2f4a")).await.unwrap();
+        repository.record_failure(issue.id, octavia, "500 Internal Server Error").await.unwrap();
+        repository.record_unsubscribe(issue.id, iain).await.unwrap();
+        let unsubscribed_at = repository.find_delivery(issue.id, iain).await.unwrap().unsubscribed_at;
+        repository.record_unsubscribe(issue.id, iain).await.unwrap();
+        assert_eq!(repository.find_delivery(issue.id, iain).await.unwrap().unsubscribed_at, unsubscribed_at);
+
+        let since = Utc::now();
+        for (subscriber_id, kind, url, seconds) in [
+            (ursula, TrackingKind::Open, None, 0),
+            (ursula, TrackingKind::Click, Some("https://example.com/docs"), 3599),
+            (iain, TrackingKind::Open, None, 3600),
+            (iain, TrackingKind::Open, None, 3 * 3600 + 1),
+        ] {
+            let mut event = TrackingEvent::new(issue.id, subscriber_id, kind, url);
+            event.occurred_at = since + chrono::Duration::seconds(seconds);
+            assert!(repository.record_event(&event).await.unwrap());
+        }
+        //早于开始时间的事件不计入曲线
+        let mut early = TrackingEvent::new(issue.id, ursula, TrackingKind::Open, None);
+        early.occurred_at = since - chrono::Duration::seconds(1);
+        repository.record_event(&early).await.unwrap();
+
+        let summary = repository.delivery_summary(issue.id).await.unwrap();
+        assert_eq!(summary, DeliverySummary { recipients: 3, sent: 2, failed: 1, bounced: 0, unique_opens: 2, unique_clicks: 1, unsubscribes: 1 });
+        let buckets: Vec<(i64, i64, i64)> = repository.activity(issue.id, since, 3600).await.unwrap().iter()
+            .map(|bucket| (bucket.bucket, bucket.opens, bucket.clicks))
+            .collect();
+        assert_eq!(buckets, vec![(0, 1, 1), (1, 1, 0), (3, 1, 0)]);
+        assert_eq!(repository.link_clicks(issue.id).await.unwrap()[0].url, "https://example.com/docs");
+        assert_eq!(repository.delivery_summary(Uuid::new_v4()).await.unwrap(), DeliverySummary::default());
+    }
+
+    #[tokio::test]
+    async fn tracking_does_not_mark_unsent_deliveries_as_opened() {
+        let repository = repository().await;
+        let newsletters = SqliteNewsletterRepository::new(repository.pool.clone());
+        let issue_id = newsletters.create_issue(&new_issue("Issue 42", Utc::now())).await.unwrap().id;
+        let ursula = repository.insert(&new_subscriber("ursula@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let iain = repository.insert(&new_subscriber("iain@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        let octavia = repository.insert(&new_subscriber("octavia@example.com"), SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        repository.queue_deliveries(issue_id, &[ursula, iain, octavia]).await.unwrap();
+        repository.record_failure(issue_id, ursula, "500 Internal Server Error").await.unwrap();
+        repository.record_sent(issue_id, iain, Some("883953f4")).await.unwrap();
+        repository.record_bounce("883953f4", None).await.unwrap();
+        let summary = repository.delivery_summary(issue_id).await.unwrap();
+
+        //失败、退信和还在排队的邮件没有送到，像素和链接的请求不算打开或点击
+        for (subscriber_id, status) in [(ursula, DeliveryStatus::Failed), (iain, DeliveryStatus::Bounced), (octavia, DeliveryStatus::Queued)] {
+            for (kind, url) in [(TrackingKind::Click, Some("https://example.com/docs")), (TrackingKind::Open, None)] {
+                assert!(repository.record_event(&TrackingEvent::new(issue_id, subscriber_id, kind, url)).await.unwrap());
+            }
+            let delivery = repository.find_delivery(issue_id, subscriber_id).await.unwrap();
+            assert_eq!((delivery.status, delivery.opened_at, delivery.clicked_at), (status, None, None));
+        }
+        assert_eq!(repository.delivery_summary(issue_id).await.unwrap(), summary);
+    }
+}
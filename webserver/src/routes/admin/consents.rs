@@ -0,0 +1,113 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::repository::{ConsentQuery, SubscriberRepository};
+use crate::routes::admin::{non_empty, parse_timestamp};
+use crate::routes::errors::repository_error_response;
+
+const MAX_CONSENTS: usize = 1000;
+
+/// `GET /admin/consents` 的查询参数，都可以省略
+#[derive(Deserialize, Debug, Default)]
+pub struct ConsentParams {
+    pub subscriber_id: Option<Uuid>,
+    pub source: Option<String>,
+    pub consent_version: Option<String>,
+    //RFC 3339 时间或 YYYY-MM-DD（UTC 零点），from 包含、to 不包含
+    pub recorded_from: Option<String>,
+    pub recorded_to: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl TryFrom<ConsentParams> for ConsentQuery {
+    type Error = String;
+    fn try_from(params: ConsentParams) -> Result<Self, Self::Error> {
+        let limit = params.limit.unwrap_or(ConsentQuery::default().limit);
+        if limit == 0 || limit > MAX_CONSENTS {
+            return Err(format!("limit must be between 1 and {}", MAX_CONSENTS));
+        }
+        Ok(ConsentQuery {
+            subscriber_id: params.subscriber_id,
+            source: non_empty(params.source),
+            consent_version: non_empty(params.consent_version),
+            recorded_from: params.recorded_from.as_deref().map(parse_timestamp).transpose()?,
+            recorded_to: params.recorded_to.as_deref().map(parse_timestamp).transpose()?,
+            limit,
+        })
+    }
+}
+
+/// 按来源、同意文本版本和时间查询同意记录，按时间倒序
+#[tracing::instrument(name = "Listing consent records", skip(repository))]
+pub async fn list_consents(params: web::Query<ConsentParams>, repository: web::Data<dyn SubscriberRepository>) -> HttpResponse {
+    let query: ConsentQuery = match params.into_inner().try_into() {
+        Ok(query) => query,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    match repository.consents(&query).await {
+        Ok(consents) => HttpResponse::Ok().json(consents),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriptionStatus;
+    use crate::repository::{ConsentAction, InMemorySubscriberRepository, NewConsent, SubscriberRepository};
+    use crate::routes::admin::testing::{call, AUTHORIZATION_VALUE};
+    use crate::testing::new_subscriber;
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::test;
+    use std::sync::Arc;
+
+    async fn seed(repository: &InMemorySubscriberRepository, email: &str, source: &str, version: &str) -> uuid::Uuid {
+        let consent = NewConsent {
+            action: ConsentAction::Subscribe,
+            ip_address: Some("203.0.113.7".to_string()),
+            user_agent: Some("Mozilla/5.0".to_string()),
+            source: Some(source.to_string()),
+            consent_version: Some(version.to_string()),
+        };
+        repository.insert_with_consent(&new_subscriber(email), SubscriptionStatus::Confirmed, "subscribed via form", &consent).await.unwrap()
+    }
+
+    fn get(uri: &str) -> test::TestRequest {
+        test::TestRequest::get().uri(uri).insert_header((AUTHORIZATION, AUTHORIZATION_VALUE))
+    }
+
+    #[actix_web::test]
+    async fn consents_can_be_filtered_by_source_and_version() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        seed(&repository, "ursula@example.com", "footer", "v1").await;
+        seed(&repository, "iain@example.com", "footer", "v2").await;
+        seed(&repository, "octavia@example.com", "landing", "v2").await;
+
+        let response = call(&repository, get("/admin/consents?source=footer&consent_version=v2")).await;
+        assert_eq!(response.status(), 200);
+        let consents: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(consents.as_array().unwrap().len(), 1);
+        assert_eq!(consents[0]["ip_address"], "203.0.113.7");
+
+        //新的在前
+        let consents: serde_json::Value = test::read_body_json(call(&repository, get("/admin/consents?limit=2")).await).await;
+        let sources: Vec<&str> = consents.as_array().unwrap().iter().map(|c| c["source"].as_str().unwrap()).collect();
+        assert_eq!(sources, vec!["landing", "footer"]);
+    }
+
+    #[actix_web::test]
+    async fn subscriber_details_include_consents() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let id = seed(&repository, "ursula@example.com", "footer", "v1").await;
+        let details: serde_json::Value = test::read_body_json(call(&repository, get(&format!("/admin/subscribers/{}", id))).await).await;
+        assert_eq!(details["consents"][0]["consent_version"], "v1");
+        assert_eq!(details["consents"][0]["action"], "subscribe");
+    }
+
+    #[actix_web::test]
+    async fn bad_filters_are_rejected() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        for query in ["?limit=0", "?recorded_from=yesterday", "?subscriber_id=42"] {
+            assert_eq!(call(&repository, get(&format!("/admin/consents{}", query))).await.status(), 400, "{}", query);
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! 通讯草稿：每次保存都是一个新版本，旧版本可以对比和恢复，发布时固定发送其中一个版本
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use uuid::Uuid;
+use crate::authentication::AdminUser;
+use crate::newsletter::validate_issue;
+use crate::repository::{DraftEdit, NewsletterRepository};
+use crate::routes::admin::check_issue;
+use crate::routes::errors::repository_error_response;
+
+const DEFAULT_DRAFTS: usize = 50;
+const MAX_DRAFTS: usize = 500;
+
+//草稿的正文可以暂时为空，标题不能为空；合并标签在保存时就检查
+fn check_draft(title: &str, content: &str) -> Result<(), String> {
+    if title.trim().is_empty() {
+        return Err("title must not be empty".to_string());
+    }
+    validate_issue(title, content)
+}
+
+/// `POST /admin/newsletters/drafts` 的请求体，`content` 是 Markdown
+#[derive(Deserialize, Debug)]
+pub struct CreateDraftRequest {
+    pub title: String,
+    #[serde(default)]
+    pub content: String,
+}
+
+/// 新建草稿，返回 201 和版本 1
+#[tracing::instrument(name = "Creating a newsletter draft", skip(body, newsletters, admin))]
+pub async fn create_draft(
+    body: web::Json<CreateDraftRequest>,
+    newsletters: web::Data<dyn NewsletterRepository>,
+    admin: web::ReqData<AdminUser>,
+) -> HttpResponse {
+    let CreateDraftRequest { title, content } = body.into_inner();
+    if let Err(e) = check_draft(&title, &content) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let edit = DraftEdit { title: title.trim().to_string(), content, saved_by: admin.username.clone(), restored_from: None };
+    match newsletters.create_draft(&edit).await {
+        Ok(draft) => HttpResponse::Created().json(draft),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DraftListParams {
+    pub limit: Option<usize>,
+}
+
+/// 按最后保存时间倒序列出草稿
+#[tracing::instrument(name = "Listing newsletter drafts", skip(newsletters))]
+pub async fn list_drafts(params: web::Query<DraftListParams>, newsletters: web::Data<dyn NewsletterRepository>) -> HttpResponse {
+    let limit = params.limit.unwrap_or(DEFAULT_DRAFTS);
+    if limit == 0 || limit > MAX_DRAFTS {
+        return HttpResponse::BadRequest().body(format!("limit must be between 1 and {}", MAX_DRAFTS));
+    }
+    match newsletters.list_drafts(limit).await {
+        Ok(drafts) => HttpResponse::Ok().json(drafts),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[tracing::instrument(name = "Fetching a newsletter draft", skip(newsletters))]
+pub async fn fetch_draft(id: web::Path<Uuid>, newsletters: web::Data<dyn NewsletterRepository>) -> HttpResponse {
+    match newsletters.find_draft(id.into_inner()).await {
+        Ok(draft) => HttpResponse::Ok().json(draft),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+/// `PUT /admin/newsletters/drafts/{id}` 的请求体
+#[derive(Deserialize, Debug)]
+pub struct SaveDraftRequest {
+    pub title: String,
+    #[serde(default)]
+    pub content: String,
+    //编辑开始时读到的版本号
+    pub version: i64,
+}
+
+/// 保存为新版本；期间别人保存过时返回 409，需要重新读取后再改
+#[tracing::instrument(name = "Saving a newsletter draft", skip(body, newsletters, admin))]
+pub async fn save_draft(
+    id: web::Path<Uuid>,
+    body: web::Json<SaveDraftRequest>,
+    newsletters: web::Data<dyn NewsletterRepository>,
+    admin: web::ReqData<AdminUser>,
+) -> HttpResponse {
+    let SaveDraftRequest { title, content, version } = body.into_inner();
+    if let Err(e) = check_draft(&title, &content) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let edit = DraftEdit { title: title.trim().to_string(), content, saved_by: admin.username.clone(), restored_from: None };
+    match newsletters.update_draft(id.into_inner(), version, &edit).await {
+        Ok(draft) => HttpResponse::Ok().json(draft),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+/// 删除草稿和全部版本，已经发布的通讯不受影响
+#[tracing::instrument(name = "Deleting a newsletter draft", skip(newsletters))]
+pub async fn delete_draft(id: web::Path<Uuid>, newsletters: web::Data<dyn NewsletterRepository>) -> HttpResponse {
+    match newsletters.delete_draft(id.into_inner()).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[tracing::instrument(name = "Listing draft revisions", skip(newsletters))]
+pub async fn list_revisions(id: web::Path<Uuid>, newsletters: web::Data<dyn NewsletterRepository>) -> HttpResponse {
+    match newsletters.draft_revisions(id.into_inner()).await {
+        Ok(revisions) => HttpResponse::Ok().json(revisions),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[tracing::instrument(name = "Fetching a draft revision", skip(newsletters))]
+pub async fn fetch_revision(path: web::Path<(Uuid, i64)>, newsletters: web::Data<dyn NewsletterRepository>) -> HttpResponse {
+    let (id, version) = path.into_inner();
+    match newsletters.draft_revision(id, version).await {
+        Ok(revision) => HttpResponse::Ok().json(revision),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+/// `GET /admin/newsletters/drafts/{id}/diff` 的查询参数，`to` 省略时是最新版本
+#[derive(Deserialize, Debug)]
+pub struct DiffParams {
+    pub from: i64,
+    pub to: Option<i64>,
+}
+
+/// 两个版本之间的差异，标题和正文分别是 unified diff，没有变化时为空字符串
+#[derive(Serialize, Debug)]
+pub struct DraftDiff {
+    pub from: i64,
+    pub to: i64,
+    pub title: String,
+    pub content: String,
+}
+
+fn unified_diff(old: &str, new: &str, from: i64, to: i64) -> String {
+    if old == new {
+        return String::new();
+    }
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&format!("version {}", from), &format!("version {}", to))
+        .to_string()
+}
+
+#[tracing::instrument(name = "Comparing draft revisions", skip(newsletters))]
+pub async fn diff_revisions(
+    id: web::Path<Uuid>,
+    params: web::Query<DiffParams>,
+    newsletters: web::Data<dyn NewsletterRepository>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    let to = match params.to {
+        Some(to) => to,
+        None => match newsletters.find_draft(id).await {
+            Ok(draft) => draft.version,
+            Err(e) => return repository_error_response(&e),
+        },
+    };
+    let (old, new) = match (newsletters.draft_revision(id, params.from).await, newsletters.draft_revision(id, to).await) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => return repository_error_response(&e),
+    };
+    HttpResponse::Ok().json(DraftDiff {
+        from: old.version,
+        to: new.version,
+        title: unified_diff(&old.title, &new.title, old.version, new.version),
+        content: unified_diff(&old.content, &new.content, old.version, new.version),
+    })
+}
+
+/// `POST /admin/newsletters/drafts/{id}/restore` 的请求体
+#[derive(Deserialize, Debug)]
+pub struct RestoreRevisionRequest {
+    //要恢复的旧版本
+    pub revision: i64,
+    //当前版本，与保存时一样用来检查冲突
+    pub version: i64,
+}
+
+/// 把旧版本的内容保存为一个新版本，中间的版本保留
+#[tracing::instrument(name = "Restoring a draft revision", skip(newsletters, admin))]
+pub async fn restore_revision(
+    id: web::Path<Uuid>,
+    body: web::Json<RestoreRevisionRequest>,
+    newsletters: web::Data<dyn NewsletterRepository>,
+    admin: web::ReqData<AdminUser>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    let revision = match newsletters.draft_revision(id, body.revision).await {
+        Ok(revision) => revision,
+        Err(e) => return repository_error_response(&e),
+    };
+    //保存旧版本之后合并标签可能有变化
+    if let Err(e) = check_draft(&revision.title, &revision.content) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let edit = DraftEdit {
+        title: revision.title,
+        content: revision.content,
+        saved_by: admin.username.clone(),
+        restored_from: Some(revision.version),
+    };
+    match newsletters.update_draft(id, body.version, &edit).await {
+        Ok(draft) => HttpResponse::Ok().json(draft),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+/// `POST /admin/newsletters/drafts/{id}/publish` 的请求体
+#[derive(Deserialize, Debug)]
+pub struct PublishDraftRequest {
+    //编辑审阅过的版本，必须是最新版本
+    pub version: i64,
+    //与 `POST /admin/newsletters` 相同，省略时尽快发送
+    pub scheduled_at: Option<DateTime<Utc>>,
+    pub tracking: Option<bool>,
+}
+
+/// 把草稿的一个版本安排发送，返回 201 和新建的通讯；之后继续编辑草稿不会影响这一期
+#[tracing::instrument(name = "Publishing a newsletter draft", skip(newsletters, admin))]
+pub async fn publish_draft(
+    id: web::Path<Uuid>,
+    body: web::Json<PublishDraftRequest>,
+    newsletters: web::Data<dyn NewsletterRepository>,
+    admin: web::ReqData<AdminUser>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    let draft = match newsletters.find_draft(id).await {
+        Ok(draft) => draft,
+        Err(e) => return repository_error_response(&e),
+    };
+    //先报告版本冲突，编辑看到的可能不是现在的内容
+    if draft.version != body.version {
+        return repository_error_response(&draft.stale_version(body.version));
+    }
+    if let Err(e) = check_issue(&draft.title, &draft.content) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let scheduled_at = body.scheduled_at.unwrap_or_else(Utc::now);
+    match newsletters
+        .publish_draft(id, body.version, scheduled_at, &admin.username, body.tracking.unwrap_or(true))
+        .await
+    {
+        Ok(issue) => HttpResponse::Created().json(issue),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repository::{InMemoryNewsletterRepository, InMemorySubscriberRepository, NewsletterRepository};
+    use crate::routes::admin::testing::{call_with_newsletters, AUTHORIZATION_VALUE};
+    use actix_web::dev::ServiceResponse;
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::test;
+    use std::sync::Arc;
+
+    async fn call(newsletters: &Arc<InMemoryNewsletterRepository>, request: test::TestRequest) -> ServiceResponse {
+        let request = request.insert_header((AUTHORIZATION, AUTHORIZATION_VALUE));
+        call_with_newsletters(&Arc::new(InMemorySubscriberRepository::new()), newsletters, request).await
+    }
+
+    async fn json(response: ServiceResponse) -> serde_json::Value {
+        test::read_body_json(response).await
+    }
+
+    #[actix_web::test]
+    async fn saves_create_revisions_and_stale_versions_are_rejected() {
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        let body = serde_json::json!({ "title": "Issue 7", "content": "Hello\nworld\n" });
+        let response = call(&newsletters, test::TestRequest::post().uri("/admin/newsletters/drafts").set_json(body)).await;
+        assert_eq!(response.status(), 201);
+        let draft = json(response).await;
+        assert_eq!((draft["version"].as_i64(), draft["created_by"].as_str()), (Some(1), Some("admin")));
+        let uri = format!("/admin/newsletters/drafts/{}", draft["id"].as_str().unwrap());
+
+        let body = serde_json::json!({ "title": "Issue 7", "content": "Hello\nthere\n", "version": 1 });
+        let draft = json(call(&newsletters, test::TestRequest::put().uri(&uri).set_json(body)).await).await;
+        assert_eq!(draft["version"], 2);
+        //另一个编辑基于版本 1 的修改不会覆盖版本 2
+        let body = serde_json::json!({ "title": "Issue 7", "content": "Goodbye\n", "version": 1 });
+        let response = call(&newsletters, test::TestRequest::put().uri(&uri).set_json(body)).await;
+        assert_eq!(response.status(), 409);
+        assert_eq!(test::read_body(response).await, "Draft is at version 2 (last saved by admin), not version 1; reload it and try again");
+
+        let diff = json(call(&newsletters, test::TestRequest::get().uri(&format!("{}/diff?from=1", uri))).await).await;
+        assert_eq!((diff["from"].as_i64(), diff["to"].as_i64(), diff["title"].as_str()), (Some(1), Some(2), Some("")));
+        assert_eq!(diff["content"], "--- version 1\n+++ version 2\n@@ -1,2 +1,2 @@\n Hello\n-world\n+there\n");
+
+        let body = serde_json::json!({ "revision": 1, "version": 2 });
+        let draft = json(call(&newsletters, test::TestRequest::post().uri(&format!("{}/restore", uri)).set_json(body)).await).await;
+        assert_eq!((draft["version"].as_i64(), draft["content"].as_str()), (Some(3), Some("Hello\nworld\n")));
+        let revisions = json(call(&newsletters, test::TestRequest::get().uri(&format!("{}/revisions", uri))).await).await;
+        let versions: Vec<(i64, Option<i64>)> = revisions.as_array().unwrap().iter()
+            .map(|revision| (revision["version"].as_i64().unwrap(), revision["restored_from"].as_i64()))
+            .collect();
+        assert_eq!(versions, vec![(1, None), (2, None), (3, Some(1))]);
+        let revision = json(call(&newsletters, test::TestRequest::get().uri(&format!("{}/revisions/2", uri))).await).await;
+        assert_eq!(revision["content"], "Hello\nthere\n");
+
+        let body = serde_json::json!({ "title": "Issue 7", "content": "Hi {{ subscriber.nickname }}", "version": 3 });
+        assert_eq!(call(&newsletters, test::TestRequest::put().uri(&uri).set_json(body)).await.status(), 400);
+        assert_eq!(call(&newsletters, test::TestRequest::delete().uri(&uri)).await.status(), 204);
+        assert_eq!(call(&newsletters, test::TestRequest::get().uri(&format!("{}/revisions", uri))).await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn publishing_freezes_the_reviewed_version() {
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        let body = serde_json::json!({ "title": "Issue 7" });
+        let draft = json(call(&newsletters, test::TestRequest::post().uri("/admin/newsletters/drafts").set_json(body)).await).await;
+        let uri = format!("/admin/newsletters/drafts/{}", draft["id"].as_str().unwrap());
+        //正文为空的草稿可以保存，但不能发布
+        let body = serde_json::json!({ "version": 1 });
+        assert_eq!(call(&newsletters, test::TestRequest::post().uri(&format!("{}/publish", uri)).set_json(body)).await.status(), 400);
+
+        let body = serde_json::json!({ "title": "Issue 7", "content": "Hi {{ subscriber.name }}", "version": 1 });
+        call(&newsletters, test::TestRequest::put().uri(&uri).set_json(body)).await;
+        let body = serde_json::json!({ "version": 1 });
+        assert_eq!(call(&newsletters, test::TestRequest::post().uri(&format!("{}/publish", uri)).set_json(body)).await.status(), 409);
+        let body = serde_json::json!({ "version": 2, "scheduled_at": "2030-11-11T09:00:00Z" });
+        let response = call(&newsletters, test::TestRequest::post().uri(&format!("{}/publish", uri)).set_json(body)).await;
+        assert_eq!(response.status(), 201);
+        let issue = json(response).await;
+        assert_eq!((issue["draft_version"].as_i64(), issue["status"].as_str()), (Some(2), Some("scheduled")));
+
+        let body = serde_json::json!({ "title": "Issue 7", "content": "Changed after publishing", "version": 2 });
+        assert_eq!(call(&newsletters, test::TestRequest::put().uri(&uri).set_json(body)).await.status(), 200);
+        assert_eq!(call(&newsletters, test::TestRequest::delete().uri(&uri)).await.status(), 204);
+        let issue = newsletters.find_issue(issue["id"].as_str().unwrap().parse().unwrap()).await.unwrap();
+        assert_eq!((issue.content.as_str(), issue.draft_id, issue.draft_version), ("Hi {{ subscriber.name }}", None, Some(2)));
+    }
+}
@@ -0,0 +1,191 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::{self, Bytes};
+use actix_web::HttpResponse;
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use crate::csv::write_record;
+use crate::repository::{RepositoryError, SubscriberQuery, SubscriberRecord, SubscriberRepository};
+use crate::routes::admin::ListParams;
+
+//每个响应块最多包含的行数；只合并已经取到的行，不会为了凑满一块而等待
+const ROWS_PER_CHUNK: usize = 256;
+
+const CSV_COLUMNS: [&str; 5] = ["id", "email", "name", "status", "subscribed_at"];
+
+/// `GET /admin/subscribers/export` 特有的参数，过滤和排序参数与列表接口相同
+#[derive(Deserialize, Debug, Default)]
+pub struct ExportParams {
+    //csv（默认）或 ndjson
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn parse(format: Option<&str>) -> Result<ExportFormat, String> {
+        match format {
+            None | Some("csv") => Ok(ExportFormat::Csv),
+            Some("ndjson") => Ok(ExportFormat::Ndjson),
+            Some(other) => Err(format!("{} is not a supported export format, use csv or ndjson", other)),
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    fn file_name(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "subscribers.csv",
+            ExportFormat::Ndjson => "subscribers.ndjson",
+        }
+    }
+
+    fn header(&self) -> Option<Bytes> {
+        match self {
+            ExportFormat::Csv => {
+                let mut out = Vec::new();
+                write_record(&mut out, CSV_COLUMNS, b',');
+                Some(Bytes::from(out))
+            }
+            ExportFormat::Ndjson => None,
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>, record: &SubscriberRecord) {
+        match self {
+            ExportFormat::Csv => {
+                let id = record.id.to_string();
+                let subscribed_at = record.subscribed_at.to_rfc3339();
+                write_record(out, [id.as_str(), &record.email, &record.name, record.status.as_str(), &subscribed_at], b',');
+            }
+            ExportFormat::Ndjson => {
+                //SubscriberRecord 的字段都能序列化，写入 Vec 不会失败
+                serde_json::to_writer(&mut *out, record).expect("Failed to serialize a subscriber");
+                out.push(b'\n');
+            }
+        }
+    }
+}
+
+/// 逐行导出全部匹配的订阅者：仓库的行流直接写入响应体，内存占用与总行数无关
+///
+/// 响应头发出之后出错只能中断连接，客户端会收到不完整的分块响应
+#[tracing::instrument(name = "Exporting subscribers", skip(repository))]
+pub async fn export_subscribers(
+    params: web::Query<ListParams>,
+    export: web::Query<ExportParams>,
+    repository: web::Data<dyn SubscriberRepository>,
+) -> HttpResponse {
+    let mut params = params.into_inner();
+    //导出不分页
+    params.cursor = None;
+    params.limit = None;
+    let query: SubscriberQuery = match params.try_into() {
+        Ok(query) => query,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let format = match ExportFormat::parse(export.format.as_deref()) {
+        Ok(format) => format,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    let header = stream::iter(format.header().map(Ok::<_, RepositoryError>));
+    let rows = repository.stream(query).ready_chunks(ROWS_PER_CHUNK).map(move |rows| {
+        let mut out = Vec::new();
+        for row in rows {
+            match row {
+                Ok(record) => format.write(&mut out, &record),
+                Err(e) => {
+                    tracing::error!("Subscriber export failed: {}", e);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(Bytes::from(out))
+    });
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format.file_name().to_string())],
+        })
+        .streaming(header.chain(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus};
+    use crate::repository::InMemorySubscriberRepository;
+    use crate::routes::admin::testing::{call, AUTHORIZATION_VALUE};
+    use actix_web::http::header::{AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE};
+    use actix_web::test;
+    use std::sync::Arc;
+
+    async fn seed(repository: &InMemorySubscriberRepository) {
+        for (name, email) in [("Le Guin, Ursula", "ursula@example.com"), ("Iain Banks", "iain@example.org")] {
+            let subscriber = NewSubscriber {
+                email: SubscriberEmail::parse(email.to_string()).unwrap(),
+                name: SubscriberName::parse(name.to_string()).unwrap(),
+            };
+            repository.insert(&subscriber, SubscriptionStatus::Confirmed, "subscribed").await.unwrap();
+        }
+    }
+
+    fn export_request(query: &str) -> test::TestRequest {
+        test::TestRequest::get()
+            .uri(&format!("/admin/subscribers/export{}", query))
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE))
+    }
+
+    #[actix_web::test]
+    async fn csv_exports_have_a_header_and_quote_fields() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        seed(&repository).await;
+        let response = call(&repository, export_request("?sort=email")).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "text/csv; charset=utf-8");
+        assert!(response.headers().get(CONTENT_DISPOSITION).unwrap().to_str().unwrap().contains("subscribers.csv"));
+
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines[0], "id,email,name,status,subscribed_at");
+        assert!(lines[1].contains(",iain@example.org,Iain Banks,confirmed,"));
+        assert!(lines[2].contains(",ursula@example.com,\"Le Guin, Ursula\",confirmed,"));
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn ndjson_exports_apply_the_listing_filters() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        seed(&repository).await;
+        //limit 和 cursor 对导出不起作用
+        let response = call(&repository, export_request("?format=ndjson&domain=example.com&limit=1000")).await;
+        assert_eq!(response.status(), 200);
+        let body = test::read_body(response).await;
+        let rows: Vec<serde_json::Value> = body.split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["email"], "ursula@example.com");
+        assert_eq!(rows[0]["status"], "confirmed");
+        assert!(rows[0]["subscribed_at"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn unknown_formats_and_bad_filters_are_rejected() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        for query in ["?format=xlsx", "?status=active", "?sort=password"] {
+            assert_eq!(call(&repository, export_request(query)).await.status(), 400, "{}", query);
+        }
+    }
+}
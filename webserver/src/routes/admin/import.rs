@@ -0,0 +1,179 @@
+use actix_web::{web, HttpResponse};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use crate::authentication::AdminUser;
+use crate::csv::CsvParser;
+use crate::domain::SubscriptionStatus;
+use crate::import::{ColumnMapping, ImportError, SubscriberImport};
+use crate::repository::{DuplicatePolicy, ImportOptions, NewConsent, SubscriberRepository};
+use crate::routes::errors::repository_error_response;
+
+/// `POST /admin/subscribers/import` 的查询参数，请求体是带表头的 CSV
+#[derive(Deserialize, Debug, Default)]
+pub struct ImportParams {
+    //表头中的列名，默认 name 和 email
+    pub name_column: Option<String>,
+    pub email_column: Option<String>,
+    //单个 ASCII 字符或 tab，默认逗号
+    pub delimiter: Option<String>,
+    //skip（默认）或 merge
+    pub on_duplicate: Option<DuplicatePolicy>,
+    //新订阅者的状态，默认 confirmed：从其它工具迁移过来的订阅者已经确认过
+    pub status: Option<String>,
+    #[serde(default)]
+    pub dry_run: bool,
+    //订阅者最初是在哪里同意的，例如之前使用的工具；同意记录的来源为 import 或 import: <值>
+    pub consent_source: Option<String>,
+    //订阅者当时看到的同意文本的版本
+    pub consent_version: Option<String>,
+}
+
+fn parse_delimiter(delimiter: Option<&str>) -> Result<u8, String> {
+    match delimiter {
+        None => Ok(b','),
+        Some("tab") => Ok(b'\t'),
+        Some(delimiter) => match delimiter.as_bytes() {
+            [byte] if byte.is_ascii() && !matches!(byte, b'"' | b'\r' | b'\n') => Ok(*byte),
+            _ => Err(format!("{} cannot be used as a delimiter", delimiter)),
+        },
+    }
+}
+
+fn import_error_response(e: &ImportError) -> HttpResponse {
+    match e {
+        ImportError::Repository(e) => repository_error_response(e),
+        other => HttpResponse::BadRequest().body(other.to_string()),
+    }
+}
+
+#[tracing::instrument(name = "Importing subscribers", skip(payload, repository, admin), fields(admin = %admin.username))]
+pub async fn import_subscribers(
+    mut payload: web::Payload,
+    params: web::Query<ImportParams>,
+    repository: web::Data<dyn SubscriberRepository>,
+    admin: web::ReqData<AdminUser>,
+) -> HttpResponse {
+    let params = params.into_inner();
+    let delimiter = match parse_delimiter(params.delimiter.as_deref()) {
+        Ok(delimiter) => delimiter,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let status = match params.status.as_deref().map(SubscriptionStatus::parse).transpose() {
+        Ok(status) => status.unwrap_or(SubscriptionStatus::Confirmed),
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let defaults = ColumnMapping::default();
+    let mapping = ColumnMapping {
+        name: params.name_column.unwrap_or(defaults.name),
+        email: params.email_column.unwrap_or(defaults.email),
+    };
+    let options = ImportOptions {
+        status,
+        on_duplicate: params.on_duplicate.unwrap_or_default(),
+        dry_run: params.dry_run,
+        reason: format!("imported by admin {}", admin.username),
+        consent: NewConsent::imported(params.consent_source.as_deref(), params.consent_version),
+    };
+
+    //边读边解析，每攒满一批写入一次，不把整个文件留在内存里
+    let mut parser = CsvParser::new(delimiter);
+    let mut import = SubscriberImport::new(repository.get_ref(), mapping, options);
+    while let Some(chunk) = payload.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => return HttpResponse::BadRequest().body(format!("Failed to read the request body: {}", e)),
+        };
+        for record in parser.feed(&chunk) {
+            if let Err(e) = import.push(record).await {
+                return import_error_response(&e);
+            }
+        }
+    }
+    if let Some(record) = parser.finish() {
+        if let Err(e) = import.push(record).await {
+            return import_error_response(&e);
+        }
+    }
+    match import.finish().await {
+        Ok(report) => {
+            tracing::info!(
+                rows = report.rows,
+                imported = report.imported,
+                merged = report.merged,
+                skipped = report.skipped.len(),
+                rejected = report.rejected.len(),
+                dry_run = report.dry_run,
+                "Subscriber import finished"
+            );
+            HttpResponse::Ok().json(report)
+        }
+        Err(e) => import_error_response(&e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{ConsentQuery, InMemorySubscriberRepository, SubscriberQuery};
+    use crate::routes::admin::testing::{call, AUTHORIZATION_VALUE};
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::test;
+    use std::sync::Arc;
+
+    fn import_request(query: &str, csv: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri(&format!("/admin/subscribers/import{}", query))
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE))
+            .insert_header(("Content-Type", "text/csv"))
+            .set_payload(csv.to_string())
+    }
+
+    #[actix_web::test]
+    async fn mapped_columns_are_imported_and_rejections_reported() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let csv = "Full Name;E-mail;Plan\nUrsula Le Guin;ursula@example.com;pro\nIain;not-an-email;free\n";
+        let request = import_request(
+            "?name_column=full%20name&email_column=e-mail&delimiter=%3B&status=pending_confirmation&consent_source=mailchimp&consent_version=2024-03",
+            csv,
+        );
+        let response = call(&repository, request).await;
+        assert_eq!(response.status(), 200);
+        let report: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(report["imported"], 1);
+        assert_eq!(report["rejected"], serde_json::json!([{ "line": 3, "reason": "Email is not valid" }]));
+
+        let subscribers = repository.search(&SubscriberQuery::default()).await.unwrap();
+        assert_eq!(subscribers[0].status, SubscriptionStatus::PendingConfirmation);
+        let events = repository.events(subscribers[0].id).await.unwrap();
+        assert_eq!(events[0].reason, "imported by admin admin");
+        let consents = repository.consents(&ConsentQuery::for_subscriber(subscribers[0].id)).await.unwrap();
+        assert_eq!(consents[0].source.as_deref(), Some("import: mailchimp"));
+        assert_eq!(consents[0].consent_version.as_deref(), Some("2024-03"));
+        assert_eq!(consents[0].ip_address, None);
+    }
+
+    #[actix_web::test]
+    async fn dry_runs_report_without_writing() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let response = call(&repository, import_request("?dry_run=true", "name,email\nUrsula,ursula@example.com\n")).await;
+        let report: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(report["dry_run"], true);
+        assert_eq!(report["imported"], 1);
+        assert!(repository.search(&SubscriberQuery::default()).await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn bad_parameters_and_headers_are_rejected() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        for query in ["?delimiter=%22", "?status=active", "?on_duplicate=replace"] {
+            let response = call(&repository, import_request(query, "name,email\n")).await;
+            assert_eq!(response.status(), 400, "{}", query);
+        }
+        let response = call(&repository, import_request("", "email\nursula@example.com\n")).await;
+        assert_eq!(response.status(), 400);
+        assert_eq!(test::read_body(response).await, "CSV header has no column named name");
+
+        let request = test::TestRequest::post().uri("/admin/subscribers/import").set_payload("name,email\n");
+        assert_eq!(call(&repository, request).await.status(), 401);
+    }
+}
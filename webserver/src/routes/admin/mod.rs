@@ -0,0 +1,121 @@
+//! 管理后台接口，全部挂在 `/admin` 下，由 `authentication::require_admin` 中间件保护
+pub mod consents;
+pub mod drafts;
+pub mod export;
+pub mod import;
+pub mod newsletters;
+pub mod privacy;
+pub mod reports;
+pub mod subscribers;
+pub mod suppressions;
+
+pub use consents::*;
+pub use drafts::*;
+pub use export::*;
+pub use import::*;
+pub use newsletters::*;
+pub use privacy::*;
+pub use reports::*;
+pub use subscribers::*;
+pub use suppressions::*;
+
+use actix_web::web;
+
+/// 注册 `/admin` 下的路由，调用方负责套上 `require_admin`
+pub fn admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/subscribers", web::get().to(list_subscribers))
+        //要在 /subscribers/{id} 之前注册
+        .route("/subscribers/export", web::get().to(export_subscribers))
+        .route("/subscribers/import", web::post().to(import_subscribers))
+        .route("/subscribers/{id}", web::get().to(fetch_subscriber))
+        .route("/subscribers/{id}", web::patch().to(update_subscriber))
+        .route("/subscribers/{id}", web::delete().to(delete_subscriber))
+        .route("/consents", web::get().to(list_consents))
+        .route("/newsletters", web::get().to(list_issues))
+        .route("/newsletters", web::post().to(schedule_issue))
+        .route("/newsletters/preview", web::post().to(preview_newsletter))
+        //草稿要在 /newsletters/{id} 之前注册
+        .route("/newsletters/drafts", web::get().to(list_drafts))
+        .route("/newsletters/drafts", web::post().to(create_draft))
+        .route("/newsletters/drafts/{id}", web::get().to(fetch_draft))
+        .route("/newsletters/drafts/{id}", web::put().to(save_draft))
+        .route("/newsletters/drafts/{id}", web::delete().to(delete_draft))
+        .route("/newsletters/drafts/{id}/revisions", web::get().to(list_revisions))
+        .route("/newsletters/drafts/{id}/revisions/{version}", web::get().to(fetch_revision))
+        .route("/newsletters/drafts/{id}/diff", web::get().to(diff_revisions))
+        .route("/newsletters/drafts/{id}/restore", web::post().to(restore_revision))
+        .route("/newsletters/drafts/{id}/publish", web::post().to(publish_draft))
+        .route("/newsletters/{id}", web::get().to(fetch_issue))
+        .route("/newsletters/{id}", web::patch().to(reschedule_issue))
+        .route("/newsletters/{id}/cancel", web::post().to(cancel_issue))
+        .route("/newsletters/{id}/analytics", web::get().to(issue_analytics))
+        .route("/newsletters/{id}/report", web::get().to(issue_report))
+        .route("/newsletters/{id}/deliveries", web::get().to(list_deliveries))
+        .route("/newsletters/{id}/deliveries/{subscriber_id}", web::get().to(fetch_delivery))
+        .route("/privacy/data", web::get().to(export_personal_data))
+        .route("/privacy/erase", web::post().to(erase_subscriber_data))
+        .route("/privacy/erasures", web::get().to(list_erasures))
+        .route("/suppressions", web::get().to(list_suppressions))
+        .route("/suppressions/{email}", web::get().to(fetch_suppression))
+        .route("/suppressions/{email}", web::delete().to(lift_suppression));
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::admin_routes;
+    use crate::authentication::require_admin;
+    use crate::email_templates::EmailTemplates;
+    use crate::repository::{
+        DeliveryRepository, InMemoryNewsletterRepository, InMemorySubscriberRepository, InMemoryUserRepository,
+        NewsletterRepository, PrivacyRepository, SubscriberRepository, SuppressionRepository, TrackingRepository, UserRepository,
+    };
+    use actix_web::dev::ServiceResponse;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App};
+    use once_cell::sync::Lazy;
+    use std::sync::Arc;
+
+    //admin:correct horse battery staple
+    pub const AUTHORIZATION_VALUE: &str = "Basic YWRtaW46Y29ycmVjdCBob3JzZSBiYXR0ZXJ5IHN0YXBsZQ==";
+
+    //计算 Argon2 哈希很慢，所有测试共用一个管理员
+    static USERS: Lazy<Arc<InMemoryUserRepository>> = Lazy::new(|| {
+        let users = InMemoryUserRepository::new();
+        users.add_user("admin", "correct horse battery staple");
+        Arc::new(users)
+    });
+
+    //每次调用都新建 App，订阅者数据保存在共享的内存仓库中
+    pub async fn call(repository: &Arc<InMemorySubscriberRepository>, request: test::TestRequest) -> ServiceResponse {
+        call_with_newsletters(repository, &Arc::new(InMemoryNewsletterRepository::new()), request).await
+    }
+
+    //同上，通讯保存在调用方提供的仓库中
+    pub async fn call_with_newsletters(
+        repository: &Arc<InMemorySubscriberRepository>,
+        newsletters: &Arc<InMemoryNewsletterRepository>,
+        request: test::TestRequest,
+    ) -> ServiceResponse {
+        let users: Arc<dyn UserRepository> = USERS.clone();
+        let newsletters: Arc<dyn NewsletterRepository> = newsletters.clone();
+        let privacy: Arc<dyn PrivacyRepository> = repository.clone();
+        let tracking: Arc<dyn TrackingRepository> = repository.clone();
+        let suppressions: Arc<dyn SuppressionRepository> = repository.clone();
+        let deliveries: Arc<dyn DeliveryRepository> = repository.clone();
+        let repository: Arc<dyn SubscriberRepository> = repository.clone();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(repository))
+                .app_data(web::Data::from(privacy))
+                .app_data(web::Data::from(tracking))
+                .app_data(web::Data::from(suppressions))
+                .app_data(web::Data::from(deliveries))
+                .app_data(web::Data::from(users))
+                .app_data(web::Data::from(newsletters))
+                .app_data(web::Data::new(EmailTemplates::builtin()))
+                .app_data(web::Data::new(crate::testing::links()))
+                .service(web::scope("/admin").wrap(from_fn(require_admin)).configure(admin_routes)),
+        ).await;
+        test::call_service(&app, request.to_request()).await
+    }
+}
@@ -0,0 +1,491 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::authentication::AdminUser;
+use crate::domain::{DeliveryStatus, IssueStatus, SubscriberAttributes};
+use crate::email_templates::EmailTemplates;
+use crate::links::EmailLinks;
+use crate::newsletter::{validate_issue, IssueTemplate, Recipient};
+use crate::repository::{DeliveryQuery, DeliveryRepository, IssueAnalytics, NewIssue, NewsletterRepository, SubscriberRepository, TrackingRepository};
+use crate::routes::errors::repository_error_response;
+
+const DEFAULT_ISSUES: usize = 50;
+const MAX_ISSUES: usize = 500;
+const DEFAULT_DELIVERIES: usize = 100;
+const MAX_DELIVERIES: usize = 1000;
+
+/// `POST /admin/newsletters/preview` 的请求体，`content` 是 Markdown
+#[derive(Deserialize, Debug)]
+pub struct NewsletterPreviewRequest {
+    pub title: String,
+    pub content: String,
+    //按这个订阅者替换合并标签；省略时用示例收件人，自定义属性都取 default 中的文字
+    pub subscriber_id: Option<Uuid>,
+}
+
+//标题和正文不能为空，合并标签都要认识；错误信息直接返回给编辑
+pub(crate) fn check_issue(title: &str, content: &str) -> Result<(), String> {
+    if title.trim().is_empty() {
+        return Err("title must not be empty".to_string());
+    }
+    if content.trim().is_empty() {
+        return Err("content must not be empty".to_string());
+    }
+    validate_issue(title, content)
+}
+
+/// 返回一期通讯渲染后的主题、HTML 和纯文本，不发送；合并标签不认识时返回 400
+#[tracing::instrument(name = "Previewing a newsletter issue", skip(body, templates, links, repository))]
+pub async fn preview_newsletter(
+    body: web::Json<NewsletterPreviewRequest>,
+    templates: web::Data<EmailTemplates>,
+    links: web::Data<EmailLinks>,
+    repository: web::Data<dyn SubscriberRepository>,
+) -> HttpResponse {
+    let NewsletterPreviewRequest { title, content, subscriber_id } = body.into_inner();
+    if let Err(e) = check_issue(&title, &content) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let issue = match IssueTemplate::render(&templates, title.trim(), &content) {
+        Ok(issue) => issue,
+        Err(e) => {
+            tracing::error!("{}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let email = match subscriber_id {
+        Some(id) => match repository.find(id).await {
+            Ok(subscriber) => issue.personalize(&Recipient {
+                name: &subscriber.name,
+                email: &subscriber.email,
+                attributes: &subscriber.attributes,
+                unsubscribe_url: &links.unsubscribe_url(subscriber.id, None),
+            }),
+            Err(e) => return repository_error_response(&e),
+        },
+        None => issue.personalize(&Recipient {
+            name: "Jane Doe",
+            email: "jane.doe@example.com",
+            attributes: &SubscriberAttributes::default(),
+            unsubscribe_url: &links.unsubscribe_url(Uuid::nil(), None),
+        }),
+    };
+    HttpResponse::Ok().json(email)
+}
+
+/// `POST /admin/newsletters` 的请求体
+#[derive(Deserialize, Debug)]
+pub struct ScheduleIssueRequest {
+    pub title: String,
+    pub content: String,
+    //RFC 3339，例如 2025-11-10T09:00:00+08:00；省略或已经过去时由调度任务尽快发送
+    pub scheduled_at: Option<DateTime<Utc>>,
+    //false 时这一期不加跟踪像素、不改写链接；省略时跟踪
+    pub tracking: Option<bool>,
+}
+
+/// 新建一期通讯并安排发送，返回 201 和这一期的记录
+#[tracing::instrument(name = "Scheduling a newsletter issue", skip(body, newsletters, admin))]
+pub async fn schedule_issue(
+    body: web::Json<ScheduleIssueRequest>,
+    newsletters: web::Data<dyn NewsletterRepository>,
+    admin: web::ReqData<AdminUser>,
+) -> HttpResponse {
+    let ScheduleIssueRequest { title, content, scheduled_at, tracking } = body.into_inner();
+    if let Err(e) = check_issue(&title, &content) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    let issue = NewIssue {
+        title: title.trim().to_string(),
+        content,
+        scheduled_at: scheduled_at.unwrap_or_else(Utc::now),
+        created_by: admin.username.clone(),
+        tracking: tracking.unwrap_or(true),
+    };
+    match newsletters.create_issue(&issue).await {
+        Ok(issue) => HttpResponse::Created().json(issue),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IssueListParams {
+    pub status: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// 按计划发送时间倒序列出通讯，可以按状态过滤
+#[tracing::instrument(name = "Listing newsletter issues", skip(newsletters))]
+pub async fn list_issues(params: web::Query<IssueListParams>, newsletters: web::Data<dyn NewsletterRepository>) -> HttpResponse {
+    let IssueListParams { status, limit } = params.into_inner();
+    let status = match status.as_deref().map(IssueStatus::parse).transpose() {
+        Ok(status) => status,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let limit = limit.unwrap_or(DEFAULT_ISSUES);
+    if limit == 0 || limit > MAX_ISSUES {
+        return HttpResponse::BadRequest().body(format!("limit must be between 1 and {}", MAX_ISSUES));
+    }
+    match newsletters.list_issues(status, limit).await {
+        Ok(issues) => HttpResponse::Ok().json(issues),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[tracing::instrument(name = "Fetching a newsletter issue", skip(newsletters))]
+pub async fn fetch_issue(id: web::Path<Uuid>, newsletters: web::Data<dyn NewsletterRepository>) -> HttpResponse {
+    match newsletters.find_issue(id.into_inner()).await {
+        Ok(issue) => HttpResponse::Ok().json(issue),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+/// `PATCH /admin/newsletters/{id}` 的请求体
+#[derive(Deserialize, Debug)]
+pub struct RescheduleRequest {
+    pub scheduled_at: DateTime<Utc>,
+}
+
+/// 改期；调度任务已经开始发送之后返回 409
+#[tracing::instrument(name = "Rescheduling a newsletter issue", skip(newsletters))]
+pub async fn reschedule_issue(
+    id: web::Path<Uuid>,
+    body: web::Json<RescheduleRequest>,
+    newsletters: web::Data<dyn NewsletterRepository>,
+) -> HttpResponse {
+    match newsletters.reschedule_issue(id.into_inner(), body.scheduled_at).await {
+        Ok(issue) => HttpResponse::Ok().json(issue),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+/// 取消发送，记录保留；同样只能在开始发送之前
+#[tracing::instrument(name = "Cancelling a newsletter issue", skip(newsletters))]
+pub async fn cancel_issue(id: web::Path<Uuid>, newsletters: web::Data<dyn NewsletterRepository>) -> HttpResponse {
+    match newsletters.cancel_issue(id.into_inner()).await {
+        Ok(issue) => HttpResponse::Ok().json(issue),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueAnalyticsReport {
+    pub issue_id: Uuid,
+    pub tracking: bool,
+    pub sent_count: i64,
+    #[serde(flatten)]
+    pub analytics: IssueAnalytics,
+    //按人数算，还没有发出时为 null
+    pub open_rate: Option<f64>,
+    pub click_rate: Option<f64>,
+}
+
+/// 一期通讯的打开和点击统计；关闭跟踪的订阅者不计入，打开数受邮件客户端屏蔽图片的影响偏低
+#[tracing::instrument(name = "Fetching newsletter analytics", skip(newsletters, tracking))]
+pub async fn issue_analytics(
+    id: web::Path<Uuid>,
+    newsletters: web::Data<dyn NewsletterRepository>,
+    tracking: web::Data<dyn TrackingRepository>,
+) -> HttpResponse {
+    let issue = match newsletters.find_issue(id.into_inner()).await {
+        Ok(issue) => issue,
+        Err(e) => return repository_error_response(&e),
+    };
+    let analytics = match tracking.issue_analytics(issue.id).await {
+        Ok(analytics) => analytics,
+        Err(e) => return repository_error_response(&e),
+    };
+    let rate = |count: i64| (issue.sent_count > 0).then(|| count as f64 / issue.sent_count as f64);
+    HttpResponse::Ok().json(IssueAnalyticsReport {
+        issue_id: issue.id,
+        tracking: issue.tracking,
+        sent_count: issue.sent_count,
+        open_rate: rate(analytics.unique_opens),
+        click_rate: rate(analytics.unique_clicks),
+        analytics,
+    })
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeliveryListParams {
+    pub status: Option<String>,
+    //上一页最后一条的 subscriber_id
+    pub after: Option<Uuid>,
+    pub limit: Option<usize>,
+}
+
+/// `GET /admin/newsletters/{id}/deliveries`：这一期每个收件人的投递记录，按 subscriber_id 分页，可以按状态过滤
+#[tracing::instrument(name = "Listing newsletter deliveries", skip(newsletters, deliveries))]
+pub async fn list_deliveries(
+    id: web::Path<Uuid>,
+    params: web::Query<DeliveryListParams>,
+    newsletters: web::Data<dyn NewsletterRepository>,
+    deliveries: web::Data<dyn DeliveryRepository>,
+) -> HttpResponse {
+    let DeliveryListParams { status, after, limit } = params.into_inner();
+    let status = match status.as_deref().map(DeliveryStatus::parse).transpose() {
+        Ok(status) => status,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let limit = limit.unwrap_or(DEFAULT_DELIVERIES);
+    if limit == 0 || limit > MAX_DELIVERIES {
+        return HttpResponse::BadRequest().body(format!("limit must be between 1 and {}", MAX_DELIVERIES));
+    }
+    //不存在的一期返回 404，而不是空列表
+    let issue = match newsletters.find_issue(id.into_inner()).await {
+        Ok(issue) => issue,
+        Err(e) => return repository_error_response(&e),
+    };
+    match deliveries.list_deliveries(issue.id, &DeliveryQuery { status, after, limit }).await {
+        Ok(deliveries) => HttpResponse::Ok().json(deliveries),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+/// `GET /admin/newsletters/{id}/deliveries/{subscriber_id}`：这个订阅者有没有收到这一期，没有记录时返回 404
+#[tracing::instrument(name = "Fetching a newsletter delivery", skip(deliveries))]
+pub async fn fetch_delivery(path: web::Path<(Uuid, Uuid)>, deliveries: web::Data<dyn DeliveryRepository>) -> HttpResponse {
+    let (issue_id, subscriber_id) = path.into_inner();
+    match deliveries.find_delivery(issue_id, subscriber_id).await {
+        Ok(delivery) => HttpResponse::Ok().json(delivery),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus};
+    use crate::repository::{
+        DeliveryRepository, InMemoryNewsletterRepository, InMemorySubscriberRepository, NewsletterRepository, SubscriberRepository,
+        TrackingEvent, TrackingKind, TrackingRepository,
+    };
+    use crate::routes::admin::testing::{call, call_with_newsletters, AUTHORIZATION_VALUE};
+    use crate::testing::subscriber;
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::test;
+    use std::sync::Arc;
+
+    fn preview(body: serde_json::Value) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/admin/newsletters/preview")
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE))
+            .set_json(body)
+    }
+
+    #[actix_web::test]
+    async fn previews_contain_both_renderings() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let body = serde_json::json!({ "title": "Issue 7", "content": "Hello *world*, see [the docs](https://example.com/docs).<script>x()</script>" });
+        let response = call(&repository, preview(body)).await;
+        assert_eq!(response.status(), 200);
+        let email: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(email["subject"], "Issue 7");
+        let html = email["html"].as_str().unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("Hello <em>world</em>"));
+        assert!(!html.contains("<script>"));
+        let text = email["text"].as_str().unwrap();
+        assert!(text.contains("Hello world, see the docs [1]."));
+        assert!(text.contains("[1] https://example.com/docs"));
+    }
+
+    #[actix_web::test]
+    async fn previews_can_be_personalized_for_a_subscriber() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let id = subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let attributes = SubscriberAttributes::parse([("company".to_string(), "Acme & Co".to_string())].into()).unwrap();
+        repository.update_details(id, None, None, Some(&attributes), None).await.unwrap();
+
+        let content = "Hi {{ subscriber.name }} at {{ subscriber.attributes.company | default(\"work\") }}";
+        let body = serde_json::json!({ "title": "Issue 7", "content": content, "subscriber_id": id });
+        let email: serde_json::Value = test::read_body_json(call(&repository, preview(body)).await).await;
+        assert!(email["html"].as_str().unwrap().contains("Hi Ursula Le Guin at Acme &amp; Co"));
+        assert!(email["text"].as_str().unwrap().contains("Hi Ursula Le Guin at Acme & Co"));
+
+        //示例收件人没有自定义属性
+        let body = serde_json::json!({ "title": "Issue 7", "content": content });
+        let email: serde_json::Value = test::read_body_json(call(&repository, preview(body)).await).await;
+        assert!(email["text"].as_str().unwrap().contains("Hi Jane Doe at work"));
+    }
+
+    #[actix_web::test]
+    async fn title_content_and_merge_tags_are_checked() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        for body in [
+            serde_json::json!({ "title": " ", "content": "Hello" }),
+            serde_json::json!({ "title": "Issue 7", "content": "" }),
+            serde_json::json!({ "title": "Issue 7", "content": "Hi {{ subscriber.nickname }}" }),
+        ] {
+            assert_eq!(call(&repository, preview(body)).await.status(), 400);
+        }
+        let body = serde_json::json!({ "title": "Issue 7", "content": "Hello", "subscriber_id": uuid::Uuid::new_v4() });
+        assert_eq!(call(&repository, preview(body)).await.status(), 404);
+        let request = test::TestRequest::post()
+            .uri("/admin/newsletters/preview")
+            .set_json(serde_json::json!({ "title": "Issue 7", "content": "Hello" }));
+        assert_eq!(call(&repository, request).await.status(), 401);
+    }
+
+    fn admin(request: test::TestRequest) -> test::TestRequest {
+        request.insert_header((AUTHORIZATION, AUTHORIZATION_VALUE))
+    }
+
+    #[actix_web::test]
+    async fn issues_can_be_rescheduled_or_cancelled_until_sending_starts() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        let body = serde_json::json!({ "title": " Issue 7 ", "content": "Hi {{ subscriber.name }}", "scheduled_at": "2030-11-11T09:00:00+08:00" });
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::post().uri("/admin/newsletters").set_json(body))).await;
+        assert_eq!(response.status(), 201);
+        let issue: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(issue["title"], "Issue 7");
+        assert_eq!(issue["status"], "scheduled");
+        assert_eq!(issue["scheduled_at"], "2030-11-11T01:00:00Z");
+        assert_eq!(issue["created_by"], "admin");
+        let uri = format!("/admin/newsletters/{}", issue["id"].as_str().unwrap());
+
+        let body = serde_json::json!({ "scheduled_at": "2030-11-12T09:00:00Z" });
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::patch().uri(&uri).set_json(body))).await;
+        assert_eq!(response.status(), 200);
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::post().uri(&format!("{}/cancel", uri)))).await;
+        assert_eq!(response.status(), 200);
+        let issue: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!((issue["status"].as_str(), issue["scheduled_at"].as_str()), (Some("cancelled"), Some("2030-11-12T09:00:00Z")));
+
+        let body = serde_json::json!({ "scheduled_at": "2030-11-13T09:00:00Z" });
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::patch().uri(&uri).set_json(body))).await;
+        assert_eq!(response.status(), 409);
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::get().uri("/admin/newsletters?status=cancelled"))).await;
+        let issues: Vec<serde_json::Value> = test::read_body_json(response).await;
+        assert_eq!(issues.len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn issues_being_sent_can_no_longer_be_changed() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        //省略 scheduled_at 时立即到期
+        let body = serde_json::json!({ "title": "Issue 7", "content": "Hello" });
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::post().uri("/admin/newsletters").set_json(body))).await;
+        let issue: serde_json::Value = test::read_body_json(response).await;
+        let claimed = newsletters.claim_due_issue(chrono::Utc::now()).await.unwrap().unwrap();
+        assert_eq!(claimed.id.to_string(), issue["id"].as_str().unwrap());
+
+        let uri = format!("/admin/newsletters/{}/cancel", claimed.id);
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::post().uri(&uri))).await;
+        assert_eq!(response.status(), 409);
+        assert_eq!(test::read_body(response).await, "Issue is sending and can no longer be changed");
+        let uri = format!("/admin/newsletters/{}/cancel", uuid::Uuid::new_v4());
+        assert_eq!(call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::post().uri(&uri))).await.status(), 404);
+
+        for body in [
+            serde_json::json!({ "title": "Issue 8", "content": "Hi {{ subscriber.nickname }}" }),
+            serde_json::json!({ "title": "Issue 8", "content": "Hello", "scheduled_at": "next monday" }),
+        ] {
+            let request = admin(test::TestRequest::post().uri("/admin/newsletters").set_json(body));
+            assert_eq!(call_with_newsletters(&repository, &newsletters, request).await.status(), 400);
+        }
+        let request = admin(test::TestRequest::get().uri("/admin/newsletters?status=draft"));
+        assert_eq!(call_with_newsletters(&repository, &newsletters, request).await.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn analytics_count_people_against_the_sent_emails() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        let body = serde_json::json!({ "title": "Issue 7", "content": "Hello", "tracking": false });
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::post().uri("/admin/newsletters").set_json(body))).await;
+        let issue: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(issue["tracking"], false);
+        let uri = format!("/admin/newsletters/{}/analytics", issue["id"].as_str().unwrap());
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::get().uri(&uri))).await;
+        let analytics: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!((analytics["opens"].as_i64(), analytics["open_rate"].is_null()), (Some(0), true));
+
+        let body = serde_json::json!({ "title": "Issue 8", "content": "Hello" });
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::post().uri("/admin/newsletters").set_json(body))).await;
+        let issue: serde_json::Value = test::read_body_json(response).await;
+        let issue_id: uuid::Uuid = issue["id"].as_str().unwrap().parse().unwrap();
+        let mut subscribers = Vec::new();
+        for email in ["ursula@example.com", "octavia@example.com"] {
+            subscribers.push(subscriber(repository.as_ref(), email, SubscriptionStatus::Confirmed).await);
+        }
+        for (subscriber_id, kind, url) in [
+            (subscribers[0], TrackingKind::Open, None),
+            (subscribers[0], TrackingKind::Open, None),
+            (subscribers[0], TrackingKind::Click, Some("https://example.com/docs")),
+            (subscribers[1], TrackingKind::Click, Some("https://example.com/docs")),
+            (subscribers[1], TrackingKind::Click, Some("https://example.com/blog")),
+        ] {
+            assert!(repository.record_event(&TrackingEvent::new(issue_id, subscriber_id, kind, url)).await.unwrap());
+        }
+        newsletters.claim_due_issue(chrono::Utc::now()).await.unwrap();
+        newsletters.complete_issue(issue_id, 4, 0).await.unwrap();
+
+        let uri = format!("/admin/newsletters/{}/analytics", issue_id);
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::get().uri(&uri))).await;
+        assert_eq!(response.status(), 200);
+        let analytics: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(analytics["sent_count"], 4);
+        assert_eq!((analytics["opens"].as_i64(), analytics["unique_opens"].as_i64()), (Some(2), Some(1)));
+        assert_eq!((analytics["clicks"].as_i64(), analytics["unique_clicks"].as_i64()), (Some(3), Some(2)));
+        assert_eq!((analytics["open_rate"].as_f64(), analytics["click_rate"].as_f64()), (Some(0.25), Some(0.5)));
+        assert_eq!(analytics["links"][0], serde_json::json!({ "url": "https://example.com/docs", "clicks": 2, "unique_clicks": 2 }));
+
+        let uri = format!("/admin/newsletters/{}/analytics", uuid::Uuid::new_v4());
+        assert_eq!(call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::get().uri(&uri))).await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn deliveries_show_whether_a_subscriber_got_an_issue() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        let body = serde_json::json!({ "title": "Issue 42", "content": "Hello" });
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::post().uri("/admin/newsletters").set_json(body))).await;
+        let issue: serde_json::Value = test::read_body_json(response).await;
+        let issue_id: uuid::Uuid = issue["id"].as_str().unwrap().parse().unwrap();
+        let mut subscribers = Vec::new();
+        for email in ["alice@example.com", "octavia@example.com", "ursula@example.com"] {
+            let subscriber = NewSubscriber {
+                email: SubscriberEmail::parse(email.to_string()).unwrap(),
+                name: SubscriberName::parse("Alice".to_string()).unwrap(),
+            };
+            subscribers.push(repository.insert(&subscriber, SubscriptionStatus::Confirmed, "subscribed").await.unwrap());
+        }
+        repository.queue_deliveries(issue_id, &subscribers).await.unwrap();
+        repository.record_sent(issue_id, subscribers[0], Some("883953f4")).await.unwrap();
+        repository.record_failure(issue_id, subscribers[1], "500 Internal Server Error").await.unwrap();
+        assert!(repository.record_event(&TrackingEvent::new(issue_id, subscribers[0], TrackingKind::Click, Some("https://example.com/docs"))).await.unwrap());
+
+        let uri = format!("/admin/newsletters/{}/deliveries/{}", issue_id, subscribers[0]);
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::get().uri(&uri))).await;
+        let delivery: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!((delivery["status"].as_str(), delivery["message_id"].as_str()), (Some("clicked"), Some("883953f4")));
+        assert!(!delivery["opened_at"].is_null() && !delivery["clicked_at"].is_null());
+
+        let uri = format!("/admin/newsletters/{}/deliveries?status=failed", issue_id);
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::get().uri(&uri))).await;
+        let failed: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(failed.as_array().unwrap().len(), 1);
+        assert_eq!((failed[0]["last_error"].as_str(), failed[0]["attempts"].as_i64()), (Some("500 Internal Server Error"), Some(1)));
+
+        //按 subscriber_id 分页
+        let mut sorted = subscribers.clone();
+        sorted.sort();
+        let uri = format!("/admin/newsletters/{}/deliveries?limit=2&after={}", issue_id, sorted[0]);
+        let response = call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::get().uri(&uri))).await;
+        let page: serde_json::Value = test::read_body_json(response).await;
+        let ids: Vec<&str> = page.as_array().unwrap().iter().map(|delivery| delivery["subscriber_id"].as_str().unwrap()).collect();
+        assert_eq!(ids, [sorted[1].to_string(), sorted[2].to_string()]);
+
+        for (uri, status) in [
+            (format!("/admin/newsletters/{}/deliveries?status=delivered", issue_id), 400),
+            (format!("/admin/newsletters/{}/deliveries?limit=0", issue_id), 400),
+            (format!("/admin/newsletters/{}/deliveries", uuid::Uuid::new_v4()), 404),
+            (format!("/admin/newsletters/{}/deliveries/{}", uuid::Uuid::new_v4(), subscribers[0]), 404),
+        ] {
+            assert_eq!(call_with_newsletters(&repository, &newsletters, admin(test::TestRequest::get().uri(&uri))).await.status(), status, "{}", uri);
+        }
+    }
+}
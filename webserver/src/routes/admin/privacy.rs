@@ -0,0 +1,158 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use crate::authentication::AdminUser;
+use crate::domain::SubscriberEmail;
+use crate::repository::PrivacyRepository;
+use crate::routes::errors::repository_error_response;
+
+const DEFAULT_ERASURES: usize = 50;
+const MAX_ERASURES: usize = 500;
+
+#[derive(Deserialize, Debug)]
+pub struct PersonalDataParams {
+    pub email: String,
+}
+
+/// `POST /admin/privacy/erase` 的请求体
+#[derive(Deserialize, Debug)]
+pub struct ErasureRequest {
+    pub email: String,
+    //记录到墓碑中，例如工单号；不要写入邮箱
+    pub reason: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ErasureListParams {
+    pub limit: Option<usize>,
+}
+
+/// `GET /admin/privacy/data?email=...`：该邮箱在各表中的全部数据，用于答复数据访问请求
+///
+/// 没有数据时返回空列表，而不是 404
+#[tracing::instrument(name = "Exporting personal data for an admin", skip(params, privacy, admin), fields(admin = %admin.username))]
+pub async fn export_personal_data(
+    params: web::Query<PersonalDataParams>,
+    privacy: web::Data<dyn PrivacyRepository>,
+    admin: web::ReqData<AdminUser>,
+) -> HttpResponse {
+    let email = match SubscriberEmail::parse(params.into_inner().email) {
+        Ok(email) => email,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    match privacy.personal_data(&email).await {
+        Ok(data) => HttpResponse::Ok().json(data),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+/// `POST /admin/privacy/erase`：删除该邮箱在各表中的全部数据，返回墓碑记录；没有任何数据时返回 404
+#[tracing::instrument(name = "Erasing personal data for an admin", skip(body, privacy, admin), fields(admin = %admin.username))]
+pub async fn erase_subscriber_data(
+    body: web::Json<ErasureRequest>,
+    privacy: web::Data<dyn PrivacyRepository>,
+    admin: web::ReqData<AdminUser>,
+) -> HttpResponse {
+    let ErasureRequest { email, reason } = body.into_inner();
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return HttpResponse::BadRequest().body("reason must not be empty");
+    }
+    match privacy.erase(&email, &format!("admin {}", admin.username), reason).await {
+        Ok(tombstone) => {
+            tracing::info!(tombstone = %tombstone.id, "Personal data erased");
+            HttpResponse::Ok().json(tombstone)
+        }
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+/// `GET /admin/privacy/erasures`：最近的删除记录，按时间倒序
+pub async fn list_erasures(params: web::Query<ErasureListParams>, privacy: web::Data<dyn PrivacyRepository>) -> HttpResponse {
+    let limit = params.limit.unwrap_or(DEFAULT_ERASURES);
+    if limit == 0 || limit > MAX_ERASURES {
+        return HttpResponse::BadRequest().body(format!("limit must be between 1 and {}", MAX_ERASURES));
+    }
+    match privacy.tombstones(limit).await {
+        Ok(tombstones) => HttpResponse::Ok().json(tombstones),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriptionStatus;
+    use crate::repository::{InMemorySubscriberRepository, SubscriberRepository};
+    use crate::routes::admin::testing::{call, AUTHORIZATION_VALUE};
+    use crate::testing::subscriber;
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::test;
+    use std::sync::Arc;
+
+    async fn seed(repository: &InMemorySubscriberRepository) -> uuid::Uuid {
+        let id = subscriber(repository, "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        repository.change_status(id, SubscriptionStatus::Unsubscribed, "unsubscribed by reader").await.unwrap();
+        id
+    }
+
+    fn erase_request(body: serde_json::Value) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/admin/privacy/erase")
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE))
+            .set_json(body)
+    }
+
+    #[actix_web::test]
+    async fn exports_include_subscriptions_and_events() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let id = seed(&repository).await;
+        let request = test::TestRequest::get()
+            .uri("/admin/privacy/data?email=Ursula%40Example.com")
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE));
+        let response = call(&repository, request).await;
+        assert_eq!(response.status(), 200);
+        let data: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(data["subscriptions"][0]["id"], id.to_string());
+        assert_eq!(data["subscription_events"].as_array().unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn erasures_leave_a_tombstone_without_the_address() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let id = seed(&repository).await;
+        let response = call(&repository, erase_request(serde_json::json!({ "email": "ursula@example.com", "reason": "ticket 42" }))).await;
+        assert_eq!(response.status(), 200);
+        let tombstone: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(tombstone["requested_by"], "admin admin");
+        assert_eq!(tombstone["erased_rows"]["subscriptions"], 1);
+        assert_eq!(tombstone["erased_rows"]["subscription_events"], 2);
+        assert!(repository.find(id).await.is_err());
+
+        let request = test::TestRequest::get().uri("/admin/privacy/erasures").insert_header((AUTHORIZATION, AUTHORIZATION_VALUE));
+        let body = test::read_body(call(&repository, request).await).await;
+        let erasures: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(erasures[0]["id"], tombstone["id"]);
+        assert!(!String::from_utf8(body.to_vec()).unwrap().contains("ursula"));
+
+        //已经没有数据
+        let response = call(&repository, erase_request(serde_json::json!({ "email": "ursula@example.com", "reason": "ticket 42" }))).await;
+        assert_eq!(response.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn erasures_need_a_valid_email_and_a_reason() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        seed(&repository).await;
+        for body in [
+            serde_json::json!({ "email": "ursula", "reason": "ticket 42" }),
+            serde_json::json!({ "email": "ursula@example.com", "reason": " " }),
+        ] {
+            assert_eq!(call(&repository, erase_request(body)).await.status(), 400);
+        }
+        let request = test::TestRequest::post().uri("/admin/privacy/erase").set_json(serde_json::json!({}));
+        assert_eq!(call(&repository, request).await.status(), 401);
+    }
+}
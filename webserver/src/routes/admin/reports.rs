@@ -0,0 +1,323 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::csv::write_record;
+use crate::domain::IssueStatus;
+use crate::repository::{ActivityBucket, DeliveryRepository, DeliverySummary, LinkClicks, NewsletterRepository, TrackingRepository};
+use crate::routes::errors::repository_error_response;
+
+const CSV_COLUMNS: [&str; 4] = ["metric", "url", "bucket_start", "value"];
+
+/// `GET /admin/newsletters/{id}/report` 的参数
+#[derive(Deserialize, Debug, Default)]
+pub struct ReportParams {
+    //曲线每段的长度：hour（默认）或 day
+    pub bucket: Option<String>,
+    //json（默认）或 csv
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportBucket {
+    Hour,
+    Day,
+}
+
+impl ReportBucket {
+    pub fn parse(bucket: Option<&str>) -> Result<ReportBucket, String> {
+        match bucket {
+            None | Some("hour") => Ok(ReportBucket::Hour),
+            Some("day") => Ok(ReportBucket::Day),
+            Some(other) => Err(format!("{} is not a supported bucket, use hour or day", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportBucket::Hour => "hour",
+            ReportBucket::Day => "day",
+        }
+    }
+
+    fn duration(&self) -> Duration {
+        match self {
+            ReportBucket::Hour => Duration::hours(1),
+            ReportBucket::Day => Duration::days(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+impl ReportFormat {
+    pub fn parse(format: Option<&str>) -> Result<ReportFormat, String> {
+        match format {
+            None | Some("json") => Ok(ReportFormat::Json),
+            Some("csv") => Ok(ReportFormat::Csv),
+            Some(other) => Err(format!("{} is not a supported report format, use json or csv", other)),
+        }
+    }
+}
+
+/// 曲线上的一段，`starts_at` 是这一段的开始时间
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CurvePoint {
+    pub starts_at: DateTime<Utc>,
+    pub opens: i64,
+    pub clicks: i64,
+}
+
+/// 一期通讯的报告
+///
+/// 人数来自 deliveries，打开、点击的次数和曲线来自 tracking_events；关闭跟踪的订阅者不计入打开和点击
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IssueReport {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub status: IssueStatus,
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub summary: DeliverySummary,
+    //事件数，同一个人多次打开或点击都算
+    pub opens: i64,
+    pub clicks: i64,
+    //按人数除以 sent，还没有发出时为 null
+    pub open_rate: Option<f64>,
+    pub click_rate: Option<f64>,
+    pub unsubscribe_rate: Option<f64>,
+    pub links: Vec<LinkClicks>,
+    pub bucket: &'static str,
+    //从 started_at 开始到最后一个事件，没有事件的段补 0
+    pub curve: Vec<CurvePoint>,
+}
+
+impl IssueReport {
+    fn curve(started_at: DateTime<Utc>, bucket: ReportBucket, buckets: &[ActivityBucket]) -> Vec<CurvePoint> {
+        let Some(last) = buckets.last() else {
+            return Vec::new();
+        };
+        let mut curve: Vec<CurvePoint> = (0..=last.bucket)
+            .map(|index| CurvePoint { starts_at: started_at + bucket.duration() * index as i32, opens: 0, clicks: 0 })
+            .collect();
+        for counts in buckets {
+            if let Some(point) = usize::try_from(counts.bucket).ok().and_then(|index| curve.get_mut(index)) {
+                point.opens = counts.opens;
+                point.clicks = counts.clicks;
+            }
+        }
+        curve
+    }
+
+    //长表：每行一个数值，链接和曲线分别用 url、bucket_start 列区分
+    fn to_csv(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_record(&mut out, CSV_COLUMNS, b',');
+        let summary = &self.summary;
+        for (metric, value) in [
+            ("recipients", summary.recipients),
+            ("sent", summary.sent),
+            ("failed", summary.failed),
+            ("bounced", summary.bounced),
+            ("unique_opens", summary.unique_opens),
+            ("unique_clicks", summary.unique_clicks),
+            ("unsubscribes", summary.unsubscribes),
+            ("opens", self.opens),
+            ("clicks", self.clicks),
+        ] {
+            write_record(&mut out, [metric, "", "", &value.to_string()], b',');
+        }
+        for link in &self.links {
+            write_record(&mut out, ["link_clicks", &link.url, "", &link.clicks.to_string()], b',');
+            write_record(&mut out, ["link_unique_clicks", &link.url, "", &link.unique_clicks.to_string()], b',');
+        }
+        for point in &self.curve {
+            let starts_at = point.starts_at.to_rfc3339();
+            write_record(&mut out, ["opens", "", &starts_at, &point.opens.to_string()], b',');
+            write_record(&mut out, ["clicks", "", &starts_at, &point.clicks.to_string()], b',');
+        }
+        out
+    }
+}
+
+/// `GET /admin/newsletters/{id}/report`：发送之后的投递、打开、点击和退订统计，以及打开和点击随时间的曲线
+///
+/// `format=csv` 返回同样的数据；还没有开始发送的一期各项为 0
+#[tracing::instrument(name = "Fetching a newsletter report", skip(newsletters, deliveries, tracking))]
+pub async fn issue_report(
+    id: web::Path<Uuid>,
+    params: web::Query<ReportParams>,
+    newsletters: web::Data<dyn NewsletterRepository>,
+    deliveries: web::Data<dyn DeliveryRepository>,
+    tracking: web::Data<dyn TrackingRepository>,
+) -> HttpResponse {
+    let bucket = match ReportBucket::parse(params.bucket.as_deref()) {
+        Ok(bucket) => bucket,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let format = match ReportFormat::parse(params.format.as_deref()) {
+        Ok(format) => format,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let issue = match newsletters.find_issue(id.into_inner()).await {
+        Ok(issue) => issue,
+        Err(e) => return repository_error_response(&e),
+    };
+    let summary = match deliveries.delivery_summary(issue.id).await {
+        Ok(summary) => summary,
+        Err(e) => return repository_error_response(&e),
+    };
+    let links = match tracking.link_clicks(issue.id).await {
+        Ok(links) => links,
+        Err(e) => return repository_error_response(&e),
+    };
+    let curve = match issue.started_at {
+        Some(started_at) => match tracking.activity(issue.id, started_at, bucket.duration().num_seconds()).await {
+            Ok(buckets) => IssueReport::curve(started_at, bucket, &buckets),
+            Err(e) => return repository_error_response(&e),
+        },
+        None => Vec::new(),
+    };
+    let rate = |count: i64| (summary.sent > 0).then(|| count as f64 / summary.sent as f64);
+    let report = IssueReport {
+        issue_id: issue.id,
+        title: issue.title,
+        status: issue.status,
+        started_at: issue.started_at,
+        opens: curve.iter().map(|point| point.opens).sum(),
+        clicks: curve.iter().map(|point| point.clicks).sum(),
+        open_rate: rate(summary.unique_opens),
+        click_rate: rate(summary.unique_clicks),
+        unsubscribe_rate: rate(summary.unsubscribes),
+        summary,
+        links,
+        bucket: bucket.as_str(),
+        curve,
+    };
+    match format {
+        ReportFormat::Json => HttpResponse::Ok().json(report),
+        ReportFormat::Csv => HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(format!("issue-{}-report.csv", report.issue_id))],
+            })
+            .body(report.to_csv()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus};
+    use crate::repository::{
+        DeliveryRepository, InMemoryNewsletterRepository, InMemorySubscriberRepository, NewIssue, NewsletterRepository,
+        SubscriberRepository, TrackingEvent, TrackingKind, TrackingRepository,
+    };
+    use crate::routes::admin::testing::{call_with_newsletters, AUTHORIZATION_VALUE};
+    use actix_web::http::header::{AUTHORIZATION, CONTENT_TYPE};
+    use actix_web::test;
+    use std::sync::Arc;
+
+    fn admin(uri: &str) -> test::TestRequest {
+        test::TestRequest::get().uri(uri).insert_header((AUTHORIZATION, AUTHORIZATION_VALUE))
+    }
+
+    #[actix_web::test]
+    async fn reports_count_deliveries_and_bucket_activity_since_the_send() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        let issue = NewIssue {
+            title: "Issue 42".to_string(),
+            content: "Hello".to_string(),
+            scheduled_at: Utc::now() - Duration::minutes(1),
+            created_by: "admin".to_string(),
+            tracking: true,
+        };
+        let issue_id = newsletters.create_issue(&issue).await.unwrap().id;
+        let started_at = newsletters.claim_due_issue(Utc::now()).await.unwrap().unwrap().started_at.unwrap();
+        let mut subscribers = Vec::new();
+        for email in ["alice@example.com", "iain@example.com", "octavia@example.com", "ursula@example.com"] {
+            let subscriber = NewSubscriber {
+                email: SubscriberEmail::parse(email.to_string()).unwrap(),
+                name: SubscriberName::parse("Alice".to_string()).unwrap(),
+            };
+            subscribers.push(repository.insert(&subscriber, SubscriptionStatus::Confirmed, "subscribed").await.unwrap());
+        }
+        repository.queue_deliveries(issue_id, &subscribers).await.unwrap();
+        repository.record_sent(issue_id, subscribers[0], Some("883953f4")).await.unwrap();
+        repository.record_sent(issue_id, subscribers[1], Some("b7bc2f4a")).await.unwrap();
+        repository.record_sent(issue_id, subscribers[2], Some("0d1e2f3a")).await.unwrap();
+        repository.record_failure(issue_id, subscribers[3], "500 Internal Server Error").await.unwrap();
+        repository.record_bounce("0d1e2f3a", None).await.unwrap();
+        repository.record_unsubscribe(issue_id, subscribers[1]).await.unwrap();
+        for (subscriber, kind, url, minutes) in [
+            (subscribers[0], TrackingKind::Open, None, 5),
+            (subscribers[0], TrackingKind::Click, Some("https://example.com/docs"), 6),
+            (subscribers[0], TrackingKind::Open, None, 150),
+            (subscribers[1], TrackingKind::Open, None, 170),
+            (subscribers[1], TrackingKind::Click, Some("https://example.com/docs"), 171),
+            (subscribers[1], TrackingKind::Click, Some("https://example.com/?a=1,2"), 172),
+        ] {
+            let mut event = TrackingEvent::new(issue_id, subscriber, kind, url);
+            event.occurred_at = started_at + Duration::minutes(minutes);
+            assert!(repository.record_event(&event).await.unwrap());
+        }
+
+        let uri = format!("/admin/newsletters/{}/report", issue_id);
+        let response = call_with_newsletters(&repository, &newsletters, admin(&uri)).await;
+        assert_eq!(response.status(), 200);
+        let report: serde_json::Value = test::read_body_json(response).await;
+        let counts = ["recipients", "sent", "failed", "bounced", "unique_opens", "unique_clicks", "unsubscribes", "opens", "clicks"]
+            .map(|field| report[field].as_i64().unwrap());
+        assert_eq!(counts, [4, 3, 1, 1, 2, 2, 1, 3, 3]);
+        assert_eq!(report["unsubscribe_rate"].as_f64(), Some(1.0 / 3.0));
+        assert_eq!((report["links"][0]["url"].as_str(), report["links"][0]["unique_clicks"].as_i64()), (Some("https://example.com/docs"), Some(2)));
+        //0:05、0:06 在第一段，2:30、2:50 在第三段，中间一段补 0
+        let curve: Vec<(i64, i64)> = report["curve"].as_array().unwrap().iter()
+            .map(|point| (point["opens"].as_i64().unwrap(), point["clicks"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(curve, vec![(1, 1), (0, 0), (2, 2)]);
+        assert_eq!(report["curve"][1]["starts_at"].as_str().unwrap().parse::<chrono::DateTime<Utc>>().unwrap(), started_at + Duration::hours(1));
+
+        let response = call_with_newsletters(&repository, &newsletters, admin(&format!("{}?bucket=day&format=csv", uri))).await;
+        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "text/csv; charset=utf-8");
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(&lines[..3], ["metric,url,bucket_start,value", "recipients,,,4", "sent,,,3"]);
+        assert!(lines.contains(&"link_clicks,\"https://example.com/?a=1,2\",,1"));
+        assert!(lines.contains(&format!("opens,,{},3", started_at.to_rfc3339()).as_str()));
+        assert_eq!(lines.len(), 1 + 9 + 2 * 2 + 2);
+    }
+
+    #[actix_web::test]
+    async fn unsent_or_unknown_issues_and_bad_parameters() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let newsletters = Arc::new(InMemoryNewsletterRepository::new());
+        let issue = NewIssue {
+            title: "Issue 43".to_string(),
+            content: "Hello".to_string(),
+            scheduled_at: Utc::now() + Duration::days(1),
+            created_by: "admin".to_string(),
+            tracking: true,
+        };
+        let issue_id = newsletters.create_issue(&issue).await.unwrap().id;
+        let uri = format!("/admin/newsletters/{}/report", issue_id);
+        let response = call_with_newsletters(&repository, &newsletters, admin(&uri)).await;
+        let report: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!((report["recipients"].as_i64(), report["open_rate"].is_null()), (Some(0), true));
+        assert!(report["curve"].as_array().unwrap().is_empty());
+
+        for query in ["?bucket=minute", "?format=xlsx"] {
+            let response = call_with_newsletters(&repository, &newsletters, admin(&format!("{}{}", uri, query))).await;
+            assert_eq!(response.status(), 400, "{}", query);
+        }
+        let uri = format!("/admin/newsletters/{}/report", uuid::Uuid::new_v4());
+        assert_eq!(call_with_newsletters(&repository, &newsletters, admin(&uri)).await.status(), 404);
+    }
+}
@@ -0,0 +1,310 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+use crate::authentication::AdminUser;
+use crate::domain::{SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::repository::{
+    ConsentQuery, ConsentRecord, Cursor, SubscriberQuery, SubscriberRecord, SubscriberRepository, SubscriberSort,
+    SubscriptionEvent,
+};
+use crate::routes::errors::repository_error_response;
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+
+/// `GET /admin/subscribers` 的查询参数
+#[derive(Deserialize, Debug, Default)]
+pub struct ListParams {
+    pub status: Option<String>,
+    pub domain: Option<String>,
+    //RFC 3339 时间或 YYYY-MM-DD（UTC 零点），from 包含、to 不包含
+    pub subscribed_from: Option<String>,
+    pub subscribed_to: Option<String>,
+    pub q: Option<String>,
+    //subscribed_at（默认）、email、name，加 - 前缀表示降序
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl TryFrom<ListParams> for SubscriberQuery {
+    type Error = String;
+    fn try_from(params: ListParams) -> Result<Self, Self::Error> {
+        let sort = match params.sort.as_deref() {
+            Some(sort) => SubscriberSort::parse(sort)?,
+            None => SubscriberSort::default(),
+        };
+        let after = match params.cursor.as_deref() {
+            Some(cursor) => Some(Cursor::decode(cursor, &sort)?),
+            None => None,
+        };
+        let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if limit == 0 || limit > MAX_PAGE_SIZE {
+            return Err(format!("limit must be between 1 and {}", MAX_PAGE_SIZE));
+        }
+        Ok(SubscriberQuery {
+            status: params.status.as_deref().map(SubscriptionStatus::parse).transpose()?,
+            email_domain: non_empty(params.domain),
+            subscribed_from: params.subscribed_from.as_deref().map(parse_timestamp).transpose()?,
+            subscribed_to: params.subscribed_to.as_deref().map(parse_timestamp).transpose()?,
+            search: non_empty(params.q),
+            sort,
+            after,
+            limit,
+        })
+    }
+}
+
+pub(crate) fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+pub(crate) fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()))
+        .map_err(|_| format!("{} is not an RFC 3339 timestamp or a YYYY-MM-DD date", value))
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberPage {
+    pub subscribers: Vec<SubscriberRecord>,
+    //没有下一页时为 null
+    pub next_cursor: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberDetails {
+    #[serde(flatten)]
+    pub subscriber: SubscriberRecord,
+    pub events: Vec<SubscriptionEvent>,
+    //按时间倒序
+    pub consents: Vec<ConsentRecord>,
+}
+
+/// `PATCH /admin/subscribers/{id}` 的请求体，省略的字段保持不变
+#[derive(Deserialize, Debug)]
+pub struct SubscriberPatch {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub status: Option<SubscriptionStatus>,
+    //整体替换自定义属性，传 {} 清空
+    pub attributes: Option<BTreeMap<String, String>>,
+    //false 关闭打开和点击跟踪
+    pub tracking: Option<bool>,
+    //记录到 subscription_events，省略时记为操作的管理员
+    pub reason: Option<String>,
+}
+
+#[tracing::instrument(name = "Listing subscribers", skip(repository))]
+pub async fn list_subscribers(params: web::Query<ListParams>, repository: web::Data<dyn SubscriberRepository>) -> HttpResponse {
+    let mut query: SubscriberQuery = match params.into_inner().try_into() {
+        Ok(query) => query,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    //多取一条，用来判断是否还有下一页
+    let limit = query.limit;
+    query.limit += 1;
+    let mut subscribers = match repository.search(&query).await {
+        Ok(subscribers) => subscribers,
+        Err(e) => return repository_error_response(&e),
+    };
+    let next_cursor = if subscribers.len() > limit {
+        subscribers.truncate(limit);
+        subscribers.last().map(|last| Cursor::after(last, &query.sort).encode(&query.sort))
+    } else {
+        None
+    };
+    HttpResponse::Ok().json(SubscriberPage { subscribers, next_cursor })
+}
+
+#[tracing::instrument(name = "Fetching a subscriber", skip(repository))]
+pub async fn fetch_subscriber(id: web::Path<Uuid>, repository: web::Data<dyn SubscriberRepository>) -> HttpResponse {
+    let id = id.into_inner();
+    let subscriber = match repository.find(id).await {
+        Ok(subscriber) => subscriber,
+        Err(e) => return repository_error_response(&e),
+    };
+    let events = match repository.events(id).await {
+        Ok(events) => events,
+        Err(e) => return repository_error_response(&e),
+    };
+    match repository.consents(&ConsentQuery::for_subscriber(id)).await {
+        Ok(consents) => HttpResponse::Ok().json(SubscriberDetails { subscriber, events, consents }),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[tracing::instrument(name = "Updating a subscriber", skip(patch, repository, admin), fields(admin = %admin.username))]
+pub async fn update_subscriber(
+    id: web::Path<Uuid>,
+    patch: web::Json<SubscriberPatch>,
+    repository: web::Data<dyn SubscriberRepository>,
+    admin: web::ReqData<AdminUser>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    let patch = patch.into_inner();
+    let name = match patch.name.map(SubscriberName::parse).transpose() {
+        Ok(name) => name,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let email = match patch.email.map(SubscriberEmail::parse).transpose() {
+        Ok(email) => email,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let attributes = match patch.attributes.map(SubscriberAttributes::parse).transpose() {
+        Ok(attributes) => attributes,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    if name.is_none() && email.is_none() && attributes.is_none() && patch.tracking.is_none() && patch.status.is_none() {
+        return HttpResponse::BadRequest().body("Nothing to update");
+    }
+    //先改状态：不合法的转换返回 409，此时姓名和邮箱也不会被修改
+    if let Some(status) = patch.status {
+        let reason = patch.reason.unwrap_or_else(|| format!("changed by admin {}", admin.username));
+        if let Err(e) = repository.change_status(id, status, &reason).await {
+            return repository_error_response(&e);
+        }
+    }
+    let result = if name.is_some() || email.is_some() || attributes.is_some() || patch.tracking.is_some() {
+        repository.update_details(id, name.as_ref(), email.as_ref(), attributes.as_ref(), patch.tracking).await
+    } else {
+        repository.find(id).await
+    };
+    match result {
+        Ok(subscriber) => HttpResponse::Ok().json(subscriber),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[tracing::instrument(name = "Deleting a subscriber", skip(repository))]
+pub async fn delete_subscriber(id: web::Path<Uuid>, repository: web::Data<dyn SubscriberRepository>) -> HttpResponse {
+    match repository.delete(id.into_inner()).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::InMemorySubscriberRepository;
+    use crate::routes::admin::testing::{call, AUTHORIZATION_VALUE};
+    use crate::testing::subscriber;
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::test;
+    use std::sync::Arc;
+
+    async fn seed(repository: &InMemorySubscriberRepository, emails: &[&str]) -> Vec<Uuid> {
+        let mut ids = Vec::new();
+        for email in emails {
+            ids.push(subscriber(repository, email, SubscriptionStatus::Confirmed).await);
+        }
+        ids
+    }
+
+    #[actix_web::test]
+    async fn requests_without_valid_credentials_are_rejected() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let response = call(&repository, test::TestRequest::get().uri("/admin/subscribers")).await;
+        assert_eq!(response.status(), 401);
+        assert!(response.headers().contains_key("www-authenticate"));
+
+        let request = test::TestRequest::get()
+            .uri("/admin/subscribers")
+            .insert_header((AUTHORIZATION, "Basic YWRtaW46d3Jvbmc="));
+        assert_eq!(call(&repository, request).await.status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn subscribers_are_paged_with_a_cursor() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        seed(&repository, &["ada@example.com", "grace@example.com", "ursula@example.com"]).await;
+
+        let request = test::TestRequest::get()
+            .uri("/admin/subscribers?limit=2&sort=email")
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE));
+        let page: serde_json::Value = test::read_body_json(call(&repository, request).await).await;
+        assert_eq!(page["subscribers"].as_array().unwrap().len(), 2);
+        let cursor = page["next_cursor"].as_str().unwrap().to_string();
+
+        let request = test::TestRequest::get()
+            .uri(&format!("/admin/subscribers?limit=2&sort=email&cursor={}", cursor))
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE));
+        let page: serde_json::Value = test::read_body_json(call(&repository, request).await).await;
+        assert_eq!(page["subscribers"][0]["email"], "ursula@example.com");
+        assert!(page["next_cursor"].is_null());
+    }
+
+    #[actix_web::test]
+    async fn invalid_filters_are_rejected() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        for query in ["status=active", "sort=password", "limit=0", "subscribed_from=yesterday", "cursor=garbage"] {
+            let request = test::TestRequest::get()
+                .uri(&format!("/admin/subscribers?{}", query))
+                .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE));
+            assert_eq!(call(&repository, request).await.status(), 400, "{} was accepted", query);
+        }
+    }
+
+    #[actix_web::test]
+    async fn subscribers_can_be_updated_and_deleted() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let id = seed(&repository, &["ursula@example.com"]).await[0];
+
+        let request = test::TestRequest::patch()
+            .uri(&format!("/admin/subscribers/{}", id))
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE))
+            .set_json(serde_json::json!({ "name": "Ursula K. Le Guin", "status": "unsubscribed" }));
+        let subscriber: serde_json::Value = test::read_body_json(call(&repository, request).await).await;
+        assert_eq!(subscriber["name"], "Ursula K. Le Guin");
+        assert_eq!(subscriber["status"], "unsubscribed");
+
+        let request = test::TestRequest::get()
+            .uri(&format!("/admin/subscribers/{}", id))
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE));
+        let details: serde_json::Value = test::read_body_json(call(&repository, request).await).await;
+        assert_eq!(details["events"][1]["reason"], "changed by admin admin");
+
+        //unsubscribed 不能直接回到 confirmed
+        let request = test::TestRequest::patch()
+            .uri(&format!("/admin/subscribers/{}", id))
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE))
+            .set_json(serde_json::json!({ "status": "confirmed" }));
+        assert_eq!(call(&repository, request).await.status(), 409);
+
+        let request = test::TestRequest::delete()
+            .uri(&format!("/admin/subscribers/{}", id))
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE));
+        assert_eq!(call(&repository, request).await.status(), 204);
+        let request = test::TestRequest::get()
+            .uri(&format!("/admin/subscribers/{}", id))
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE));
+        assert_eq!(call(&repository, request).await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn attributes_are_replaced_as_a_whole() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let id = seed(&repository, &["ursula@example.com"]).await[0];
+        let patch = |body: serde_json::Value| test::TestRequest::patch()
+            .uri(&format!("/admin/subscribers/{}", id))
+            .insert_header((AUTHORIZATION, AUTHORIZATION_VALUE))
+            .set_json(body);
+
+        let response = call(&repository, patch(serde_json::json!({ "attributes": { "company": "Acme", "city": "Portland" } }))).await;
+        let subscriber: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(subscriber["attributes"], serde_json::json!({ "city": "Portland", "company": "Acme" }));
+
+        let response = call(&repository, patch(serde_json::json!({ "attributes": { "company": "Initech" } }))).await;
+        let subscriber: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(subscriber["attributes"], serde_json::json!({ "company": "Initech" }));
+
+        let response = call(&repository, patch(serde_json::json!({ "attributes": { "first name": "Ursula" } }))).await;
+        assert_eq!(response.status(), 400);
+    }
+}
@@ -0,0 +1,106 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use crate::authentication::AdminUser;
+use crate::domain::SubscriberEmail;
+use crate::repository::{RepositoryError, SuppressionRepository};
+use crate::routes::errors::repository_error_response;
+
+const DEFAULT_SUPPRESSIONS: usize = 50;
+const MAX_SUPPRESSIONS: usize = 500;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct SuppressionListParams {
+    pub limit: Option<usize>,
+}
+
+/// `GET /admin/suppressions`：停发名单，按加入时间倒序
+#[tracing::instrument(name = "Listing suppressions", skip(suppressions))]
+pub async fn list_suppressions(params: web::Query<SuppressionListParams>, suppressions: web::Data<dyn SuppressionRepository>) -> HttpResponse {
+    let limit = params.limit.unwrap_or(DEFAULT_SUPPRESSIONS);
+    if limit == 0 || limit > MAX_SUPPRESSIONS {
+        return HttpResponse::BadRequest().body(format!("limit must be between 1 and {}", MAX_SUPPRESSIONS));
+    }
+    match suppressions.list_suppressions(limit).await {
+        Ok(suppressions) => HttpResponse::Ok().json(suppressions),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+/// `GET /admin/suppressions/{email}`：不在名单中时返回 404
+#[tracing::instrument(name = "Fetching a suppression", skip(email, suppressions))]
+pub async fn fetch_suppression(email: web::Path<String>, suppressions: web::Data<dyn SuppressionRepository>) -> HttpResponse {
+    let email = match SubscriberEmail::parse(email.into_inner()) {
+        Ok(email) => email,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    match suppressions.find_suppression(&email).await {
+        Ok(Some(suppression)) => HttpResponse::Ok().json(suppression),
+        Ok(None) => repository_error_response(&RepositoryError::NotFound),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+/// `DELETE /admin/suppressions/{email}`：解除停发，成功返回 204
+///
+/// 订阅者的状态不变：退信的订阅者要重新订阅（或由管理员改回 `pending_confirmation`）才会再收到通讯
+#[tracing::instrument(name = "Lifting a suppression", skip(email, suppressions, admin), fields(admin = %admin.username))]
+pub async fn lift_suppression(
+    email: web::Path<String>,
+    suppressions: web::Data<dyn SuppressionRepository>,
+    admin: web::ReqData<AdminUser>,
+) -> HttpResponse {
+    let email = match SubscriberEmail::parse(email.into_inner()) {
+        Ok(email) => email,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    match suppressions.lift_suppression(&email).await {
+        Ok(()) => {
+            tracing::info!("Suppression lifted");
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::repository::{InMemorySubscriberRepository, Suppression, SuppressionReason, SuppressionRepository};
+    use crate::routes::admin::testing::{call, AUTHORIZATION_VALUE};
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::test;
+    use std::sync::Arc;
+
+    fn admin(request: test::TestRequest) -> test::TestRequest {
+        request.insert_header((AUTHORIZATION, AUTHORIZATION_VALUE))
+    }
+
+    #[actix_web::test]
+    async fn suppressions_can_be_listed_and_lifted() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        for (email, reason) in [("ursula@example.com", SuppressionReason::Bounce), ("iain@example.com", SuppressionReason::Complaint)] {
+            let email = SubscriberEmail::parse(email.to_string()).unwrap();
+            repository.suppress(&Suppression::new(&email, reason, Some("mailbox does not exist"), Some("883953f4"))).await.unwrap();
+        }
+
+        let response = call(&repository, admin(test::TestRequest::get().uri("/admin/suppressions"))).await;
+        let suppressions: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(suppressions[0]["email"], "iain@example.com");
+        assert_eq!(suppressions[1]["reason"], "bounce");
+        let response = call(&repository, admin(test::TestRequest::get().uri("/admin/suppressions/Ursula%40Example.com"))).await;
+        let suppression: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!((suppression["detail"].as_str(), suppression["message_id"].as_str()), (Some("mailbox does not exist"), Some("883953f4")));
+
+        let request = admin(test::TestRequest::delete().uri("/admin/suppressions/ursula@example.com"));
+        assert_eq!(call(&repository, request).await.status(), 204);
+        let request = admin(test::TestRequest::delete().uri("/admin/suppressions/ursula@example.com"));
+        assert_eq!(call(&repository, request).await.status(), 404);
+        let request = admin(test::TestRequest::get().uri("/admin/suppressions/ursula@example.com"));
+        assert_eq!(call(&repository, request).await.status(), 404);
+        let request = admin(test::TestRequest::delete().uri("/admin/suppressions/ursula"));
+        assert_eq!(call(&repository, request).await.status(), 400);
+        let request = admin(test::TestRequest::get().uri("/admin/suppressions?limit=0"));
+        assert_eq!(call(&repository, request).await.status(), 400);
+        assert_eq!(call(&repository, test::TestRequest::get().uri("/admin/suppressions")).await.status(), 401);
+    }
+}
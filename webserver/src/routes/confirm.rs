@@ -0,0 +1,152 @@
+//! 确认邮件中的链接（双重确认），确认之后订阅者才会收到通讯
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::Deserialize;
+use crate::domain::email_client::EmailClient;
+use crate::domain::{SubscriberEmail, SubscriptionStatus};
+use crate::email_templates::{EmailTemplates, WelcomeEmail};
+use crate::links::EmailLinks;
+use crate::repository::{ConsentAction, RepositoryError, SubscriberRepository};
+use crate::routes::errors::repository_error_response;
+use crate::routes::subscribe::consent_from_request;
+
+#[derive(Deserialize, Debug)]
+pub struct ConfirmParams {
+    pub token: String,
+}
+
+fn page(body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!("<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\"><title>Confirm your subscription</title></head><body>{}</body></html>", body))
+}
+
+fn invalid_link() -> HttpResponse {
+    HttpResponse::BadRequest().body("This confirmation link is invalid or has expired, subscribe again to get a new one")
+}
+
+fn confirmed() -> HttpResponse {
+    page("<p>Thank you, your subscription is confirmed.</p>")
+}
+
+/// `GET /subscriptions/confirm?token=...`：确认页面，实际确认由页面上的按钮 POST 完成
+///
+/// 与退订链接一样，邮件客户端和安全网关的预取不能替订阅者表示同意
+#[tracing::instrument(name = "Showing the confirmation page", skip(params, links))]
+pub async fn confirm_page(params: web::Query<ConfirmParams>, links: web::Data<EmailLinks>) -> HttpResponse {
+    if links.verify_confirmation(&params.token, Utc::now()).is_none() {
+        return invalid_link();
+    }
+    //令牌只含 base64url 字符和点，不需要转义
+    page(&format!(
+        "<p>Please confirm that you want to receive our newsletter.</p>\
+         <form method=\"post\" action=\"/subscriptions/confirm?token={}\"><button type=\"submit\">Confirm subscription</button></form>",
+        params.token
+    ))
+}
+
+/// `POST /subscriptions/confirm?token=...`：把待确认的订阅者改为 confirmed，并记录这次确认的同意记录
+///
+/// 重复提交也返回成功；确认之后发送欢迎邮件，发送失败只记录日志
+#[tracing::instrument(name = "Confirming a subscription", skip(req, params, links, repository, email_client, templates))]
+pub async fn confirm_subscription(
+    req: HttpRequest,
+    params: web::Query<ConfirmParams>,
+    links: web::Data<EmailLinks>,
+    repository: web::Data<dyn SubscriberRepository>,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<EmailTemplates>,
+) -> HttpResponse {
+    let Some(id) = links.verify_confirmation(&params.token, Utc::now()) else {
+        return invalid_link();
+    };
+    let subscriber = match repository.find(id).await {
+        Ok(subscriber) => subscriber,
+        //订阅者的数据已被删除
+        Err(RepositoryError::NotFound) => return invalid_link(),
+        Err(e) => return repository_error_response(&e),
+    };
+    if subscriber.status == SubscriptionStatus::Confirmed {
+        return confirmed();
+    }
+    let consent = consent_from_request(&req, ConsentAction::Confirm, None, None);
+    match repository.change_status_with_consent(id, SubscriptionStatus::Confirmed, "confirmed via email link", &consent).await {
+        Ok(_) => {}
+        //确认之前已经退订、退信或投诉，这个链接不再有效
+        Err(RepositoryError::InvalidTransition(_)) => return invalid_link(),
+        Err(e) => return repository_error_response(&e),
+    }
+    //邮箱保存时已经校验过
+    let sent = match (templates.render(&WelcomeEmail { name: &subscriber.name }), SubscriberEmail::parse(subscriber.email)) {
+        (Ok(message), Ok(email)) => email_client.send_rendered(email, &message).await,
+        (Err(e), _) | (_, Err(e)) => Err(e),
+    };
+    if let Err(e) = sent {
+        tracing::error!("Failed to send the welcome email: {}", e);
+    }
+    confirmed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{ConsentQuery, InMemorySubscriberRepository};
+    use crate::testing::{call_with_email_server, links, subscriber, uri};
+    use actix_web::test;
+    use std::sync::Arc;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[actix_web::test]
+    async fn the_link_confirms_only_after_the_button_is_pressed() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let id = subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::PendingConfirmation).await;
+        let email_server = MockServer::start().await;
+        //欢迎邮件
+        Mock::given(method("POST")).and(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&email_server)
+            .await;
+        let url = links().confirmation_url(id, Utc::now());
+        let uri = uri(&url);
+
+        let response = call_with_email_server(&repository, &email_server, test::TestRequest::get().uri(uri)).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(repository.find(id).await.unwrap().status, SubscriptionStatus::PendingConfirmation);
+
+        let request = test::TestRequest::post().uri(uri).insert_header(("X-Forwarded-For", "203.0.113.7"));
+        assert_eq!(call_with_email_server(&repository, &email_server, request).await.status(), 200);
+        assert_eq!(repository.find(id).await.unwrap().status, SubscriptionStatus::Confirmed);
+        let consents = repository.consents(&ConsentQuery::for_subscriber(id)).await.unwrap();
+        assert_eq!(consents[0].action, "confirm");
+        assert_eq!(consents[0].ip_address.as_deref(), Some("203.0.113.7"));
+
+        //再次提交不会重复记录，也不会再发欢迎邮件
+        assert_eq!(call_with_email_server(&repository, &email_server, test::TestRequest::post().uri(uri)).await.status(), 200);
+        assert_eq!(repository.consents(&ConsentQuery::for_subscriber(id)).await.unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn invalid_expired_and_stale_links_are_rejected() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let id = subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::PendingConfirmation).await;
+        let email_server = MockServer::start().await;
+
+        let expired = links().confirmation_url(id, Utc::now() - chrono::Duration::days(30));
+        let unsubscribe = links().unsubscribe_url(id, None);
+        for url in [expired.as_str(), unsubscribe.as_str()] {
+            let token = url.split_once("token=").unwrap().1;
+            let request = test::TestRequest::post().uri(&format!("/subscriptions/confirm?token={}", token));
+            assert_eq!(call_with_email_server(&repository, &email_server, request).await.status(), 400);
+        }
+
+        //确认之前已经退订
+        repository.change_status(id, SubscriptionStatus::Unsubscribed, "unsubscribed").await.unwrap();
+        let url = links().confirmation_url(id, Utc::now());
+        let request = test::TestRequest::post().uri(uri(&url));
+        assert_eq!(call_with_email_server(&repository, &email_server, request).await.status(), 400);
+        assert_eq!(repository.find(id).await.unwrap().status, SubscriptionStatus::Unsubscribed);
+    }
+}
@@ -0,0 +1,62 @@
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::HttpResponse;
+use crate::repository::RepositoryError;
+
+//连接池耗尽时建议客户端等待的秒数
+const RETRY_AFTER_SECONDS: u32 = 5;
+
+/// 把仓库错误转换为 HTTP 响应
+///
+/// 连接池耗尽（取连接超时）或已关闭时返回 503 + Retry-After，记录不存在返回 404，
+/// 不合法的状态变化或与记录当前状态冲突的操作返回 409，其它错误返回 500
+pub fn repository_error_response(e: &RepositoryError) -> HttpResponse {
+    match e {
+        RepositoryError::Unavailable(_) => HttpResponse::ServiceUnavailable()
+            .insert_header((RETRY_AFTER, RETRY_AFTER_SECONDS.to_string()))
+            .finish(),
+        RepositoryError::NotFound => HttpResponse::NotFound().finish(),
+        RepositoryError::InvalidTransition(e) => HttpResponse::Conflict().body(e.to_string()),
+        RepositoryError::Conflict(e) => HttpResponse::Conflict().body(e.clone()),
+        RepositoryError::Database(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// 直接使用 sqlx 的地方（迁移、健康检查等）沿用同样的映射
+pub fn database_error_response(e: sqlx::Error) -> HttpResponse {
+    repository_error_response(&RepositoryError::from(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{InvalidTransition, SubscriptionStatus};
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn pool_exhaustion_is_reported_as_503_with_retry_after() {
+        let response = database_error_response(sqlx::Error::PoolTimedOut);
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "5");
+    }
+
+    #[test]
+    fn other_database_errors_are_reported_as_500() {
+        let response = database_error_response(sqlx::Error::Protocol("unexpected message".into()));
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(response.headers().get(RETRY_AFTER).is_none());
+    }
+
+    #[test]
+    fn missing_records_are_reported_as_404() {
+        assert_eq!(repository_error_response(&RepositoryError::NotFound).status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn invalid_status_changes_are_reported_as_409() {
+        let error = RepositoryError::InvalidTransition(InvalidTransition {
+            from: SubscriptionStatus::Complained,
+            to: SubscriptionStatus::Confirmed,
+        });
+        assert_eq!(repository_error_response(&error).status(), StatusCode::CONFLICT);
+    }
+}
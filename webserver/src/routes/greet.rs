@@ -1,4 +1,4 @@
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use actix_web::{HttpRequest, HttpResponse, Responder};
 use uuid::Uuid;
 pub async fn greet(req: HttpRequest) -> impl Responder {
     let request_id = Uuid::new_v4();
@@ -1,8 +1,18 @@
 use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use uuid::Uuid;
+use crate::shutdown::Shutdown;
 
 pub async fn health_check(req: HttpRequest) -> impl Responder {
     let request_id = Uuid::new_v4();
     tracing::info!(" request_id: {} request body: {:?}", request_id, req);
     HttpResponse::Ok().finish()
-}
\ No newline at end of file
+}
+
+/// 就绪检查：进程开始排空（收到 SIGTERM/SIGINT）后返回 503，负载均衡据此停止转发流量
+pub async fn readiness(shutdown: web::Data<Shutdown>) -> impl Responder {
+    if shutdown.is_triggered() {
+        HttpResponse::ServiceUnavailable().finish()
+    } else {
+        HttpResponse::Ok().finish()
+    }
+}
@@ -1,9 +1,45 @@
+use actix_web::web;
+
 pub mod subscribe;
+pub mod confirm;
 pub mod health;
 pub mod greet;
 pub mod telemetry;
+pub mod errors;
+pub mod admin;
+pub mod privacy;
+pub mod unsubscribe;
+pub mod tracking;
+pub mod webhooks;
 
 pub use subscribe::*;   
+pub use confirm::*;
 pub use health::*;   
 pub use greet::*;   
-pub use telemetry::*;
\ No newline at end of file
+pub use telemetry::*;
+pub use errors::*;
+pub use admin::*;
+pub use privacy::*;
+pub use unsubscribe::*;
+pub use tracking::*;
+pub use webhooks::*;
+
+/// 订阅者和邮件服务商访问的公开路由：订阅和确认、退订、跟踪、个人数据和 webhook
+///
+/// 退订等链接都是 GET，要在 `/{name}` 之前注册
+pub fn subscriber_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/subscribe", web::post().to(subscribe))
+        //确认邮件中的链接，GET 只显示页面、POST 才确认
+        .route("/subscriptions/confirm", web::get().to(confirm_page))
+        .route("/subscriptions/confirm", web::post().to(confirm_subscription))
+        //通讯中的退订链接，同样是 GET 显示确认页面
+        .route("/unsubscribe", web::get().to(unsubscribe_page))
+        .route("/unsubscribe", web::post().to(unsubscribe))
+        //通讯中的跟踪像素和改写后的链接
+        .route("/t/o/{token}", web::get().to(track_open))
+        .route("/t/c/{token}", web::get().to(track_click))
+        //邮件服务商推送的退信、投诉和投递事件
+        .route("/webhooks/email-events", web::post().to(email_events))
+        //订阅者通过邮件中的魔法链接查看或删除自己的数据
+        .service(web::scope("/privacy").configure(privacy_routes));
+}
@@ -0,0 +1,240 @@
+//! 订阅者自助的数据访问和删除（GDPR 第 15、17 条），身份通过发到订阅邮箱的魔法链接确认
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use crate::domain::email_client::EmailClient;
+use crate::domain::SubscriberEmail;
+use crate::email_templates::{EmailTemplates, PrivacyLinkEmail};
+use crate::privacy::{hash_token, PrivacyToken, LINK_LIFETIME_MINUTES};
+use crate::repository::{PrivacyRepository, SubscriberRepository, SuppressionRepository};
+use crate::routes::errors::repository_error_response;
+use crate::startup::ApplicationBaseUrl;
+
+/// 注册 `/privacy` 下的路由
+pub fn privacy_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/requests", web::post().to(request_privacy_link))
+        .route("/data", web::get().to(fetch_personal_data))
+        .route("/erase", web::post().to(erase_personal_data));
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PrivacyRequestForm {
+    pub email: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PrivacyTokenParams {
+    pub token: String,
+}
+
+/// `POST /privacy/requests`：给订阅过的邮箱发送一个魔法链接
+///
+/// 不论邮箱是否在列表中都返回 202，不能用来探测某个地址是否订阅过。
+/// 停发名单中的地址收不到邮件，不发送；发送失败只记日志，响应同样是 202
+#[tracing::instrument(name = "Requesting a privacy link", skip(form, repository, privacy, suppressions, email_client, templates, base_url))]
+pub async fn request_privacy_link(
+    form: web::Form<PrivacyRequestForm>,
+    repository: web::Data<dyn SubscriberRepository>,
+    privacy: web::Data<dyn PrivacyRepository>,
+    suppressions: web::Data<dyn SuppressionRepository>,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<EmailTemplates>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid email"),
+    };
+    match repository.find_by_email(&email).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::Accepted().finish(),
+        Err(e) => return repository_error_response(&e),
+    }
+    //退信或投诉过的地址，这些订阅者的请求由管理员处理
+    match suppressions.find_suppression(&email).await {
+        Ok(None) => {}
+        Ok(Some(suppression)) => {
+            tracing::info!("Privacy link not sent to a suppressed address ({})", suppression.reason);
+            return HttpResponse::Accepted().finish();
+        }
+        Err(e) => return repository_error_response(&e),
+    }
+    let token = PrivacyToken::generate();
+    if let Err(e) = privacy.insert_token(&token.token_hash, &email, token.expires_at).await {
+        return repository_error_response(&e);
+    }
+    let link = token.link(&base_url.0);
+    let message = match templates.render(&PrivacyLinkEmail { link: &link, expires_in_minutes: LINK_LIFETIME_MINUTES }) {
+        Ok(message) => message,
+        Err(e) => {
+            tracing::error!("{}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    //返回 5xx 会暴露这个地址订阅过
+    if let Err(e) = email_client.send_rendered(email, &message).await {
+        tracing::error!("Failed to send the privacy link: {}", e);
+    }
+    HttpResponse::Accepted().finish()
+}
+
+//令牌不存在或已过期
+fn invalid_link() -> HttpResponse {
+    HttpResponse::Unauthorized().body("This link is invalid or has expired, request a new one")
+}
+
+async fn token_email(privacy: &dyn PrivacyRepository, token: &str) -> Result<SubscriberEmail, HttpResponse> {
+    match privacy.token_email(&hash_token(token)).await {
+        //保存时已经校验过
+        Ok(Some(email)) => SubscriberEmail::parse(email).map_err(|_| invalid_link()),
+        Ok(None) => Err(invalid_link()),
+        Err(e) => Err(repository_error_response(&e)),
+    }
+}
+
+/// `GET /privacy/data?token=...`：以 JSON 返回该邮箱的全部数据
+#[tracing::instrument(name = "Exporting personal data", skip(params, privacy))]
+pub async fn fetch_personal_data(params: web::Query<PrivacyTokenParams>, privacy: web::Data<dyn PrivacyRepository>) -> HttpResponse {
+    let email = match token_email(privacy.get_ref(), &params.token).await {
+        Ok(email) => email,
+        Err(response) => return response,
+    };
+    match privacy.personal_data(&email).await {
+        Ok(data) => HttpResponse::Ok().json(data),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+/// `POST /privacy/erase`（表单字段 token）：删除该邮箱的全部数据，返回不含邮箱的墓碑记录
+///
+/// 只接受 POST，邮件客户端预取链接不会误删数据；删除后链接随之失效
+#[tracing::instrument(name = "Erasing personal data on request", skip(form, privacy))]
+pub async fn erase_personal_data(form: web::Form<PrivacyTokenParams>, privacy: web::Data<dyn PrivacyRepository>) -> HttpResponse {
+    let email = match token_email(privacy.get_ref(), &form.token).await {
+        Ok(email) => email,
+        Err(response) => return response,
+    };
+    match privacy.erase(&email, "subscriber", "requested via privacy link").await {
+        Ok(tombstone) => HttpResponse::Ok().json(tombstone),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::SubscriptionStatus;
+    use crate::repository::{InMemorySubscriberRepository, Suppression, SuppressionReason};
+    use crate::testing::{call, call_with_email_server, subscriber};
+    use actix_web::test;
+    use std::sync::Arc;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn request_link(email: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/privacy/requests")
+            .insert_header(("Content-Type", "application/x-www-form-urlencoded"))
+            .set_payload(format!("email={}", email))
+    }
+
+    //从模拟邮件服务收到的最后一封邮件中取出令牌
+    async fn emailed_token(email_server: &MockServer) -> String {
+        let requests = email_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+        let text = body["text_body"].as_str().unwrap();
+        let start = text.find("token=").unwrap() + "token=".len();
+        text[start..].split_whitespace().next().unwrap().to_string()
+    }
+
+    #[actix_web::test]
+    async fn links_are_only_emailed_to_subscribers() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let email_server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&email_server)
+            .await;
+
+        //两种情况的响应相同
+        assert_eq!(call_with_email_server(&repository, &email_server, request_link("ursula%40example.com")).await.status(), 202);
+        assert_eq!(call_with_email_server(&repository, &email_server, request_link("nobody%40example.com")).await.status(), 202);
+        assert_eq!(call_with_email_server(&repository, &email_server, request_link("not-an-email")).await.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn suppressed_addresses_get_the_same_response_without_an_email() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Bounced).await;
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        repository.suppress(&Suppression::new(&email, SuppressionReason::Bounce, None, None)).await.unwrap();
+        let email_server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&email_server)
+            .await;
+
+        assert_eq!(call_with_email_server(&repository, &email_server, request_link("ursula%40example.com")).await.status(), 202);
+    }
+
+    #[actix_web::test]
+    async fn failed_sends_get_the_same_response() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let email_server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/email"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&email_server)
+            .await;
+
+        assert_eq!(call_with_email_server(&repository, &email_server, request_link("ursula%40example.com")).await.status(), 202);
+    }
+
+    #[actix_web::test]
+    async fn the_emailed_link_exports_and_erases_the_data() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let email_server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&email_server)
+            .await;
+        call_with_email_server(&repository, &email_server, request_link("ursula%40example.com")).await;
+        let token = emailed_token(&email_server).await;
+
+        let request = test::TestRequest::get().uri(&format!("/privacy/data?token={}", token));
+        let response = call_with_email_server(&repository, &email_server, request).await;
+        assert_eq!(response.status(), 200);
+        let data: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(data["email"], "ursula@example.com");
+        assert_eq!(data["subscriptions"][0]["name"], "Ursula Le Guin");
+        assert_eq!(data["subscription_events"][0]["reason"], "subscribed");
+
+        let erase = || test::TestRequest::post()
+            .uri("/privacy/erase")
+            .insert_header(("Content-Type", "application/x-www-form-urlencoded"))
+            .set_payload(format!("token={}", token));
+        let response = call_with_email_server(&repository, &email_server, erase()).await;
+        assert_eq!(response.status(), 200);
+        let tombstone: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(tombstone["requested_by"], "subscriber");
+        assert_eq!(tombstone["erased_rows"]["subscriptions"], 1);
+        assert!(!tombstone.to_string().contains("ursula"));
+
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        assert!(repository.find_by_email(&email).await.unwrap().is_none());
+        //令牌随数据一起删除
+        assert_eq!(call_with_email_server(&repository, &email_server, erase()).await.status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn unknown_tokens_are_rejected() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let request = test::TestRequest::get().uri("/privacy/data?token=guessed");
+        assert_eq!(call(&repository, request).await.status(), 401);
+    }
+}
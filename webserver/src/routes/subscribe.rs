@@ -1,18 +1,28 @@
 use serde::Deserialize;
+use actix_web::http::header::{REFERER, USER_AGENT};
 use actix_web::{web, HttpRequest, HttpResponse, Responder};
-use sqlx::PgPool;
-use uuid::Uuid;
-use unicode_segmentation::UnicodeSegmentation;  
+use chrono::Utc;
+use std::net::{IpAddr, SocketAddr};
+use crate::domain::email_client::EmailClient;
 use crate::domain::NewSubscriber;
-use crate::domain::{SubscriberName, SubscriberEmail};
+use crate::domain::{SubscriberName, SubscriberEmail, SubscriptionStatus};
+use crate::repository::{ConsentAction, NewConsent, RepositoryError, SubscriberRecord, SubscriberRepository, SuppressionRepository};
+use crate::email_templates::{ConfirmationEmail, EmailTemplates};
+use crate::links::EmailLinks;
+use crate::routes::errors::repository_error_response;
+
+//同意记录中每个文本字段最多保存的字符数
+const MAX_CONSENT_FIELD_CHARS: usize = 512;
 
 #[derive(Deserialize, Debug)]
 pub struct Subscriber {
     pub name: String,
     pub email: String,
+    //表单所在的页面或来源标识，省略时使用 Referer
+    pub source: Option<String>,
+    //表单上展示的同意文本的版本
+    pub consent_version: Option<String>,
 }
-#[tracing::instrument(name = "Converting a subscriber to a new subscriber", skip(subscriber))]
-
 //TryFrom<Subscriber> for NewSubscriber 表示：将 Subscriber 类型转换为 NewSubscriber 类型
 impl TryFrom<Subscriber> for NewSubscriber {
     //Subscriber：源类型（未验证的原始数据）
@@ -49,34 +59,266 @@ pub fn parse_subscriber(form: web::Form<Subscriber>) -> Result<NewSubscriber, St
     Ok(NewSubscriber {email, name})
 }   
 
+//去掉首尾空白，截断过长的值，空字符串视为没有
+fn consent_field(value: Option<&str>) -> Option<String> {
+    value.map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| value.chars().take(MAX_CONSENT_FIELD_CHARS).collect())
+}
+
+/// 从请求中取出同意记录需要的信息
+///
+/// 客户端 IP 优先取 Forwarded / X-Forwarded-For（部署在负载均衡之后），否则取连接的对端地址；
+/// 这些头可以被客户端伪造，记录只作为证据，不用于鉴权
+pub fn consent_from_request(req: &HttpRequest, action: ConsentAction, source: Option<&str>, consent_version: Option<&str>) -> NewConsent {
+    let ip_address = req.connection_info().realip_remote_addr().map(|address| {
+        //对端地址带端口，代理头里的通常不带
+        match address.parse::<SocketAddr>() {
+            Ok(address) => address.ip().to_string(),
+            Err(_) => address.parse::<IpAddr>().map(|ip| ip.to_string()).unwrap_or_else(|_| address.to_string()),
+        }
+    });
+    let header = |name| req.headers().get(name).and_then(|value| value.to_str().ok());
+    NewConsent {
+        action,
+        ip_address: consent_field(ip_address.as_deref()),
+        user_agent: consent_field(header(USER_AGENT)),
+        source: consent_field(source).or_else(|| consent_field(header(REFERER))),
+        consent_version: consent_field(consent_version),
+    }
+}
+
 #[tracing::instrument(
     name = "Adding a new subscriber", 
-    skip(form, db_pool),
+    skip(req, form, repository, suppressions, email_client, templates, links),
     fields(email = %form.email,name = %form.name))]
-pub async fn subscribe(_req: HttpRequest, form: web::Form<Subscriber>,db_pool: web::Data<PgPool>) -> impl Responder {
-    let new_subscriber =  match form.0.try_into() {
+pub async fn subscribe(
+    req: HttpRequest,
+    form: web::Form<Subscriber>,
+    repository: web::Data<dyn SubscriberRepository>,
+    suppressions: web::Data<dyn SuppressionRepository>,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<EmailTemplates>,
+    links: web::Data<EmailLinks>,
+) -> impl Responder {
+    let consent = consent_from_request(&req, ConsentAction::Subscribe, form.source.as_deref(), form.consent_version.as_deref());
+    let new_subscriber: NewSubscriber = match form.0.try_into() {
         //form.0.try_into() 等价于： TryFrom::try_from(form.0)
 
         Ok(subscriber) => subscriber,
         Err(e) => return HttpResponse::BadRequest().body(e),
     };
-    match insert_subscriber(&db_pool, &new_subscriber).await {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    //退信或投诉过的地址收不到确认邮件，状态不变，响应与其它情况相同
+    match suppressions.find_suppression(&new_subscriber.email).await {
+        Ok(None) => {}
+        Ok(Some(suppression)) => {
+            tracing::info!("Subscription form submitted for a suppressed address ({})", suppression.reason);
+            return HttpResponse::Ok().finish();
+        }
+        Err(e) => return repository_error_response(&e),
+    }
+    //同一个邮箱只有一行：已有记录时按状态机改变状态，而不是再插入一行
+    let id = match repository.find_by_email(&new_subscriber.email).await {
+        Ok(Some(existing)) => match resubscribe(repository.get_ref(), &existing, &consent).await {
+            Ok(true) => existing.id,
+            Ok(false) => return HttpResponse::Ok().finish(),
+            Err(e) => return repository_error_response(&e),
+        },
+        //点击确认邮件中的链接之后才变为 confirmed
+        Ok(None) => match repository.insert_with_consent(&new_subscriber, SubscriptionStatus::PendingConfirmation, "subscribed via form", &consent).await {
+            Ok(id) => id,
+            //同一个邮箱的两个请求同时到达，另一个已经插入并发出确认邮件
+            Err(RepositoryError::Conflict(_)) => return HttpResponse::Ok().finish(),
+            Err(e) => return repository_error_response(&e),
+        },
+        Err(e) => return repository_error_response(&e),
+    };
+    let link = links.confirmation_url(id, Utc::now());
+    let message = match templates.render(&ConfirmationEmail { name: new_subscriber.name.as_ref(), confirmation_link: &link }) {
+        Ok(message) => message,
+        Err(e) => {
+            tracing::error!("{}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    //订阅者还是待确认，再次提交表单会重发确认邮件
+    if let Err(e) = email_client.send_rendered(new_subscriber.email, &message).await {
+        tracing::error!("Failed to send the confirmation email: {}", e);
+        return HttpResponse::InternalServerError().finish();
     }
+    HttpResponse::Ok().finish()
 }
 
-#[tracing::instrument(name = "Inserting a new subscriber", 
-skip(form, db_pool))]
-
-pub async fn insert_subscriber(db_pool: &PgPool, form:&NewSubscriber) -> Result<(), sqlx::Error> {
-    sqlx::query!("INSERT INTO subscriptions (id,email, name, subscribed_at) VALUES ($1, $2, $3, $4)"
-    , Uuid::new_v4(), form.email.as_ref(), form.name.as_ref(), chrono::Utc::now())
-    .execute(db_pool).await
-    .map_err(|e| {
-        tracing::error!("Failed to insert subscriber: {}", e);
-        e
-    })?;
-    Ok(())
+/// 已有的订阅者再次提交表单，返回是否要（重新）发送确认邮件
+///
+/// 待确认的重发确认邮件；退订或退信的回到待确认，并记录这次同意；已确认和投诉过的保持不变。
+/// 各种情况的响应相同，不向提交表单的人透露这个邮箱的状态。停发名单中的地址在调用之前已经返回
+async fn resubscribe(repository: &dyn SubscriberRepository, existing: &SubscriberRecord, consent: &NewConsent) -> Result<bool, RepositoryError> {
+    match existing.status {
+        SubscriptionStatus::PendingConfirmation => Ok(true),
+        status if status.can_transition_to(SubscriptionStatus::PendingConfirmation) => {
+            repository
+                .change_status_with_consent(existing.id, SubscriptionStatus::PendingConfirmation, "resubscribed via form", consent)
+                .await?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{ConsentQuery, InMemorySubscriberRepository, SubscriberQuery, Suppression, SuppressionReason};
+    use crate::testing::{call, call_with_email_server, subscriber};
+    use actix_web::test;
+    use std::sync::Arc;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn subscribe_request(body: &'static str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/subscribe")
+            .insert_header(("Content-Type", "application/x-www-form-urlencoded"))
+            .set_payload(body)
+    }
+
+    //路由测试使用内存仓库，不需要数据库；邮件发到模拟的邮件服务，所有请求都返回 200
+    async fn post_subscribe(repository: &Arc<InMemorySubscriberRepository>, body: &'static str) -> actix_web::dev::ServiceResponse {
+        call(repository, subscribe_request(body)).await
+    }
+
+    #[actix_web::test]
+    async fn subscribe_returns_a_200_and_stores_valid_form_data() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let response = post_subscribe(&repository, "name=le%20guin&email=ursula_le_guin%40gmail.com").await;
+        assert_eq!(response.status(), 200);
+        let saved = repository.search(&SubscriberQuery::default()).await.unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].email, "ursula_le_guin@gmail.com");
+        assert_eq!(saved[0].name, "le guin");
+        assert_eq!(saved[0].status, SubscriptionStatus::PendingConfirmation);
+    }
+
+    #[actix_web::test]
+    async fn subscribe_sends_a_confirmation_link() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let email_server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&email_server)
+            .await;
+        let response = call_with_email_server(&repository, &email_server, subscribe_request("name=Ursula&email=ursula%40example.com")).await;
+        assert_eq!(response.status(), 200);
+
+        let requests = email_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["to"], "ursula@example.com");
+        assert!(body["text_body"].as_str().unwrap().contains("https://newsletter.example.com/subscriptions/confirm?token="));
+    }
+
+    #[actix_web::test]
+    async fn subscribe_returns_a_500_when_the_confirmation_email_cannot_be_sent() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let email_server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/email"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&email_server)
+            .await;
+        let response = call_with_email_server(&repository, &email_server, subscribe_request("name=Ursula&email=ursula%40example.com")).await;
+        assert_eq!(response.status(), 500);
+    }
+
+    #[actix_web::test]
+    async fn resubscribing_moves_the_existing_row_through_the_state_machine() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        post_subscribe(&repository, "name=Ursula&email=ursula%40example.com").await;
+        let id = repository.search(&SubscriberQuery::default()).await.unwrap()[0].id;
+        repository.change_status(id, SubscriptionStatus::Unsubscribed, "unsubscribed").await.unwrap();
+
+        //邮箱不区分大小写；重新订阅同样记录同意
+        let response = post_subscribe(&repository, "name=Ursula&email=Ursula%40Example.com&source=footer").await;
+        assert_eq!(response.status(), 200);
+        let saved = repository.search(&SubscriberQuery::default()).await.unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].status, SubscriptionStatus::PendingConfirmation);
+        let consents = repository.consents(&ConsentQuery::for_subscriber(id)).await.unwrap();
+        assert_eq!(consents.len(), 2);
+        assert_eq!(consents[0].source.as_deref(), Some("footer"));
+
+        //投诉过的地址保持原状，响应与其它情况相同
+        repository.change_status(id, SubscriptionStatus::Complained, "complaint").await.unwrap();
+        assert_eq!(post_subscribe(&repository, "name=Ursula&email=ursula%40example.com").await.status(), 200);
+        assert_eq!(repository.find(id).await.unwrap().status, SubscriptionStatus::Complained);
+        assert_eq!(repository.search(&SubscriberQuery::default()).await.unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn suppressed_addresses_keep_their_status_and_get_no_email() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let id = subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Bounced).await;
+        let email = SubscriberEmail::parse("ursula@example.com".to_string()).unwrap();
+        repository.suppress(&Suppression::new(&email, SuppressionReason::Bounce, None, None)).await.unwrap();
+        let email_server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&email_server)
+            .await;
+
+        let response = call_with_email_server(&repository, &email_server, subscribe_request("name=Ursula&email=ursula%40example.com")).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(repository.find(id).await.unwrap().status, SubscriptionStatus::Bounced);
+        assert_eq!(repository.consents(&ConsentQuery::for_subscriber(id)).await.unwrap().len(), 0);
+    }
+
+    #[actix_web::test]
+    async fn subscribe_returns_a_400_when_fields_are_invalid() {
+        let test_cases = vec![
+            ("name=&email=ursula_le_guin%40gmail.com", "empty name"),
+            ("name=Ursula&email=", "empty email"),
+            ("name=Ursula&email=definitely-not-an-email", "invalid email"),
+        ];
+        for (body, description) in test_cases {
+            let repository = Arc::new(InMemorySubscriberRepository::new());
+            let response = post_subscribe(&repository, body).await;
+            assert_eq!(response.status(), 400, "The API did not return 400 when the payload had an {}", description);
+            assert!(repository.search(&SubscriberQuery::default()).await.unwrap().is_empty());
+        }
+    }
+
+    #[actix_web::test]
+    async fn subscribe_records_how_consent_was_given() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let request = subscribe_request("name=Ursula&email=ursula%40example.com&source=footer&consent_version=2025-11")
+            .insert_header(("User-Agent", "Mozilla/5.0"))
+            .insert_header(("X-Forwarded-For", "203.0.113.7, 10.0.0.1"))
+            .insert_header(("Referer", "https://example.com/blog"));
+        assert_eq!(call(&repository, request).await.status(), 200);
+
+        let saved = repository.search(&SubscriberQuery::default()).await.unwrap();
+        let consents = repository.consents(&ConsentQuery::for_subscriber(saved[0].id)).await.unwrap();
+        assert_eq!(consents.len(), 1);
+        assert_eq!(consents[0].action, "subscribe");
+        assert_eq!(consents[0].ip_address.as_deref(), Some("203.0.113.7"));
+        assert_eq!(consents[0].user_agent.as_deref(), Some("Mozilla/5.0"));
+        assert_eq!(consents[0].source.as_deref(), Some("footer"));
+        assert_eq!(consents[0].consent_version.as_deref(), Some("2025-11"));
+    }
+
+    #[actix_web::test]
+    async fn consent_falls_back_to_the_peer_address_and_referer() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let request = subscribe_request("name=Ursula&email=ursula%40example.com")
+            .peer_addr("198.51.100.4:51234".parse().unwrap())
+            .insert_header(("Referer", "https://example.com/blog"));
+        call(&repository, request).await;
+
+        let consents = repository.consents(&ConsentQuery::default()).await.unwrap();
+        assert_eq!(consents[0].ip_address.as_deref(), Some("198.51.100.4"));
+        assert_eq!(consents[0].source.as_deref(), Some("https://example.com/blog"));
+        assert_eq!(consents[0].user_agent, None);
+        assert_eq!(consents[0].consent_version, None);
+    }
+}
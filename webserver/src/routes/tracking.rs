@@ -0,0 +1,103 @@
+//! 通讯中的跟踪像素和改写后的链接，令牌由 `EmailLinks` 签名
+use actix_web::http::header::{CacheControl, CacheDirective, LOCATION};
+use actix_web::{web, HttpResponse};
+use crate::links::EmailLinks;
+use crate::repository::{TrackingEvent, TrackingKind, TrackingRepository};
+
+//1x1 透明 GIF
+const PIXEL: &[u8] = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff!\xf9\x04\x01\x00\x00\x00\x00,\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02D\x01\x00;";
+
+//记录失败不影响读者：像素照样返回，链接照样跳转
+async fn record(tracking: &dyn TrackingRepository, event: TrackingEvent) {
+    if let Err(e) = tracking.record_event(&event).await {
+        tracing::error!("Failed to record {} of issue {}: {}", event.kind, event.issue_id, e);
+    }
+}
+
+/// `GET /t/o/{token}`：跟踪像素，令牌无效时也返回图片，邮件中不会出现破图
+#[tracing::instrument(name = "Tracking an open", skip(token, links, tracking))]
+pub async fn track_open(
+    token: web::Path<String>,
+    links: web::Data<EmailLinks>,
+    tracking: web::Data<dyn TrackingRepository>,
+) -> HttpResponse {
+    if let Some((issue_id, subscriber_id)) = links.verify_open(&token) {
+        record(tracking.get_ref(), TrackingEvent::new(issue_id, subscriber_id, TrackingKind::Open, None)).await;
+    }
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        //每次打开都要请求到服务器
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(PIXEL)
+}
+
+/// `GET /t/c/{token}`：记录点击并 302 到令牌中的地址
+///
+/// 地址在签名范围内，伪造或篡改的令牌返回 400，不能被用作开放重定向
+#[tracing::instrument(name = "Tracking a click", skip(token, links, tracking))]
+pub async fn track_click(
+    token: web::Path<String>,
+    links: web::Data<EmailLinks>,
+    tracking: web::Data<dyn TrackingRepository>,
+) -> HttpResponse {
+    let Some((issue_id, subscriber_id, url)) = links.verify_click(&token) else {
+        return HttpResponse::BadRequest().body("This link is invalid");
+    };
+    record(tracking.get_ref(), TrackingEvent::new(issue_id, subscriber_id, TrackingKind::Click, Some(&url))).await;
+    HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::SubscriptionStatus;
+    use crate::repository::{InMemorySubscriberRepository, SubscriberRepository};
+    use crate::testing::{call, links, subscriber, uri};
+    use actix_web::dev::ServiceResponse;
+    use actix_web::test;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    async fn get(repository: &Arc<InMemorySubscriberRepository>, url: &str) -> ServiceResponse {
+        call(repository, test::TestRequest::get().uri(uri(url))).await
+    }
+
+    #[actix_web::test]
+    async fn opens_and_clicks_are_recorded() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await);
+
+        let response = get(&repository, &links().open_url(issue_id, subscriber_id)).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers().get("content-type").unwrap(), "image/gif");
+        for _ in 0..2 {
+            let response = get(&repository, &links().click_url(issue_id, subscriber_id, "https://example.com/docs")).await;
+            assert_eq!(response.status(), 302);
+            assert_eq!(response.headers().get(LOCATION).unwrap(), "https://example.com/docs");
+        }
+
+        let analytics = repository.issue_analytics(issue_id).await.unwrap();
+        assert_eq!((analytics.opens, analytics.unique_opens, analytics.clicks, analytics.unique_clicks), (1, 1, 2, 1));
+        assert_eq!((analytics.links[0].url.as_str(), analytics.links[0].clicks), ("https://example.com/docs", 2));
+    }
+
+    #[actix_web::test]
+    async fn opted_out_subscribers_and_forged_tokens_are_not_recorded() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await);
+        repository.update_details(subscriber_id, None, None, None, Some(false)).await.unwrap();
+
+        //关闭跟踪之前发出的链接仍然跳转，但不再记录
+        let response = get(&repository, &links().click_url(issue_id, subscriber_id, "https://example.com/docs")).await;
+        assert_eq!(response.status(), 302);
+        assert_eq!(get(&repository, &links().open_url(issue_id, subscriber_id)).await.status(), 200);
+        assert_eq!(repository.issue_analytics(issue_id).await.unwrap(), Default::default());
+
+        let forged = format!("/t/c/{}.c2lnbmF0dXJl", Uuid::new_v4());
+        assert_eq!(get(&repository, &forged).await.status(), 400);
+        assert_eq!(get(&repository, "/t/o/garbage").await.status(), 200);
+    }
+}
@@ -0,0 +1,118 @@
+//! 通讯中的退订链接（合并标签 `{{ unsubscribe_url }}`）
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use crate::domain::SubscriptionStatus;
+use crate::links::EmailLinks;
+use crate::repository::{DeliveryRepository, RepositoryError, SubscriberRepository};
+use crate::routes::errors::repository_error_response;
+
+#[derive(Deserialize, Debug)]
+pub struct UnsubscribeParams {
+    pub token: String,
+}
+
+fn page(body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!("<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\"><title>Unsubscribe</title></head><body>{}</body></html>", body))
+}
+
+fn invalid_link() -> HttpResponse {
+    HttpResponse::BadRequest().body("This unsubscribe link is invalid")
+}
+
+fn unsubscribed() -> HttpResponse {
+    page("<p>You have been unsubscribed and will not receive further issues.</p>")
+}
+
+/// `GET /unsubscribe?token=...`：确认页面，实际退订由页面上的按钮 POST 完成
+///
+/// 邮件客户端和安全网关会预取链接，GET 不能改变状态
+#[tracing::instrument(name = "Showing the unsubscribe page", skip(params, links))]
+pub async fn unsubscribe_page(params: web::Query<UnsubscribeParams>, links: web::Data<EmailLinks>) -> HttpResponse {
+    if links.verify_unsubscribe(&params.token).is_none() {
+        return invalid_link();
+    }
+    //令牌只含 base64url 字符和点，不需要转义
+    page(&format!(
+        "<p>Do you want to stop receiving our newsletter?</p>\
+         <form method=\"post\" action=\"/unsubscribe?token={}\"><button type=\"submit\">Unsubscribe</button></form>",
+        params.token
+    ))
+}
+
+/// `POST /unsubscribe?token=...`：退订，重复提交也返回成功
+///
+/// 同样的请求也满足 RFC 8058 的一键退订（邮件客户端 POST `List-Unsubscribe=One-Click`）。
+/// 链接带期号时，这次退订记在那一期的投递记录上，重复提交不会再记
+#[tracing::instrument(name = "Unsubscribing via link", skip(params, links, repository, deliveries))]
+pub async fn unsubscribe(
+    params: web::Query<UnsubscribeParams>,
+    links: web::Data<EmailLinks>,
+    repository: web::Data<dyn SubscriberRepository>,
+    deliveries: web::Data<dyn DeliveryRepository>,
+) -> HttpResponse {
+    let Some((id, issue_id)) = links.verify_unsubscribe(&params.token) else {
+        return invalid_link();
+    };
+    match repository.change_status(id, SubscriptionStatus::Unsubscribed, "unsubscribed via email link").await {
+        Ok(_) => {
+            //订阅者已经退订成功，归属记录失败只影响报告
+            if let Some(issue_id) = issue_id {
+                if let Err(e) = deliveries.record_unsubscribe(issue_id, id).await {
+                    tracing::error!("Failed to attribute the unsubscribe to issue {}: {}", issue_id, e);
+                }
+            }
+            unsubscribed()
+        }
+        //已经退订、投诉过或数据已被删除：结果一样是不会再收到邮件
+        Err(RepositoryError::InvalidTransition(_)) | Err(RepositoryError::NotFound) => unsubscribed(),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::InMemorySubscriberRepository;
+    use crate::testing::{call, links, subscriber, uri};
+    use actix_web::test;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    #[actix_web::test]
+    async fn the_link_unsubscribes_only_after_confirmation() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let id = subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let issue_id = Uuid::new_v4();
+        repository.record_sent(issue_id, id, None).await.unwrap();
+        let url = links().unsubscribe_url(id, Some(issue_id));
+
+        let response = call(&repository, test::TestRequest::get().uri(uri(&url))).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(repository.find(id).await.unwrap().status, SubscriptionStatus::Confirmed);
+
+        for _ in 0..2 {
+            let response = call(&repository, test::TestRequest::post().uri(uri(&url)).set_payload("List-Unsubscribe=One-Click")).await;
+            assert_eq!(response.status(), 200);
+        }
+        assert_eq!(repository.find(id).await.unwrap().status, SubscriptionStatus::Unsubscribed);
+        assert_eq!(repository.events(id).await.unwrap().last().unwrap().reason, "unsubscribed via email link");
+        let unsubscribed_at = repository.find_delivery(issue_id, id).await.unwrap().unsubscribed_at;
+        assert!(unsubscribed_at.is_some());
+
+        //之后再从别的链接提交，时间和归属都不变
+        let url = links().unsubscribe_url(id, None);
+        assert_eq!(call(&repository, test::TestRequest::post().uri(uri(&url))).await.status(), 200);
+        assert_eq!(repository.find_delivery(issue_id, id).await.unwrap().unsubscribed_at, unsubscribed_at);
+    }
+
+    #[actix_web::test]
+    async fn forged_tokens_are_rejected() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let request = test::TestRequest::post().uri(&format!("/unsubscribe?token={}.c2lnbmF0dXJl", Uuid::new_v4()));
+        assert_eq!(call(&repository, request).await.status(), 400);
+        assert_eq!(call(&repository, test::TestRequest::get().uri("/unsubscribe?token=x")).await.status(), 400);
+    }
+}
@@ -0,0 +1,222 @@
+//! 邮件服务商推送的退信、投诉和投递事件
+use actix_web::http::header::{HeaderValue, WWW_AUTHENTICATE};
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::domain::{SubscriberEmail, SubscriptionStatus};
+use crate::email_events::{EmailEvent, WebhookCredentials};
+use crate::repository::{
+    DeliveryRepository, RepositoryError, SubscriberRecord, SubscriberRepository, Suppression, SuppressionReason, SuppressionRepository,
+};
+use crate::routes::errors::repository_error_response;
+
+/// `POST /webhooks/email-events`：Basic 凭据不对返回 401，格式不对返回 400
+///
+/// 硬退信和投诉把地址加入停发名单，并把收到这封邮件的订阅者改为 bounced / complained；重复推送的事件不会重复处理。
+/// 硬退信还按 MessageID 把那一期的投递记录改为 bounced。数据库出错时返回 5xx，服务商会重试
+#[tracing::instrument(name = "Receiving an email event", skip(request, body, credentials, repository, suppressions, deliveries))]
+pub async fn email_events(
+    request: HttpRequest,
+    body: web::Bytes,
+    credentials: web::Data<WebhookCredentials>,
+    repository: web::Data<dyn SubscriberRepository>,
+    suppressions: web::Data<dyn SuppressionRepository>,
+    deliveries: web::Data<dyn DeliveryRepository>,
+) -> HttpResponse {
+    if let Err(e) = credentials.verify(request.headers()) {
+        tracing::warn!("Rejected an email event: {}", e);
+        return HttpResponse::Unauthorized()
+            .insert_header((WWW_AUTHENTICATE, HeaderValue::from_static(r#"Basic realm="webhooks""#)))
+            .finish();
+    }
+    let event = match EmailEvent::parse(&body) {
+        Ok(event) => event,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let result = match event {
+        EmailEvent::Bounce(bounce) if bounce.is_hard() => {
+            let reason = SuppressionReason::Bounce;
+            let (email, detail, message_id) = (&bounce.email, bounce.description.as_deref(), bounce.message_id.as_deref());
+            match suppress(repository.get_ref(), suppressions.get_ref(), deliveries.get_ref(), email, reason, detail, message_id).await {
+                Ok(()) => record_bounce(deliveries.get_ref(), bounce.message_id.as_deref(), bounce.description.as_deref()).await,
+                Err(e) => Err(e),
+            }
+        }
+        EmailEvent::Bounce(bounce) => {
+            tracing::info!("Soft bounce ({}) of message {:?}", bounce.kind, bounce.message_id);
+            Ok(())
+        }
+        EmailEvent::Complaint(complaint) => {
+            let reason = SuppressionReason::Complaint;
+            let message_id = complaint.message_id.as_deref();
+            suppress(repository.get_ref(), suppressions.get_ref(), deliveries.get_ref(), &complaint.email, reason, None, message_id).await
+        }
+        EmailEvent::Delivery(delivery) => {
+            tracing::info!("Message {:?} delivered", delivery.message_id);
+            Ok(())
+        }
+        EmailEvent::Other => Ok(()),
+    };
+    match result {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => repository_error_response(&e),
+    }
+}
+
+//测试邮件、隐私链接等不在 deliveries 中，找不到对应的记录是正常的
+async fn record_bounce(deliveries: &dyn DeliveryRepository, message_id: Option<&str>, detail: Option<&str>) -> Result<(), RepositoryError> {
+    let Some(message_id) = message_id else {
+        return Ok(());
+    };
+    if deliveries.record_bounce(message_id, detail).await? {
+        tracing::info!("Delivery of message {} marked as bounced", message_id);
+    }
+    Ok(())
+}
+
+async fn suppress(
+    repository: &dyn SubscriberRepository,
+    suppressions: &dyn SuppressionRepository,
+    deliveries: &dyn DeliveryRepository,
+    email: &str,
+    reason: SuppressionReason,
+    detail: Option<&str>,
+    message_id: Option<&str>,
+) -> Result<(), RepositoryError> {
+    //我们不会发信到不合法的地址，这样的事件与本站无关
+    let Ok(email) = SubscriberEmail::parse(email.to_string()) else {
+        tracing::warn!("Ignored a {} for an invalid address", reason.as_str());
+        return Ok(());
+    };
+    if suppressions.suppress(&Suppression::new(&email, reason, detail, message_id)).await? {
+        tracing::info!("Address suppressed after a {}", reason.as_str());
+    }
+    let Some(subscriber) = recipient(repository, deliveries, &email, message_id).await? else {
+        return Ok(());
+    };
+    let (status, note) = match reason {
+        SuppressionReason::Bounce => (SubscriptionStatus::Bounced, "hard bounce reported by the email provider"),
+        SuppressionReason::Complaint => (SubscriptionStatus::Complained, "spam complaint reported by the email provider"),
+    };
+    match repository.change_status(subscriber.id, status, note).await {
+        //已经是这个状态（重复推送）、已经退订或投诉过：地址已在停发名单中，不需要再改
+        Ok(_) | Err(RepositoryError::InvalidTransition(_)) | Err(RepositoryError::NotFound) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+//MessageID 对应的投递记录指明了收件人；测试邮件等没有投递记录，或者收件人之后改了地址时，按地址找当前使用它的订阅者
+async fn recipient(
+    repository: &dyn SubscriberRepository,
+    deliveries: &dyn DeliveryRepository,
+    email: &SubscriberEmail,
+    message_id: Option<&str>,
+) -> Result<Option<SubscriberRecord>, RepositoryError> {
+    if let Some(message_id) = message_id {
+        if let Some(delivery) = deliveries.find_delivery_by_message_id(message_id).await? {
+            match repository.find(delivery.subscriber_id).await {
+                Ok(subscriber) if subscriber.email.eq_ignore_ascii_case(email.as_ref()) => return Ok(Some(subscriber)),
+                Ok(_) | Err(RepositoryError::NotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    repository.find_by_email(email).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::DeliveryStatus;
+    use crate::repository::InMemorySubscriberRepository;
+    use crate::testing::{call, subscriber};
+    use actix_web::dev::ServiceResponse;
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::test;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    //postmark:webhook-password，与 testing::webhook_credentials 相同
+    const AUTHORIZATION_VALUE: &str = "Basic cG9zdG1hcms6d2ViaG9vay1wYXNzd29yZA==";
+
+    //authorized 为 false 时密码不对
+    async fn post_event(repository: &Arc<InMemorySubscriberRepository>, body: serde_json::Value, authorized: bool) -> ServiceResponse {
+        let authorization = if authorized { AUTHORIZATION_VALUE } else { "Basic cG9zdG1hcms6d3Jvbmc=" };
+        let request = test::TestRequest::post()
+            .uri("/webhooks/email-events")
+            .insert_header((AUTHORIZATION, authorization))
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(serde_json::to_vec(&body).unwrap());
+        call(repository, request).await
+    }
+
+    async fn suppressed(repository: &InMemorySubscriberRepository, email: &str) -> Option<Suppression> {
+        let email = SubscriberEmail::parse(email.to_string()).unwrap();
+        repository.find_suppression(&email).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn hard_bounces_and_complaints_suppress_the_address() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let ursula = subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let iain = subscriber(repository.as_ref(), "iain@example.com", SubscriptionStatus::Confirmed).await;
+        let issue_id = Uuid::new_v4();
+        repository.record_sent(issue_id, ursula, Some("883953f4")).await.unwrap();
+
+        let bounce = serde_json::json!({ "RecordType": "Bounce", "Type": "HardBounce", "Email": "Ursula@example.com",
+                                         "MessageID": "883953f4", "Description": "Mailbox does not exist" });
+        for _ in 0..2 {
+            assert_eq!(post_event(&repository, bounce.clone(), true).await.status(), 200);
+        }
+        assert_eq!(repository.find(ursula).await.unwrap().status, SubscriptionStatus::Bounced);
+        let suppression = suppressed(&repository, "ursula@example.com").await.unwrap();
+        assert_eq!((suppression.reason.as_str(), suppression.detail.as_deref()), ("bounce", Some("Mailbox does not exist")));
+        assert_eq!(repository.events(ursula).await.unwrap().len(), 2);
+        let delivery = repository.find_delivery(issue_id, ursula).await.unwrap();
+        assert_eq!((delivery.status, delivery.last_error.as_deref()), (DeliveryStatus::Bounced, Some("Mailbox does not exist")));
+
+        let complaint = serde_json::json!({ "RecordType": "SpamComplaint", "Email": "iain@example.com", "MessageID": "b7bc2f4a" });
+        assert_eq!(post_event(&repository, complaint, true).await.status(), 200);
+        assert_eq!(repository.find(iain).await.unwrap().status, SubscriptionStatus::Complained);
+        assert_eq!(suppressed(&repository, "iain@example.com").await.unwrap().reason, "complaint");
+
+        //不在列表中的地址同样停发
+        let complaint = serde_json::json!({ "RecordType": "SpamComplaint", "Email": "ada@example.com" });
+        assert_eq!(post_event(&repository, complaint, true).await.status(), 200);
+        assert!(suppressed(&repository, "ada@example.com").await.is_some());
+    }
+
+    #[actix_web::test]
+    async fn bounces_of_an_address_the_recipient_no_longer_uses_only_suppress_it() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let ursula = subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        repository.record_sent(Uuid::new_v4(), ursula, Some("883953f4")).await.unwrap();
+        let new_email = SubscriberEmail::parse("ursula@example.org".to_string()).unwrap();
+        repository.update_details(ursula, None, Some(&new_email), None, None).await.unwrap();
+
+        let bounce = serde_json::json!({ "RecordType": "Bounce", "Type": "HardBounce", "Email": "ursula@example.com", "MessageID": "883953f4" });
+        assert_eq!(post_event(&repository, bounce, true).await.status(), 200);
+        assert_eq!(repository.find(ursula).await.unwrap().status, SubscriptionStatus::Confirmed);
+        assert!(suppressed(&repository, "ursula@example.com").await.is_some());
+    }
+
+    #[actix_web::test]
+    async fn soft_bounces_deliveries_and_unauthenticated_events_change_nothing() {
+        let repository = Arc::new(InMemorySubscriberRepository::new());
+        let ursula = subscriber(repository.as_ref(), "ursula@example.com", SubscriptionStatus::Confirmed).await;
+        let issue_id = Uuid::new_v4();
+        repository.record_sent(issue_id, ursula, Some("883953f4")).await.unwrap();
+        for event in [
+            serde_json::json!({ "RecordType": "Bounce", "Type": "SoftBounce", "Email": "ursula@example.com" }),
+            serde_json::json!({ "RecordType": "Delivery", "Recipient": "ursula@example.com", "MessageID": "883953f4" }),
+            serde_json::json!({ "RecordType": "Open", "Recipient": "ursula@example.com" }),
+        ] {
+            assert_eq!(post_event(&repository, event, true).await.status(), 200);
+        }
+        let forged = serde_json::json!({ "RecordType": "SpamComplaint", "Email": "ursula@example.com" });
+        assert_eq!(post_event(&repository, forged, false).await.status(), 401);
+        assert_eq!(post_event(&repository, serde_json::json!({ "RecordType": "Bounce" }), true).await.status(), 400);
+
+        assert_eq!(repository.find(ursula).await.unwrap().status, SubscriptionStatus::Confirmed);
+        assert!(suppressed(&repository, "ursula@example.com").await.is_none());
+        assert_eq!(repository.find_delivery(issue_id, ursula).await.unwrap().status, DeliveryStatus::Sent);
+    }
+}
@@ -0,0 +1,154 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// 进程级的关闭信号，可以克隆给 HTTP 处理器和后台任务
+///
+/// 触发后 `/health/ready` 返回 503，后台任务在完成当前这一单元的工作后退出
+#[derive(Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        Self { sender: Arc::new(sender), receiver }
+    }
+
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// 等待关闭信号；已经触发时立即返回
+    pub async fn wait(&self) {
+        let mut receiver = self.receiver.clone();
+        while !*receiver.borrow_and_update() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 等待 SIGINT（Ctrl+C）或 SIGTERM
+pub async fn wait_for_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("Failed to install Ctrl+C handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("Received SIGINT"),
+        _ = terminate => tracing::info!("Received SIGTERM"),
+    }
+}
+
+/// 与 HTTP 服务器一起运行的后台任务（投递 worker、定时任务等）
+///
+/// 每个任务拿到一个 `Shutdown`，应当在两次工作之间检查它，而不是在工作中途退出
+pub struct BackgroundTasks {
+    shutdown: Shutdown,
+    handles: Vec<(String, JoinHandle<()>)>,
+}
+
+impl BackgroundTasks {
+    pub fn new(shutdown: Shutdown) -> Self {
+        Self { shutdown, handles: Vec::new() }
+    }
+
+    pub fn spawn<F, Fut>(&mut self, name: &str, task: F)
+    where
+        F: FnOnce(Shutdown) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(task(self.shutdown.clone()));
+        self.handles.push((name.to_string(), handle));
+    }
+
+    /// 等待所有任务结束；超过 `timeout` 仍未结束的任务会被中止
+    pub async fn join(self, timeout: Duration) {
+        let deadline = tokio::time::Instant::now() + timeout;
+        for (name, mut handle) in self.handles {
+            match tokio::time::timeout_at(deadline, &mut handle).await {
+                Ok(Ok(())) => tracing::info!("Background task {} stopped", name),
+                Ok(Err(e)) => tracing::error!("Background task {} failed: {}", name, e),
+                Err(_) => {
+                    tracing::warn!("Background task {} did not stop within {:?}, aborting", name, timeout);
+                    handle.abort();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn wait_returns_once_triggered() {
+        let shutdown = Shutdown::new();
+        assert!(!shutdown.is_triggered());
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.wait().await }
+        });
+        shutdown.trigger();
+        tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+        assert!(shutdown.is_triggered());
+        //已经触发之后再等待也会立即返回
+        tokio::time::timeout(Duration::from_secs(1), shutdown.wait()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn background_tasks_finish_their_current_unit_of_work() {
+        let shutdown = Shutdown::new();
+        let completed = Arc::new(AtomicUsize::new(0));
+        let mut tasks = BackgroundTasks::new(shutdown.clone());
+        tasks.spawn("worker", {
+            let completed = completed.clone();
+            move |shutdown| async move {
+                while !shutdown.is_triggered() {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    completed.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        shutdown.trigger();
+        tasks.join(Duration::from_secs(1)).await;
+        assert_eq!(completed.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn background_tasks_are_aborted_after_the_timeout() {
+        let shutdown = Shutdown::new();
+        let mut tasks = BackgroundTasks::new(shutdown.clone());
+        tasks.spawn("stuck", |_| std::future::pending::<()>());
+        shutdown.trigger();
+        tokio::time::timeout(Duration::from_secs(1), tasks.join(Duration::from_millis(50))).await.unwrap();
+    }
+}
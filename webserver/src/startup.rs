@@ -1,33 +1,262 @@
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
 use actix_web::dev::Server;
 use std::net::TcpListener;
-use crate::configuration::get_configuration;
-use crate::routes::{greet, health_check, subscribe};
-use sqlx::PgPool;
+use crate::configuration::{DatabaseSettings, Settings};
+use crate::migration::{check_schema, run_migrations};
+use crate::database::DatabasePool;
+use crate::authentication::require_admin;
+use crate::repository::{
+    DeliveryRepository, NewsletterRepository, PrivacyRepository, Repositories, SubscriberRepository, SuppressionRepository,
+    TrackingRepository, UserRepository,
+};
+use crate::routes::{admin_routes, greet, health_check, readiness, subscriber_routes};
+use crate::shutdown::{wait_for_signal, BackgroundTasks, Shutdown};
+use std::time::Duration;
 use tracing_actix_web::TracingLogger;
 use crate::domain::email_client::EmailClient;
+use crate::email_events::WebhookCredentials;
+use crate::email_templates::EmailTemplates;
+use crate::links::EmailLinks;
+use crate::newsletter::Scheduler;
+use std::path::Path;
 
-pub  fn run(listener: TcpListener, db_pool:PgPool, email_client: EmailClient) -> Result<Server, std::io::Error> {
-        let _settings = get_configuration().expect("Failed to load configuration");
+/// 持有已绑定端口的服务器，main 和测试共用同一条构建路径
+pub struct Application {
+    port: u16,
+    server: Server,
+    shutdown: Shutdown,
+    background_tasks: BackgroundTasks,
+    drain_timeout: Duration,
+    readiness_delay: Duration,
+}
+
+impl Application {
+    /// 根据配置创建连接池、邮件客户端并绑定端口；`application.port` 为 0 时由系统分配端口
+    pub async fn build(settings: Settings) -> Result<Self, std::io::Error> {
+        let db_pool = if settings.database.pool.connect_on_startup {
+            connect_with_retry(&settings.database).await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::ConnectionRefused, e))?
+        } else {
+            DatabasePool::connect_lazy(&settings.database)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?
+        };
+        //数据库结构比程序新时拒绝启动；懒连接池在这里建立第一个连接，检查不会被跳过
+        if settings.database.auto_migrate {
+            run_migrations(&db_pool).await
+                .map_err(std::io::Error::other)?;
+        } else {
+            let pending = check_schema(&db_pool).await
+                .map_err(std::io::Error::other)?;
+            if !pending.is_empty() {
+                tracing::warn!("Migrations {:?} are pending, run `webserver migrate` to apply them", pending);
+            }
+        }
+        let sender = settings.email_client.sender()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let repositories = db_pool.repositories();
+        let timeout = settings.email_client.timeout();
+        let email_client = EmailClient::new(
+            sender,
+            settings.email_client.base_url,
+            settings.email_client.authorization_token,
+            timeout,
+            repositories.suppressions.clone(),
+        );
+        let webhooks = WebhookCredentials::new(settings.email_client.webhook_username, settings.email_client.webhook_password);
+        let templates = EmailTemplates::load(settings.email_client.templates_dir.as_deref().map(Path::new))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+        let address = format!("{}:{}", settings.application.host, settings.application.port);
+        let listener = TcpListener::bind(address)?;
+        //端口为 0 时，实际端口只能从 listener 中读取
+        let port = listener.local_addr()?.port();
+        let shutdown = Shutdown::new();
+        let drain_timeout = Duration::from_secs(settings.application.drain_timeout_seconds);
+        let links = EmailLinks::new(settings.application.base_url, settings.application.hmac_secret);
+        let scheduler = Scheduler::new(
+            repositories.newsletters.clone(),
+            repositories.subscribers.clone(),
+            repositories.deliveries.clone(),
+            email_client.clone(),
+            templates.clone(),
+            links.clone(),
+            Duration::from_secs(settings.application.scheduler_interval_seconds),
+        );
+        let server = run(listener, repositories, email_client, templates, links, webhooks, shutdown.clone(), drain_timeout)?;
+        let mut background_tasks = BackgroundTasks::new(shutdown.clone());
+        background_tasks.spawn("newsletter scheduler", |shutdown| scheduler.run(shutdown));
+        Ok(Self {
+            port,
+            server,
+            shutdown,
+            background_tasks,
+            drain_timeout,
+            readiness_delay: Duration::from_secs(settings.application.readiness_delay_seconds),
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// 关闭信号的句柄，除了 SIGTERM/SIGINT 之外也可以在代码里（例如测试中）触发排空
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// 运行直到收到关闭信号，然后依次：
+    /// 1. `/health/ready` 返回 503，等待 `readiness_delay_seconds` 让负载均衡摘除实例
+    /// 2. 停止接受新连接，等待进行中的请求完成
+    /// 3. 等待后台任务完成当前的工作
+    ///
+    /// 第 2、3 步共用同一个 `drain_timeout_seconds` 截止时间，停机总时长不超过 `readiness_delay + drain_timeout`
+    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+        let handle = self.server.handle();
+        let mut server = tokio::spawn(self.server);
+        let shutdown = self.shutdown.clone();
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            shutdown.trigger();
+        });
+
+        tokio::select! {
+            //服务器自己退出了（例如出错），不需要排空
+            result = &mut server => return result.map_err(std::io::Error::other)?,
+            _ = self.shutdown.wait() => {}
+        }
+        tracing::info!("Draining: readiness is now failing");
+        tokio::time::sleep(self.readiness_delay).await;
+
+        //后台任务在收到关闭信号时已经开始收尾，与 HTTP 排空并行，两者共用一个截止时间
+        let deadline = tokio::time::Instant::now() + self.drain_timeout;
+        tracing::info!("Stopping HTTP server, waiting up to {:?} for in-flight requests", self.drain_timeout);
+        match tokio::time::timeout_at(deadline, async {
+            handle.stop(true).await;
+            (&mut server).await
+        }).await {
+            Ok(result) => result.map_err(std::io::Error::other)??,
+            Err(_) => {
+                tracing::warn!("HTTP server did not stop within {:?}, aborting", self.drain_timeout);
+                server.abort();
+            }
+        }
+
+        self.background_tasks.join(deadline.saturating_duration_since(tokio::time::Instant::now())).await;
+        tracing::info!("Shutdown complete");
+        Ok(())
+    }
+}
+
+/// 立即建立连接池，失败时按指数退避重试 `pool.connect_retries` 次（数据库可能比应用启动得晚）
+pub async fn connect_with_retry(configuration: &DatabaseSettings) -> Result<DatabasePool, sqlx::Error> {
+    let mut attempt = 0;
+    loop {
+        match DatabasePool::connect(configuration).await {
+            Ok(pool) => return Ok(pool),
+            //配置错误（例如未编译 SQLite 支持）重试也不会成功
+            Err(e @ sqlx::Error::Configuration(_)) => return Err(e),
+            Err(e) if attempt < configuration.pool.connect_retries => {
+                let delay = retry_delay(attempt);
+                tracing::warn!("Failed to connect to the database (attempt {}): {}, retrying in {:?}", attempt + 1, e, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                tracing::error!("Giving up connecting to the database after {} attempts: {}", attempt + 1, e);
+                return Err(e);
+            }
+        }
+    }
+}
+
+//500ms、1s、2s、4s……最多 10s
+fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_millis(500u64.saturating_mul(1 << attempt.min(16))).min(Duration::from_secs(10))
+}
+
+/// 邮件中链接使用的公开地址（`application.base_url`）
+pub struct ApplicationBaseUrl(pub String);
+
+//参数都是 Application::build 准备好的共享状态，逐个注册为 app_data
+#[allow(clippy::too_many_arguments)]
+pub  fn run(
+    listener: TcpListener,
+    repositories: Repositories,
+    email_client: EmailClient,
+    templates: EmailTemplates,
+    links: EmailLinks,
+    webhooks: WebhookCredentials,
+    shutdown: Shutdown,
+    drain_timeout: Duration,
+) -> Result<Server, std::io::Error> {
         //web::Data::new 用于在 actix-web 中注册共享的应用状态，让所有请求处理器都能访问同一个数据实例。
-        //db_pool 和 email_client 是两个不同的数据实例，但是它们都存储在 web::Data 中，
+        //repository 和 email_client 是两个不同的数据实例，但是它们都存储在 web::Data 中，
         //这样就可以让所有请求处理器都能访问同一个数据实例。
-        let db_pool = web::Data::new(db_pool);
+        //trait 对象用 web::Data::from(Arc<dyn ...>) 注册，处理器中以 web::Data<dyn SubscriberRepository> 注入
+        let repository: web::Data<dyn SubscriberRepository> = web::Data::from(repositories.subscribers);
+        let users: web::Data<dyn UserRepository> = web::Data::from(repositories.users);
+        let privacy: web::Data<dyn PrivacyRepository> = web::Data::from(repositories.privacy);
+        let newsletters: web::Data<dyn NewsletterRepository> = web::Data::from(repositories.newsletters);
+        let tracking: web::Data<dyn TrackingRepository> = web::Data::from(repositories.tracking);
+        let suppressions: web::Data<dyn SuppressionRepository> = web::Data::from(repositories.suppressions);
+        let deliveries: web::Data<dyn DeliveryRepository> = web::Data::from(repositories.deliveries);
         let email_client = web::Data::new(email_client);
+        let templates = web::Data::new(templates);
+        let base_url = web::Data::new(ApplicationBaseUrl(links.base_url().to_string()));
+        let links = web::Data::new(links);
+        let webhooks = web::Data::new(webhooks);
+        let shutdown = web::Data::new(shutdown);
         let server = HttpServer::new(move || {  
          App::new()
          .wrap(TracingLogger::default())
+         //订阅、确认、退订等公开路由，要在 /{name} 之前注册
+         .configure(subscriber_routes)
          .route("/", web::get().to(greet))  
          .route("/{name}", web::get().to(greet))
          .route("/health", web::get().to(health_check))
-         .route("/subscribe", web::post().to(subscribe))
+         .route("/health/ready", web::get().to(readiness))
+         //管理后台，require_admin 校验 HTTP Basic 凭据
+         .service(
+             web::scope("/admin")
+                 .wrap(from_fn(require_admin))
+                 .configure(admin_routes)
+         )
          //app_data 用于在 actix-web 中注册共享的应用状态，让所有请求处理器都能访问同一个数据实例。
          //clone() 仅克隆 Arc，数据本身不会被复制
-         //处理器中自动注入（subscribe.rs） web::Data<PgPool>  web::Data<EmailClient>  // actix-web 自动注入
-         .app_data(db_pool.clone())
-         .app_data(email_client.clone())})
-        
+         //处理器中自动注入（subscribe.rs） web::Data<dyn SubscriberRepository>  web::Data<EmailClient>  // actix-web 自动注入
+         .app_data(repository.clone())
+         .app_data(users.clone())
+         .app_data(privacy.clone())
+         .app_data(newsletters.clone())
+         .app_data(tracking.clone())
+         .app_data(suppressions.clone())
+         .app_data(deliveries.clone())
+         .app_data(email_client.clone())
+         .app_data(templates.clone())
+         .app_data(base_url.clone())
+         .app_data(links.clone())
+         .app_data(webhooks.clone())
+         .app_data(shutdown.clone())})
+     //信号由 Application::run_until_stopped 处理，先把就绪检查切到 503 再停止服务器
+     .disable_signals()
+     .shutdown_timeout(drain_timeout.as_secs())
      .listen(listener)?
      .run();
      Ok(server)
- }
\ No newline at end of file
+ }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_grows_exponentially_up_to_a_cap() {
+        assert_eq!(retry_delay(0), Duration::from_millis(500));
+        assert_eq!(retry_delay(1), Duration::from_secs(1));
+        assert_eq!(retry_delay(3), Duration::from_secs(4));
+        assert_eq!(retry_delay(5), Duration::from_secs(10));
+        assert_eq!(retry_delay(40), Duration::from_secs(10));
+    }
+}
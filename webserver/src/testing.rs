@@ -0,0 +1,95 @@
+//! 单元测试共用的数据和应用：同一个示例订阅者、同一套链接签名，以及和生产环境相同的公开路由
+use crate::domain::email_client::EmailClient;
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::email_events::WebhookCredentials;
+use crate::email_templates::EmailTemplates;
+use crate::links::EmailLinks;
+use crate::repository::{
+    DeliveryRepository, InMemorySubscriberRepository, PrivacyRepository, SubscriberRepository, SuppressionRepository, TrackingRepository,
+};
+use crate::routes::subscriber_routes;
+use crate::startup::ApplicationBaseUrl;
+use actix_web::dev::ServiceResponse;
+use actix_web::{test, web, App};
+use secrecy::Secret;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// 测试中邮件链接的公开地址
+pub const BASE_URL: &str = "https://newsletter.example.com";
+
+/// 名为 Ursula Le Guin 的新订阅者
+pub fn new_subscriber(email: &str) -> NewSubscriber {
+    NewSubscriber {
+        email: SubscriberEmail::parse(email.to_string()).unwrap(),
+        name: SubscriberName::parse("Ursula Le Guin".to_string()).unwrap(),
+    }
+}
+
+/// 保存一个 `new_subscriber`，返回 id
+pub async fn subscriber(repository: &dyn SubscriberRepository, email: &str, status: SubscriptionStatus) -> Uuid {
+    repository.insert(&new_subscriber(email), status, "subscribed").await.unwrap()
+}
+
+pub fn links() -> EmailLinks {
+    EmailLinks::new(BASE_URL.to_string(), Secret::new("secret".to_string()))
+}
+
+/// 请求头中的 Basic 凭据是 `postmark:webhook-password`
+pub fn webhook_credentials() -> WebhookCredentials {
+    WebhookCredentials::new("postmark".to_string(), Secret::new("webhook-password".to_string()))
+}
+
+/// 发到 `email_server` 的邮件客户端，停发名单是内存仓库
+pub fn email_client(repository: &Arc<InMemorySubscriberRepository>, email_server: &MockServer) -> EmailClient {
+    let sender = SubscriberEmail::parse("newsletter@example.com".to_string()).unwrap();
+    EmailClient::new(sender, email_server.uri(), Secret::new("token".to_string()), Duration::from_secs(2), repository.clone())
+}
+
+/// 接受所有邮件的模拟邮件服务
+pub async fn email_server() -> MockServer {
+    let email_server = MockServer::start().await;
+    Mock::given(method("POST")).and(path("/email")).respond_with(ResponseTemplate::new(200)).mount(&email_server).await;
+    email_server
+}
+
+//每次调用都新建 App，数据保存在共享的内存仓库中；邮件发到 `email_server`，所有请求都返回 200
+pub async fn call(repository: &Arc<InMemorySubscriberRepository>, request: test::TestRequest) -> ServiceResponse {
+    call_with_email_server(repository, &email_server().await, request).await
+}
+
+//同上，邮件发到调用方提供的模拟服务，便于检查发出的邮件或模拟发送失败
+pub async fn call_with_email_server(
+    repository: &Arc<InMemorySubscriberRepository>,
+    email_server: &MockServer,
+    request: test::TestRequest,
+) -> ServiceResponse {
+    let subscribers: Arc<dyn SubscriberRepository> = repository.clone();
+    let privacy: Arc<dyn PrivacyRepository> = repository.clone();
+    let tracking: Arc<dyn TrackingRepository> = repository.clone();
+    let suppressions: Arc<dyn SuppressionRepository> = repository.clone();
+    let deliveries: Arc<dyn DeliveryRepository> = repository.clone();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(subscribers))
+            .app_data(web::Data::from(privacy))
+            .app_data(web::Data::from(tracking))
+            .app_data(web::Data::from(suppressions))
+            .app_data(web::Data::from(deliveries))
+            .app_data(web::Data::new(email_client(repository, email_server)))
+            .app_data(web::Data::new(EmailTemplates::builtin()))
+            .app_data(web::Data::new(ApplicationBaseUrl(BASE_URL.to_string())))
+            .app_data(web::Data::new(links()))
+            .app_data(web::Data::new(webhook_credentials()))
+            .configure(subscriber_routes),
+    ).await;
+    test::call_service(&app, request.to_request()).await
+}
+
+/// 邮件中的完整链接去掉公开地址，用作测试请求的 uri；已经是路径的原样返回
+pub fn uri(url: &str) -> &str {
+    url.strip_prefix(BASE_URL).unwrap_or(url)
+}
@@ -0,0 +1,137 @@
+//! 端到端测试：通过 Application::build 启动完整的应用，与 main 走同一条构建路径
+//!
+//! 使用临时的 SQLite 数据库文件，需要 `cargo test --features sqlite`
+#![cfg(feature = "sqlite")]
+
+use once_cell::sync::Lazy;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+use webserver::configuration::{get_configuration, DatabaseBackend};
+use webserver::routes::telemetry::{get_subscriber, init_subscriber};
+use webserver::startup::Application;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+static TRACING: Lazy<()> = Lazy::new(|| {
+    let env_filter = "info".to_string();
+    if std::env::var("TEST_LOG").is_ok() {
+        init_subscriber(get_subscriber("test".into(), env_filter, std::io::stdout));
+    } else {
+        init_subscriber(get_subscriber("test".into(), env_filter, std::io::sink));
+    }
+});
+
+pub struct TestApp {
+    pub address: String,
+    //测试直接检查数据库，与应用使用同一个文件
+    pub db_pool: SqlitePool,
+    pub email_server: MockServer,
+}
+
+impl TestApp {
+    async fn post_subscribe(&self, body: &'static str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscribe", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    //邮件中的链接指向 application.base_url，换成测试服务器的地址
+    async fn confirmation_link(&self) -> String {
+        let requests = self.email_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let text = body["text_body"].as_str().unwrap();
+        let start = text.find("/subscriptions/confirm?token=").expect("No confirmation link in the email");
+        let link = text[start..].split_whitespace().next().unwrap();
+        format!("{}{}", self.address, link)
+    }
+
+    async fn status(&self, email: &str) -> String {
+        sqlx::query_scalar("SELECT status FROM subscriptions WHERE email = ?")
+            .bind(email)
+            .fetch_one(&self.db_pool)
+            .await
+            .expect("Failed to fetch saved subscription")
+    }
+}
+
+async fn spawn_app() -> TestApp {
+    Lazy::force(&TRACING);
+
+    let email_server = MockServer::start().await;
+    let sqlite_path = std::env::temp_dir().join(format!("webserver-{}.db", Uuid::new_v4()));
+    let configuration = {
+        let mut c = get_configuration().expect("Failed to get configuration");
+        //端口 0 由系统分配随机端口，测试之间互不冲突
+        c.application.port = 0;
+        c.database.backend = DatabaseBackend::Sqlite;
+        c.database.sqlite_path = sqlite_path.display().to_string();
+        c.database.auto_migrate = true;
+        c.email_client.base_url = email_server.uri();
+        c
+    };
+    let application = Application::build(configuration).await.expect("Failed to build application");
+    let port = application.port();
+    tokio::spawn(application.run_until_stopped());
+    let db_pool = SqlitePool::connect_with(SqliteConnectOptions::new().filename(sqlite_path))
+        .await
+        .expect("Failed to connect to the test database");
+    TestApp {
+        address: format!("http://127.0.0.1:{}", port),
+        db_pool,
+        email_server,
+    }
+}
+
+#[tokio::test]
+async fn health() {
+    let app = spawn_app().await;
+    let resp = reqwest::get(format!("{}/health", &app.address)).await.expect("Failed to send request");
+    assert_eq!(resp.status(), 200);
+}
+
+#[tokio::test]
+async fn subscribers_stay_pending_until_they_follow_the_confirmation_link() {
+    let app = spawn_app().await;
+    //确认邮件和确认之后的欢迎邮件
+    Mock::given(method("POST")).and(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let resp = app.post_subscribe("name=le%20guin&email=ursula_le_guin%40gmail.com").await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(app.status("ursula_le_guin@gmail.com").await, "pending_confirmation");
+
+    //GET 只显示确认页面，POST 才会确认
+    let link = app.confirmation_link().await;
+    assert_eq!(reqwest::get(&link).await.unwrap().status(), 200);
+    assert_eq!(app.status("ursula_le_guin@gmail.com").await, "pending_confirmation");
+    let resp = reqwest::Client::new().post(&link).send().await.expect("Failed to send request");
+    assert_eq!(resp.status(), 200);
+    assert_eq!(app.status("ursula_le_guin@gmail.com").await, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_when_data_is_missing() {
+    let app = spawn_app().await;
+    Mock::given(method("POST")).and(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let test_cases = vec![
+        ("name=le%20guin", "missing the email"),
+        ("email=ursula_le_guin%40gmail.com", "missing the name"),
+        ("", "missing both name and email"),
+    ];
+    for (invalid_body, error_message) in test_cases {
+        let resp = app.post_subscribe(invalid_body).await;
+        assert_eq!(resp.status(), 400, "The API did not fail with 400 when the payload was {}", error_message);
+    }
+}